@@ -1,6 +1,11 @@
 use camino::Utf8PathBuf;
 use clap::Parser;
 use clap::Subcommand;
+use paketkoll_core::config::CommonFileCheckConfiguration;
+use paketkoll_core::config::ConfigFiles;
+use paketkoll_core::resource_limits::IoPriority;
+use paketkoll_core::resource_limits::MEGABYTE;
+use paketkoll_core::resource_limits::ReadLimiter;
 
 #[derive(Debug, Parser)]
 #[command(version, about, long_about = None)]
@@ -14,6 +19,25 @@ pub struct Cli {
     /// Debian))
     #[arg(long)]
     pub trust_mtime: bool,
+    /// Number of threads to use for scanning files (default: number of CPUs)
+    #[arg(long)]
+    pub threads: Option<usize>,
+    /// Nice level to scan files with (see nice(1))
+    #[arg(long, allow_negative_numbers = true)]
+    pub nice: Option<i32>,
+    /// I/O priority to scan files with: idle, best-effort or
+    /// best-effort:<level> (0-7, see ionice(1))
+    #[arg(long)]
+    pub ionice: Option<IoPriority>,
+    /// Limit reading of file contents for checksums to this many MB/s
+    #[arg(long)]
+    pub read_limit: Option<u64>,
+    /// Don't verify checksums of files larger than this many MB
+    #[arg(long)]
+    pub max_file_size: Option<u64>,
+    /// Skip files on network file systems (such as NFS or CIFS)
+    #[arg(long)]
+    pub skip_network_fs: bool,
     /// How much to ask for confirmation
     #[arg(long, short = 'p', default_value = "ask")]
     pub confirmation: Paranoia,
@@ -25,6 +49,22 @@ pub struct Cli {
     pub command: Commands,
 }
 
+impl Cli {
+    /// Configuration for checking files on the file system
+    pub fn file_check_config(&self) -> eyre::Result<CommonFileCheckConfiguration> {
+        Ok(CommonFileCheckConfiguration::builder()
+            .trust_mtime(self.trust_mtime)
+            .config_files(ConfigFiles::Include)
+            .threads(self.threads)
+            .nice(self.nice)
+            .io_priority(self.ionice)
+            .read_limit(self.read_limit.map(|mb| ReadLimiter::new(mb * MEGABYTE)))
+            .max_file_size(self.max_file_size.map(|mb| mb * MEGABYTE))
+            .skip_network_filesystems(self.skip_network_fs)
+            .build()?)
+    }
+}
+
 #[derive(Debug, Subcommand)]
 pub enum Commands {
     /// Create a new template config directory
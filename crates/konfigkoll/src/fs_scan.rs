@@ -9,9 +9,9 @@ use konfigkoll_types::FsInstruction;
 use ouroboros::self_referencing;
 use paketkoll_core::config::CheckAllFilesConfiguration;
 use paketkoll_core::config::CommonFileCheckConfiguration;
-use paketkoll_core::config::ConfigFiles;
 use paketkoll_core::file_ops::canonicalize_file_entries;
 use paketkoll_core::file_ops::create_path_map;
+use paketkoll_core::file_ops::merge_file_layers;
 use paketkoll_core::file_ops::mismatching_and_unexpected_files;
 use paketkoll_types::backend::ArchiveQueryError;
 use paketkoll_types::backend::Files;
@@ -35,17 +35,22 @@ pub(crate) struct ScanResult {
 pub(crate) fn scan_fs(
     interner: &Arc<Interner>,
     backend: &Arc<dyn Files>,
+    extra_backends: &[Arc<dyn Files>],
     package_map: &PackageMap,
     ignores: &[CompactString],
-    trust_mtime: bool,
+    common_config: &CommonFileCheckConfiguration,
 ) -> eyre::Result<(ScanResult, Vec<FsInstruction>)> {
     tracing::debug!("Scanning filesystem");
     let mut fs_instructions_sys = vec![];
-    let files = if backend.prefer_files_from_archive() {
+    let mut files = if backend.prefer_files_from_archive() {
         file_vec_from_archive(interner, backend, package_map)?
     } else {
         file_vec_from_db(interner, backend)?
     };
+    for extra in extra_backends {
+        tracing::debug!("Adding files from {}", extra.name());
+        merge_file_layers(&mut files, file_vec_from_db(interner, extra)?);
+    }
 
     tracing::debug!("Building path map");
     let scan_result = ScanResultBuilder {
@@ -55,10 +60,6 @@ pub(crate) fn scan_fs(
     .build();
 
     tracing::debug!("Checking for unexpected files");
-    let common_config = CommonFileCheckConfiguration::builder()
-        .trust_mtime(trust_mtime)
-        .config_files(ConfigFiles::Include)
-        .build()?;
     let unexpected_config = CheckAllFilesConfiguration::builder()
         .canonicalize_paths(backend.may_need_canonicalization())
         .ignored_paths(ignores.to_owned())
@@ -67,7 +68,7 @@ pub(crate) fn scan_fs(
     let issues = mismatching_and_unexpected_files(
         scan_result.borrow_files(),
         scan_result.borrow_path_map(),
-        &common_config,
+        common_config,
         &unexpected_config,
     )?;
 
@@ -70,6 +70,7 @@ fn main() -> eyre::Result<()> {
 
 #[tracing::instrument(level = "debug", skip_all)]
 async fn run_main(cli: Cli) -> Result<(), eyre::Error> {
+    let file_check_config = cli.file_check_config()?;
     let config_path = match cli.config_path {
         Some(v) => v,
         None => std::env::current_dir()?.try_into()?,
@@ -153,6 +154,23 @@ async fn run_main(cli: Cli) -> Result<(), eyre::Error> {
         Arc::new(backend)
     };
 
+    let extra_backends_files: Vec<Arc<dyn Files>> = script_engine
+        .state()
+        .settings()
+        .extra_file_backends()
+        .into_iter()
+        .filter(|b| *b != file_backend_id)
+        .map(|b| {
+            let b: ConcreteBackend = b
+                .try_into()
+                .wrap_err("Backend is not supported by current build")?;
+            let backend = b
+                .create_files(&backend_cfg, &interner)
+                .wrap_err_with(|| format!("Failed to create backend {b}"))?;
+            Ok(Arc::from(backend))
+        })
+        .collect::<eyre::Result<_>>()?;
+
     // Load installed packages
     tracing::info!("Starting package loading background job");
     let package_loader = {
@@ -177,9 +195,9 @@ async fn run_main(cli: Cli) -> Result<(), eyre::Error> {
             .iter()
             .cloned()
             .collect();
-        let trust_mtime = cli.trust_mtime;
         let interner = interner.clone();
         let backends_files = backend_files.clone();
+        let extra_backends_files = extra_backends_files.clone();
         let package_map = package_maps
             .get(&backend_files.as_backend_enum())
             .expect("No matching package backend?")
@@ -188,9 +206,10 @@ async fn run_main(cli: Cli) -> Result<(), eyre::Error> {
             fs_scan::scan_fs(
                 &interner,
                 &backends_files,
+                &extra_backends_files,
                 &package_map,
                 &ignores,
-                trust_mtime,
+                &file_check_config,
             )
         })
     };
@@ -1,7 +1,5 @@
 //! Apply a stream of instructions to the current system
 
-use crate::confirm::Choices;
-use crate::confirm::MultiOptionConfirm;
 use crate::diff::show_fs_instr_diff;
 use crate::utils::IdKey;
 use crate::utils::NameToNumericResolveCache;
@@ -25,6 +23,8 @@ use paketkoll_types::backend::PackageMap;
 use paketkoll_types::backend::PackageMapMap;
 use paketkoll_types::intern::Interner;
 use paketkoll_types::intern::PackageRef;
+use paketkoll_utils::confirm::Choices;
+use paketkoll_utils::confirm::MultiOptionConfirm;
 use std::fs::Permissions;
 use std::os::unix::fs::OpenOptionsExt;
 use std::os::unix::fs::PermissionsExt;
@@ -79,6 +79,7 @@ fn convert_issue(
 ) -> eyre::Result<()> {
     tracing::debug!("Converting issue");
     let path: &Utf8Path = issue.path().try_into()?;
+    let first_result = results.len();
     for kind in issue.kinds() {
         match kind {
             paketkoll_types::issue::IssueKind::Missing => results.push(FsInstruction {
@@ -199,6 +200,15 @@ fn convert_issue(
             _ => todo!(),
         };
     }
+    if pkg.is_none()
+        && let Some(source) = issue.owner(None)
+    {
+        for instr in &mut results[first_result..] {
+            instr
+                .comment
+                .get_or_insert_with(|| format_compact!("Managed by {source}"));
+        }
+    }
     Ok(())
 }
 
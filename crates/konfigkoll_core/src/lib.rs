@@ -5,9 +5,11 @@
 #![allow(semicolon_in_expressions_from_macros)]
 
 pub mod apply;
-pub mod confirm;
 pub mod conversion;
 pub mod diff;
 pub mod save;
 pub mod state;
 pub mod utils;
+
+/// Moved to `paketkoll_utils`, re-exported for compatibility
+pub use paketkoll_utils::confirm;
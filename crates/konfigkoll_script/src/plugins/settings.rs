@@ -24,6 +24,8 @@ const DEFAUT_SENSITIVE: &[&str] = &[
 #[rune(item = ::settings)]
 pub struct Settings {
     file_backend: Mutex<Option<paketkoll_types::backend::Backend>>,
+    /// Additional file backends, in priority order
+    extra_file_backends: Mutex<Vec<paketkoll_types::backend::Backend>>,
     enabled_pkg_backends: Mutex<AHashSet<paketkoll_types::backend::Backend>>,
     /// Configuration files (such as `/etc/passwd`) that should be applied
     /// early, before installing packages.
@@ -44,6 +46,7 @@ impl Default for Settings {
     fn default() -> Self {
         Self {
             file_backend: Mutex::new(None),
+            extra_file_backends: Mutex::new(vec![]),
             enabled_pkg_backends: Mutex::new(AHashSet::new()),
             early_configs: Mutex::new(AHashSet::from_iter(DEFAULT_EARLY.iter().map(Into::into))),
             sensitive_configs: Mutex::new(AHashSet::from_iter(
@@ -60,7 +63,7 @@ impl Default for Settings {
 impl Settings {
     pub fn is_file_backend_enabled(&self, backend: paketkoll_types::backend::Backend) -> bool {
         let guard = self.file_backend.lock();
-        *guard == Some(backend)
+        *guard == Some(backend) || self.extra_file_backends.lock().contains(&backend)
     }
 
     pub fn is_pkg_backend_enabled(&self, backend: paketkoll_types::backend::Backend) -> bool {
@@ -73,6 +76,12 @@ impl Settings {
         *guard
     }
 
+    /// Get additional file backends (in priority order)
+    pub fn extra_file_backends(&self) -> Vec<paketkoll_types::backend::Backend> {
+        let guard = self.extra_file_backends.lock();
+        guard.clone()
+    }
+
     /// Get enabled package backends
     pub fn enabled_pkg_backends(
         &self,
@@ -138,7 +147,8 @@ impl Settings {
     ///
     /// Unlike package manager backends, there can only be one of these
     /// (otherwise the semantics would get confusing regarding which files
-    /// are managed by which tool).
+    /// are managed by which tool). Use `add_file_backend` to also take files
+    /// from other sources into account.
     ///
     /// Valid values are:
     /// * "pacman" (Arch Linux and derivatives)
@@ -158,6 +168,31 @@ impl Settings {
         Ok(())
     }
 
+    /// Add an additional data source for file system checks, layered below
+    /// the one set with `set_file_backend`.
+    ///
+    /// Files listed by such backends are no longer considered unexpected.
+    /// If several backends list the same path, the file backend takes
+    /// priority, followed by additional backends in the order they were
+    /// added.
+    ///
+    /// Typically this is "systemd-tmpfiles" (files created by
+    /// systemd-tmpfiles). Backends that don't support file checks (such as
+    /// flatpak) will result in an error later on.
+    #[rune::function]
+    pub fn add_file_backend(&self, name: &str) -> KResult<()> {
+        let backend = paketkoll_types::backend::Backend::from_str(name)
+            .wrap_err_with(|| format!("Unknown backend {name}"))?;
+        let mut guard = self.extra_file_backends.lock();
+        if guard.contains(&backend) {
+            tracing::warn!("File backend {name} was added more than once");
+        } else {
+            guard.push(backend);
+        }
+
+        Ok(())
+    }
+
     /// Enable a package manager or other backend as a data source and target
     /// for package operations.
     ///
@@ -252,6 +287,7 @@ pub(crate) fn module() -> Result<Module, ContextError> {
     let mut m = Module::from_meta(module_meta)?;
     m.ty::<Settings>()?;
     m.function_meta(Settings::set_file_backend)?;
+    m.function_meta(Settings::add_file_backend)?;
     m.function_meta(Settings::enable_pkg_backend)?;
     m.function_meta(Settings::early_config)?;
     m.function_meta(Settings::sensitive_config)?;
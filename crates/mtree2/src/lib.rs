@@ -46,6 +46,13 @@
 //!
 //! Parsing of [strsvis VIS_CSTYLE](https://man.netbsd.org/strsvis.3)
 //! coded characters is added in addition as specified in [mtree(8)](https://man.netbsd.org/mtree.8) for the netbsd6 flavor.
+//!
+//! Entries can be written back out with [`MTreeWriter`] (only octal escapes
+//! are produced). [`entries_from_dir`] generates entries from a directory tree
+//! on the file system.
+//!
+//! * `gzip`: Enables [`MTreeWriter::write_gzip`].
+//! * `digests`: Include md5 and sha256 digests in [`entries_from_dir`].
 
 pub use parser::FileMode;
 pub use parser::FileType;
@@ -69,9 +76,13 @@ use std::path::PathBuf;
 use std::time::SystemTime;
 use std::time::UNIX_EPOCH;
 use util::decode_escapes_path;
+pub use walk::entries_from_dir;
+pub use writer::MTreeWriter;
 
 mod parser;
 mod util;
+mod walk;
+mod writer;
 
 #[cfg(not(unix))]
 compiler_error!("This library currently only supports unix, due to windows using utf-16 for paths");
@@ -405,6 +416,166 @@ impl Entry {
     pub fn uname(&self) -> Option<&[u8]> {
         self.params.uname.as_ref().map(AsRef::as_ref)
     }
+
+    /// Create a new entry without any parameters
+    ///
+    /// Use the `set_*` methods to fill in the parameters.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            params: Params::default(),
+        }
+    }
+
+    /// Set `cksum` checksum, see [`Self::checksum`]
+    pub fn set_checksum(&mut self, value: Option<u64>) -> &mut Self {
+        self.params.checksum = value;
+        self
+    }
+
+    /// Set `device` number, see [`Self::device`]
+    pub fn set_device(&mut self, value: Option<Device>) -> &mut Self {
+        self.params.device = value.map(Box::new);
+        self
+    }
+
+    /// Set `contents` path, see [`Self::contents`]
+    pub fn set_contents(&mut self, value: Option<PathBuf>) -> &mut Self {
+        self.params.contents = value;
+        self
+    }
+
+    /// Set `flags`, see [`Self::flags`]
+    pub fn set_flags(&mut self, value: Option<Box<[u8]>>) -> &mut Self {
+        self.params.flags = value;
+        self
+    }
+
+    /// Set `gid`, see [`Self::gid`]
+    pub fn set_gid(&mut self, value: Option<u32>) -> &mut Self {
+        self.params.gid = value;
+        self
+    }
+
+    /// Set `gname`, see [`Self::gname`]
+    pub fn set_gname(&mut self, value: Option<Box<[u8]>>) -> &mut Self {
+        self.params.gname = value;
+        self
+    }
+
+    /// Set `ignore` flag, see [`Self::ignore`]
+    pub fn set_ignore(&mut self, value: bool) -> &mut Self {
+        self.params.ignore = value;
+        self
+    }
+
+    /// Set `inode` number, see [`Self::inode`]
+    pub fn set_inode(&mut self, value: Option<u64>) -> &mut Self {
+        self.params.inode = value;
+        self
+    }
+
+    /// Set `link` target, see [`Self::link`]
+    pub fn set_link(&mut self, value: Option<PathBuf>) -> &mut Self {
+        self.params.link = value;
+        self
+    }
+
+    /// Set `md5` digest, see [`Self::md5`]
+    pub fn set_md5(&mut self, value: Option<u128>) -> &mut Self {
+        self.params.md5 = value;
+        self
+    }
+
+    /// Set `mode`, see [`Self::mode`]
+    pub fn set_mode(&mut self, value: Option<FileMode>) -> &mut Self {
+        self.params.mode = value;
+        self
+    }
+
+    /// Set `nlink` count, see [`Self::nlink`]
+    pub fn set_nlink(&mut self, value: Option<u64>) -> &mut Self {
+        self.params.nlink = value;
+        self
+    }
+
+    /// Set `nochange` flag, see [`Self::no_change`]
+    pub fn set_no_change(&mut self, value: bool) -> &mut Self {
+        self.params.no_change = value;
+        self
+    }
+
+    /// Set `optional` flag, see [`Self::optional`]
+    pub fn set_optional(&mut self, value: bool) -> &mut Self {
+        self.params.optional = value;
+        self
+    }
+
+    /// Set `resdevice` number, see [`Self::resident_device`]
+    pub fn set_resident_device(&mut self, value: Option<Device>) -> &mut Self {
+        self.params.resident_device = value.map(Box::new);
+        self
+    }
+
+    /// Set `rmd160` digest, see [`Self::rmd160`]
+    pub fn set_rmd160(&mut self, value: Option<[u8; 20]>) -> &mut Self {
+        self.params.rmd160 = value.map(Box::new);
+        self
+    }
+
+    /// Set `sha1` digest, see [`Self::sha1`]
+    pub fn set_sha1(&mut self, value: Option<[u8; 20]>) -> &mut Self {
+        self.params.sha1 = value.map(Box::new);
+        self
+    }
+
+    /// Set `sha256` digest, see [`Self::sha256`]
+    pub fn set_sha256(&mut self, value: Option<[u8; 32]>) -> &mut Self {
+        self.params.sha256 = value;
+        self
+    }
+
+    /// Set `sha384` digest, see [`Self::sha384`]
+    pub fn set_sha384(&mut self, value: Option<[u8; 48]>) -> &mut Self {
+        self.params.sha384 = value.map(Box::new);
+        self
+    }
+
+    /// Set `sha512` digest, see [`Self::sha512`]
+    pub fn set_sha512(&mut self, value: Option<[u8; 64]>) -> &mut Self {
+        self.params.sha512 = value.map(Box::new);
+        self
+    }
+
+    /// Set `size`, see [`Self::size`]
+    pub fn set_size(&mut self, value: Option<u64>) -> &mut Self {
+        self.params.size = value;
+        self
+    }
+
+    /// Set `time` (modification time), see [`Self::time`]
+    pub fn set_time(&mut self, value: Option<SystemTime>) -> &mut Self {
+        self.params.time = value;
+        self
+    }
+
+    /// Set `type`, see [`Self::file_type`]
+    pub fn set_file_type(&mut self, value: Option<FileType>) -> &mut Self {
+        self.params.file_type = value;
+        self
+    }
+
+    /// Set `uid`, see [`Self::uid`]
+    pub fn set_uid(&mut self, value: Option<u32>) -> &mut Self {
+        self.params.uid = value;
+        self
+    }
+
+    /// Set `uname`, see [`Self::uname`]
+    pub fn set_uname(&mut self, value: Option<Box<[u8]>>) -> &mut Self {
+        self.params.uname = value;
+        self
+    }
 }
 
 /// All possible parameters to an entry.
@@ -376,6 +376,27 @@ pub enum Format {
 }
 
 impl Format {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Native => "native",
+            Self::Bsd386 => "386bsd",
+            Self::Bsd4 => "4bsd",
+            Self::BsdOs => "bsdos",
+            Self::FreeBsd => "freebsd",
+            Self::Hpux => "hpux",
+            Self::Isc => "isc",
+            Self::Linux => "linux",
+            Self::NetBsd => "netbsd",
+            Self::Osf1 => "osf1",
+            Self::Sco => "sco",
+            Self::Solaris => "solaris",
+            Self::SunOs => "sunos",
+            Self::Svr3 => "svr3",
+            Self::Svr4 => "svr4",
+            Self::Ultrix => "ultrix",
+        }
+    }
+
     fn from_bytes(bytes: &[u8]) -> ParserResult<Self> {
         Ok(match bytes {
             b"native" => Self::Native,
@@ -471,7 +492,7 @@ impl FileType {
         })
     }
 
-    fn as_str(self) -> &'static str {
+    pub(crate) fn as_str(self) -> &'static str {
         match self {
             Self::BlockDevice => "block",
             Self::CharacterDevice => "char",
@@ -602,6 +623,17 @@ impl FileMode {
     }
 }
 
+/// Convert from u32 (as used in the standard library)
+///
+/// Only the permission bits (including setuid, setgid and sticky) are kept.
+impl From<u32> for FileMode {
+    fn from(value: u32) -> Self {
+        Self {
+            mode: value & 0o7777,
+        }
+    }
+}
+
 /// Convert to u32 for compatibility with standard library
 impl From<FileMode> for u32 {
     fn from(value: FileMode) -> Self {
@@ -294,6 +294,24 @@ pub fn decode_escapes(buf: &mut [u8]) -> Option<&mut [u8]> {
     Some(&mut buf[..write_idx])
 }
 
+/// Escape a path or other value for writing, the inverse of [`decode_escapes`]
+///
+/// Only octal escapes are used, as those are understood by all mtree flavours.
+pub fn encode_escapes(input: &[u8], output: &mut Vec<u8>) {
+    for &byte in input {
+        if byte.is_ascii_graphic() && byte != b'\\' && byte != b'#' {
+            output.push(byte);
+        } else {
+            output.extend_from_slice(&[
+                b'\\',
+                b'0' + (byte >> 6),
+                b'0' + ((byte >> 3) & 0o7),
+                b'0' + (byte & 0o7),
+            ]);
+        }
+    }
+}
+
 fn get_control_char_from_caret(i: u8) -> Option<u8> {
     if (b'@'..=b'~').contains(&i) {
         return Some(i - b'@');
@@ -387,6 +405,7 @@ mod tests {
     use super::FromHex;
     use super::decode_escapes;
     use super::decode_escapes_path;
+    use super::encode_escapes;
     use std::path::PathBuf;
 
     #[test]
@@ -588,4 +607,13 @@ mod tests {
             decode_escapes(b"test\\s\\stest".to_owned().as_mut()).unwrap()
         );
     }
+
+    #[test]
+    fn test_encode_escapes_round_trip() {
+        let input = b"a b\\c#d\n\xff\x00e";
+        let mut encoded = Vec::new();
+        encode_escapes(input, &mut encoded);
+        assert_eq!(b"a\\040b\\134c\\043d\\012\\377\\000e".as_slice(), encoded);
+        assert_eq!(input.as_slice(), decode_escapes(&mut encoded).unwrap());
+    }
 }
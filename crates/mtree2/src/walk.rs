@@ -0,0 +1,114 @@
+//! Generate entries from a directory tree on the file system.
+use crate::Device;
+use crate::Entry;
+use crate::FileType;
+use crate::Format;
+use std::fs::Metadata;
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Create entries for everything below `root` (but not `root` itself).
+///
+/// The entries get paths relative to root, prefixed with `./` (the same
+/// format pacman uses). Directories are listed before their contents, and
+/// entries within a directory are sorted by name, so the output is
+/// reproducible.
+///
+/// Recorded are type, uid, gid, mode, modification time, size (regular files
+/// only), symlink targets and device numbers. With the `digests` feature
+/// enabled, md5 and sha256 digests of regular files are included as well.
+pub fn entries_from_dir(root: &Path) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    walk(root, Path::new("."), &mut entries)?;
+    Ok(entries)
+}
+
+fn walk(dir: &Path, relative: &Path, entries: &mut Vec<Entry>) -> io::Result<()> {
+    let mut children = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    children.sort_by_key(std::fs::DirEntry::file_name);
+    for child in children {
+        let path = child.path();
+        let relative = relative.join(child.file_name());
+        let metadata = std::fs::symlink_metadata(&path)?;
+        entries.push(entry_from_metadata(&path, relative.clone(), &metadata)?);
+        if metadata.is_dir() {
+            walk(&path, &relative, entries)?;
+        }
+    }
+    Ok(())
+}
+
+/// Create an entry for a single file system object.
+fn entry_from_metadata(path: &Path, relative: PathBuf, metadata: &Metadata) -> io::Result<Entry> {
+    let file_type = metadata.file_type();
+    let mut entry = Entry::new(relative);
+    entry
+        .set_uid(Some(metadata.uid()))
+        .set_gid(Some(metadata.gid()))
+        .set_mode(Some(metadata.mode().into()))
+        .set_time(Some(metadata.modified()?));
+    if file_type.is_file() {
+        entry
+            .set_file_type(Some(FileType::File))
+            .set_size(Some(metadata.len()));
+        #[cfg(feature = "digests")]
+        set_digests(&mut entry, path)?;
+    } else if file_type.is_dir() {
+        entry.set_file_type(Some(FileType::Directory));
+    } else if file_type.is_symlink() {
+        entry
+            .set_file_type(Some(FileType::SymbolicLink))
+            .set_link(Some(std::fs::read_link(path)?));
+    } else if file_type.is_fifo() {
+        entry.set_file_type(Some(FileType::Fifo));
+    } else if file_type.is_socket() {
+        entry.set_file_type(Some(FileType::Socket));
+    } else if file_type.is_block_device() || file_type.is_char_device() {
+        let rdev = metadata.rdev();
+        entry
+            .set_file_type(Some(if file_type.is_block_device() {
+                FileType::BlockDevice
+            } else {
+                FileType::CharacterDevice
+            }))
+            .set_device(Some(Device {
+                format: Format::Linux,
+                major: libc::major(rdev).to_string().into_bytes(),
+                minor: libc::minor(rdev).to_string().into_bytes(),
+                subunit: None,
+            }));
+    }
+    Ok(entry)
+}
+
+/// Compute md5 and sha256 digests of a regular file.
+#[cfg(feature = "digests")]
+fn set_digests(entry: &mut Entry, path: &Path) -> io::Result<()> {
+    use md5::Digest;
+    use std::io::Read;
+
+    let mut reader = std::fs::File::open(path)?;
+    let mut md5 = md5::Md5::new();
+    let mut sha256 = ring::digest::Context::new(&ring::digest::SHA256);
+    let mut buffer = [0; 16 * 1024];
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        md5.update(&buffer[..n]);
+        sha256.update(&buffer[..n]);
+    }
+    let sha256: [u8; 32] = sha256
+        .finish()
+        .as_ref()
+        .try_into()
+        .expect("SHA256 digest has wrong length");
+    entry
+        .set_md5(Some(u128::from_be_bytes(md5.finalize().into())))
+        .set_sha256(Some(sha256));
+    Ok(())
+}
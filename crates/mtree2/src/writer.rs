@@ -0,0 +1,283 @@
+//! Serialization of entries back into mtree specifications.
+use crate::Device;
+use crate::Entry;
+use crate::util::encode_escapes;
+use std::io;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// Keywords that are candidates for being moved to a `/set` line.
+const SET_KEYWORDS: &[&[u8]] = &[b"type", b"uid", b"gid", b"mode", b"uname", b"gname"];
+
+/// Writer for mtree specifications.
+///
+/// Paths are always written as full paths (in the mtree sense, i.e. containing
+/// a `/`). Paths without any `/` are written with a `./` prefix. Absolute
+/// paths can not be represented in mtree, they are written relative to the
+/// root (`/usr` becomes `./usr`).
+///
+/// # Examples
+///
+/// ```
+/// use mtree2::{Entry, FileType, MTree, MTreeWriter};
+///
+/// let mut entry = Entry::new("./usr/bin");
+/// entry
+///     .set_file_type(Some(FileType::Directory))
+///     .set_mode(Some(0o755.into()));
+/// let mut output = Vec::new();
+/// MTreeWriter::new().write(&mut output, [&entry]).unwrap();
+///
+/// let parsed: Vec<_> = MTree::from_reader_with_empty_cwd(output.as_slice())
+///     .collect::<Result<_, _>>()
+///     .unwrap();
+/// assert_eq!(parsed, vec![entry]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct MTreeWriter {
+    set_compression: bool,
+}
+
+impl Default for MTreeWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MTreeWriter {
+    /// Create a writer with default options (`/set` compression enabled).
+    pub fn new() -> Self {
+        Self {
+            set_compression: true,
+        }
+    }
+
+    /// Control if common keywords (type, owner, group, mode) are moved to
+    /// `/set` lines instead of being repeated on every entry.
+    pub fn set_compression(mut self, enabled: bool) -> Self {
+        self.set_compression = enabled;
+        self
+    }
+
+    /// Write entries as an mtree specification.
+    pub fn write<'entry>(
+        &self,
+        mut writer: impl Write,
+        entries: impl IntoIterator<Item = &'entry Entry>,
+    ) -> io::Result<()> {
+        let lines: Vec<(Vec<u8>, Vec<Vec<u8>>)> = entries
+            .into_iter()
+            .map(|entry| (format_path(entry.path()), format_keywords(entry)))
+            .collect();
+        let defaults = if self.set_compression {
+            common_keywords(&lines)
+        } else {
+            vec![]
+        };
+
+        writer.write_all(b"#mtree\n")?;
+        if !defaults.is_empty() {
+            writer.write_all(b"/set")?;
+            for keyword in &defaults {
+                writer.write_all(b" ")?;
+                writer.write_all(keyword)?;
+            }
+            writer.write_all(b"\n")?;
+        }
+        for (path, keywords) in &lines {
+            writer.write_all(path)?;
+            for keyword in keywords {
+                if defaults.contains(keyword) {
+                    continue;
+                }
+                writer.write_all(b" ")?;
+                writer.write_all(keyword)?;
+            }
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()
+    }
+
+    /// Write entries as a gzip compressed mtree specification (as used by
+    /// pacman for `.MTREE` files).
+    #[cfg(feature = "gzip")]
+    pub fn write_gzip<'entry>(
+        &self,
+        writer: impl Write,
+        entries: impl IntoIterator<Item = &'entry Entry>,
+    ) -> io::Result<()> {
+        let mut encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+        self.write(&mut encoder, entries)?;
+        encoder.finish()?.flush()
+    }
+}
+
+/// Format and escape the path of an entry.
+fn format_path(path: &Path) -> Vec<u8> {
+    let raw = path.as_os_str().as_bytes();
+    let mut output = Vec::with_capacity(raw.len() + 2);
+    if let Some(stripped) = raw.strip_prefix(b"/") {
+        output.extend_from_slice(b"./");
+        encode_escapes(stripped, &mut output);
+    } else {
+        if !raw.contains(&b'/') {
+            output.extend_from_slice(b"./");
+        }
+        encode_escapes(raw, &mut output);
+    }
+    output
+}
+
+/// Format all keywords of an entry as `key=value` (or just `key` for flags).
+fn format_keywords(entry: &Entry) -> Vec<Vec<u8>> {
+    fn keyword(key: &str, value: impl std::fmt::Display) -> Vec<u8> {
+        format!("{key}={value}").into_bytes()
+    }
+    fn raw(key: &str, value: &[u8]) -> Vec<u8> {
+        let mut output = format!("{key}=").into_bytes();
+        encode_escapes(value, &mut output);
+        output
+    }
+    fn hex(key: &str, value: &[u8]) -> Vec<u8> {
+        keyword(key, faster_hex::hex_string(value))
+    }
+    fn device(key: &str, value: &Device) -> Vec<u8> {
+        let mut output = format!("{key}={},", value.format.as_str()).into_bytes();
+        output.extend_from_slice(&value.major);
+        output.push(b',');
+        output.extend_from_slice(&value.minor);
+        if let Some(ref subunit) = value.subunit {
+            output.push(b',');
+            output.extend_from_slice(subunit);
+        }
+        output
+    }
+
+    let params = &entry.params;
+    let mut keywords = Vec::new();
+    if let Some(v) = params.file_type {
+        keywords.push(keyword("type", v.as_str()));
+    }
+    if let Some(v) = params.uid {
+        keywords.push(keyword("uid", v));
+    }
+    if let Some(v) = params.gid {
+        keywords.push(keyword("gid", v));
+    }
+    if let Some(ref v) = params.uname {
+        keywords.push(raw("uname", v));
+    }
+    if let Some(ref v) = params.gname {
+        keywords.push(raw("gname", v));
+    }
+    if let Some(v) = params.mode {
+        keywords.push(keyword("mode", format_args!("{:o}", u32::from(v))));
+    }
+    if let Some(v) = params.nlink {
+        keywords.push(keyword("nlink", v));
+    }
+    if let Some(v) = params.inode {
+        keywords.push(keyword("inode", v));
+    }
+    if let Some(ref v) = params.flags {
+        keywords.push(raw("flags", v));
+    }
+    if let Some(v) = params.time {
+        // Times before the epoch can not be represented, skip them
+        if let Ok(time) = v.duration_since(UNIX_EPOCH) {
+            keywords.push(keyword(
+                "time",
+                format_args!("{}.{:09}", time.as_secs(), time.subsec_nanos()),
+            ));
+        }
+    }
+    if let Some(v) = params.size {
+        keywords.push(keyword("size", v));
+    }
+    if let Some(ref v) = params.device {
+        keywords.push(device("device", v));
+    }
+    if let Some(ref v) = params.resident_device {
+        keywords.push(device("resdevice", v));
+    }
+    if let Some(ref v) = params.link {
+        keywords.push(raw("link", v.as_os_str().as_bytes()));
+    }
+    if let Some(ref v) = params.contents {
+        keywords.push(raw("contents", v.as_os_str().as_bytes()));
+    }
+    if let Some(v) = params.checksum {
+        keywords.push(keyword("cksum", v));
+    }
+    if let Some(v) = params.md5 {
+        keywords.push(keyword("md5digest", format_args!("{v:032x}")));
+    }
+    if let Some(ref v) = params.rmd160 {
+        keywords.push(hex("rmd160digest", v.as_slice()));
+    }
+    if let Some(ref v) = params.sha1 {
+        keywords.push(hex("sha1digest", v.as_slice()));
+    }
+    if let Some(ref v) = params.sha256 {
+        keywords.push(hex("sha256digest", v.as_slice()));
+    }
+    if let Some(ref v) = params.sha384 {
+        keywords.push(hex("sha384digest", v.as_slice()));
+    }
+    if let Some(ref v) = params.sha512 {
+        keywords.push(hex("sha512digest", v.as_slice()));
+    }
+    if params.ignore {
+        keywords.push(b"ignore".to_vec());
+    }
+    if params.no_change {
+        keywords.push(b"nochange".to_vec());
+    }
+    if params.optional {
+        keywords.push(b"optional".to_vec());
+    }
+    keywords
+}
+
+/// Find the most common value for each of the [`SET_KEYWORDS`].
+///
+/// Since `/unset` is not supported by the parser, a keyword is only a
+/// candidate if every entry has it.
+fn common_keywords(lines: &[(Vec<u8>, Vec<Vec<u8>>)]) -> Vec<Vec<u8>> {
+    let mut defaults = Vec::new();
+    for key in SET_KEYWORDS {
+        let value_of = |keywords: &'_ [Vec<u8>]| -> Option<Vec<u8>> {
+            keywords
+                .iter()
+                .find(|k| k.strip_prefix(*key).is_some_and(|r| r.starts_with(b"=")))
+                .cloned()
+        };
+        let mut counts: Vec<(Vec<u8>, usize)> = Vec::new();
+        let mut all_have_key = true;
+        for (_, keywords) in lines {
+            let Some(value) = value_of(keywords) else {
+                all_have_key = false;
+                break;
+            };
+            match counts.iter_mut().find(|(v, _)| *v == value) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((value, 1)),
+            }
+        }
+        if !all_have_key {
+            continue;
+        }
+        // Prefer the first seen value on ties to get stable output
+        let best = counts
+            .into_iter()
+            .rev()
+            .max_by_key(|(_, count)| *count)
+            .filter(|(_, count)| *count > 1);
+        if let Some((value, _)) = best {
+            defaults.push(value);
+        }
+    }
+    defaults
+}
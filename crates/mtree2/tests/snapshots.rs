@@ -1,5 +1,6 @@
 use insta::assert_debug_snapshot;
 use mtree2::MTree;
+use mtree2::MTreeWriter;
 use std::fs::File;
 use std::path::PathBuf;
 
@@ -47,3 +48,46 @@ test_snapshot_with_empty_cwd!(
     test_not_unicode_netbsd6_flavor,
     "tests/data/not_unicode_netbsd6.mtree"
 );
+
+/// Parse, write and parse again, the result should be identical
+macro_rules! test_round_trip {
+    ($name:ident, $path:expr) => {
+        #[test]
+        fn $name() {
+            let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join($path);
+            let entries: Vec<_> = MTree::from_reader_with_empty_cwd(File::open(path).unwrap())
+                .filter_map(Result::ok)
+                .collect();
+            assert!(!entries.is_empty());
+            for writer in [
+                MTreeWriter::new(),
+                MTreeWriter::new().set_compression(false),
+            ] {
+                let mut output = Vec::new();
+                writer.write(&mut output, &entries).unwrap();
+                let reparsed: Vec<_> = MTree::from_reader_with_empty_cwd(output.as_slice())
+                    .collect::<Result<_, _>>()
+                    .unwrap();
+                assert_eq!(entries, reparsed);
+            }
+        }
+    };
+}
+test_round_trip!(round_trip_gedit, "examples/gedit.mtree");
+test_round_trip!(round_trip_xterm, "tests/data/xterm.mtree");
+test_round_trip!(round_trip_relative_paths, "tests/data/relative_paths.mtree");
+test_round_trip!(
+    round_trip_wrapped_lines,
+    "tests/data/relative_paths_wrapped.mtree"
+);
+test_round_trip!(
+    round_trip_wrapped_lines_exceeding_root,
+    "tests/data/relative_paths_wrapped_exceeding_root.mtree"
+);
+test_round_trip!(round_trip_freebsd9_flavor, "tests/data/test_freebsd9.mtree");
+test_round_trip!(round_trip_mtree_flavor, "tests/data/test_mtree.mtree");
+test_round_trip!(round_trip_not_unicode, "tests/data/not_unicode.mtree");
+test_round_trip!(
+    round_trip_not_unicode_netbsd6_flavor,
+    "tests/data/not_unicode_netbsd6.mtree"
+);
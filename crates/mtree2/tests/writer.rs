@@ -0,0 +1,78 @@
+use mtree2::FileType;
+use mtree2::MTree;
+use mtree2::MTreeWriter;
+use mtree2::entries_from_dir;
+use std::path::Path;
+
+fn create_tree(root: &Path) {
+    std::fs::create_dir(root.join("usr")).expect("Failed to create test tree");
+    std::fs::create_dir(root.join("usr/with space")).expect("Failed to create test tree");
+    std::fs::write(root.join("usr/with space/a#file"), b"hello\n")
+        .expect("Failed to create test tree");
+    std::fs::write(root.join("usr/b"), b"").expect("Failed to create test tree");
+    std::os::unix::fs::symlink("with space/a#file", root.join("usr/link"))
+        .expect("Failed to create test tree");
+}
+
+#[test]
+fn test_entries_from_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    create_tree(dir.path());
+    let entries = entries_from_dir(dir.path()).unwrap();
+
+    let summary: Vec<_> = entries
+        .iter()
+        .map(|e| (e.path().to_str().unwrap(), e.file_type().unwrap(), e.size()))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("./usr", FileType::Directory, None),
+            ("./usr/b", FileType::File, Some(0)),
+            ("./usr/link", FileType::SymbolicLink, None),
+            ("./usr/with space", FileType::Directory, None),
+            ("./usr/with space/a#file", FileType::File, Some(6)),
+        ]
+    );
+    assert_eq!(entries[2].link(), Some(Path::new("with space/a#file")));
+    #[cfg(feature = "digests")]
+    assert_eq!(entries[4].md5(), Some(0xb1946ac92492d2347c6235b4d2611184));
+}
+
+#[test]
+fn test_write_with_set() {
+    let dir = tempfile::tempdir().unwrap();
+    create_tree(dir.path());
+    let entries = entries_from_dir(dir.path()).unwrap();
+
+    let mut output = Vec::new();
+    MTreeWriter::new().write(&mut output, &entries).unwrap();
+    let text = String::from_utf8(output.clone()).unwrap();
+    let mut lines = text.lines();
+    assert_eq!(lines.next(), Some("#mtree"));
+    assert!(lines.next().unwrap().starts_with("/set "));
+    assert!(text.contains("./usr/with\\040space/a\\043file "));
+
+    let reparsed: Vec<_> = MTree::from_reader_with_empty_cwd(output.as_slice())
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(entries, reparsed);
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn test_write_gzip() {
+    let dir = tempfile::tempdir().unwrap();
+    create_tree(dir.path());
+    let entries = entries_from_dir(dir.path()).unwrap();
+
+    let mut output = Vec::new();
+    MTreeWriter::new()
+        .write_gzip(&mut output, &entries)
+        .unwrap();
+    let decoder = flate2::read::GzDecoder::new(output.as_slice());
+    let reparsed: Vec<_> = MTree::from_reader_with_empty_cwd(decoder)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(entries, reparsed);
+}
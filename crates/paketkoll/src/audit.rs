@@ -0,0 +1,123 @@
+//! Check installed packages against security advisories
+
+use eyre::OptionExt;
+use paketkoll::cli::AdvisoryFormat;
+use paketkoll::cli::Cli;
+use paketkoll::cli::Format;
+use paketkoll_core::audit;
+use paketkoll_core::audit::AdvisorySource;
+use paketkoll_core::backend::ConcreteBackend;
+use proc_exit::Code;
+use proc_exit::Exit;
+use std::io::BufWriter;
+use std::io::Write;
+use std::io::stdout;
+use std::path::Path;
+
+pub(crate) fn run_audit(
+    cli: &Cli,
+    db_format: AdvisoryFormat,
+    release: Option<&str>,
+    path: &Path,
+) -> eyre::Result<Exit> {
+    let backend: ConcreteBackend = cli.backend.try_into()?;
+    let source = match db_format {
+        AdvisoryFormat::Arch => AdvisorySource::ArchLinux,
+        AdvisoryFormat::Debian => AdvisorySource::Debian {
+            release: match release {
+                Some(release) => release.into(),
+                None => crate::os_release("VERSION_CODENAME")
+                    .ok_or_eyre("Unable to determine release codename, pass --release")?
+                    .into(),
+            },
+        },
+        AdvisoryFormat::Osv => AdvisorySource::Osv {
+            ecosystem: match release {
+                Some(release) => release.into(),
+                None => default_ecosystem(backend)
+                    .ok_or_eyre("Unable to determine OSV ecosystem, pass --release")?
+                    .into(),
+            },
+        },
+    };
+    let advisories = audit::load_advisories(&source, path)?;
+    tracing::debug!("Loaded {} advisories", advisories.len());
+    let (interner, mut findings) = audit::audit(backend, &cli.try_into()?, &advisories)?;
+    findings.sort_by(|a, b| {
+        (a.package.as_str(&interner), &a.advisory.id)
+            .cmp(&(b.package.as_str(&interner), &b.advisory.id))
+    });
+
+    let mut stdout = BufWriter::new(stdout().lock());
+    match cli.format {
+        Format::Human => {
+            for finding in &findings {
+                let advisory = finding.advisory;
+                write!(
+                    stdout,
+                    "{} {}: {}",
+                    finding.package.as_str(&interner),
+                    finding.version,
+                    advisory.id
+                )?;
+                if !advisory.aliases.is_empty() {
+                    write!(stdout, " ({})", advisory.aliases.join(", "))?;
+                }
+                if let Some(ref severity) = advisory.severity {
+                    write!(stdout, " [{severity}]")?;
+                }
+                match advisory.fixed {
+                    Some(ref fixed) => writeln!(stdout, ", fixed in {fixed}")?,
+                    None => writeln!(stdout, ", not fixed")?,
+                }
+            }
+        }
+        #[cfg(feature = "json")]
+        Format::Json => {
+            let findings: Vec<_> = findings
+                .iter()
+                .map(|finding| AuditReport {
+                    package: finding.package.as_str(&interner),
+                    version: &finding.version,
+                    advisory: finding.advisory,
+                })
+                .collect();
+            serde_json::to_writer_pretty(&mut stdout, &findings)?;
+        }
+        format => eyre::bail!("{format} format is not supported for audit results"),
+    }
+    stdout.flush()?;
+
+    Ok(if findings.is_empty() {
+        Exit::new(Code::SUCCESS)
+    } else {
+        Exit::new(Code::FAILURE)
+    })
+}
+
+/// Guess the OSV ecosystem of the running system
+fn default_ecosystem(backend: ConcreteBackend) -> Option<String> {
+    match backend {
+        #[cfg(feature = "arch_linux")]
+        ConcreteBackend::Pacman => Some("Arch Linux".into()),
+        #[cfg(feature = "debian")]
+        ConcreteBackend::Apt => {
+            let distro = match crate::os_release("ID")?.as_str() {
+                "debian" => "Debian",
+                "ubuntu" => "Ubuntu",
+                _ => return None,
+            };
+            let version = crate::os_release("VERSION_ID")?;
+            Some(format!("{distro}:{version}"))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(feature = "json")]
+#[derive(Debug, serde::Serialize)]
+struct AuditReport<'a> {
+    package: &'a str,
+    version: &'a str,
+    advisory: &'a audit::Advisory,
+}
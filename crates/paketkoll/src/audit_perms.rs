@@ -0,0 +1,86 @@
+//! Report security relevant file permissions
+
+use paketkoll::cli::Cli;
+use paketkoll::cli::Format;
+use paketkoll_core::permissions;
+use paketkoll_core::permissions::Ownership;
+use proc_exit::Code;
+use proc_exit::Exit;
+use std::io::BufWriter;
+use std::io::Write;
+use std::io::stdout;
+use std::os::unix::ffi::OsStrExt;
+
+pub(crate) fn run_audit_perms(
+    cli: &Cli,
+    canonicalize: bool,
+    unexpected_only: bool,
+) -> eyre::Result<Exit> {
+    let (interner, mut findings) = permissions::audit_permissions(
+        cli.backend.try_into()?,
+        &cli.try_into()?,
+        &crate::unexpected_config(cli, canonicalize)?,
+    )?;
+    if unexpected_only {
+        findings.retain(|finding| finding.ownership != Ownership::Expected);
+    }
+    findings.sort_by(|a, b| a.path.cmp(&b.path));
+    let has_unexpected = findings
+        .iter()
+        .any(|finding| finding.ownership != Ownership::Expected);
+
+    let mut stdout = BufWriter::new(stdout().lock());
+    match cli.format {
+        Format::Human => {
+            for finding in &findings {
+                if let Some(pkg) = finding.package.and_then(|p| p.try_as_str(&interner)) {
+                    write!(stdout, "{pkg}: ")?;
+                }
+                stdout.write_all(finding.path.as_os_str().as_bytes())?;
+                let kinds: Vec<_> = finding.kinds.iter().map(ToString::to_string).collect();
+                write!(stdout, " {} (mode {:o}, ", kinds.join(", "), finding.mode)?;
+                match finding.ownership {
+                    Ownership::Expected => writeln!(stdout, "as expected by package)")?,
+                    Ownership::Unexpected { expected } => {
+                        writeln!(stdout, "package expects {expected:o})")?;
+                    }
+                    Ownership::Unknown => writeln!(stdout, "expected mode unknown)")?,
+                    Ownership::Unowned => writeln!(stdout, "not owned by any package)")?,
+                    _ => writeln!(stdout, "{:?})", finding.ownership)?,
+                }
+            }
+        }
+        #[cfg(feature = "json")]
+        Format::Json => {
+            let findings: Vec<_> = findings
+                .iter()
+                .map(|finding| PermissionReport {
+                    path: &finding.path,
+                    package: finding.package.and_then(|p| p.try_as_str(&interner)),
+                    mode: finding.mode,
+                    kinds: &finding.kinds,
+                    ownership: finding.ownership,
+                })
+                .collect();
+            serde_json::to_writer_pretty(&mut stdout, &findings)?;
+        }
+        format => eyre::bail!("{format} format is not supported for permission audits"),
+    }
+    stdout.flush()?;
+
+    Ok(if has_unexpected {
+        Exit::new(Code::FAILURE)
+    } else {
+        Exit::new(Code::SUCCESS)
+    })
+}
+
+#[cfg(feature = "json")]
+#[derive(Debug, serde::Serialize)]
+struct PermissionReport<'a> {
+    path: &'a std::path::Path,
+    package: Option<&'a str>,
+    mode: paketkoll_types::files::Mode,
+    kinds: &'a [permissions::PermissionKind],
+    ownership: Ownership,
+}
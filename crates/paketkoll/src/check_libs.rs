@@ -0,0 +1,106 @@
+//! Report broken shared library dependencies
+
+use paketkoll::cli::Cli;
+use paketkoll::cli::Format;
+use paketkoll_core::shared_libs;
+use paketkoll_core::shared_libs::LibraryIssueKind;
+use proc_exit::Code;
+use proc_exit::Exit;
+use std::io::BufWriter;
+use std::io::Write;
+use std::io::stdout;
+use std::os::unix::ffi::OsStrExt;
+
+pub(crate) fn run_check_libs(cli: &Cli, owners: bool) -> eyre::Result<Exit> {
+    let (interner, mut issues) =
+        shared_libs::check_libraries(cli.backend.try_into()?, &cli.try_into()?, owners)?;
+    // Group by owning package
+    issues.sort_by(|a, b| {
+        (a.package.map(|p| p.as_str(&interner)), &a.path, &a.library).cmp(&(
+            b.package.map(|p| p.as_str(&interner)),
+            &b.path,
+            &b.library,
+        ))
+    });
+
+    let mut stdout = BufWriter::new(stdout().lock());
+    match cli.format {
+        Format::Human => {
+            for issue in &issues {
+                if let Some(pkg) = issue.package {
+                    write!(stdout, "{}: ", pkg.as_str(&interner))?;
+                }
+                stdout.write_all(issue.path.as_os_str().as_bytes())?;
+                write!(stdout, " needs {}", issue.library)?;
+                match issue.kind {
+                    LibraryIssueKind::Missing { provider: None } => {
+                        writeln!(stdout, ", which is missing")?;
+                    }
+                    LibraryIssueKind::Missing {
+                        provider: Some(provider),
+                    } => writeln!(
+                        stdout,
+                        ", which is missing (should be provided by {})",
+                        provider.as_str(&interner)
+                    )?,
+                    LibraryIssueKind::Unowned { ref resolved } => writeln!(
+                        stdout,
+                        ", found as {} which is not owned by any package",
+                        resolved.display()
+                    )?,
+                    _ => writeln!(stdout, ": {:?}", issue.kind)?,
+                }
+            }
+        }
+        #[cfg(feature = "json")]
+        Format::Json => {
+            let issues: Vec<_> = issues
+                .iter()
+                .filter_map(|issue| {
+                    let kind = match issue.kind {
+                        LibraryIssueKind::Missing { provider } => KindReport::Missing {
+                            provider: provider.map(|p| p.as_str(&interner)),
+                        },
+                        LibraryIssueKind::Unowned { ref resolved } => {
+                            KindReport::Unowned { resolved }
+                        }
+                        _ => return None,
+                    };
+                    Some(LibraryReport {
+                        package: issue.package.map(|p| p.as_str(&interner)),
+                        path: &issue.path,
+                        library: &issue.library,
+                        kind,
+                    })
+                })
+                .collect();
+            serde_json::to_writer_pretty(&mut stdout, &issues)?;
+        }
+        format => eyre::bail!("{format} format is not supported for library checks"),
+    }
+    stdout.flush()?;
+
+    Ok(if issues.is_empty() {
+        Exit::new(Code::SUCCESS)
+    } else {
+        Exit::new(Code::FAILURE)
+    })
+}
+
+#[cfg(feature = "json")]
+#[derive(Debug, serde::Serialize)]
+struct LibraryReport<'a> {
+    package: Option<&'a str>,
+    path: &'a std::path::Path,
+    library: &'a str,
+    #[serde(flatten)]
+    kind: KindReport<'a>,
+}
+
+#[cfg(feature = "json")]
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum KindReport<'a> {
+    Missing { provider: Option<&'a str> },
+    Unowned { resolved: &'a std::path::Path },
+}
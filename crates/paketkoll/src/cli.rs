@@ -1,7 +1,10 @@
 use clap::Parser;
 use clap::Subcommand;
 use compact_str::CompactString;
+use paketkoll_core::resource_limits::IoPriority;
 use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
 
 #[derive(Debug, Parser)]
 #[command(version, about, long_about = None)]
@@ -17,6 +20,12 @@ pub struct Cli {
     /// Which package manager backend to use
     #[arg(short, long, default_value_t = Backend::Auto)]
     pub backend: Backend,
+    /// Additional file backends to check along with the main one (such as
+    /// systemd-tmpfiles), can be given multiple times. Paths listed by several
+    /// backends are checked against the main backend (or the first one given).
+    /// Used by check and check-unexpected.
+    #[arg(long)]
+    pub extra_backend: Vec<Backend>,
     /// Output format to use
     #[arg(short, long, default_value_t = Format::Human)]
     pub format: Format,
@@ -24,6 +33,37 @@ pub struct Cli {
     /// supported. Use ** to match any number of path components.
     #[arg(long)]
     pub ignore: Vec<CompactString>,
+    /// Files with waivers for accepted issues, can be given multiple times.
+    /// Each line is a glob, an issue kind (such as `wrong_mode`, or `*` for
+    /// any) optionally followed by `=<actual value>`, an expiry date
+    /// (YYYY-MM-DD or `never`) and the reason. Expired waivers are reported
+    /// as issues. Used by check, check-unexpected and baseline verify.
+    #[arg(long)]
+    pub waivers: Vec<PathBuf>,
+    /// Print issues as soon as they are found instead of sorted at the end.
+    /// Supported by check and check-unexpected with line based output formats
+    /// (human, pacman-qkk and dpkg-verify).
+    #[arg(long)]
+    pub stream: bool,
+    /// Number of threads to use for checking files (default: number of CPUs)
+    #[arg(long)]
+    pub threads: Option<usize>,
+    /// Nice level to check files with (see nice(1))
+    #[arg(long, allow_negative_numbers = true)]
+    pub nice: Option<i32>,
+    /// I/O priority to check files with: idle, best-effort or
+    /// best-effort:<level> (0-7, see ionice(1))
+    #[arg(long)]
+    pub ionice: Option<IoPriority>,
+    /// Limit reading of file contents for checksums to this many MB/s
+    #[arg(long)]
+    pub read_limit: Option<u64>,
+    /// Don't verify checksums of files larger than this many MB
+    #[arg(long)]
+    pub max_file_size: Option<u64>,
+    /// Skip files on network file systems (such as NFS or CIFS)
+    #[arg(long)]
+    pub skip_network_fs: bool,
     /// Operation to perform
     #[command(subcommand)]
     pub command: Commands,
@@ -35,6 +75,12 @@ pub enum Commands {
     Check {
         /// Packages to check (default: all of them)
         packages: Vec<String>,
+        /// Only verify the contents of files of packages changed since this
+        /// time (according to the package manager logs). Other files only get
+        /// their metadata checked. Either `last` (the previous full run of
+        /// check), `YYYY-MM-DD [HH:MM[:SS]]` or `@<unix timestamp>`.
+        #[arg(long)]
+        since: Option<Since>,
     },
     /// Check package files and search for unexpected files
     CheckUnexpected {
@@ -43,9 +89,180 @@ pub enum Commands {
         /// merge.
         #[arg(long)]
         canonicalize: bool,
+        /// How to handle files known to be generated by package scripts,
+        /// hooks and services (caches, host keys, initramfs images, ...)
+        #[arg(long, default_value_t = Generated::Hide)]
+        generated: Generated,
+        /// Additional rule files for generated files. Each line is a glob
+        /// followed by what generates the files (or `-` for "not generated").
+        /// These take precedence over the built in rules.
+        #[arg(long)]
+        generated_rules: Vec<PathBuf>,
+    },
+    /// Show disk usage of files not owned by any package, per directory
+    Du {
+        /// Should paths be canonicalized before checking? Required on Debian
+        /// due to lack of /usr merge.
+        #[arg(long)]
+        canonicalize: bool,
+        /// How many levels of directories below / to show
+        #[arg(long, default_value_t = 3)]
+        max_depth: usize,
+        /// Only show this many of the largest entries in each directory (0 to
+        /// show all)
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+    },
+    /// Find package executables and libraries with shared library
+    /// dependencies that can't be found
+    CheckLibs {
+        /// Look up which package should provide missing libraries, and report
+        /// libraries that resolve to files not owned by any package
+        #[arg(long)]
+        owners: bool,
+    },
+    /// Record or verify a baseline of files not owned by any package
+    Baseline {
+        #[command(subcommand)]
+        command: BaselineCommand,
     },
     /// Get a list of installed packages
     InstalledPackages,
+    /// Check installed packages against a local database of security
+    /// advisories
+    Audit {
+        /// Format of the advisory database
+        #[arg(long)]
+        db_format: AdvisoryFormat,
+        /// Release to match advisories for: the codename for the Debian
+        /// tracker (e.g. bookworm) or the ecosystem for OSV (e.g. Debian:12).
+        /// The default is based on /etc/os-release.
+        #[arg(long)]
+        release: Option<String>,
+        /// Advisory database: a JSON file (arch, debian) or a directory of
+        /// JSON files (osv)
+        path: PathBuf,
+    },
+    /// Find setuid/setgid, world writable and capability carrying files, and
+    /// whether the package manager expects them to be like that
+    AuditPerms {
+        /// Should paths be canonicalized before checking? Required on Debian
+        /// due to lack of /usr merge.
+        #[arg(long)]
+        canonicalize: bool,
+        /// Only report files that are unowned or don't match the expected mode
+        #[arg(long)]
+        unexpected_only: bool,
+    },
+    /// Show package transactions (installs, upgrades and removals) from the
+    /// package manager logs
+    History {
+        /// Only show transactions involving these packages
+        #[arg(long = "package", short = 'p')]
+        packages: Vec<String>,
+        /// Only show transactions started at or after this time
+        /// (`YYYY-MM-DD [HH:MM[:SS]]` or `@<unix timestamp>`)
+        #[arg(long, value_parser = paketkoll_core::package_log::parse_time)]
+        since: Option<i64>,
+        /// Only show transactions started before this time
+        #[arg(long, value_parser = paketkoll_core::package_log::parse_time)]
+        until: Option<i64>,
+    },
+    /// Keep running and report changed package files and new unowned files
+    /// as they appear (respects --config-files, --ignore and --waivers)
+    Watch {
+        /// How to get notified about changes
+        #[arg(long, default_value_t = WatchMethod::Auto)]
+        method: WatchMethod,
+        /// Seconds to wait for a file (or the package database) to stop
+        /// changing before verifying it
+        #[arg(long, default_value_t = 2)]
+        settle: u64,
+        /// Should paths be canonicalized before checking? Required on Debian
+        /// due to lack of /usr merge.
+        #[arg(long)]
+        canonicalize: bool,
+        /// How to handle new files known to be generated (see
+        /// check-unexpected)
+        #[arg(long, default_value_t = Generated::Hide)]
+        generated: Generated,
+        /// Additional rule files for generated files (see check-unexpected)
+        #[arg(long)]
+        generated_rules: Vec<PathBuf>,
+        /// Directory trees to watch
+        #[arg(default_value = "/")]
+        paths: Vec<PathBuf>,
+    },
+    /// Find paths owned by more than one package, and package files that are
+    /// diverted or also managed by systemd-tmpfiles
+    Conflicts {
+        /// Should paths be canonicalized before checking? This finds conflicts
+        /// between paths that differ only due to /usr merge on Debian.
+        #[arg(long)]
+        canonicalize: bool,
+        /// Also find package files managed by systemd-tmpfiles
+        #[cfg(feature = "systemd_tmpfiles")]
+        #[arg(long)]
+        tmpfiles: bool,
+    },
+    /// Export packages, dependencies and files to an sqlite database
+    #[cfg(feature = "sqlite")]
+    ExportDb {
+        /// Also run a check and include the issues found (respects
+        /// --config-files, --trust-mtime and --ignore)
+        #[arg(long)]
+        issues: bool,
+        /// Replace the file at path even if it is not an `SQLite` database
+        #[arg(long)]
+        force: bool,
+        /// Database file to write (an existing database will be replaced)
+        path: PathBuf,
+    },
+    /// Generate a software bill of materials (SBOM) for installed packages
+    #[cfg(feature = "json")]
+    Sbom {
+        /// SBOM format to generate
+        #[arg(long, default_value_t = SbomFormat::Cyclonedx)]
+        format: SbomFormat,
+        /// Include the files (with checksums) of each package
+        #[arg(long)]
+        files: bool,
+        /// Also run a check and attach the verification status of packages and
+        /// files (respects --config-files, --trust-mtime and --ignore)
+        #[arg(long)]
+        verify: bool,
+    },
+    /// List files of packages, with all metadata known by the package manager
+    Files {
+        /// Packages to list files for (default: all of them)
+        packages: Vec<String>,
+        /// Extract the file data from the package archives instead of the
+        /// package database (slow, but has more details on some backends)
+        #[arg(long)]
+        from_archives: bool,
+    },
+    /// Show differences between files and their original package versions
+    Diff {
+        /// Command to use for diffing regular files (split on whitespace)
+        #[arg(long, default_value = "diff -aur")]
+        diff_command: String,
+        /// Paths to compare
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+    /// Restore files to the state the package manager installed them in
+    Restore {
+        /// How much to ask for confirmation
+        #[arg(long, short = 'p', default_value_t = Paranoia::Ask)]
+        confirmation: Paranoia,
+        /// Restore all files with issues found by `check` (respects
+        /// --config-files, --trust-mtime and --ignore)
+        #[arg(long, conflicts_with = "paths")]
+        all_issues: bool,
+        /// Paths to restore
+        #[arg(required_unless_present = "all_issues")]
+        paths: Vec<String>,
+    },
     /// Find package that owns a given file.
     Owns {
         /// Path to query
@@ -60,10 +277,27 @@ pub enum Commands {
         /// Path to query
         path: String,
     },
-    #[clap(hide = true)]
-    DebugPackageFileData {
-        /// Package to query
-        package: String,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BaselineCommand {
+    /// Record all unexpected files (in mtree format)
+    Create {
+        /// Should paths be canonicalized before checking? (see
+        /// check-unexpected)
+        #[arg(long)]
+        canonicalize: bool,
+        /// File to write the baseline to
+        path: PathBuf,
+    },
+    /// Report files added, removed or changed since the baseline was created
+    Verify {
+        /// Should paths be canonicalized before checking? (see
+        /// check-unexpected)
+        #[arg(long)]
+        canonicalize: bool,
+        /// Baseline file to verify against
+        path: PathBuf,
     },
 }
 
@@ -75,6 +309,16 @@ pub enum Format {
     /// JSON formatted output
     #[cfg(feature = "json")]
     Json,
+    /// mtree-like output (only supported for file listings)
+    Mtree,
+    /// Same format as `pacman -Qkk` (only supported for check results)
+    PacmanQkk,
+    /// Same format as `dpkg --verify` (only supported for check results)
+    DpkgVerify,
+    /// Prometheus node exporter text file (only supported for check results)
+    Prometheus,
+    /// `JUnit` XML report (only supported for check results)
+    Junit,
 }
 
 impl Display for Format {
@@ -83,6 +327,52 @@ impl Display for Format {
             Self::Human => write!(f, "human"),
             #[cfg(feature = "json")]
             Self::Json => write!(f, "json"),
+            Self::Mtree => write!(f, "mtree"),
+            Self::PacmanQkk => write!(f, "pacman-qkk"),
+            Self::DpkgVerify => write!(f, "dpkg-verify"),
+            Self::Prometheus => write!(f, "prometheus"),
+            Self::Junit => write!(f, "junit"),
+        }
+    }
+}
+
+/// SBOM format to generate
+#[cfg(feature = "json")]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, clap::ValueEnum)]
+pub enum SbomFormat {
+    /// `CycloneDX` 1.6 (JSON)
+    Cyclonedx,
+    /// SPDX 2.3 (JSON)
+    Spdx,
+}
+
+#[cfg(feature = "json")]
+impl Display for SbomFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cyclonedx => write!(f, "cyclonedx"),
+            Self::Spdx => write!(f, "spdx"),
+        }
+    }
+}
+
+/// Format of advisory database
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, clap::ValueEnum)]
+pub enum AdvisoryFormat {
+    /// Arch Linux security tracker (all.json)
+    Arch,
+    /// Debian security tracker (data/json)
+    Debian,
+    /// Directory of OSV records
+    Osv,
+}
+
+impl Display for AdvisoryFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Arch => write!(f, "arch"),
+            Self::Debian => write!(f, "debian"),
+            Self::Osv => write!(f, "osv"),
         }
     }
 }
@@ -120,6 +410,70 @@ impl Display for Backend {
     }
 }
 
+/// How much to ask for confirmation
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, clap::ValueEnum)]
+pub enum Paranoia {
+    /// Don't ask, just do it
+    Yolo,
+    /// Ask before making changes
+    Ask,
+    /// Dry run, don't do anything
+    DryRun,
+}
+
+impl Display for Paranoia {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Yolo => write!(f, "yolo"),
+            Self::Ask => write!(f, "ask"),
+            Self::DryRun => write!(f, "dry-run"),
+        }
+    }
+}
+
+/// How to handle known generated files
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, clap::ValueEnum)]
+pub enum Generated {
+    /// Don't report generated files
+    Hide,
+    /// Report generated files, along with what generated them
+    Report,
+    /// Don't use any rules, report generated files as unexpected
+    Off,
+}
+
+impl Display for Generated {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Hide => write!(f, "hide"),
+            Self::Report => write!(f, "report"),
+            Self::Off => write!(f, "off"),
+        }
+    }
+}
+
+/// How to get notified about file system changes
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, clap::ValueEnum)]
+pub enum WatchMethod {
+    /// inotify, unless it is not available
+    Auto,
+    /// Watch the whole mounts with fanotify (requires root, only sees writes,
+    /// not deletions, permission changes or renames)
+    Fanotify,
+    /// Watch each package directory with inotify
+    Inotify,
+}
+
+impl Display for WatchMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Auto => write!(f, "auto"),
+            Self::Fanotify => write!(f, "fanotify"),
+            Self::Inotify => write!(f, "inotify"),
+        }
+    }
+}
+
 /// Describe how to check config files
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, clap::ValueEnum)]
 pub enum ConfigFiles {
@@ -140,3 +494,24 @@ impl Display for ConfigFiles {
         }
     }
 }
+
+/// Point in time to check changes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Since {
+    /// The previous recorded run of the check command
+    Last,
+    /// Seconds since the Unix epoch
+    Time(i64),
+}
+
+impl FromStr for Since {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "last" {
+            Ok(Self::Last)
+        } else {
+            Ok(Self::Time(paketkoll_core::package_log::parse_time(s)?))
+        }
+    }
+}
@@ -0,0 +1,486 @@
+//! System-wide configuration (`/etc/paketkoll/config.toml` and drop-ins)
+//!
+//! Configuration files provide defaults for command line options, using the
+//! long option names as keys. Options for a subcommand go in a table named
+//! after it (such as `[check-unexpected]` or `[baseline.create]`). Ignores and
+//! waivers are added to those given on the command line instead, and ignores
+//! for a specific backend go in `[backends.<backend>]`.
+//!
+//! Options given on the command line take precedence. Boolean flags that the
+//! configuration turns on can be turned off with (hidden) `--no-<flag>`
+//! options.
+
+use clap::Arg;
+use clap::ArgAction;
+use clap::ArgMatches;
+use clap::Command;
+use clap::CommandFactory;
+use clap::FromArgMatches;
+use clap::ValueEnum;
+use clap::builder::ArgPredicate;
+use clap::error::ErrorKind;
+use compact_str::CompactString;
+use eyre::WrapErr;
+use paketkoll::cli::Backend;
+use paketkoll::cli::Cli;
+use paketkoll_core::backend::ConcreteBackend;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Directory with the main configuration file and the drop-in directory
+const CONFIG_DIR: &str = "/etc/paketkoll";
+
+/// Subcommands and options that are not built into this binary
+///
+/// Settings for these are ignored with a warning (rather than being an error),
+/// so the same configuration can be shared between builds.
+const COMPILED_OUT: &[&str] = &[
+    #[cfg(not(feature = "sqlite"))]
+    "export-db",
+    #[cfg(not(feature = "json"))]
+    "sbom",
+    #[cfg(not(feature = "systemd_tmpfiles"))]
+    "tmpfiles",
+];
+
+/// Settings that are added to the command line rather than being defaults
+#[derive(Debug, Default)]
+struct Additions {
+    ignore: Vec<CompactString>,
+    waivers: Vec<PathBuf>,
+    backend_ignore: Vec<(Backend, Vec<CompactString>)>,
+}
+
+/// Parse the command line, with defaults from the configuration files
+pub(crate) fn parse_cli() -> eyre::Result<Cli> {
+    let (command, additions) = match load(Path::new(CONFIG_DIR)) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            // Still allow showing help and the version
+            if let Err(clap_err) = Cli::command().try_get_matches()
+                && matches!(
+                    clap_err.kind(),
+                    ErrorKind::DisplayHelp
+                        | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
+                        | ErrorKind::DisplayVersion
+                )
+            {
+                clap_err.exit();
+            }
+            return Err(err);
+        }
+    };
+    let matches = command.get_matches();
+    Ok(finish(&matches, additions).unwrap_or_else(|err| err.exit()))
+}
+
+/// Build the command with defaults from the configuration files in `dir`
+fn load(dir: &Path) -> eyre::Result<(Command, Additions)> {
+    let mut command = add_negations(Cli::command());
+    let mut additions = Additions::default();
+    for path in config_files(dir)? {
+        let contents =
+            std::fs::read_to_string(&path).wrap_err_with(|| format!("Failed to read {path:?}"))?;
+        let table: toml::Table = contents
+            .parse()
+            .wrap_err_with(|| format!("Failed to parse {path:?}"))?;
+        command = apply_file(command, table, &mut additions)
+            .wrap_err_with(|| format!("Invalid configuration in {path:?}"))?;
+    }
+    Ok((command, additions))
+}
+
+/// Create the parsed command line, with the additions from the configuration
+fn finish(matches: &ArgMatches, additions: Additions) -> Result<Cli, clap::Error> {
+    let mut cli = Cli::from_arg_matches(matches)?;
+    cli.ignore.extend(additions.ignore);
+    cli.waivers.extend(additions.waivers);
+    let active: Vec<ConcreteBackend> = std::iter::once(cli.backend)
+        .chain(cli.extra_backend.iter().copied())
+        .filter_map(|backend| backend.try_into().ok())
+        .collect();
+    for (backend, ignore) in additions.backend_ignore {
+        if ConcreteBackend::try_from(backend).is_ok_and(|backend| active.contains(&backend)) {
+            cli.ignore.extend(ignore);
+        }
+    }
+    Ok(cli)
+}
+
+/// Add hidden `--no-<flag>` options for all boolean flags of a command and
+/// its subcommands, that turn the flag off even if the configuration sets it.
+fn add_negations(mut command: Command) -> Command {
+    let flags: Vec<_> = command
+        .get_arguments()
+        .filter(|arg| matches!(arg.get_action(), ArgAction::SetTrue))
+        .filter_map(|arg| Some((arg.get_id().clone(), arg.get_long()?.to_string())))
+        .collect();
+    for (id, long) in flags {
+        let negation = format!("no-{long}");
+        command = command
+            .mut_arg(id, |arg| {
+                // Flags always have a value, so check that it is set
+                arg.default_value_if(
+                    negation.clone(),
+                    ArgPredicate::Equals("true".into()),
+                    "false",
+                )
+            })
+            .arg(
+                Arg::new(negation.clone())
+                    .long(negation)
+                    .action(ArgAction::SetTrue)
+                    .hide(true),
+            );
+    }
+    let subcommands: Vec<_> = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect();
+    for name in subcommands {
+        command = command.mut_subcommand(name, add_negations);
+    }
+    command
+}
+
+/// The main configuration file followed by the drop-ins (in name order)
+fn config_files(dir: &Path) -> eyre::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let main = dir.join("config.toml");
+    if main.exists() {
+        files.push(main);
+    }
+    let drop_in_dir = dir.join("paketkoll.d");
+    let entries = match std::fs::read_dir(&drop_in_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(files),
+        Err(err) => return Err(err).wrap_err_with(|| format!("Failed to read {drop_in_dir:?}")),
+    };
+    let mut drop_ins = vec![];
+    for entry in entries {
+        let path = entry
+            .wrap_err_with(|| format!("Failed to read {drop_in_dir:?}"))?
+            .path();
+        if path.extension().is_some_and(|ext| ext == "toml") {
+            drop_ins.push(path);
+        }
+    }
+    drop_ins.sort();
+    files.extend(drop_ins);
+    Ok(files)
+}
+
+/// Apply the settings from one configuration file
+fn apply_file(
+    command: Command,
+    mut table: toml::Table,
+    additions: &mut Additions,
+) -> eyre::Result<Command> {
+    if let Some(value) = table.remove("ignore") {
+        additions
+            .ignore
+            .extend(values("ignore", value)?.into_iter().map(Into::into));
+    }
+    if let Some(value) = table.remove("waivers") {
+        additions
+            .waivers
+            .extend(values("waivers", value)?.into_iter().map(Into::into));
+    }
+    if let Some(value) = table.remove("backends") {
+        let toml::Value::Table(backends) = value else {
+            eyre::bail!("`backends` must be a table");
+        };
+        for (name, settings) in backends {
+            let toml::Value::Table(mut settings) = settings else {
+                eyre::bail!("`backends.{name}` must be a table");
+            };
+            let ignore = match settings.remove("ignore") {
+                Some(value) => values("ignore", value)?,
+                None => vec![],
+            };
+            if let Some(key) = settings.keys().next() {
+                eyre::bail!("Unknown setting `{key}` for backend {name}");
+            }
+            // Drop-ins may be shipped for backends not built into this binary
+            let Ok(backend) = Backend::from_str(&name, false) else {
+                tracing::warn!("Ignoring settings for unknown backend {name}");
+                continue;
+            };
+            additions
+                .backend_ignore
+                .push((backend, ignore.into_iter().map(Into::into).collect()));
+        }
+    }
+    apply_defaults(command, table)
+}
+
+/// Set defaults for the options of a (sub)command, with nested tables for
+/// subcommands
+fn apply_defaults(mut command: Command, table: toml::Table) -> eyre::Result<Command> {
+    for (key, value) in table {
+        if COMPILED_OUT.contains(&key.as_str()) {
+            tracing::warn!("Ignoring settings for `{key}` (not built into this binary)");
+            continue;
+        }
+        command = match value {
+            toml::Value::Table(table) => {
+                let Some(subcommand) = command.find_subcommand(&key) else {
+                    eyre::bail!("Unknown command `{key}`");
+                };
+                let name = subcommand.get_name().to_string();
+                let subcommand = apply_defaults(subcommand.clone(), table)
+                    .wrap_err_with(|| format!("Invalid settings for {name}"))?;
+                command.mut_subcommand(name, |_| subcommand)
+            }
+            value => set_default(command, &key, value)?,
+        };
+    }
+    Ok(command)
+}
+
+/// Set the default value of the option with the long name `key`
+fn set_default(command: Command, key: &str, value: toml::Value) -> eyre::Result<Command> {
+    let Some(arg) = command.get_arguments().find(|arg| {
+        arg.get_long() == Some(key)
+            && !matches!(
+                arg.get_action(),
+                ArgAction::Help | ArgAction::HelpShort | ArgAction::HelpLong | ArgAction::Version
+            )
+    }) else {
+        eyre::bail!("Unknown option `{key}` for {}", command.get_name());
+    };
+    let values = match value {
+        toml::Value::Array(_) if !matches!(arg.get_action(), ArgAction::Append) => {
+            eyre::bail!("`{key}` only takes a single value");
+        }
+        value => values(key, value)?,
+    };
+    let id = arg.get_id().clone();
+    let command = command.mut_arg(id, |arg| arg.default_values(values));
+    // Let clap validate the value. Without any arguments parsing fails on
+    // missing arguments, but only after the defaults have been parsed.
+    match command.clone().try_get_matches_from([command.get_name()]) {
+        Ok(_) => Ok(command),
+        Err(err)
+            if matches!(
+                err.kind(),
+                ErrorKind::MissingRequiredArgument
+                    | ErrorKind::MissingSubcommand
+                    | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
+            ) =>
+        {
+            Ok(command)
+        }
+        Err(err) => {
+            // Leave out the prefix and the hint about --help
+            let message = err.render().to_string();
+            let message: Vec<&str> = message
+                .trim_start_matches("error: ")
+                .lines()
+                .take_while(|line| !line.starts_with("For more information"))
+                .collect();
+            eyre::bail!(
+                "Invalid value for `{key}`: {}",
+                message.join("\n").trim_end()
+            );
+        }
+    }
+}
+
+/// Convert a value or array of values to strings (as they would be given on
+/// the command line)
+fn values(key: &str, value: toml::Value) -> eyre::Result<Vec<String>> {
+    match value {
+        toml::Value::Array(values) => values.into_iter().map(|value| scalar(key, value)).collect(),
+        value => Ok(vec![scalar(key, value)?]),
+    }
+}
+
+fn scalar(key: &str, value: toml::Value) -> eyre::Result<String> {
+    match value {
+        toml::Value::String(value) => Ok(value),
+        toml::Value::Integer(value) => Ok(value.to_string()),
+        toml::Value::Float(value) => Ok(value.to_string()),
+        toml::Value::Boolean(value) => Ok(value.to_string()),
+        toml::Value::Datetime(value) => Ok(value.to_string()),
+        toml::Value::Array(_) | toml::Value::Table(_) => {
+            eyre::bail!("Unexpected nested array or table in `{key}`")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paketkoll::cli::Commands;
+    use paketkoll::cli::Generated;
+    use pretty_assertions::assert_eq;
+
+    /// Create a configuration directory with the given files
+    fn config_dir(files: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("paketkoll.d")).unwrap();
+        for (name, contents) in files {
+            std::fs::write(dir.path().join(name), contents).unwrap();
+        }
+        dir
+    }
+
+    fn parse(dir: &tempfile::TempDir, args: &[&str]) -> eyre::Result<Cli> {
+        let (command, additions) = load(dir.path())?;
+        let matches = command
+            .try_get_matches_from(std::iter::once("paketkoll").chain(args.iter().copied()))?;
+        Ok(finish(&matches, additions)?)
+    }
+
+    /// Get the options of check-unexpected
+    fn check_unexpected(cli: &Cli) -> (bool, Generated) {
+        match cli.command {
+            Commands::CheckUnexpected {
+                canonicalize,
+                generated,
+                ..
+            } => (canonicalize, generated),
+            _ => panic!("Unexpected command {:?}", cli.command),
+        }
+    }
+
+    const MAIN: &str = r#"
+        trust-mtime = true
+        ignore = ["/main"]
+
+        [check-unexpected]
+        canonicalize = true
+        generated = "report"
+    "#;
+
+    #[test]
+    fn test_config_files_order() {
+        let dir = config_dir(&[
+            ("config.toml", MAIN),
+            ("paketkoll.d/20-second.toml", ""),
+            ("paketkoll.d/10-first.toml", ""),
+            ("paketkoll.d/README", ""),
+        ]);
+        let names: Vec<_> = config_files(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|path| {
+                path.strip_prefix(dir.path())
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "config.toml",
+                "paketkoll.d/10-first.toml",
+                "paketkoll.d/20-second.toml"
+            ]
+        );
+        // Neither the main file nor the drop-in directory are required
+        assert_eq!(
+            config_files(&dir.path().join("missing")).unwrap(),
+            Vec::<PathBuf>::new()
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let dir = config_dir(&[
+            ("config.toml", MAIN),
+            (
+                "paketkoll.d/10-first.toml",
+                "ignore = \"/first\"\n[check-unexpected]\ngenerated = \"off\"\n",
+            ),
+            (
+                "paketkoll.d/20-second.toml",
+                "[check-unexpected]\ngenerated = \"hide\"\n",
+            ),
+        ]);
+        let cli = parse(&dir, &["check-unexpected"]).unwrap();
+        assert!(cli.trust_mtime);
+        assert_eq!(cli.ignore, vec!["/main", "/first"]);
+        // Later files override earlier ones
+        assert_eq!(check_unexpected(&cli), (true, Generated::Hide));
+    }
+
+    #[test]
+    fn test_command_line_precedence() {
+        let dir = config_dir(&[("config.toml", MAIN)]);
+        let cli = parse(
+            &dir,
+            &[
+                "--no-trust-mtime",
+                "--ignore",
+                "/cli",
+                "check-unexpected",
+                "--no-canonicalize",
+                "--generated",
+                "off",
+            ],
+        )
+        .unwrap();
+        assert!(!cli.trust_mtime);
+        assert_eq!(cli.ignore, vec!["/cli", "/main"]);
+        assert_eq!(check_unexpected(&cli), (false, Generated::Off));
+
+        // Without a configuration the negations are accepted too
+        let dir = config_dir(&[]);
+        let cli = parse(&dir, &["check-unexpected", "--no-canonicalize"]).unwrap();
+        assert_eq!(check_unexpected(&cli), (false, Generated::Hide));
+        let cli = parse(&dir, &["check-unexpected", "--canonicalize"]).unwrap();
+        assert_eq!(check_unexpected(&cli), (true, Generated::Hide));
+    }
+
+    #[test]
+    #[cfg(all(feature = "arch_linux", feature = "debian"))]
+    fn test_backend_ignore() {
+        let dir = config_dir(&[(
+            "config.toml",
+            "[backends.arch-linux]\nignore = [\"/arch\"]\n[backends.debian]\nignore = \
+             [\"/debian\"]\n[backends.not-built-in]\nignore = [\"/other\"]\n",
+        )]);
+        let cli = parse(&dir, &["--backend", "debian", "check"]).unwrap();
+        assert_eq!(cli.ignore, vec!["/debian"]);
+        let cli = parse(
+            &dir,
+            &[
+                "--backend",
+                "debian",
+                "--extra-backend",
+                "arch-linux",
+                "check",
+            ],
+        )
+        .unwrap();
+        assert_eq!(cli.ignore, vec!["/arch", "/debian"]);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let error = |contents: &str| {
+            let dir = config_dir(&[("config.toml", contents)]);
+            format!("{:#}", parse(&dir, &["check"]).unwrap_err())
+        };
+        assert!(error("trust-mtime = ").contains("Failed to parse"));
+        assert!(error("bogus = 1").contains("Unknown option `bogus`"));
+        assert!(error("[bogus]\nx = 1").contains("Unknown command `bogus`"));
+        assert!(error("[check-unexpected]\ngenerated = \"maybe\"").contains("Invalid value"));
+        assert!(error("[backends.debian]\nbogus = 1").contains("Unknown setting `bogus`"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "systemd_tmpfiles"))]
+    fn test_compiled_out() {
+        let mut contents = String::from("[conflicts]\ntmpfiles = true\n");
+        if cfg!(not(feature = "sqlite")) {
+            contents.push_str("[export-db]\nissues = true\n");
+        }
+        let dir = config_dir(&[("config.toml", &contents)]);
+        let cli = parse(&dir, &["conflicts"]).unwrap();
+        assert!(matches!(cli.command, Commands::Conflicts { .. }));
+    }
+}
@@ -0,0 +1,127 @@
+//! Report paths claimed by more than one package
+
+use paketkoll::cli::Cli;
+#[cfg(feature = "systemd_tmpfiles")]
+use paketkoll::cli::Commands;
+use paketkoll::cli::Format;
+#[cfg(feature = "systemd_tmpfiles")]
+use paketkoll_core::backend::ConcreteBackend;
+use paketkoll_core::conflicts;
+use paketkoll_core::conflicts::ConflictKind;
+use proc_exit::Code;
+use proc_exit::Exit;
+use std::io::BufWriter;
+use std::io::Write;
+use std::io::stdout;
+use std::os::unix::ffi::OsStrExt;
+
+pub(crate) fn run_conflicts(cli: &Cli, canonicalize: bool) -> eyre::Result<Exit> {
+    let shadowing = match cli.command {
+        #[cfg(feature = "systemd_tmpfiles")]
+        Commands::Conflicts { tmpfiles: true, .. } => vec![ConcreteBackend::SystemdTmpfiles],
+        _ => vec![],
+    };
+    let (interner, mut conflicts) = conflicts::file_conflicts(
+        cli.backend.try_into()?,
+        &cli.try_into()?,
+        &crate::unexpected_config(cli, canonicalize)?,
+        &shadowing,
+    )?;
+    conflicts.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut stdout = BufWriter::new(stdout().lock());
+    match cli.format {
+        Format::Human => {
+            for conflict in &conflicts {
+                let packages: Vec<_> = conflict
+                    .packages
+                    .iter()
+                    .map(|p| p.as_str(&interner))
+                    .collect();
+                write!(stdout, "{}: ", packages.join(", "))?;
+                stdout.write_all(conflict.path.as_os_str().as_bytes())?;
+                match conflict.kind {
+                    ConflictKind::MultiplePackages { ref differences } => {
+                        write!(stdout, " owned by multiple packages")?;
+                        if !differences.is_empty() {
+                            write!(stdout, " (differing {})", differences.join(", "))?;
+                        }
+                        writeln!(stdout)?;
+                    }
+                    ConflictKind::Diverted { by, ref to } => {
+                        writeln!(
+                            stdout,
+                            " diverted to {} by {}",
+                            to.display(),
+                            by.as_str(&interner)
+                        )?;
+                    }
+                    ConflictKind::Shadowed { by } => writeln!(stdout, " also managed by {by}")?,
+                    _ => writeln!(stdout, " {:?}", conflict.kind)?,
+                }
+            }
+        }
+        #[cfg(feature = "json")]
+        Format::Json => {
+            let conflicts: Vec<_> = conflicts
+                .iter()
+                .filter_map(|conflict| {
+                    let kind = match conflict.kind {
+                        ConflictKind::MultiplePackages { ref differences } => {
+                            KindReport::MultiplePackages { differences }
+                        }
+                        ConflictKind::Diverted { by, ref to } => KindReport::Diverted {
+                            by: by.as_str(&interner),
+                            to,
+                        },
+                        ConflictKind::Shadowed { by } => KindReport::Shadowed { by },
+                        _ => return None,
+                    };
+                    Some(ConflictReport {
+                        path: &conflict.path,
+                        packages: conflict
+                            .packages
+                            .iter()
+                            .map(|p| p.as_str(&interner))
+                            .collect(),
+                        kind,
+                    })
+                })
+                .collect();
+            serde_json::to_writer_pretty(&mut stdout, &conflicts)?;
+        }
+        format => eyre::bail!("{format} format is not supported for conflicts"),
+    }
+    stdout.flush()?;
+
+    Ok(if conflicts.is_empty() {
+        Exit::new(Code::SUCCESS)
+    } else {
+        Exit::new(Code::FAILURE)
+    })
+}
+
+#[cfg(feature = "json")]
+#[derive(Debug, serde::Serialize)]
+struct ConflictReport<'a> {
+    path: &'a std::path::Path,
+    packages: Vec<&'a str>,
+    #[serde(flatten)]
+    kind: KindReport<'a>,
+}
+
+#[cfg(feature = "json")]
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum KindReport<'a> {
+    MultiplePackages {
+        differences: &'a [&'static str],
+    },
+    Diverted {
+        by: &'a str,
+        to: &'a std::path::Path,
+    },
+    Shadowed {
+        by: &'static str,
+    },
+}
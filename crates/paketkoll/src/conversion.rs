@@ -2,7 +2,10 @@ use crate::cli::Backend;
 use crate::cli::Cli;
 use crate::cli::Commands;
 use crate::cli::ConfigFiles;
+use crate::cli::WatchMethod;
 use ahash::AHashSet;
+use paketkoll_core::resource_limits::MEGABYTE;
+use paketkoll_core::resource_limits::ReadLimiter;
 
 impl TryFrom<Backend> for paketkoll_core::backend::ConcreteBackend {
     type Error = eyre::Error;
@@ -50,6 +53,16 @@ impl From<ConfigFiles> for paketkoll_core::config::ConfigFiles {
     }
 }
 
+impl From<WatchMethod> for paketkoll_core::config::WatchMethod {
+    fn from(value: WatchMethod) -> Self {
+        match value {
+            WatchMethod::Auto => Self::Auto,
+            WatchMethod::Fanotify => Self::Fanotify,
+            WatchMethod::Inotify => Self::Inotify,
+        }
+    }
+}
+
 impl TryFrom<&Cli> for paketkoll_core::backend::BackendConfiguration {
     type Error = eyre::Error;
 
@@ -57,14 +70,30 @@ impl TryFrom<&Cli> for paketkoll_core::backend::BackendConfiguration {
         let mut builder = Self::builder();
 
         match value.command {
-            Commands::Check { ref packages } => {
+            Commands::Check { ref packages, .. } => {
+                builder.package_filter(convert_filter(packages.clone()));
+            }
+            Commands::CheckUnexpected { .. } => {}
+            Commands::Du { .. } => {}
+            Commands::CheckLibs { .. } => {}
+            Commands::Baseline { .. } => {}
+            Commands::Files { ref packages, .. } => {
                 builder.package_filter(convert_filter(packages.clone()));
             }
-            Commands::CheckUnexpected { canonicalize: _ } => {}
+            Commands::Diff { .. } => {}
             Commands::InstalledPackages => {}
+            Commands::Audit { .. } => {}
+            Commands::AuditPerms { .. } => {}
+            Commands::History { .. } => {}
+            Commands::Watch { .. } => {}
+            Commands::Conflicts { .. } => {}
+            #[cfg(feature = "sqlite")]
+            Commands::ExportDb { .. } => {}
             Commands::OriginalFile { .. } => {}
+            #[cfg(feature = "json")]
+            Commands::Sbom { .. } => {}
             Commands::Owns { .. } => {}
-            Commands::DebugPackageFileData { .. } => {}
+            Commands::Restore { .. } => {}
         }
         Ok(builder.build()?)
     }
@@ -78,6 +107,19 @@ impl TryFrom<&Cli> for paketkoll_core::config::CommonFileCheckConfiguration {
 
         builder.trust_mtime(value.trust_mtime);
         builder.config_files(value.config_files.into());
+        builder.extra_file_backends(
+            value
+                .extra_backend
+                .iter()
+                .map(|&backend| backend.try_into())
+                .collect::<eyre::Result<Vec<_>>>()?,
+        );
+        builder.threads(value.threads);
+        builder.nice(value.nice);
+        builder.io_priority(value.ionice);
+        builder.read_limit(value.read_limit.map(|mb| ReadLimiter::new(mb * MEGABYTE)));
+        builder.max_file_size(value.max_file_size.map(|mb| mb * MEGABYTE));
+        builder.skip_network_filesystems(value.skip_network_fs);
 
         Ok(builder.build()?)
     }
@@ -0,0 +1,145 @@
+//! Show differences between files and what the package manager installed
+
+use crate::original::OriginalFiles;
+use paketkoll::cli::Cli;
+use paketkoll_core::config::CommonFileCheckConfiguration;
+use paketkoll_core::config::ConfigFiles;
+use paketkoll_core::file_ops;
+use paketkoll_types::issue::IssueKind;
+use proc_exit::Code;
+use proc_exit::Exit;
+use std::io::Write;
+use std::io::stdout;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+pub(crate) fn run_diff(cli: &Cli, paths: &[String], diff_command: &str) -> eyre::Result<Exit> {
+    let diff_command: Vec<&str> = diff_command.split_whitespace().collect();
+    if diff_command.is_empty() {
+        eyre::bail!("Diff command must not be empty");
+    }
+    let paths = paths
+        .iter()
+        .map(std::path::absolute)
+        .collect::<Result<Vec<PathBuf>, _>>()?;
+
+    let originals = OriginalFiles::new(cli)?;
+    let entries = originals.entries(&paths)?;
+    let contents = originals.contents(
+        entries
+            .iter()
+            .filter(|e| e.properties.is_regular_file() == Some(true)),
+    )?;
+
+    // Content differences are shown by the diff command, so only report the
+    // remaining metadata differences here.
+    let mut check_config = CommonFileCheckConfiguration::builder();
+    check_config.config_files(ConfigFiles::Include);
+    let mut issues = file_ops::check_file_entries(&entries, &check_config.build()?)?;
+    issues.sort_by(|(_, a), (_, b)| a.path().cmp(b.path()));
+
+    let mut has_differences = false;
+    for (pkg, issue) in &issues {
+        let pkg = pkg.and_then(|e| e.try_as_str(originals.interner()));
+        for kind in issue.kinds() {
+            if matches!(
+                kind,
+                IssueKind::ChecksumIncorrect { .. } | IssueKind::SizeIncorrect { .. }
+            ) && contents.contains_key(issue.path())
+            {
+                continue;
+            }
+            has_differences = true;
+            let mut stdout = stdout().lock();
+            if let Some(pkg) = pkg {
+                write!(stdout, "{pkg}: ")?;
+            }
+            stdout.write_all(issue.path().as_os_str().as_bytes())?;
+            writeln!(stdout, " {kind}")?;
+        }
+    }
+
+    for path in &paths {
+        let Some(original) = contents.get(path) else {
+            continue;
+        };
+        has_differences |= diff_contents(&diff_command, path, original)?;
+    }
+
+    Ok(if has_differences {
+        Exit::new(Code::FAILURE)
+    } else {
+        Exit::new(Code::SUCCESS)
+    })
+}
+
+/// Run the diff command between the original contents and the file on disk
+///
+/// A missing file is diffed against `/dev/null` (so the diff command doesn't
+/// need to support `-N`). Returns true if there were differences.
+pub(crate) fn diff_contents(
+    diff_command: &[&str],
+    path: &Path,
+    original: &[u8],
+) -> eyre::Result<bool> {
+    let current = match path.try_exists() {
+        Ok(false) => Path::new("/dev/null"),
+        _ => path,
+    };
+    stdout().flush()?;
+    let output = duct::cmd(
+        diff_command[0],
+        diff_command[1..]
+            .iter()
+            .map(PathBuf::from)
+            .chain([PathBuf::from("/dev/stdin"), current.to_path_buf()]),
+    )
+    .stdin_bytes(original)
+    .unchecked()
+    .run()?;
+    match output.status.code() {
+        Some(0) => Ok(false),
+        Some(1) => Ok(true),
+        Some(value) => eyre::bail!("Diff command indicates trouble (exit code {value})"),
+        None => eyre::bail!("Diff command terminated by signal"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // cmp is used instead of diff to keep the test output clean, it has the
+    // same exit codes.
+    const CMP: &[&str] = &["cmp", "-s"];
+
+    #[test]
+    fn test_diff_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("foo.conf");
+        std::fs::write(&path, "a = 1\n").unwrap();
+
+        assert!(!diff_contents(CMP, &path, b"a = 1\n").unwrap());
+        assert!(diff_contents(CMP, &path, b"a = 2\n").unwrap());
+    }
+
+    #[test]
+    fn test_diff_contents_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing.conf");
+
+        // cmp fails on missing files (as does diff without -N), so this only
+        // works when the missing file is replaced by /dev/null
+        assert!(diff_contents(CMP, &path, b"a = 1\n").unwrap());
+        assert!(!diff_contents(CMP, &path, b"").unwrap());
+    }
+
+    #[test]
+    fn test_diff_contents_trouble() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let err = diff_contents(CMP, dir.path(), b"a = 1\n").unwrap_err();
+        assert!(err.to_string().contains("exit code 2"), "{err}");
+    }
+}
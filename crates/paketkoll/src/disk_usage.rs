@@ -0,0 +1,72 @@
+//! Disk usage report of files not owned by any package
+
+use paketkoll::cli::Cli;
+use paketkoll::cli::Format;
+use paketkoll_core::disk_usage;
+use paketkoll_core::disk_usage::DiskUsage;
+use proc_exit::Code;
+use proc_exit::Exit;
+use std::io::BufWriter;
+use std::io::Write;
+use std::io::stdout;
+use std::os::unix::ffi::OsStrExt;
+
+pub(crate) fn run_du(
+    cli: &Cli,
+    canonicalize: bool,
+    max_depth: usize,
+    top: usize,
+) -> eyre::Result<Exit> {
+    let files = disk_usage::unexpected_files(
+        cli.backend.try_into()?,
+        &cli.try_into()?,
+        &crate::unexpected_config(cli, canonicalize)?,
+    )?;
+    let tree = DiskUsage::aggregate(files, max_depth, (top > 0).then_some(top));
+
+    let mut stdout = BufWriter::new(stdout().lock());
+    match cli.format {
+        Format::Human => print_tree(&mut stdout, &tree, 0)?,
+        #[cfg(feature = "json")]
+        Format::Json => serde_json::to_writer_pretty(&mut stdout, &tree)?,
+        format => eyre::bail!("{format} format is not supported for disk usage"),
+    }
+    stdout.flush()?;
+
+    Ok(Exit::new(Code::SUCCESS))
+}
+
+fn print_tree(output: &mut impl Write, node: &DiskUsage, depth: usize) -> eyre::Result<()> {
+    write!(
+        output,
+        "{:>10}  {:>8}  {:indent$}",
+        format_size(node.size),
+        node.entries,
+        "",
+        indent = depth * 2
+    )?;
+    output.write_all(node.path.as_os_str().as_bytes())?;
+    writeln!(output)?;
+    for child in &node.children {
+        print_tree(output, child, depth + 1)?;
+    }
+    Ok(())
+}
+
+/// Format a size with binary units
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+    let mut unit = 0;
+    let mut scaled = size;
+    while scaled >= 1024 && unit < UNITS.len() - 1 {
+        scaled /= 1024;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{size} B")
+    } else {
+        #[allow(clippy::cast_precision_loss)]
+        let value = size as f64 / (1_u64 << (10 * unit)) as f64;
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
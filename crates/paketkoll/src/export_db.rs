@@ -0,0 +1,457 @@
+//! Export package and file data to an `SQLite` database
+
+use ahash::AHashMap;
+use eyre::WrapErr;
+use paketkoll::cli::Cli;
+use paketkoll_core::backend::ConcreteBackend;
+use paketkoll_core::file_ops;
+use paketkoll_types::files::FileEntry;
+use paketkoll_types::files::FileFlags;
+use paketkoll_types::files::Gid;
+use paketkoll_types::files::Mode;
+use paketkoll_types::files::Properties;
+use paketkoll_types::files::Uid;
+use paketkoll_types::intern::Interner;
+use paketkoll_types::intern::PackageRef;
+use paketkoll_types::issue::PackageIssue;
+use paketkoll_types::package::Dependency;
+use paketkoll_types::package::InstallReason;
+use paketkoll_types::package::PackageInstallStatus;
+use paketkoll_types::package::PackageInterned;
+use proc_exit::Code;
+use proc_exit::Exit;
+use rusqlite::Transaction;
+use rusqlite::params;
+use std::io::Read;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+/// Database schema, dependencies with alternatives ("a | b") share the same
+/// `alternative_group`. Paths are stored as BLOBs, as they need not be valid
+/// UTF-8.
+const SCHEMA: &str = r#"
+CREATE TABLE packages (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    architecture TEXT,
+    version TEXT NOT NULL,
+    description TEXT,
+    install_reason TEXT,
+    status TEXT NOT NULL
+);
+CREATE TABLE package_aliases (
+    package_id INTEGER NOT NULL REFERENCES packages(id),
+    alias TEXT NOT NULL
+);
+CREATE TABLE provides (
+    package_id INTEGER NOT NULL REFERENCES packages(id),
+    name TEXT NOT NULL
+);
+CREATE TABLE dependencies (
+    package_id INTEGER NOT NULL REFERENCES packages(id),
+    alternative_group INTEGER NOT NULL,
+    name TEXT NOT NULL
+);
+CREATE TABLE files (
+    id INTEGER PRIMARY KEY,
+    package_id INTEGER REFERENCES packages(id),
+    path BLOB NOT NULL,
+    type TEXT NOT NULL,
+    mode INTEGER,
+    owner INTEGER,
+    "group" INTEGER,
+    size INTEGER,
+    checksum TEXT,
+    link_target BLOB,
+    config INTEGER NOT NULL,
+    source TEXT NOT NULL
+);
+CREATE TABLE issues (
+    file_id INTEGER REFERENCES files(id),
+    package_id INTEGER REFERENCES packages(id),
+    path BLOB NOT NULL,
+    kind TEXT NOT NULL,
+    description TEXT NOT NULL
+);
+CREATE INDEX packages_name ON packages(name);
+CREATE INDEX dependencies_name ON dependencies(name);
+CREATE INDEX files_package ON files(package_id);
+CREATE INDEX files_path ON files(path);
+CREATE INDEX issues_file ON issues(file_id);
+"#;
+
+/// Header of `SQLite` database files
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+pub(crate) fn run_export(
+    cli: &Cli,
+    path: &Path,
+    with_issues: bool,
+    force: bool,
+) -> eyre::Result<Exit> {
+    if !force && !replaceable(path)? {
+        eyre::bail!("{path:?} exists and is not an SQLite database, use --force to replace it");
+    }
+    let interner = Interner::new();
+    let backend: ConcreteBackend = cli.backend.try_into()?;
+    let backend_cfg = cli.try_into()?;
+    let packages = backend
+        .create_packages(&backend_cfg, &interner)
+        .wrap_err_with(|| format!("Failed to create backend {backend}"))?
+        .packages(&interner)
+        .wrap_err_with(|| format!("Failed to collect information from backend {backend}"))?;
+    let files = backend
+        .create_files(&backend_cfg, &interner)
+        .wrap_err_with(|| format!("Failed to create backend {backend}"))?
+        .files(&interner)
+        .wrap_err_with(|| format!("Failed to collect information from backend {backend}"))?;
+    let issues = if with_issues {
+        let mut issues = file_ops::check_file_entries(&files, &cli.try_into()?)?;
+        crate::remove_ignored_issues(&cli.ignore, &mut issues)?;
+        issues
+    } else {
+        vec![]
+    };
+
+    write_database(path, &packages, &files, &issues, &interner)?;
+    tracing::info!(
+        "Exported {} packages, {} files and {} issues",
+        packages.len(),
+        files.len(),
+        issues.len()
+    );
+    Ok(Exit::new(Code::SUCCESS))
+}
+
+/// Check if the file at path doesn't exist, is empty or is an `SQLite`
+/// database (and as such can be replaced without `--force`)
+fn replaceable(path: &Path) -> eyre::Result<bool> {
+    let mut file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(true),
+        Err(err) => return Err(err).wrap_err_with(|| format!("Failed to open {path:?}")),
+    };
+    let mut header = Vec::with_capacity(SQLITE_HEADER.len());
+    file.by_ref()
+        .take(SQLITE_HEADER.len() as u64)
+        .read_to_end(&mut header)
+        .wrap_err_with(|| format!("Failed to read {path:?}"))?;
+    Ok(header.is_empty() || header == SQLITE_HEADER)
+}
+
+/// Write a new database to path
+///
+/// The database is written to a temporary file next to path, which then
+/// replaces path once complete. As such a failed export leaves any previous
+/// database in place. Like any other new file it is created readable by
+/// everyone (subject to the umask), not private like most temporary files.
+fn write_database(
+    path: &Path,
+    packages: &[PackageInterned],
+    files: &[FileEntry],
+    issues: &[PackageIssue],
+    interner: &Interner,
+) -> eyre::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let tmp = tempfile::Builder::new()
+        .prefix(".paketkoll-export")
+        .permissions(std::fs::Permissions::from_mode(0o666))
+        .tempfile_in(dir)
+        .wrap_err_with(|| format!("Failed to create temporary file in {dir:?}"))?;
+    let mut db = rusqlite::Connection::open(tmp.path())
+        .wrap_err_with(|| format!("Failed to create database {:?}", tmp.path()))?;
+    let tx = db.transaction()?;
+    tx.execute_batch(SCHEMA)
+        .wrap_err("Failed to create schema")?;
+    let package_ids = insert_packages(&tx, packages, interner)?;
+    let file_ids = insert_files(&tx, files, &package_ids)?;
+    insert_issues(&tx, issues, &package_ids, &file_ids)?;
+    tx.commit().wrap_err("Failed to commit to database")?;
+    db.close()
+        .map_err(|(_, err)| err)
+        .wrap_err("Failed to close database")?;
+    tmp.persist(path)
+        .wrap_err_with(|| format!("Failed to replace {path:?}"))?;
+    Ok(())
+}
+
+/// Insert packages, returning a map from the IDs of each package to the row ID
+///
+/// The name is only used if the package has no IDs, as on Debian packages for
+/// different architectures share the same name (`libfoo:amd64` and
+/// `libfoo:i386` are both named `libfoo`).
+fn insert_packages(
+    tx: &Transaction<'_>,
+    packages: &[PackageInterned],
+    interner: &Interner,
+) -> eyre::Result<AHashMap<PackageRef, i64>> {
+    let mut insert_package = tx.prepare(
+        "INSERT INTO packages (name, architecture, version, description, install_reason, status) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )?;
+    let mut insert_alias =
+        tx.prepare("INSERT INTO package_aliases (package_id, alias) VALUES (?1, ?2)")?;
+    let mut insert_provides =
+        tx.prepare("INSERT INTO provides (package_id, name) VALUES (?1, ?2)")?;
+    let mut insert_dependency = tx.prepare(
+        "INSERT INTO dependencies (package_id, alternative_group, name) VALUES (?1, ?2, ?3)",
+    )?;
+
+    let mut package_ids = AHashMap::new();
+    for package in packages {
+        insert_package.execute(params![
+            package.name.as_str(interner),
+            package.architecture.and_then(|a| a.try_as_str(interner)),
+            package.version.as_str(),
+            package.desc.as_deref(),
+            package.reason.map(|r| match r {
+                InstallReason::Explicit => "explicit",
+                InstallReason::Dependency => "dependency",
+            }),
+            match package.status {
+                PackageInstallStatus::Installed => "installed",
+                PackageInstallStatus::Partial => "partial",
+            },
+        ])?;
+        let id = tx.last_insert_rowid();
+        if package.ids.is_empty() {
+            package_ids.insert(package.name, id);
+        }
+        for alias in &package.ids {
+            insert_alias.execute(params![id, alias.as_str(interner)])?;
+            package_ids.insert(*alias, id);
+        }
+        for provided in &package.provides {
+            insert_provides.execute(params![id, provided.as_str(interner)])?;
+        }
+        for (group, dependency) in (0_i64..).zip(&package.depends) {
+            let alternatives = match dependency {
+                Dependency::Single(pkg) => std::slice::from_ref(pkg),
+                Dependency::Disjunction(pkgs) => pkgs.as_slice(),
+            };
+            for pkg in alternatives {
+                insert_dependency.execute(params![id, group, pkg.as_str(interner)])?;
+            }
+        }
+    }
+    Ok(package_ids)
+}
+
+/// Insert files, returning a map from path to row ID
+fn insert_files<'files>(
+    tx: &Transaction<'_>,
+    files: &'files [FileEntry],
+    package_ids: &AHashMap<PackageRef, i64>,
+) -> eyre::Result<AHashMap<&'files Path, i64>> {
+    let mut insert_file = tx.prepare(
+        "INSERT INTO files (package_id, path, type, mode, owner, \"group\", size, checksum, \
+         link_target, config, source) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+    )?;
+    let mut file_ids = AHashMap::with_capacity(files.len());
+    for entry in files {
+        let properties = &entry.properties;
+        insert_file.execute(params![
+            entry.package.and_then(|p| package_ids.get(&p)),
+            entry.path.as_os_str().as_bytes(),
+            properties.type_name(),
+            properties.mode().map(Mode::as_raw),
+            properties.owner().map(Uid::as_raw),
+            properties.group().map(Gid::as_raw),
+            properties.size().map(i64::try_from).transpose()?,
+            properties.checksum().map(ToString::to_string),
+            match properties {
+                Properties::Symlink(link) => Some(link.target.as_os_str().as_bytes()),
+                _ => None,
+            },
+            entry.flags.contains(FileFlags::CONFIG),
+            entry.source,
+        ])?;
+        file_ids.insert(entry.path.as_path(), tx.last_insert_rowid());
+    }
+    Ok(file_ids)
+}
+
+/// Insert issues (one row per kind of issue)
+fn insert_issues(
+    tx: &Transaction<'_>,
+    issues: &[PackageIssue],
+    package_ids: &AHashMap<PackageRef, i64>,
+    file_ids: &AHashMap<&Path, i64>,
+) -> eyre::Result<()> {
+    let mut insert_issue = tx.prepare(
+        "INSERT INTO issues (file_id, package_id, path, kind, description) VALUES (?1, ?2, ?3, \
+         ?4, ?5)",
+    )?;
+    for (package, issue) in issues {
+        for kind in issue.kinds() {
+            insert_issue.execute(params![
+                file_ids.get(issue.path()),
+                package.and_then(|p| package_ids.get(&p)),
+                issue.path().as_os_str().as_bytes(),
+                kind.name(),
+                kind.to_string(),
+            ])?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paketkoll_types::files::Symlink;
+    use paketkoll_types::intern::ArchitectureRef;
+    use paketkoll_types::issue::Issue;
+    use paketkoll_types::issue::IssueKind;
+    use pretty_assertions::assert_eq;
+    use std::ffi::OsStr;
+    use std::path::PathBuf;
+
+    fn file(package: Option<PackageRef>, path: &Path, properties: Properties) -> FileEntry {
+        FileEntry {
+            package,
+            path: path.into(),
+            properties,
+            flags: FileFlags::empty(),
+            source: "test",
+            seen: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_write_database() {
+        let interner = Interner::new();
+        let foo = PackageRef::get_or_intern(&interner, "foo");
+        let foo_i386 = PackageRef::get_or_intern(&interner, "foo:i386");
+        let bar = PackageRef::get_or_intern(&interner, "bar");
+        let amd64 = ArchitectureRef::get_or_intern(&interner, "amd64");
+        let i386 = ArchitectureRef::get_or_intern(&interner, "i386");
+        let mut packages = vec![PackageInterned {
+            name: foo,
+            architecture: Some(amd64),
+            version: "1.0-1".into(),
+            desc: Some("A package".into()),
+            depends: vec![
+                Dependency::Single(bar),
+                Dependency::Disjunction(vec![bar, PackageRef::get_or_intern(&interner, "baz")]),
+            ],
+            provides: vec![],
+            reason: Some(InstallReason::Explicit),
+            status: PackageInstallStatus::Installed,
+            ids: smallvec::smallvec![foo, PackageRef::get_or_intern(&interner, "foo:amd64")],
+        }];
+        // Multi-Arch: same package installed for a second architecture
+        packages.push(PackageInterned {
+            architecture: Some(i386),
+            depends: vec![],
+            ids: smallvec::smallvec![foo_i386],
+            ..packages[0].clone()
+        });
+        let non_utf8 = Path::new(OsStr::from_bytes(b"/usr/share/foo/\xff"));
+        let files = vec![
+            file(Some(foo), non_utf8, Properties::Unknown),
+            file(
+                Some(foo),
+                Path::new("/usr/bin/foo"),
+                Properties::Symlink(Symlink {
+                    owner: Uid::new(0),
+                    group: Gid::new(0),
+                    target: PathBuf::from("foo-1.0"),
+                }),
+            ),
+            file(
+                Some(foo_i386),
+                Path::new("/usr/lib/i386-linux-gnu/libfoo.so"),
+                Properties::Unknown,
+            ),
+            file(None, Path::new("/etc/unowned"), Properties::Unknown),
+        ];
+        let issues = vec![(
+            Some(foo),
+            Issue::new(
+                non_utf8.into(),
+                smallvec::smallvec![IssueKind::Missing],
+                None,
+            ),
+        )];
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.db");
+        std::fs::write(&path, "").unwrap();
+        write_database(&path, &packages, &files, &issues, &interner).unwrap();
+        // Only the database remains, the temporary file was renamed
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+        // With the same permissions as any other new file
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode();
+        let other = dir.path().join("other");
+        std::fs::File::create(&other).unwrap();
+        assert_eq!(mode(&path), mode(&other));
+
+        let db = rusqlite::Connection::open(&path).unwrap();
+        let query = |sql: &str| -> Vec<(Vec<u8>, Option<String>)> {
+            let mut stmt = db.prepare(sql).unwrap();
+            stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap()
+        };
+        assert_eq!(
+            query(
+                "SELECT f.path, p.architecture FROM files f LEFT JOIN packages p ON f.package_id \
+                 = p.id ORDER BY f.id"
+            ),
+            vec![
+                (b"/usr/share/foo/\xff".to_vec(), Some("amd64".into())),
+                (b"/usr/bin/foo".to_vec(), Some("amd64".into())),
+                (
+                    b"/usr/lib/i386-linux-gnu/libfoo.so".to_vec(),
+                    Some("i386".into())
+                ),
+                (b"/etc/unowned".to_vec(), None),
+            ]
+        );
+        assert_eq!(
+            query("SELECT link_target, type FROM files WHERE link_target IS NOT NULL"),
+            vec![(b"foo-1.0".to_vec(), Some("link".into()))]
+        );
+        assert_eq!(
+            query(
+                "SELECT i.path, i.kind FROM issues i JOIN files f ON i.file_id = f.id \
+                 WHERE f.path = i.path"
+            ),
+            vec![(b"/usr/share/foo/\xff".to_vec(), Some("missing".into()))]
+        );
+        let mut stmt = db
+            .prepare("SELECT alternative_group, name FROM dependencies ORDER BY rowid")
+            .unwrap();
+        let dependencies: Vec<(i64, String)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            dependencies,
+            vec![(0, "bar".into()), (1, "bar".into()), (1, "baz".into())]
+        );
+    }
+
+    #[test]
+    fn test_replaceable() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        assert!(replaceable(&path).unwrap());
+        std::fs::write(&path, "").unwrap();
+        assert!(replaceable(&path).unwrap());
+        std::fs::write(&path, "root:x:0:0::/root:/bin/bash\n").unwrap();
+        assert!(!replaceable(&path).unwrap());
+        rusqlite::Connection::open(dir.path().join("db"))
+            .unwrap()
+            .execute_batch(SCHEMA)
+            .unwrap();
+        assert!(replaceable(&dir.path().join("db")).unwrap());
+    }
+}
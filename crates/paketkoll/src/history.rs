@@ -0,0 +1,76 @@
+//! Show package transactions from the package manager logs
+
+use paketkoll::cli::Cli;
+use paketkoll::cli::Format;
+use paketkoll_core::package_log;
+use paketkoll_core::package_log::Transaction;
+use proc_exit::Code;
+use proc_exit::Exit;
+use std::io::BufWriter;
+use std::io::Write;
+use std::io::stdout;
+
+pub(crate) fn run_history(
+    cli: &Cli,
+    packages: &[String],
+    since: Option<i64>,
+    until: Option<i64>,
+) -> eyre::Result<Exit> {
+    let history = package_log::history(cli.backend.try_into()?)?;
+    if let Some(since) = since
+        && history.earliest.is_none_or(|earliest| earliest > since)
+    {
+        tracing::warn!("The package manager logs don't go back to the requested time");
+    }
+    let transactions: Vec<&Transaction> = history
+        .transactions
+        .iter()
+        .filter(|transaction| {
+            since.is_none_or(|since| transaction.start >= since)
+                && until.is_none_or(|until| transaction.start < until)
+                && (packages.is_empty()
+                    || transaction
+                        .changes
+                        .iter()
+                        .any(|change| packages.iter().any(|p| p == change.package.as_str())))
+        })
+        .collect();
+
+    let mut stdout = BufWriter::new(stdout().lock());
+    match cli.format {
+        Format::Human => {
+            for transaction in &transactions {
+                write!(
+                    stdout,
+                    "{} {}",
+                    package_log::format_local_time(transaction.start),
+                    transaction.source
+                )?;
+                if let Some(ref command) = transaction.command {
+                    write!(stdout, ": {command}")?;
+                }
+                if let Some(ref requested_by) = transaction.requested_by {
+                    write!(stdout, " (requested by {requested_by})")?;
+                }
+                writeln!(stdout)?;
+                for change in &transaction.changes {
+                    write!(stdout, "  {} {}", change.action, change.package)?;
+                    match (&change.old_version, &change.new_version) {
+                        (Some(old), Some(new)) if old != new => write!(stdout, " {old} -> {new}")?,
+                        (Some(version), _) | (None, Some(version)) => {
+                            write!(stdout, " {version}")?;
+                        }
+                        (None, None) => (),
+                    }
+                    writeln!(stdout)?;
+                }
+            }
+        }
+        #[cfg(feature = "json")]
+        Format::Json => serde_json::to_writer_pretty(&mut stdout, &transactions)?,
+        format => eyre::bail!("{format} format is not supported for history"),
+    }
+    stdout.flush()?;
+
+    Ok(Exit::new(Code::SUCCESS))
+}
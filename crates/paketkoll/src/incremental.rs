@@ -0,0 +1,92 @@
+//! Incremental checks (`check --since`)
+
+use ahash::AHashSet;
+use compact_str::CompactString;
+use eyre::OptionExt;
+use eyre::WrapErr;
+use paketkoll::cli::Since;
+use paketkoll_core::backend::ConcreteBackend;
+use paketkoll_core::package_log;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Find the packages to verify the file contents of
+///
+/// Returns `None` if everything needs to be verified, such as when there is
+/// no previous run or the package manager logs don't go back far enough.
+pub(crate) fn packages_to_verify(
+    backend: ConcreteBackend,
+    since: Since,
+) -> eyre::Result<Option<AHashSet<CompactString>>> {
+    let since = match since {
+        Since::Time(time) => time,
+        Since::Last => match last_run(backend)? {
+            Some(time) => time,
+            None => {
+                tracing::info!("No previous run of check recorded, checking everything");
+                return Ok(None);
+            }
+        },
+    };
+    let packages = package_log::changed_packages(backend, since)?;
+    match packages {
+        Some(ref packages) => tracing::info!(
+            "Verifying contents of files from {} changed packages",
+            packages.len()
+        ),
+        None => {
+            tracing::info!("Package manager logs don't go back far enough, checking everything");
+        }
+    }
+    Ok(packages)
+}
+
+/// Get the current time in seconds since the Unix epoch
+pub(crate) fn now() -> eyre::Result<i64> {
+    let duration = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .wrap_err("System clock is before 1970")?;
+    Ok(duration.as_secs().try_into()?)
+}
+
+/// Record the start time of a completed check of all packages
+pub(crate) fn record_run(backend: ConcreteBackend, started: i64) {
+    let result = state_file(backend).and_then(|path| {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .wrap_err_with(|| format!("Failed to create {parent:?}"))?;
+        }
+        std::fs::write(&path, format!("{started}\n"))
+            .wrap_err_with(|| format!("Failed to write {path:?}"))
+    });
+    if let Err(err) = result {
+        tracing::warn!("Failed to record time of check: {err:?}");
+    }
+}
+
+/// Start time of the previous recorded check, if any
+fn last_run(backend: ConcreteBackend) -> eyre::Result<Option<i64>> {
+    let path = state_file(backend)?;
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => Ok(Some(
+            contents
+                .trim()
+                .parse()
+                .wrap_err_with(|| format!("Invalid time in {path:?}"))?,
+        )),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).wrap_err_with(|| format!("Failed to read {path:?}")),
+    }
+}
+
+/// File storing the start time of the last check for a backend
+fn state_file(backend: ConcreteBackend) -> eyre::Result<PathBuf> {
+    let proj_dirs = directories::ProjectDirs::from("", "", "paketkoll")
+        .ok_or_eyre("Failed to get directories for state")?;
+    // Only Linux has a state dir, fall back to local data elsewhere
+    let dir = proj_dirs
+        .state_dir()
+        .unwrap_or_else(|| proj_dirs.data_local_dir());
+    Ok(dir.join(format!("last-check-{backend}")))
+}
@@ -1,31 +1,73 @@
 //! Implements the CLI for paketkoll
 
+use ahash::AHashMap;
 use ahash::AHashSet;
-use clap::Parser;
+use compact_str::CompactString;
 use eyre::WrapErr;
+use paketkoll::cli::BaselineCommand;
 use paketkoll::cli::Cli;
 use paketkoll::cli::Commands;
 use paketkoll::cli::Format;
+use paketkoll::cli::Generated;
+use paketkoll_core::backend::ConcreteBackend;
+use paketkoll_core::baseline;
+use paketkoll_core::compat;
 use paketkoll_core::config::CheckAllFilesConfiguration;
+use paketkoll_core::config::CheckAllFilesConfigurationBuilder;
+use paketkoll_core::config::CommonFileCheckConfiguration;
 use paketkoll_core::file_ops;
+use paketkoll_core::file_ops::CancellationToken;
+use paketkoll_core::file_ops::CheckStatistics;
+use paketkoll_core::generated::GeneratedFiles;
+use paketkoll_core::generated::GeneratedRule;
+use paketkoll_core::mtree;
 use paketkoll_core::package_ops;
 use paketkoll_core::paketkoll_types::intern::Interner;
 use paketkoll_core::paketkoll_types::intern::PackageRef;
 use paketkoll_core::paketkoll_types::issue::Issue;
+use paketkoll_core::paketkoll_types::issue::PackageIssue;
 use paketkoll_core::paketkoll_types::package::InstallReason;
+use paketkoll_core::reports;
+use paketkoll_core::waivers::Date;
+use paketkoll_core::waivers::Waiver;
+use paketkoll_core::waivers::Waivers;
 use paketkoll_types::backend::OriginalFileQuery;
+use paketkoll_types::files::FileEntry;
+use paketkoll_types::files::FileFlags;
+use paketkoll_types::files::Properties;
 use paketkoll_types::package::PackageInterned;
 use proc_exit::Code;
 use proc_exit::Exit;
 use rayon::prelude::*;
+use std::fs::File;
+use std::io::BufReader;
 use std::io::BufWriter;
 use std::io::Write;
 use std::io::stdout;
 use std::os::unix::ffi::OsStrExt;
 use std::path::Path;
+use std::path::PathBuf;
+use std::time::Instant;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
+mod audit;
+mod audit_perms;
+mod check_libs;
+mod config_file;
+mod conflicts;
+mod diff;
+mod disk_usage;
+#[cfg(feature = "sqlite")]
+mod export_db;
+mod history;
+mod incremental;
+mod original;
+mod restore;
+#[cfg(feature = "json")]
+mod sbom;
+mod watch;
+
 #[cfg(target_env = "musl")]
 mod _musl {
     use mimalloc::MiMalloc;
@@ -43,10 +85,55 @@ fn main() -> eyre::Result<Exit> {
         .with(filter)
         .with(tracing_error::ErrorLayer::default())
         .init();
-    let cli = Cli::parse();
+    let cli = config_file::parse_cli()?;
 
     match cli.command {
-        Commands::Check { .. } | Commands::CheckUnexpected { .. } => run_file_checks(&cli),
+        Commands::Check { .. }
+        | Commands::CheckUnexpected { .. }
+        | Commands::Baseline {
+            command: BaselineCommand::Verify { .. },
+        } => {
+            if cli.stream {
+                run_streaming_checks(&cli)
+            } else {
+                run_file_checks(&cli)
+            }
+        }
+        Commands::Baseline {
+            command:
+                BaselineCommand::Create {
+                    canonicalize,
+                    ref path,
+                },
+        } => {
+            let entries = baseline::create(
+                cli.backend.try_into()?,
+                &(&cli).try_into()?,
+                &unexpected_config(&cli, canonicalize)?,
+            )?;
+            let mut writer = BufWriter::new(
+                File::create(path).wrap_err_with(|| format!("Failed to create {path:?}"))?,
+            );
+            mtree::write_entries(&mut writer, entries.iter())?;
+            writer.flush()?;
+            tracing::info!("Recorded {} entries in baseline", entries.len());
+            Ok(Exit::new(Code::SUCCESS))
+        }
+        Commands::CheckLibs { owners } => check_libs::run_check_libs(&cli, owners),
+        Commands::Du {
+            canonicalize,
+            max_depth,
+            top,
+        } => disk_usage::run_du(&cli, canonicalize, max_depth, top),
+        Commands::Diff {
+            ref diff_command,
+            ref paths,
+        } => diff::run_diff(&cli, paths, diff_command),
+        Commands::Restore {
+            confirmation,
+            all_issues,
+            ref paths,
+        } => restore::run_restore(&cli, paths, all_issues, confirmation),
         Commands::InstalledPackages => {
             let (interner, packages) =
                 package_ops::installed_packages(cli.backend.try_into()?, &(&cli).try_into()?)?;
@@ -56,12 +143,55 @@ fn main() -> eyre::Result<Exit> {
 
             Ok(Exit::new(Code::SUCCESS))
         }
+        Commands::Audit {
+            db_format,
+            ref release,
+            ref path,
+        } => audit::run_audit(&cli, db_format, release.as_deref(), path),
+        Commands::AuditPerms {
+            canonicalize,
+            unexpected_only,
+        } => audit_perms::run_audit_perms(&cli, canonicalize, unexpected_only),
+        Commands::History {
+            ref packages,
+            since,
+            until,
+        } => history::run_history(&cli, packages, since, until),
+        Commands::Watch {
+            method,
+            settle,
+            canonicalize,
+            generated,
+            ref generated_rules,
+            ref paths,
+        } => watch::run_watch(
+            &cli,
+            paths,
+            method,
+            settle,
+            canonicalize,
+            generated,
+            generated_rules,
+        ),
+        Commands::Conflicts { canonicalize, .. } => conflicts::run_conflicts(&cli, canonicalize),
+        #[cfg(feature = "sqlite")]
+        Commands::ExportDb {
+            issues,
+            force,
+            ref path,
+        } => export_db::run_export(&cli, path, issues, force),
+        #[cfg(feature = "json")]
+        Commands::Sbom {
+            format,
+            files,
+            verify,
+        } => sbom::run_sbom(&cli, format, files, verify),
         Commands::OriginalFile {
             ref package,
             ref path,
         } => {
             let interner = Interner::new();
-            let backend: paketkoll_core::backend::ConcreteBackend = cli.backend.try_into()?;
+            let backend: ConcreteBackend = cli.backend.try_into()?;
             let backend_impl = backend
                 .create_full(&(&cli).try_into()?, &interner)
                 .wrap_err("Failed to create backend")?;
@@ -112,7 +242,7 @@ fn main() -> eyre::Result<Exit> {
         }
         Commands::Owns { ref paths } => {
             let interner = Interner::new();
-            let backend: paketkoll_core::backend::ConcreteBackend = cli.backend.try_into()?;
+            let backend: ConcreteBackend = cli.backend.try_into()?;
             let backend_impl = backend
                 .create_files(&(&cli).try_into()?, &interner)
                 .wrap_err("Failed to create backend")?;
@@ -129,31 +259,26 @@ fn main() -> eyre::Result<Exit> {
             }
             Ok(Exit::new(Code::SUCCESS))
         }
-        Commands::DebugPackageFileData { ref package } => {
-            let interner = Interner::new();
-            let backend: paketkoll_core::backend::ConcreteBackend = cli.backend.try_into()?;
-            let backend_impl = backend
-                .create_full(&(&cli).try_into()?, &interner)
-                .wrap_err("Failed to create backend")?;
-
-            let package_map = backend_impl
-                .package_map_complete(&interner)
-                .wrap_err_with(|| {
-                    format!("Failed to collect information from backend {backend}")
-                })?;
-
-            let pkg_ref = PackageRef::get_or_intern(&interner, package);
-
-            let files = backend_impl
-                .files_from_archives(&[pkg_ref], &package_map, &interner)
-                .wrap_err_with(|| {
-                    format!(
-                        "Failed to collect file information for package {package} from backend \
-                         {backend}"
-                    )
-                })?;
+        Commands::Files {
+            ref packages,
+            from_archives,
+        } => {
+            let archive_packages: Vec<&str> = packages.iter().map(String::as_str).collect();
+            if from_archives && archive_packages.is_empty() {
+                eyre::bail!("Packages must be given explicitly when using --from-archives");
+            }
+            let (interner, mut files) = file_ops::package_files(
+                cli.backend.try_into()?,
+                &(&cli).try_into()?,
+                from_archives.then_some(archive_packages.as_slice()),
+            )?;
+            files.sort_by(|a, b| {
+                (a.package.and_then(|e| e.try_as_str(&interner)), &a.path)
+                    .cmp(&(b.package.and_then(|e| e.try_as_str(&interner)), &b.path))
+            });
+            let mut stdout = BufWriter::new(stdout().lock());
 
-            println!("{files:?}");
+            print_files(&cli, &files, &interner, &mut stdout)?;
 
             Ok(Exit::new(Code::SUCCESS))
         }
@@ -195,28 +320,119 @@ fn print_packages(
                 .collect();
             serde_json::to_writer_pretty(stdout, &packages)?;
         }
+        format => eyre::bail!("{format} format is not supported for package listings"),
     };
     Ok(())
 }
 
+fn print_files(
+    cli: &Cli,
+    files: &[FileEntry],
+    interner: &Interner,
+    stdout: &mut BufWriter<std::io::StdoutLock<'_>>,
+) -> eyre::Result<()> {
+    match cli.format {
+        Format::Human => {
+            for entry in files {
+                if let Some(pkg) = entry.package.and_then(|e| e.try_as_str(interner)) {
+                    write!(stdout, "{pkg}: ")?;
+                }
+                stdout.write_all(entry.path.as_os_str().as_bytes())?;
+                write!(stdout, " {}", entry.properties.type_name())?;
+                if let Some(mode) = entry.properties.mode() {
+                    write!(stdout, " mode={:04o}", mode.as_raw())?;
+                }
+                if let Some(owner) = entry.properties.owner() {
+                    write!(stdout, " owner={owner}")?;
+                }
+                if let Some(group) = entry.properties.group() {
+                    write!(stdout, " group={group}")?;
+                }
+                if let Some(size) = entry.properties.size() {
+                    write!(stdout, " size={size}")?;
+                }
+                if let Some(checksum) = entry.properties.checksum() {
+                    write!(stdout, " checksum={checksum}")?;
+                }
+                if let Properties::Symlink(link) = &entry.properties {
+                    write!(stdout, " -> ")?;
+                    stdout.write_all(link.target.as_os_str().as_bytes())?;
+                }
+                if entry.flags.contains(FileFlags::CONFIG) {
+                    write!(stdout, " (config)")?;
+                }
+                writeln!(stdout)?;
+            }
+        }
+        #[cfg(feature = "json")]
+        Format::Json => {
+            let files: Vec<_> = files
+                .par_iter()
+                .map(|entry| FileReport {
+                    package: entry.package.and_then(|e| e.try_as_str(interner)),
+                    path: &entry.path,
+                    properties: &entry.properties,
+                    flags: entry.flags,
+                    source: entry.source,
+                })
+                .collect();
+            serde_json::to_writer_pretty(stdout, &files)?;
+        }
+        Format::Mtree => mtree::write_entries(stdout, files.iter())?,
+        format => eyre::bail!("{format} format is not supported for file listings"),
+    }
+    Ok(())
+}
+
 fn run_file_checks(cli: &Cli) -> eyre::Result<Exit> {
-    let (interner, mut found_issues) = match cli.command {
-        Commands::Check { .. } => file_ops::check_installed_files(
-            cli.backend.try_into()?,
-            &cli.try_into()?,
-            &cli.try_into()?,
-        )?,
-        Commands::CheckUnexpected { canonicalize } => file_ops::check_all_files(
-            cli.backend.try_into()?,
-            &cli.try_into()?,
-            &cli.try_into()?,
-            &{
-                let mut builder = CheckAllFilesConfiguration::builder();
-                builder.ignored_paths(cli.ignore.clone());
-                builder.canonicalize_paths(canonicalize);
-                builder.build()?
-            },
-        )?,
+    let start = Instant::now();
+    let (interner, mut found_issues, statistics) = match cli.command {
+        Commands::Check { ref packages, .. } => {
+            let started = incremental::now()?;
+            let backend = cli.backend.try_into()?;
+            let results = file_ops::check_installed_files(
+                backend,
+                &cli.try_into()?,
+                &file_check_config(cli, backend)?,
+            )?;
+            if packages.is_empty() {
+                incremental::record_run(backend, started);
+            }
+            results
+        }
+        Commands::CheckUnexpected {
+            canonicalize,
+            generated,
+            ref generated_rules,
+        } => {
+            let backend = cli.backend.try_into()?;
+            file_ops::check_all_files(
+                backend,
+                &cli.try_into()?,
+                &cli.try_into()?,
+                &check_unexpected_config(cli, backend, canonicalize, generated, generated_rules)?,
+            )?
+        }
+        Commands::Baseline {
+            command:
+                BaselineCommand::Verify {
+                    canonicalize,
+                    ref path,
+                },
+        } => {
+            let reader = BufReader::new(
+                File::open(path).wrap_err_with(|| format!("Failed to open {path:?}"))?,
+            );
+            let entries = mtree::read_entries(reader, baseline::SOURCE)
+                .wrap_err_with(|| format!("Failed to load baseline from {path:?}"))?;
+            baseline::verify(
+                cli.backend.try_into()?,
+                &cli.try_into()?,
+                &cli.try_into()?,
+                &unexpected_config(cli, canonicalize)?,
+                entries,
+            )?
+        }
         _ => unreachable!(),
     };
 
@@ -238,36 +454,29 @@ fn run_file_checks(cli: &Cli) -> eyre::Result<Exit> {
     {
         // Do post-processing of ignores as the check command doesn't have that built
         // in.
-        let ignores = file_ops::build_ignore_overrides(&cli.ignore)?;
-        found_issues.retain(|(_, issue)| {
-            let path = issue.path();
-            match ignores.matched(path, path.is_dir()) {
-                ignore::Match::None => (),
-                ignore::Match::Ignore(_) => {
-                    return false;
-                }
-                ignore::Match::Whitelist(_) => (),
-            }
-            true
-        });
+        remove_ignored_issues(&cli.ignore, &mut found_issues)?;
+    }
+
+    if let Some(waivers) = load_waivers(cli)? {
+        let expired = waivers.apply(&mut found_issues, Date::today());
+        found_issues.extend(expired);
     }
 
     let has_issues = !found_issues.is_empty();
 
     match cli.format {
-        Format::Human => {
+        Format::Human | Format::PacmanQkk | Format::DpkgVerify => {
             let mut stdout = BufWriter::new(stdout().lock());
             for (pkg, issue) in &found_issues {
                 let pkg = pkg.and_then(|e| interner.try_resolve(&e.as_interner_ref()));
-                for kind in issue.kinds() {
-                    if let Some(pkg) = pkg {
-                        write!(stdout, "{pkg}: ")?;
-                    }
-                    // Prefer to not do any escaping. This doesn't assume unicode.
-                    // Also, it is faster.
-                    stdout.write_all(issue.path().as_os_str().as_bytes())?;
-                    writeln!(stdout, " {kind}")?;
+                write_issue(&mut stdout, cli.format, pkg, issue)?;
+            }
+            if cli.format == Format::PacmanQkk {
+                let mut altered = AHashMap::new();
+                for pkg in found_issues.iter().filter_map(|(pkg, _)| *pkg) {
+                    *altered.entry(pkg).or_default() += 1;
                 }
+                write_pacman_summaries(&mut stdout, &statistics, &altered, &interner)?;
             }
         }
         #[cfg(feature = "json")]
@@ -282,6 +491,157 @@ fn run_file_checks(cli: &Cli) -> eyre::Result<Exit> {
                 .collect();
             serde_json::to_writer_pretty(&mut stdout, &found_issues)?;
         }
+        Format::Prometheus => {
+            let mut stdout = BufWriter::new(stdout().lock());
+            reports::write_prometheus(
+                &mut stdout,
+                &found_issues,
+                &interner,
+                &statistics,
+                start.elapsed(),
+            )?;
+        }
+        Format::Junit => {
+            let suite_name = match cli.command {
+                Commands::Check { .. } => "paketkoll check",
+                Commands::CheckUnexpected { .. } => "paketkoll check-unexpected",
+                _ => "paketkoll baseline verify",
+            };
+            let mut stdout = BufWriter::new(stdout().lock());
+            reports::write_junit(
+                &mut stdout,
+                suite_name,
+                &found_issues,
+                &interner,
+                &statistics,
+                start.elapsed(),
+            )?;
+        }
+        Format::Mtree => eyre::bail!("mtree format is not supported for check results"),
+    }
+
+    Ok(if has_issues {
+        Exit::new(Code::FAILURE)
+    } else {
+        Exit::new(Code::SUCCESS)
+    })
+}
+
+/// File check configuration, with `check --since` only verifying the contents
+/// of changed packages
+fn file_check_config(
+    cli: &Cli,
+    backend: ConcreteBackend,
+) -> eyre::Result<CommonFileCheckConfiguration> {
+    let mut config: CommonFileCheckConfiguration = cli.try_into()?;
+    if let Commands::Check {
+        since: Some(since), ..
+    } = cli.command
+    {
+        config.verify_contents_of = incremental::packages_to_verify(backend, since)?;
+    }
+    Ok(config)
+}
+
+/// Run check or check-unexpected, printing issues as they are found
+fn run_streaming_checks(cli: &Cli) -> eyre::Result<Exit> {
+    match cli.format {
+        Format::Human | Format::PacmanQkk | Format::DpkgVerify => (),
+        format => eyre::bail!("{format} format is not supported with --stream"),
+    }
+    let started = incremental::now()?;
+    let backend = cli.backend.try_into()?;
+    let backend_config = cli.try_into()?;
+    let filecheck_config = file_check_config(cli, backend)?;
+    let unexpected_cfg = match cli.command {
+        Commands::Check { .. } => None,
+        Commands::CheckUnexpected {
+            canonicalize,
+            generated,
+            ref generated_rules,
+        } => Some(check_unexpected_config(
+            cli,
+            backend,
+            canonicalize,
+            generated,
+            generated_rules,
+        )?),
+        _ => eyre::bail!("--stream is only supported for check and check-unexpected"),
+    };
+    // The check command doesn't have ignores built in
+    let ignores = match cli.command {
+        Commands::Check { .. } if !cli.ignore.is_empty() => {
+            Some(file_ops::build_ignore_overrides(&cli.ignore)?)
+        }
+        _ => None,
+    };
+    let waivers = load_waivers(cli)?;
+    let today = Date::today();
+
+    let interner = Interner::new();
+    let cancel = CancellationToken::new();
+    let (collector, issues) = flume::bounded(1024);
+    // Stdout is line buffered, so issues show up as they are written
+    let mut stdout = stdout().lock();
+    let mut has_issues = false;
+    let mut altered: AHashMap<PackageRef, usize> = AHashMap::new();
+    let statistics = std::thread::scope(|scope| {
+        let checker = scope.spawn(|| match unexpected_cfg {
+            None => file_ops::check_installed_files_streaming(
+                backend,
+                &backend_config,
+                &filecheck_config,
+                &interner,
+                collector,
+                &cancel,
+            ),
+            Some(ref unexpected_cfg) => file_ops::check_all_files_streaming(
+                backend,
+                &backend_config,
+                &filecheck_config,
+                unexpected_cfg,
+                &interner,
+                collector,
+                &cancel,
+            ),
+        });
+        let expired = waivers
+            .as_ref()
+            .map(|w| w.expired(today))
+            .unwrap_or_default();
+        let found = issues.iter().filter_map(|(pkg, mut issue)| {
+            let keep = !ignores.as_ref().is_some_and(|i| is_ignored(i, &issue))
+                && waivers.as_ref().is_none_or(|w| w.waive(&mut issue, today));
+            keep.then_some((pkg, issue))
+        });
+        let mut result: eyre::Result<()> = Ok(());
+        for (pkg, issue) in expired.into_iter().chain(found) {
+            has_issues = true;
+            if let Some(pkg) = pkg {
+                *altered.entry(pkg).or_default() += 1;
+            }
+            let pkg = pkg.and_then(|e| interner.try_resolve(&e.as_interner_ref()));
+            if let Err(err) = write_issue(&mut stdout, cli.format, pkg, &issue) {
+                // Most likely a closed pipe (such as from head), stop checking
+                cancel.cancel();
+                if err.kind() != std::io::ErrorKind::BrokenPipe {
+                    result = Err(err.into());
+                }
+                break;
+            }
+        }
+        drop(issues);
+        let statistics = checker.join().expect("Check thread panicked")?;
+        result.map(|()| statistics)
+    })?;
+    if cli.format == Format::PacmanQkk && !cancel.is_cancelled() {
+        write_pacman_summaries(&mut stdout, &statistics, &altered, &interner)?;
+    }
+    if let Commands::Check { ref packages, .. } = cli.command
+        && packages.is_empty()
+        && !cancel.is_cancelled()
+    {
+        incremental::record_run(backend, started);
     }
 
     Ok(if has_issues {
@@ -291,6 +651,147 @@ fn run_file_checks(cli: &Cli) -> eyre::Result<Exit> {
     })
 }
 
+/// Write the per package summary lines of `pacman -Qkk`, given the number of
+/// files with issues in each package
+fn write_pacman_summaries(
+    stdout: &mut impl Write,
+    statistics: &CheckStatistics,
+    altered: &AHashMap<PackageRef, usize>,
+    interner: &Interner,
+) -> std::io::Result<()> {
+    let mut summaries: Vec<_> = statistics
+        .packages_checked
+        .iter()
+        .filter_map(|(pkg, total_files)| {
+            let altered_files = altered.get(pkg).copied().unwrap_or_default();
+            Some((pkg.try_as_str(interner)?, *total_files, altered_files))
+        })
+        .collect();
+    summaries.sort_unstable();
+    for (pkg, total_files, altered_files) in summaries {
+        compat::write_pacman_qkk_summary(stdout, pkg, total_files, altered_files)?;
+    }
+    Ok(())
+}
+
+/// Write a single issue in a line based output format
+fn write_issue(
+    stdout: &mut impl Write,
+    format: Format,
+    pkg: Option<&str>,
+    issue: &Issue,
+) -> std::io::Result<()> {
+    let pkg = issue.owner(pkg);
+    match format {
+        // Like pacman, the warnings go to stderr (and the summary lines to stdout)
+        Format::PacmanQkk => compat::write_pacman_qkk(&mut std::io::stderr().lock(), pkg, issue),
+        Format::DpkgVerify => compat::write_dpkg_verify(stdout, issue),
+        _ => {
+            for kind in issue.kinds() {
+                if let Some(pkg) = pkg {
+                    write!(stdout, "{pkg}: ")?;
+                }
+                // Prefer to not do any escaping. This doesn't assume unicode.
+                // Also, it is faster.
+                stdout.write_all(issue.path().as_os_str().as_bytes())?;
+                writeln!(stdout, " {kind}")?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Load the waiver files given on the command line (if any)
+fn load_waivers(cli: &Cli) -> eyre::Result<Option<Waivers>> {
+    if cli.waivers.is_empty() {
+        return Ok(None);
+    }
+    let mut waivers = vec![];
+    for path in &cli.waivers {
+        let contents =
+            std::fs::read_to_string(path).wrap_err_with(|| format!("Failed to read {path:?}"))?;
+        waivers.extend(
+            Waiver::parse(&contents)
+                .wrap_err_with(|| format!("Failed to parse waivers in {path:?}"))?,
+        );
+    }
+    Ok(Some(Waivers::new(waivers)?))
+}
+
+/// Configuration for check-unexpected, including rules for generated files
+fn check_unexpected_config(
+    cli: &Cli,
+    backend: ConcreteBackend,
+    canonicalize: bool,
+    generated: Generated,
+    generated_rules: &[PathBuf],
+) -> eyre::Result<CheckAllFilesConfiguration> {
+    let mut builder = unexpected_config_builder(cli, canonicalize);
+    if generated != Generated::Off {
+        let mut rules = GeneratedRule::builtin(backend);
+        for path in generated_rules {
+            let contents = std::fs::read_to_string(path)
+                .wrap_err_with(|| format!("Failed to read {path:?}"))?;
+            rules.extend(
+                GeneratedRule::parse(&contents)
+                    .wrap_err_with(|| format!("Failed to parse rules in {path:?}"))?,
+            );
+        }
+        builder.generated_files(Some(GeneratedFiles::new(rules)?));
+        builder.report_generated(generated == Generated::Report);
+    }
+    Ok(builder.build()?)
+}
+
+fn unexpected_config(cli: &Cli, canonicalize: bool) -> eyre::Result<CheckAllFilesConfiguration> {
+    Ok(unexpected_config_builder(cli, canonicalize).build()?)
+}
+
+fn unexpected_config_builder(cli: &Cli, canonicalize: bool) -> CheckAllFilesConfigurationBuilder {
+    let mut builder = CheckAllFilesConfiguration::builder();
+    builder.ignored_paths(cli.ignore.clone());
+    builder.canonicalize_paths(canonicalize);
+    builder
+}
+
+/// Get the value of a field in `/etc/os-release`
+fn os_release(key: &str) -> Option<String> {
+    let os_release = std::fs::read_to_string("/etc/os-release").ok()?;
+    os_release.lines().find_map(|line| {
+        line.strip_prefix(key)
+            .and_then(|rest| rest.strip_prefix('='))
+            .map(|value| value.trim_matches(['"', '\'']).to_string())
+    })
+}
+
+/// Remove issues for paths matching the given ignore globs
+fn remove_ignored_issues(
+    ignore: &[CompactString],
+    issues: &mut Vec<PackageIssue>,
+) -> eyre::Result<()> {
+    let ignores = file_ops::build_ignore_overrides(ignore)?;
+    issues.retain(|(_, issue)| !is_ignored(&ignores, issue));
+    Ok(())
+}
+
+fn is_ignored(ignores: &ignore::overrides::Override, issue: &Issue) -> bool {
+    let path = issue.path();
+    matches!(
+        ignores.matched(path, path.is_dir()),
+        ignore::Match::Ignore(_)
+    )
+}
+
+#[cfg(feature = "json")]
+#[derive(Debug, serde::Serialize)]
+struct FileReport<'a> {
+    package: Option<&'a str>,
+    path: &'a Path,
+    properties: &'a Properties,
+    flags: FileFlags,
+    source: &'static str,
+}
+
 #[cfg(feature = "json")]
 #[derive(Debug, serde::Serialize)]
 struct IssueReport<'interner> {
@@ -0,0 +1,161 @@
+//! Look up what the package manager originally installed for given paths
+
+use ahash::AHashMap;
+use ahash::AHashSet;
+use eyre::OptionExt;
+use eyre::WrapErr;
+use paketkoll::cli::Cli;
+use paketkoll_cache::FromArchiveCache;
+use paketkoll_cache::OriginalFilesCache;
+use paketkoll_core::backend::ConcreteBackend;
+use paketkoll_types::backend::ArchiveQueryError;
+use paketkoll_types::backend::Files;
+use paketkoll_types::backend::OriginalFileQuery;
+use paketkoll_types::backend::PackageMap;
+use paketkoll_types::files::FileEntry;
+use paketkoll_types::intern::Interner;
+use paketkoll_types::intern::PackageRef;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Backend state needed to query original files (with disk caching)
+pub(crate) struct OriginalFiles {
+    interner: Interner,
+    backend: Box<dyn Files>,
+    package_map: PackageMap,
+}
+
+impl OriginalFiles {
+    /// Create backends as selected on the command line
+    pub(crate) fn new(cli: &Cli) -> eyre::Result<Self> {
+        let interner = Interner::new();
+        let backend: ConcreteBackend = cli.backend.try_into()?;
+        let backend_cfg = cli.try_into()?;
+        let proj_dirs = directories::ProjectDirs::from("", "", "paketkoll")
+            .ok_or_eyre("Failed to get directories for disk cache")?;
+
+        let package_map = backend
+            .create_packages(&backend_cfg, &interner)
+            .wrap_err_with(|| format!("Failed to create backend {backend}"))?
+            .package_map_complete(&interner)
+            .wrap_err_with(|| format!("Failed to collect information from backend {backend}"))?;
+
+        let files = backend
+            .create_files(&backend_cfg, &interner)
+            .wrap_err_with(|| format!("Failed to create backend {backend}"))?;
+        let files: Box<dyn Files> = if files.prefer_files_from_archive() {
+            // This is slow so we need to cache it
+            Box::new(
+                FromArchiveCache::from_path(files, proj_dirs.cache_dir())
+                    .wrap_err("Failed to create archive disk cache")?,
+            )
+        } else {
+            files
+        };
+        let files = OriginalFilesCache::from_path(files, proj_dirs.cache_dir())
+            .wrap_err("Failed to create original files disk cache")?;
+
+        Ok(Self {
+            interner,
+            backend: Box::new(files),
+            package_map,
+        })
+    }
+
+    pub(crate) const fn interner(&self) -> &Interner {
+        &self.interner
+    }
+
+    /// Find the package file entries for the given paths
+    ///
+    /// It is an error if any of the paths are not owned by a package.
+    pub(crate) fn entries(&self, paths: &[PathBuf]) -> eyre::Result<Vec<FileEntry>> {
+        let inputs: AHashSet<&Path> = paths.iter().map(PathBuf::as_path).collect();
+        let owners = self.backend.owning_packages(&inputs, &self.interner)?;
+        let mut packages = AHashSet::new();
+        for path in paths {
+            match owners.get(path).as_deref() {
+                Some(Some(package)) => {
+                    packages.insert(*package);
+                }
+                _ => eyre::bail!("No package owns {path:?}"),
+            }
+        }
+
+        let mut entries: AHashMap<PathBuf, FileEntry> = if self.backend.prefer_files_from_archive()
+        {
+            let packages: Vec<PackageRef> = packages.into_iter().collect();
+            let mut entries = AHashMap::new();
+            for result in
+                self.backend
+                    .files_from_archives(&packages, &self.package_map, &self.interner)?
+            {
+                match result {
+                    Ok((_, files)) => {
+                        entries.extend(files.into_iter().map(|e| (e.path.clone(), e)));
+                    }
+                    Err(ArchiveQueryError::PackageMissing { query, .. }) => {
+                        eyre::bail!(
+                            "Failed to find or download package archive for {}",
+                            query.as_str(&self.interner)
+                        );
+                    }
+                    Err(err) => {
+                        return Err(err).wrap_err("Failed to load files from package archive");
+                    }
+                }
+            }
+            entries
+        } else {
+            self.backend
+                .files(&self.interner)?
+                .into_iter()
+                .filter(|e| e.package.is_some_and(|p| packages.contains(&p)))
+                .map(|e| (e.path.clone(), e))
+                .collect()
+        };
+
+        paths
+            .iter()
+            .map(|path| {
+                entries
+                    .remove(path)
+                    .ok_or_else(|| eyre::eyre!("Failed to find package data for {path:?}"))
+            })
+            .collect()
+    }
+
+    /// Get the original contents of the given (regular file) entries
+    pub(crate) fn contents<'entry>(
+        &self,
+        entries: impl Iterator<Item = &'entry FileEntry>,
+    ) -> eyre::Result<AHashMap<PathBuf, Vec<u8>>> {
+        let queries: Vec<_> = entries
+            .map(|entry| {
+                Ok(OriginalFileQuery {
+                    package: entry
+                        .package
+                        .ok_or_eyre("File entry without package")?
+                        .as_str(&self.interner)
+                        .into(),
+                    path: entry
+                        .path
+                        .to_str()
+                        .ok_or_else(|| eyre::eyre!("Path is not valid UTF-8: {:?}", entry.path))?
+                        .into(),
+                })
+            })
+            .collect::<eyre::Result<_>>()?;
+        if queries.is_empty() {
+            return Ok(AHashMap::new());
+        }
+        let results = self
+            .backend
+            .original_files(&queries, &self.package_map, &self.interner)
+            .wrap_err("Failed to collect original files")?;
+        Ok(results
+            .into_iter()
+            .map(|(query, contents)| (PathBuf::from(query.path.as_str()), contents))
+            .collect())
+    }
+}
@@ -0,0 +1,579 @@
+//! Restore files to the state the package manager installed them in
+
+use crate::diff::diff_contents;
+use crate::original::OriginalFiles;
+use ahash::AHashMap;
+use eyre::WrapErr;
+use paketkoll::cli::Cli;
+use paketkoll::cli::Paranoia;
+use paketkoll_core::config::CommonFileCheckConfiguration;
+use paketkoll_core::config::ConfigFiles;
+use paketkoll_core::file_ops;
+use paketkoll_types::files::FileEntry;
+use paketkoll_types::files::Gid;
+use paketkoll_types::files::Mode;
+use paketkoll_types::files::Properties;
+use paketkoll_types::files::Uid;
+use paketkoll_types::issue::IssueKind;
+use paketkoll_utils::MODE_MASK;
+use paketkoll_utils::confirm::Choices;
+use paketkoll_utils::confirm::MultiOptionConfirm;
+use proc_exit::Code;
+use proc_exit::Exit;
+use std::fmt::Display;
+use std::io::Write;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum PromptChoices {
+    Yes,
+    Abort,
+    Interactive,
+}
+
+impl Choices for PromptChoices {
+    fn options() -> &'static [(char, &'static str, Self)] {
+        &[
+            ('y', "Yes", Self::Yes),
+            ('a', "Abort", Self::Abort),
+            ('i', "Interactive (file by file)", Self::Interactive),
+        ]
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum InteractivePromptChoices {
+    Yes,
+    Abort,
+    Skip,
+    ShowDiff,
+}
+
+impl Choices for InteractivePromptChoices {
+    fn options() -> &'static [(char, &'static str, Self)] {
+        &[
+            ('y', "Yes", Self::Yes),
+            ('a', "Abort", Self::Abort),
+            ('s', "Skip", Self::Skip),
+            ('d', "show Diff", Self::ShowDiff),
+        ]
+    }
+}
+
+/// What needs to be done to restore a single file
+#[derive(Debug)]
+struct RestoreAction {
+    entry: FileEntry,
+    /// Recreate the file system entity (contents, symlink target, ...)
+    recreate: bool,
+    /// Set the permissions
+    mode: bool,
+    /// Set owner and group
+    owner: bool,
+}
+
+impl Display for RestoreAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if self.recreate {
+            parts.push(match &self.entry.properties {
+                Properties::Symlink(link) => format!("restore symlink to {:?}", link.target),
+                Properties::Directory(_) => "create directory".to_string(),
+                _ => "restore contents".to_string(),
+            });
+        }
+        if self.mode
+            && let Some(mode) = self.entry.properties.mode()
+        {
+            parts.push(format!("set mode {:04o}", mode.as_raw() & MODE_MASK));
+        }
+        if self.owner {
+            if let Some(owner) = self.entry.properties.owner() {
+                parts.push(format!("set owner {owner}"));
+            }
+            if let Some(group) = self.entry.properties.group() {
+                parts.push(format!("set group {group}"));
+            }
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+pub(crate) fn run_restore(
+    cli: &Cli,
+    paths: &[String],
+    all_issues: bool,
+    confirmation: Paranoia,
+) -> eyre::Result<Exit> {
+    let paths: Vec<PathBuf> = if all_issues {
+        issue_paths(cli)?
+    } else {
+        paths
+            .iter()
+            .map(std::path::absolute)
+            .collect::<Result<_, _>>()?
+    };
+    if paths.is_empty() {
+        println!("Nothing to restore");
+        return Ok(Exit::new(Code::SUCCESS));
+    }
+
+    let originals = OriginalFiles::new(cli)?;
+    let entries = originals.entries(&paths)?;
+    let actions = plan_actions(entries)?;
+    if actions.is_empty() {
+        println!("Nothing to restore");
+        return Ok(Exit::new(Code::SUCCESS));
+    }
+    let contents = originals.contents(
+        actions
+            .iter()
+            .filter(|a| a.recreate && a.entry.properties.is_regular_file() == Some(true))
+            .map(|a| &a.entry),
+    )?;
+
+    println!("Would restore:");
+    for action in &actions {
+        println!(" {}: {action}", action.entry.path.display());
+    }
+
+    match confirmation {
+        Paranoia::DryRun => return Ok(Exit::new(Code::SUCCESS)),
+        Paranoia::Yolo => {
+            for action in &actions {
+                apply_action(action, &contents)?;
+            }
+        }
+        Paranoia::Ask => {
+            let mut prompt_builder = MultiOptionConfirm::builder();
+            prompt_builder.prompt("Do you want to restore these files?");
+            match prompt_builder.build().prompt()? {
+                PromptChoices::Yes => {
+                    for action in &actions {
+                        apply_action(action, &contents)?;
+                    }
+                }
+                PromptChoices::Abort => eyre::bail!("User aborted"),
+                PromptChoices::Interactive => {
+                    let mut prompt_builder = MultiOptionConfirm::builder();
+                    prompt_builder.prompt("Restore this file?");
+                    let confirmer = prompt_builder.build();
+                    for action in &actions {
+                        interactive_apply(action, &contents, &confirmer)?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(Exit::new(Code::SUCCESS))
+}
+
+/// Run a check and collect the paths of all files with issues we can fix
+fn issue_paths(cli: &Cli) -> eyre::Result<Vec<PathBuf>> {
+    let (_interner, mut issues, _) = file_ops::check_installed_files(
+        cli.backend.try_into()?,
+        &cli.try_into()?,
+        &cli.try_into()?,
+    )?;
+    crate::remove_ignored_issues(&cli.ignore, &mut issues)?;
+    let mut paths: Vec<PathBuf> = issues
+        .into_iter()
+        .filter(|(_, issue)| issue.kinds().any(is_restorable))
+        .map(|(_, issue)| issue.path().to_path_buf())
+        .collect();
+    paths.sort();
+    paths.dedup();
+    Ok(paths)
+}
+
+const fn is_restorable(kind: &IssueKind) -> bool {
+    matches!(
+        kind,
+        IssueKind::Missing
+            | IssueKind::TypeIncorrect { .. }
+            | IssueKind::SizeIncorrect { .. }
+            | IssueKind::ChecksumIncorrect { .. }
+            | IssueKind::SymlinkTarget { .. }
+            | IssueKind::WrongOwner { .. }
+            | IssueKind::WrongGroup { .. }
+            | IssueKind::WrongMode { .. }
+    )
+}
+
+/// Compare entries to the file system and figure out what needs to be done
+fn plan_actions(entries: Vec<FileEntry>) -> eyre::Result<Vec<RestoreAction>> {
+    let mut entries: AHashMap<PathBuf, FileEntry> =
+        entries.into_iter().map(|e| (e.path.clone(), e)).collect();
+    let mut check_config = CommonFileCheckConfiguration::builder();
+    check_config.config_files(ConfigFiles::Include);
+    let check_config = check_config.build()?;
+    let file_entries: Vec<_> = entries.values().cloned().collect();
+    let mut issues = file_ops::check_file_entries(&file_entries, &check_config)?;
+    issues.sort_by(|(_, a), (_, b)| a.path().cmp(b.path()));
+
+    let mut actions = Vec::new();
+    for (_, issue) in issues {
+        let Some(entry) = entries.remove(issue.path()) else {
+            continue;
+        };
+        let mut action = RestoreAction {
+            entry,
+            recreate: false,
+            mode: false,
+            owner: false,
+        };
+        for kind in issue.kinds() {
+            match kind {
+                IssueKind::Missing
+                | IssueKind::TypeIncorrect { .. }
+                | IssueKind::SizeIncorrect { .. }
+                | IssueKind::ChecksumIncorrect { .. }
+                | IssueKind::SymlinkTarget { .. } => {
+                    // A recreated entry needs all metadata set as well
+                    action.recreate = true;
+                    action.mode = true;
+                    action.owner = true;
+                }
+                IssueKind::WrongMode { .. } => action.mode = true,
+                IssueKind::WrongOwner { .. } | IssueKind::WrongGroup { .. } => {
+                    action.owner = true;
+                }
+                _ => tracing::warn!("{}: Can not restore: {kind}", issue.path().display()),
+            }
+        }
+        if action.recreate || action.mode || action.owner {
+            actions.push(action);
+        }
+    }
+    Ok(actions)
+}
+
+fn interactive_apply(
+    action: &RestoreAction,
+    contents: &AHashMap<PathBuf, Vec<u8>>,
+    confirmer: &MultiOptionConfirm<InteractivePromptChoices>,
+) -> eyre::Result<()> {
+    println!(
+        "Under consideration: {} with change: {action}",
+        action.entry.path.display()
+    );
+    loop {
+        match confirmer.prompt()? {
+            InteractivePromptChoices::Yes => return apply_action(action, contents),
+            InteractivePromptChoices::Abort => eyre::bail!("User aborted"),
+            InteractivePromptChoices::Skip => {
+                tracing::info!("Skipping {}", action.entry.path.display());
+                return Ok(());
+            }
+            InteractivePromptChoices::ShowDiff => match contents.get(&action.entry.path) {
+                Some(original) => {
+                    diff_contents(&["diff", "-aur"], &action.entry.path, original)?;
+                }
+                None => println!("No content changes"),
+            },
+        }
+    }
+}
+
+fn apply_action(action: &RestoreAction, contents: &AHashMap<PathBuf, Vec<u8>>) -> eyre::Result<()> {
+    let path = &action.entry.path;
+    tracing::info!("Restoring {}: {action}", path.display());
+    if action.recreate {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).wrap_err("Failed to create parent directory")?;
+        }
+        match &action.entry.properties {
+            Properties::RegularFileBasic(_)
+            | Properties::RegularFileSystemd(_)
+            | Properties::RegularFile(_) => {
+                let data = contents
+                    .get(path)
+                    .ok_or_else(|| eyre::eyre!("No original contents for {path:?}"))?;
+                // This sets all the metadata as well
+                return replace_file(path, data, &action.entry.properties);
+            }
+            Properties::Symlink(link) => {
+                remove_unless(path, |_| false)?;
+                std::os::unix::fs::symlink(&link.target, path)
+                    .wrap_err("Failed to create symlink")?;
+            }
+            Properties::Directory(_) => {
+                remove_unless(path, std::fs::FileType::is_dir)?;
+                std::fs::create_dir_all(path).wrap_err("Failed to create directory")?;
+            }
+            _ => eyre::bail!(
+                "Restoring entries of type {} is not supported ({path:?})",
+                action.entry.properties.type_name()
+            ),
+        }
+    }
+    // Owner first, as changing the owner clears setuid/setgid bits
+    if action.owner {
+        std::os::unix::fs::lchown(
+            path,
+            action.entry.properties.owner().map(Uid::as_raw),
+            action.entry.properties.group().map(Gid::as_raw),
+        )
+        .wrap_err("Failed to set owner")?;
+    }
+    if action.mode
+        && !matches!(action.entry.properties, Properties::Symlink(_))
+        && let Some(mode) = action.entry.properties.mode()
+    {
+        std::fs::set_permissions(
+            path,
+            std::fs::Permissions::from_mode(mode.as_raw() & MODE_MASK),
+        )
+        .wrap_err("Failed to set mode")?;
+    }
+    Ok(())
+}
+
+/// Atomically replace the file at `path` with `data`
+///
+/// The data is written to a temporary file in the same directory, which gets
+/// its owner and mode set and is synced to disk before being renamed over
+/// `path`. That way the file never has partial contents or the wrong
+/// permissions, and running executables can be replaced.
+///
+/// Metadata the package manager doesn't know about is taken from the
+/// existing file (if any).
+fn replace_file(path: &Path, data: &[u8], properties: &Properties) -> eyre::Result<()> {
+    // Refuse to replace directories, anything else gets replaced by the rename
+    remove_unless(path, |file_type| !file_type.is_dir())?;
+    let existing = match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_file() => Some(metadata),
+        Ok(_) => None,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err).wrap_err("Failed to get metadata"),
+    };
+    let owner = properties
+        .owner()
+        .map(Uid::as_raw)
+        .or_else(|| existing.as_ref().map(MetadataExt::uid));
+    let group = properties
+        .group()
+        .map(Gid::as_raw)
+        .or_else(|| existing.as_ref().map(MetadataExt::gid));
+    let mode = properties
+        .mode()
+        .map(Mode::as_raw)
+        .or_else(|| existing.as_ref().map(MetadataExt::mode))
+        .unwrap_or(0o644)
+        & MODE_MASK;
+
+    let dir = path
+        .parent()
+        .ok_or_else(|| eyre::eyre!("No parent directory for {path:?}"))?;
+    let mut tmp = tempfile::Builder::new()
+        .prefix(".paketkoll-restore")
+        .tempfile_in(dir)
+        .wrap_err_with(|| format!("Failed to create temporary file in {dir:?}"))?;
+    tmp.write_all(data).wrap_err("Failed to write file data")?;
+    let file = tmp.as_file();
+    // Owner first, as changing the owner clears setuid/setgid bits
+    std::os::unix::fs::fchown(file, owner, group).wrap_err("Failed to set owner")?;
+    file.set_permissions(std::fs::Permissions::from_mode(mode))
+        .wrap_err("Failed to set mode")?;
+    file.sync_all().wrap_err("Failed to sync file data")?;
+    tmp.persist(path)
+        .wrap_err_with(|| format!("Failed to replace {path:?}"))?;
+    Ok(())
+}
+
+/// Remove whatever is at `path`, unless it is of the type we want to keep.
+///
+/// Directories are never removed (they may contain things we don't know
+/// about), that is an error instead.
+fn remove_unless(path: &Path, keep: impl Fn(&std::fs::FileType) -> bool) -> eyre::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if keep(&metadata.file_type()) => Ok(()),
+        Ok(metadata) if metadata.is_dir() => {
+            eyre::bail!("Refusing to replace directory {path:?}, remove it manually first")
+        }
+        Ok(_) => std::fs::remove_file(path).wrap_err("Failed to remove existing entry"),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err).wrap_err("Failed to get metadata"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paketkoll_types::files::FileFlags;
+    use paketkoll_types::files::RegularFile;
+    use paketkoll_types::files::Symlink;
+    use pretty_assertions::assert_eq;
+    use std::time::SystemTime;
+
+    /// Expected entries for files in `dir`, owned by the current user
+    struct Fixture {
+        dir: tempfile::TempDir,
+        owner: Uid,
+        group: Gid,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let metadata = std::fs::metadata(dir.path()).unwrap();
+            Self {
+                owner: Uid::new(metadata.uid()),
+                group: Gid::new(metadata.gid()),
+                dir,
+            }
+        }
+
+        fn path(&self, name: &str) -> PathBuf {
+            self.dir.path().join(name)
+        }
+
+        fn entry(&self, name: &str, properties: Properties) -> FileEntry {
+            FileEntry {
+                package: None,
+                path: self.path(name),
+                properties,
+                flags: FileFlags::empty(),
+                source: "test",
+                seen: Default::default(),
+            }
+        }
+
+        fn file(&self, name: &str, contents: &[u8], mode: u32) -> FileEntry {
+            self.entry(
+                name,
+                Properties::RegularFile(RegularFile {
+                    mode: Mode::new(mode),
+                    owner: self.owner,
+                    group: self.group,
+                    size: contents.len() as u64,
+                    mtime: SystemTime::UNIX_EPOCH,
+                    checksum: paketkoll_utils::checksum::sha256_buffer(contents),
+                }),
+            )
+        }
+
+        fn symlink(&self, name: &str, target: &str) -> FileEntry {
+            self.entry(
+                name,
+                Properties::Symlink(Symlink {
+                    owner: self.owner,
+                    group: self.group,
+                    target: target.into(),
+                }),
+            )
+        }
+
+        fn write(&self, name: &str, contents: &[u8], mode: u32) {
+            let path = self.path(name);
+            std::fs::write(&path, contents).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+        }
+
+        fn mode(&self, name: &str) -> u32 {
+            std::fs::symlink_metadata(self.path(name)).unwrap().mode() & MODE_MASK
+        }
+    }
+
+    /// Summarise actions as (name, recreate, mode, owner)
+    fn summary(actions: &[RestoreAction]) -> Vec<(String, bool, bool, bool)> {
+        actions
+            .iter()
+            .map(|action| {
+                let name = action.entry.path.file_name().unwrap();
+                (
+                    name.to_string_lossy().into_owned(),
+                    action.recreate,
+                    action.mode,
+                    action.owner,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_plan_actions() {
+        let fixture = Fixture::new();
+        fixture.write("changed", b"modified\n", 0o644);
+        fixture.write("mode", b"original\n", 0o600);
+        fixture.write("same", b"original\n", 0o644);
+        std::os::unix::fs::symlink("elsewhere", fixture.path("link")).unwrap();
+
+        let actions = plan_actions(vec![
+            fixture.file("changed", b"original\n", 0o644),
+            fixture.file("missing", b"original\n", 0o644),
+            fixture.file("mode", b"original\n", 0o644),
+            fixture.file("same", b"original\n", 0o644),
+            fixture.symlink("link", "target"),
+        ])
+        .unwrap();
+        assert_eq!(
+            summary(&actions),
+            vec![
+                ("changed".into(), true, true, true),
+                ("link".into(), true, true, true),
+                ("missing".into(), true, true, true),
+                ("mode".into(), false, true, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_apply_actions() {
+        let fixture = Fixture::new();
+        fixture.write("changed", b"modified\n", 0o644);
+        fixture.write("mode", b"original\n", 0o600);
+        std::os::unix::fs::symlink("elsewhere", fixture.path("link")).unwrap();
+        std::fs::create_dir(fixture.path("dir")).unwrap();
+
+        let actions = plan_actions(vec![
+            fixture.file("changed", b"original\n", 0o4755),
+            fixture.file("missing", b"original\n", 0o640),
+            fixture.file("mode", b"original\n", 0o644),
+            fixture.symlink("link", "target"),
+        ])
+        .unwrap();
+        let contents: AHashMap<PathBuf, Vec<u8>> = ["changed", "missing"]
+            .into_iter()
+            .map(|name| (fixture.path(name), b"original\n".to_vec()))
+            .collect();
+        for action in &actions {
+            apply_action(action, &contents).unwrap();
+        }
+
+        for name in ["changed", "missing", "mode"] {
+            assert_eq!(std::fs::read(fixture.path(name)).unwrap(), b"original\n");
+        }
+        assert_eq!(fixture.mode("changed"), 0o4755);
+        assert_eq!(fixture.mode("missing"), 0o640);
+        assert_eq!(fixture.mode("mode"), 0o644);
+        assert_eq!(
+            std::fs::read_link(fixture.path("link")).unwrap(),
+            Path::new("target")
+        );
+        // Nothing is left to restore, and no temporary files are left behind
+        let actions = plan_actions(vec![
+            fixture.file("changed", b"original\n", 0o4755),
+            fixture.file("missing", b"original\n", 0o640),
+            fixture.file("mode", b"original\n", 0o644),
+            fixture.symlink("link", "target"),
+        ])
+        .unwrap();
+        assert_eq!(summary(&actions), vec![]);
+        assert_eq!(std::fs::read_dir(fixture.dir.path()).unwrap().count(), 5);
+
+        // Directories are not replaced by files
+        let action = RestoreAction {
+            entry: fixture.file("dir", b"original\n", 0o644),
+            recreate: true,
+            mode: true,
+            owner: true,
+        };
+        let contents = AHashMap::from_iter([(fixture.path("dir"), b"original\n".to_vec())]);
+        assert!(apply_action(&action, &contents).is_err());
+        assert!(fixture.path("dir").is_dir());
+    }
+}
@@ -0,0 +1,944 @@
+//! Generate software bills of materials (SBOM) in `CycloneDX` or SPDX format
+
+use ahash::AHashMap;
+use eyre::WrapErr;
+use paketkoll::cli::Cli;
+use paketkoll::cli::ConfigFiles;
+use paketkoll::cli::SbomFormat;
+use paketkoll_core::backend::ConcreteBackend;
+use paketkoll_core::file_ops;
+use paketkoll_core::time;
+use paketkoll_types::files::Checksum;
+use paketkoll_types::files::FileEntry;
+use paketkoll_types::files::FileFlags;
+use paketkoll_types::intern::Interner;
+use paketkoll_types::intern::PackageRef;
+use paketkoll_types::issue::IssueKind;
+use paketkoll_types::issue::PackageIssue;
+use paketkoll_types::package::Dependency;
+use paketkoll_types::package::InstallReason;
+use paketkoll_types::package::PackageInterned;
+use proc_exit::Code;
+use proc_exit::Exit;
+use serde_json::Value;
+use serde_json::json;
+use std::fmt::Write as _;
+use std::io::BufWriter;
+use std::io::Write;
+use std::io::stdout;
+use std::path::Path;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+/// Prefix for our properties / annotations
+const PROPERTY_PREFIX: &str = "paketkoll";
+
+/// A package with everything that goes into the SBOM for it
+struct Component<'a> {
+    package: &'a PackageInterned,
+    purl: String,
+    /// Indices of the components this one depends on
+    depends: Vec<usize>,
+    files: Vec<ComponentFile<'a>>,
+    /// Number of issues found, if a check was run
+    issues: Option<usize>,
+}
+
+/// Checksum of a file, as algorithm names and hex digest
+struct FileChecksum {
+    cyclonedx_algorithm: &'static str,
+    spdx_algorithm: &'static str,
+    digest: String,
+}
+
+/// A file of a package
+struct ComponentFile<'a> {
+    path: &'a Path,
+    checksum: FileChecksum,
+    /// Kinds of issues found, if a check was run
+    issues: Option<Vec<&'static str>>,
+}
+
+pub(crate) fn run_sbom(
+    cli: &Cli,
+    format: SbomFormat,
+    with_files: bool,
+    verify: bool,
+) -> eyre::Result<Exit> {
+    let interner = Interner::new();
+    let backend: ConcreteBackend = cli.backend.try_into()?;
+    let backend_cfg = cli.try_into()?;
+    let mut packages = backend
+        .create_packages(&backend_cfg, &interner)
+        .wrap_err_with(|| format!("Failed to create backend {backend}"))?
+        .packages(&interner)
+        .wrap_err_with(|| format!("Failed to collect information from backend {backend}"))?;
+    packages.sort_by(|a, b| {
+        (
+            a.name.as_str(&interner),
+            a.architecture.and_then(|e| e.try_as_str(&interner)),
+        )
+            .cmp(&(
+                b.name.as_str(&interner),
+                b.architecture.and_then(|e| e.try_as_str(&interner)),
+            ))
+    });
+    let mut files = if with_files || verify {
+        backend
+            .create_files(&backend_cfg, &interner)
+            .wrap_err_with(|| format!("Failed to create backend {backend}"))?
+            .files(&interner)
+            .wrap_err_with(|| format!("Failed to collect information from backend {backend}"))?
+    } else {
+        vec![]
+    };
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    let issues = if verify {
+        let mut issues = file_ops::check_file_entries(&files, &cli.try_into()?)?;
+        crate::remove_ignored_issues(&cli.ignore, &mut issues)?;
+        Some(issues)
+    } else {
+        None
+    };
+
+    let distro = crate::os_release("ID");
+    let components = build_components(
+        backend,
+        distro.as_deref(),
+        &packages,
+        with_files.then_some(files.as_slice()),
+        issues.as_deref(),
+        cli.config_files,
+        &interner,
+    );
+    let now = SystemTime::now();
+    let document = match format {
+        SbomFormat::Cyclonedx => cyclonedx(&components, &interner, now),
+        SbomFormat::Spdx => spdx(&components, &interner, backend, now),
+    };
+    let mut stdout = BufWriter::new(stdout().lock());
+    serde_json::to_writer_pretty(&mut stdout, &document)?;
+    writeln!(stdout)?;
+    stdout.flush()?;
+    Ok(Exit::new(Code::SUCCESS))
+}
+
+/// Build the components for packages (sorted by name), with dependencies
+/// resolved to installed packages, and the files if given
+///
+/// The issues are from a check, if one was run.
+fn build_components<'a>(
+    backend: ConcreteBackend,
+    distro: Option<&str>,
+    packages: &'a [PackageInterned],
+    files: Option<&'a [FileEntry]>,
+    issues: Option<&[PackageIssue]>,
+    config_files: ConfigFiles,
+    interner: &Interner,
+) -> Vec<Component<'a>> {
+    // Collect per package and per file issues
+    let mut package_issues: AHashMap<PackageRef, usize> = AHashMap::new();
+    let mut file_issues: AHashMap<&Path, Vec<&'static str>> = AHashMap::new();
+    for (package, issue) in issues.unwrap_or_default() {
+        if let Some(package) = package {
+            *package_issues.entry(*package).or_default() += 1;
+        }
+        file_issues
+            .entry(issue.path())
+            .or_default()
+            .extend(issue.kinds().map(IssueKind::name));
+    }
+
+    let mut components: Vec<Component<'_>> = packages
+        .iter()
+        .map(|package| Component {
+            package,
+            purl: purl(backend, distro, package, interner),
+            depends: vec![],
+            files: vec![],
+            issues: issues.map(|_| {
+                std::iter::once(&package.name)
+                    .chain(&package.ids)
+                    .filter_map(|id| package_issues.get(id))
+                    .sum()
+            }),
+        })
+        .collect();
+
+    // Map all names a package can be referred to by to the component. Real
+    // names take priority over provided names.
+    let mut lookup: AHashMap<PackageRef, usize> = AHashMap::new();
+    for (idx, package) in packages.iter().enumerate() {
+        for id in &package.ids {
+            lookup.insert(*id, idx);
+        }
+        lookup.entry(package.name).or_insert(idx);
+    }
+    let owners = lookup.clone();
+    for (idx, package) in packages.iter().enumerate() {
+        for provided in &package.provides {
+            lookup.entry(*provided).or_insert(idx);
+        }
+    }
+
+    for (component, package) in components.iter_mut().zip(packages) {
+        // For alternatives, use the first one that is installed
+        component.depends = package
+            .depends
+            .iter()
+            .filter_map(|dependency| match dependency {
+                Dependency::Single(pkg) => lookup.get(pkg).copied(),
+                Dependency::Disjunction(pkgs) => pkgs.iter().find_map(|p| lookup.get(p).copied()),
+            })
+            .collect();
+        component.depends.sort_unstable();
+        component.depends.dedup();
+    }
+    for entry in files.unwrap_or_default() {
+        let (Some(package), Some(checksum)) = (
+            entry.package,
+            entry.properties.checksum().and_then(file_checksum),
+        ) else {
+            continue;
+        };
+        if let Some(&idx) = owners.get(&package) {
+            components[idx].files.push(ComponentFile {
+                path: &entry.path,
+                checksum,
+                issues: issues.filter(|_| is_checked(config_files, entry)).map(|_| {
+                    file_issues
+                        .get(entry.path.as_path())
+                        .cloned()
+                        .unwrap_or_default()
+                }),
+            });
+        }
+    }
+    components
+}
+
+/// Generate a `CycloneDX` 1.6 document
+fn cyclonedx(components: &[Component<'_>], interner: &Interner, created: SystemTime) -> Value {
+    let property = |name: &str, value: &str| json!({"name": format!("{PROPERTY_PREFIX}:{name}"), "value": value});
+    let bom_components: Vec<Value> = components
+        .iter()
+        .map(|component| {
+            let package = component.package;
+            let mut properties = vec![];
+            if let Some(arch) = package.architecture.and_then(|a| a.try_as_str(interner)) {
+                properties.push(property("architecture", arch));
+            }
+            if let Some(reason) = package.reason {
+                properties.push(property("install_reason", reason_name(reason)));
+            }
+            if let Some(issues) = component.issues {
+                properties.push(property("verification", verification_status(issues)));
+                properties.push(property("issue_count", &issues.to_string()));
+            }
+            let files: Vec<Value> = component
+                .files
+                .iter()
+                .map(|file| {
+                    let mut value = json!({
+                        "type": "file",
+                        "bom-ref": format!("{}#{}", component.purl, file.path.display()),
+                        "name": file.path.to_string_lossy(),
+                        "hashes": [{
+                            "alg": file.checksum.cyclonedx_algorithm,
+                            "content": file.checksum.digest,
+                        }],
+                    });
+                    if let Some(ref issues) = file.issues {
+                        let mut status =
+                            vec![property("verification", verification_status(issues.len()))];
+                        if !issues.is_empty() {
+                            status.push(property("issues", &issues.join(",")));
+                        }
+                        value["properties"] = json!(status);
+                    }
+                    value
+                })
+                .collect();
+
+            let mut value = json!({
+                "type": "library",
+                "bom-ref": component.purl,
+                "name": package.name.as_str(interner),
+                "version": package.version,
+                "purl": component.purl,
+            });
+            if let Some(ref desc) = package.desc {
+                value["description"] = json!(desc);
+            }
+            if !properties.is_empty() {
+                value["properties"] = json!(properties);
+            }
+            if !files.is_empty() {
+                value["components"] = json!(files);
+            }
+            value
+        })
+        .collect();
+    let dependencies: Vec<Value> = components
+        .iter()
+        .map(|component| {
+            json!({
+                "ref": component.purl,
+                "dependsOn": component
+                    .depends
+                    .iter()
+                    .map(|&idx| &components[idx].purl)
+                    .collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.6",
+        "version": 1,
+        "metadata": {
+            "timestamp": format_timestamp(created),
+            "tools": {
+                "components": [{
+                    "type": "application",
+                    "name": "paketkoll",
+                    "version": env!("CARGO_PKG_VERSION"),
+                }],
+            },
+        },
+        "components": bom_components,
+        "dependencies": dependencies,
+    })
+}
+
+/// Generate an SPDX 2.3 document
+///
+/// SPDX wants a SHA1 checksum for every file, which no backend provides. We
+/// only include the checksums the package manager has.
+fn spdx(
+    components: &[Component<'_>],
+    interner: &Interner,
+    backend: ConcreteBackend,
+    created: SystemTime,
+) -> Value {
+    let timestamp = format_timestamp(created);
+    let annotator = format!("Tool: paketkoll-{}", env!("CARGO_PKG_VERSION"));
+    let annotation = |name: &str, value: &str| {
+        json!({
+            "annotationType": "REVIEW",
+            "annotator": annotator,
+            "annotationDate": timestamp,
+            "comment": format!("{PROPERTY_PREFIX}:{name}={value}"),
+        })
+    };
+    let package_id = |idx: usize| format!("SPDXRef-Package-{idx}");
+
+    let mut packages = vec![];
+    let mut files = vec![];
+    let mut relationships = vec![];
+    for (idx, component) in components.iter().enumerate() {
+        let package = component.package;
+        let mut value = json!({
+            "name": package.name.as_str(interner),
+            "SPDXID": package_id(idx),
+            "versionInfo": package.version,
+            "downloadLocation": "NOASSERTION",
+            "filesAnalyzed": false,
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": "NOASSERTION",
+            "copyrightText": "NOASSERTION",
+            "externalRefs": [{
+                "referenceCategory": "PACKAGE-MANAGER",
+                "referenceType": "purl",
+                "referenceLocator": component.purl,
+            }],
+        });
+        if let Some(ref desc) = package.desc {
+            value["summary"] = json!(desc);
+        }
+        if let Some(issues) = component.issues {
+            value["annotations"] = json!([
+                annotation("verification", verification_status(issues)),
+                annotation("issue_count", &issues.to_string()),
+            ]);
+        }
+        packages.push(value);
+        relationships.push(json!({
+            "spdxElementId": "SPDXRef-DOCUMENT",
+            "relationshipType": "DESCRIBES",
+            "relatedSpdxElement": package_id(idx),
+        }));
+        for &dependency in &component.depends {
+            relationships.push(json!({
+                "spdxElementId": package_id(idx),
+                "relationshipType": "DEPENDS_ON",
+                "relatedSpdxElement": package_id(dependency),
+            }));
+        }
+        for file in &component.files {
+            let file_id = format!("SPDXRef-File-{}", files.len());
+            let mut value = json!({
+                "fileName": format!(".{}", file.path.display()),
+                "SPDXID": file_id,
+                "checksums": [{
+                    "algorithm": file.checksum.spdx_algorithm,
+                    "checksumValue": file.checksum.digest,
+                }],
+                "licenseConcluded": "NOASSERTION",
+                "copyrightText": "NOASSERTION",
+            });
+            if let Some(ref issues) = file.issues {
+                let mut status = vec![annotation(
+                    "verification",
+                    verification_status(issues.len()),
+                )];
+                if !issues.is_empty() {
+                    status.push(annotation("issues", &issues.join(",")));
+                }
+                value["annotations"] = json!(status);
+            }
+            files.push(value);
+            relationships.push(json!({
+                "spdxElementId": package_id(idx),
+                "relationshipType": "CONTAINS",
+                "relatedSpdxElement": file_id,
+            }));
+        }
+    }
+
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": format!("paketkoll-{backend}"),
+        "documentNamespace": format!(
+            "https://github.com/VorpalBlade/paketkoll/spdx/{backend}-{}",
+            created
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos())
+        ),
+        "creationInfo": {
+            "created": timestamp,
+            "creators": [annotator],
+        },
+        "packages": packages,
+        "files": files,
+        "relationships": relationships,
+    })
+}
+
+/// Build a package URL (purl) for a package
+fn purl(
+    backend: ConcreteBackend,
+    distro: Option<&str>,
+    package: &PackageInterned,
+    interner: &Interner,
+) -> String {
+    let (purl_type, namespace, name) = match backend {
+        #[cfg(feature = "arch_linux")]
+        ConcreteBackend::Pacman => (
+            "alpm",
+            Some(distro.unwrap_or("arch")),
+            package.name.as_str(interner),
+        ),
+        #[cfg(feature = "debian")]
+        ConcreteBackend::Apt => (
+            "deb",
+            Some(distro.unwrap_or("debian")),
+            package.name.as_str(interner),
+        ),
+        ConcreteBackend::Flatpak => ("flatpak", None, package.canonical_id().as_str(interner)),
+        #[cfg(feature = "systemd_tmpfiles")]
+        ConcreteBackend::SystemdTmpfiles => ("generic", None, package.name.as_str(interner)),
+    };
+    let mut purl = format!("pkg:{purl_type}/");
+    if let Some(namespace) = namespace {
+        percent_encode(namespace, &mut purl);
+        purl.push('/');
+    }
+    percent_encode(name, &mut purl);
+    if !package.version.is_empty() {
+        purl.push('@');
+        percent_encode(&package.version, &mut purl);
+    }
+    if let Some(arch) = package.architecture.and_then(|a| a.try_as_str(interner)) {
+        purl.push_str("?arch=");
+        percent_encode(arch, &mut purl);
+    }
+    purl
+}
+
+/// Percent encode a purl component (everything except unreserved characters)
+fn percent_encode(input: &str, output: &mut String) {
+    for byte in input.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            output.push(char::from(byte));
+        } else {
+            write!(output, "%{byte:02X}").expect("Writing to a String can't fail");
+        }
+    }
+}
+
+fn file_checksum(checksum: &Checksum) -> Option<FileChecksum> {
+    let (cyclonedx_algorithm, spdx_algorithm, digest) = match checksum {
+        Checksum::Md5(value) => ("MD5", "MD5", faster_hex::hex_string(value)),
+        Checksum::Sha256(value) => ("SHA-256", "SHA256", faster_hex::hex_string(value)),
+        _ => return None,
+    };
+    Some(FileChecksum {
+        cyclonedx_algorithm,
+        spdx_algorithm,
+        digest,
+    })
+}
+
+/// Check if a file is included in checks with the configured --config-files
+fn is_checked(config_files: ConfigFiles, entry: &FileEntry) -> bool {
+    let is_config = entry.flags.contains(FileFlags::CONFIG);
+    match config_files {
+        ConfigFiles::Include => true,
+        ConfigFiles::Exclude => !is_config,
+        ConfigFiles::Only => is_config,
+    }
+}
+
+fn reason_name(reason: InstallReason) -> &'static str {
+    match reason {
+        InstallReason::Explicit => "explicit",
+        InstallReason::Dependency => "dependency",
+    }
+}
+
+fn verification_status(issues: usize) -> &'static str {
+    if issues == 0 { "passed" } else { "failed" }
+}
+
+/// Format a time as an RFC 3339 UTC timestamp (with second precision)
+fn format_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    time::format_rfc3339_utc(i64::try_from(secs).unwrap_or(i64::MAX))
+}
+
+#[cfg(all(test, feature = "debian"))]
+mod tests {
+    use super::*;
+    use paketkoll_types::files::Properties;
+    use paketkoll_types::files::RegularFileBasic;
+    use paketkoll_types::intern::ArchitectureRef;
+    use paketkoll_types::issue::Issue;
+    use paketkoll_types::package::PackageInstallStatus;
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    /// 2024-05-20T12:00:00Z
+    const CREATED: Duration = Duration::from_secs(1_716_206_400);
+
+    struct Fixture {
+        interner: Interner,
+        packages: Vec<PackageInterned>,
+        files: Vec<FileEntry>,
+        issues: Vec<PackageIssue>,
+    }
+
+    /// Two packages, where foo depends on bar through a virtual package
+    fn fixture() -> Fixture {
+        let interner = Interner::new();
+        let pkg = |name: &str| PackageRef::get_or_intern(&interner, name);
+        let arch = |name: &str| Some(ArchitectureRef::get_or_intern(&interner, name));
+        let packages = vec![
+            PackageInterned {
+                name: pkg("bar"),
+                architecture: arch("all"),
+                version: "1.0+dfsg-2".into(),
+                desc: None,
+                depends: vec![],
+                provides: vec![pkg("virtual-bar")],
+                reason: Some(InstallReason::Dependency),
+                status: PackageInstallStatus::Installed,
+                ids: smallvec::smallvec![pkg("bar:all")],
+            },
+            PackageInterned {
+                name: pkg("foo"),
+                architecture: arch("amd64"),
+                version: "1:2.0~rc1-1".into(),
+                desc: Some("The foo tool".into()),
+                depends: vec![
+                    Dependency::Single(pkg("not-installed")),
+                    Dependency::Disjunction(vec![pkg("also-not-installed"), pkg("virtual-bar")]),
+                ],
+                provides: vec![],
+                reason: Some(InstallReason::Explicit),
+                status: PackageInstallStatus::Installed,
+                ids: smallvec::smallvec![pkg("foo:amd64")],
+            },
+        ];
+        let file = |package: &str, path: &str, checksum: Checksum, flags: FileFlags| FileEntry {
+            package: Some(pkg(package)),
+            path: path.into(),
+            properties: Properties::RegularFileBasic(RegularFileBasic {
+                size: None,
+                checksum,
+            }),
+            flags,
+            source: "test",
+            seen: Default::default(),
+        };
+        let files = vec![
+            file(
+                "bar:all",
+                "/etc/bar.conf",
+                Checksum::Md5([0xab; 16]),
+                FileFlags::CONFIG,
+            ),
+            file(
+                "foo:amd64",
+                "/usr/bin/foo",
+                Checksum::Md5([0x12; 16]),
+                FileFlags::empty(),
+            ),
+        ];
+        let issues = vec![(
+            Some(pkg("foo:amd64")),
+            Issue::new(
+                PathBuf::from("/usr/bin/foo"),
+                smallvec::smallvec![IssueKind::ChecksumIncorrect {
+                    actual: Checksum::Md5([0x34; 16]),
+                    expected: Checksum::Md5([0x12; 16]),
+                }],
+                None,
+            ),
+        )];
+        Fixture {
+            interner,
+            packages,
+            files,
+            issues,
+        }
+    }
+
+    #[test]
+    fn test_purl() {
+        let Fixture {
+            interner, packages, ..
+        } = fixture();
+        let purls: Vec<_> = packages
+            .iter()
+            .map(|package| purl(ConcreteBackend::Apt, Some("debian"), package, &interner))
+            .collect();
+        assert_eq!(
+            purls,
+            vec![
+                "pkg:deb/debian/bar@1.0%2Bdfsg-2?arch=all",
+                "pkg:deb/debian/foo@1%3A2.0~rc1-1?arch=amd64",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cyclonedx() {
+        let fixture = fixture();
+        let components = build_components(
+            ConcreteBackend::Apt,
+            Some("debian"),
+            &fixture.packages,
+            Some(&fixture.files),
+            Some(&fixture.issues),
+            ConfigFiles::Exclude,
+            &fixture.interner,
+        );
+        let document = cyclonedx(&components, &fixture.interner, UNIX_EPOCH + CREATED);
+        let version = env!("CARGO_PKG_VERSION");
+        assert_eq!(
+            document,
+            json!({
+                "bomFormat": "CycloneDX",
+                "components": [
+                    {
+                        "bom-ref": "pkg:deb/debian/bar@1.0%2Bdfsg-2?arch=all",
+                        "components": [
+                            {
+                                "bom-ref": "pkg:deb/debian/bar@1.0%2Bdfsg-2?arch=all#/etc/bar.conf",
+                                "hashes": [
+                                    {
+                                        "alg": "MD5",
+                                        "content": "abababababababababababababababab"
+                                    }
+                                ],
+                                "name": "/etc/bar.conf",
+                                "type": "file"
+                            }
+                        ],
+                        "name": "bar",
+                        "properties": [
+                            {
+                                "name": "paketkoll:architecture",
+                                "value": "all"
+                            },
+                            {
+                                "name": "paketkoll:install_reason",
+                                "value": "dependency"
+                            },
+                            {
+                                "name": "paketkoll:verification",
+                                "value": "passed"
+                            },
+                            {
+                                "name": "paketkoll:issue_count",
+                                "value": "0"
+                            }
+                        ],
+                        "purl": "pkg:deb/debian/bar@1.0%2Bdfsg-2?arch=all",
+                        "type": "library",
+                        "version": "1.0+dfsg-2"
+                    },
+                    {
+                        "bom-ref": "pkg:deb/debian/foo@1%3A2.0~rc1-1?arch=amd64",
+                        "components": [
+                            {
+                                "bom-ref": "pkg:deb/debian/foo@1%3A2.0~rc1-1?arch=amd64#/usr/bin/foo",
+                                "hashes": [
+                                    {
+                                        "alg": "MD5",
+                                        "content": "12121212121212121212121212121212"
+                                    }
+                                ],
+                                "name": "/usr/bin/foo",
+                                "properties": [
+                                    {
+                                        "name": "paketkoll:verification",
+                                        "value": "failed"
+                                    },
+                                    {
+                                        "name": "paketkoll:issues",
+                                        "value": "checksum_incorrect"
+                                    }
+                                ],
+                                "type": "file"
+                            }
+                        ],
+                        "description": "The foo tool",
+                        "name": "foo",
+                        "properties": [
+                            {
+                                "name": "paketkoll:architecture",
+                                "value": "amd64"
+                            },
+                            {
+                                "name": "paketkoll:install_reason",
+                                "value": "explicit"
+                            },
+                            {
+                                "name": "paketkoll:verification",
+                                "value": "failed"
+                            },
+                            {
+                                "name": "paketkoll:issue_count",
+                                "value": "1"
+                            }
+                        ],
+                        "purl": "pkg:deb/debian/foo@1%3A2.0~rc1-1?arch=amd64",
+                        "type": "library",
+                        "version": "1:2.0~rc1-1"
+                    }
+                ],
+                "dependencies": [
+                    {
+                        "dependsOn": [],
+                        "ref": "pkg:deb/debian/bar@1.0%2Bdfsg-2?arch=all"
+                    },
+                    {
+                        "dependsOn": [
+                            "pkg:deb/debian/bar@1.0%2Bdfsg-2?arch=all"
+                        ],
+                        "ref": "pkg:deb/debian/foo@1%3A2.0~rc1-1?arch=amd64"
+                    }
+                ],
+                "metadata": {
+                    "timestamp": "2024-05-20T12:00:00Z",
+                    "tools": {
+                        "components": [
+                            {
+                                "name": "paketkoll",
+                                "type": "application",
+                                "version": version
+                            }
+                        ]
+                    }
+                },
+                "specVersion": "1.6",
+                "version": 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_spdx() {
+        let fixture = fixture();
+        let components = build_components(
+            ConcreteBackend::Apt,
+            Some("debian"),
+            &fixture.packages,
+            Some(&fixture.files),
+            Some(&fixture.issues),
+            ConfigFiles::Exclude,
+            &fixture.interner,
+        );
+        let document = spdx(
+            &components,
+            &fixture.interner,
+            ConcreteBackend::Apt,
+            UNIX_EPOCH + CREATED,
+        );
+        let annotator = format!("Tool: paketkoll-{}", env!("CARGO_PKG_VERSION"));
+        assert_eq!(
+            document,
+            json!({
+                "SPDXID": "SPDXRef-DOCUMENT",
+                "creationInfo": {
+                    "created": "2024-05-20T12:00:00Z",
+                    "creators": [
+                        annotator
+                    ]
+                },
+                "dataLicense": "CC0-1.0",
+                "documentNamespace": "https://github.com/VorpalBlade/paketkoll/spdx/apt-1716206400000000000",
+                "files": [
+                    {
+                        "SPDXID": "SPDXRef-File-0",
+                        "checksums": [
+                            {
+                                "algorithm": "MD5",
+                                "checksumValue": "abababababababababababababababab"
+                            }
+                        ],
+                        "copyrightText": "NOASSERTION",
+                        "fileName": "./etc/bar.conf",
+                        "licenseConcluded": "NOASSERTION"
+                    },
+                    {
+                        "SPDXID": "SPDXRef-File-1",
+                        "annotations": [
+                            {
+                                "annotationDate": "2024-05-20T12:00:00Z",
+                                "annotationType": "REVIEW",
+                                "annotator": annotator,
+                                "comment": "paketkoll:verification=failed"
+                            },
+                            {
+                                "annotationDate": "2024-05-20T12:00:00Z",
+                                "annotationType": "REVIEW",
+                                "annotator": annotator,
+                                "comment": "paketkoll:issues=checksum_incorrect"
+                            }
+                        ],
+                        "checksums": [
+                            {
+                                "algorithm": "MD5",
+                                "checksumValue": "12121212121212121212121212121212"
+                            }
+                        ],
+                        "copyrightText": "NOASSERTION",
+                        "fileName": "./usr/bin/foo",
+                        "licenseConcluded": "NOASSERTION"
+                    }
+                ],
+                "name": "paketkoll-apt",
+                "packages": [
+                    {
+                        "SPDXID": "SPDXRef-Package-0",
+                        "annotations": [
+                            {
+                                "annotationDate": "2024-05-20T12:00:00Z",
+                                "annotationType": "REVIEW",
+                                "annotator": annotator,
+                                "comment": "paketkoll:verification=passed"
+                            },
+                            {
+                                "annotationDate": "2024-05-20T12:00:00Z",
+                                "annotationType": "REVIEW",
+                                "annotator": annotator,
+                                "comment": "paketkoll:issue_count=0"
+                            }
+                        ],
+                        "copyrightText": "NOASSERTION",
+                        "downloadLocation": "NOASSERTION",
+                        "externalRefs": [
+                            {
+                                "referenceCategory": "PACKAGE-MANAGER",
+                                "referenceLocator": "pkg:deb/debian/bar@1.0%2Bdfsg-2?arch=all",
+                                "referenceType": "purl"
+                            }
+                        ],
+                        "filesAnalyzed": false,
+                        "licenseConcluded": "NOASSERTION",
+                        "licenseDeclared": "NOASSERTION",
+                        "name": "bar",
+                        "versionInfo": "1.0+dfsg-2"
+                    },
+                    {
+                        "SPDXID": "SPDXRef-Package-1",
+                        "annotations": [
+                            {
+                                "annotationDate": "2024-05-20T12:00:00Z",
+                                "annotationType": "REVIEW",
+                                "annotator": annotator,
+                                "comment": "paketkoll:verification=failed"
+                            },
+                            {
+                                "annotationDate": "2024-05-20T12:00:00Z",
+                                "annotationType": "REVIEW",
+                                "annotator": annotator,
+                                "comment": "paketkoll:issue_count=1"
+                            }
+                        ],
+                        "copyrightText": "NOASSERTION",
+                        "downloadLocation": "NOASSERTION",
+                        "externalRefs": [
+                            {
+                                "referenceCategory": "PACKAGE-MANAGER",
+                                "referenceLocator": "pkg:deb/debian/foo@1%3A2.0~rc1-1?arch=amd64",
+                                "referenceType": "purl"
+                            }
+                        ],
+                        "filesAnalyzed": false,
+                        "licenseConcluded": "NOASSERTION",
+                        "licenseDeclared": "NOASSERTION",
+                        "name": "foo",
+                        "summary": "The foo tool",
+                        "versionInfo": "1:2.0~rc1-1"
+                    }
+                ],
+                "relationships": [
+                    {
+                        "relatedSpdxElement": "SPDXRef-Package-0",
+                        "relationshipType": "DESCRIBES",
+                        "spdxElementId": "SPDXRef-DOCUMENT"
+                    },
+                    {
+                        "relatedSpdxElement": "SPDXRef-File-0",
+                        "relationshipType": "CONTAINS",
+                        "spdxElementId": "SPDXRef-Package-0"
+                    },
+                    {
+                        "relatedSpdxElement": "SPDXRef-Package-1",
+                        "relationshipType": "DESCRIBES",
+                        "spdxElementId": "SPDXRef-DOCUMENT"
+                    },
+                    {
+                        "relatedSpdxElement": "SPDXRef-Package-0",
+                        "relationshipType": "DEPENDS_ON",
+                        "spdxElementId": "SPDXRef-Package-1"
+                    },
+                    {
+                        "relatedSpdxElement": "SPDXRef-File-1",
+                        "relationshipType": "CONTAINS",
+                        "spdxElementId": "SPDXRef-Package-1"
+                    }
+                ],
+                "spdxVersion": "SPDX-2.3"
+            })
+        );
+    }
+}
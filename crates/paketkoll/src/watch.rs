@@ -0,0 +1,100 @@
+//! Report issues with files as they change (`watch`)
+
+use crate::check_unexpected_config;
+use crate::is_ignored;
+use crate::load_waivers;
+use crate::write_issue;
+use paketkoll::cli::Cli;
+use paketkoll::cli::Format;
+use paketkoll::cli::Generated;
+use paketkoll::cli::WatchMethod;
+use paketkoll_core::config::WatchConfiguration;
+use paketkoll_core::file_ops;
+use paketkoll_core::file_ops::CancellationToken;
+use paketkoll_core::paketkoll_types::intern::Interner;
+use paketkoll_core::waivers::Date;
+use proc_exit::Code;
+use proc_exit::Exit;
+use std::io::Write;
+use std::io::stdout;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn run_watch(
+    cli: &Cli,
+    paths: &[PathBuf],
+    method: WatchMethod,
+    settle: u64,
+    canonicalize: bool,
+    generated: Generated,
+    generated_rules: &[PathBuf],
+) -> eyre::Result<Exit> {
+    match cli.format {
+        Format::Human | Format::PacmanQkk | Format::DpkgVerify => (),
+        format => eyre::bail!("{format} format is not supported for watch"),
+    }
+    let backend = cli.backend.try_into()?;
+    let backend_config = cli.try_into()?;
+    let filecheck_config = cli.try_into()?;
+    let unexpected_cfg =
+        check_unexpected_config(cli, backend, canonicalize, generated, generated_rules)?;
+    let watch_config = WatchConfiguration::builder()
+        .paths(paths.to_vec())
+        .method(method.into())
+        .settle_time(Duration::from_secs(settle))
+        .build()?;
+    // The watcher handles ignores for new files, but not for package files
+    let ignores = file_ops::build_ignore_overrides(&cli.ignore)?;
+    let waivers = load_waivers(cli)?;
+
+    let interner = Interner::new();
+    let cancel = CancellationToken::new();
+    let (collector, issues) = flume::bounded(1024);
+    std::thread::scope(|scope| {
+        let watcher = scope.spawn(|| {
+            let result = paketkoll_core::watch::watch_files(
+                backend,
+                &backend_config,
+                &filecheck_config,
+                &unexpected_cfg,
+                &watch_config,
+                &interner,
+                &collector,
+                &cancel,
+            );
+            // Close the channel, so that the loop below ends if watching fails
+            drop(collector);
+            result
+        });
+        let mut result: eyre::Result<()> = Ok(());
+        for (pkg, mut issue) in &issues {
+            if is_ignored(&ignores, &issue)
+                || waivers
+                    .as_ref()
+                    .is_some_and(|w| !w.waive(&mut issue, Date::today()))
+            {
+                continue;
+            }
+            let pkg = pkg.and_then(|e| interner.try_resolve(&e.as_interner_ref()));
+            // Only lock stdout while writing, as the watcher thread logs there.
+            // Each issue is flushed right away, so it shows up immediately (also
+            // in the journal when running as a service).
+            let mut stdout = stdout().lock();
+            if let Err(err) =
+                write_issue(&mut stdout, cli.format, pkg, &issue).and_then(|()| stdout.flush())
+            {
+                cancel.cancel();
+                if err.kind() != std::io::ErrorKind::BrokenPipe {
+                    result = Err(err.into());
+                }
+                break;
+            }
+        }
+        drop(issues);
+        watcher.join().expect("Watch thread panicked")?;
+        result
+    })?;
+
+    Ok(Exit::new(Code::SUCCESS))
+}
@@ -0,0 +1,68 @@
+//! Benchmark of checking files one at a time compared to in batches with
+//! `io_uring`
+//!
+//! Run with `cargo bench -p paketkoll_core --features debian,io_uring`. The
+//! files are in the page cache after the first iteration, so this mostly
+//! measures the system call overhead.
+
+use divan::Bencher;
+use paketkoll_core::config::CommonFileCheckConfiguration;
+use paketkoll_core::file_ops::check_file_entries;
+use paketkoll_types::files::FileEntry;
+use paketkoll_types::files::FileFlags;
+use paketkoll_types::files::Properties;
+use paketkoll_types::files::RegularFileBasic;
+use paketkoll_utils::checksum::sha256_buffer;
+use std::path::Path;
+
+fn main() {
+    divan::main();
+}
+
+/// Create files with the given sizes, returning entries describing them
+fn create_files(dir: &Path, sizes: impl Iterator<Item = usize>) -> Vec<FileEntry> {
+    sizes
+        .enumerate()
+        .map(|(idx, size)| {
+            let path = dir.join(format!("file{idx}"));
+            let contents: Vec<u8> = (0..size).map(|byte| (byte * 31 + idx) as u8).collect();
+            std::fs::write(&path, &contents).expect("Failed to write file");
+            FileEntry {
+                package: None,
+                path,
+                properties: Properties::RegularFileBasic(RegularFileBasic {
+                    size: Some(size as u64),
+                    checksum: sha256_buffer(&contents),
+                }),
+                flags: FileFlags::empty(),
+                source: "bench",
+                seen: Default::default(),
+            }
+        })
+        .collect()
+}
+
+fn bench_check(bencher: Bencher<'_, '_>, sizes: impl Iterator<Item = usize>, io_uring: bool) {
+    let dir = tempfile::tempdir().expect("Failed to create directory");
+    let entries = create_files(dir.path(), sizes);
+    let config = CommonFileCheckConfiguration::builder()
+        .io_uring(io_uring)
+        .build()
+        .expect("Failed to build config");
+    bencher.bench(|| {
+        let issues = check_file_entries(&entries, &config).expect("Check failed");
+        assert!(issues.is_empty());
+    });
+}
+
+/// Many small files (like most of /usr)
+#[divan::bench(args = [false, true])]
+fn small_files(bencher: Bencher<'_, '_>, io_uring: bool) {
+    bench_check(bencher, (0..10_000).map(|idx| idx % 4096), io_uring);
+}
+
+/// Fewer larger files, where hashing dominates
+#[divan::bench(args = [false, true])]
+fn large_files(bencher: Bencher<'_, '_>, io_uring: bool) {
+    bench_check(bencher, (0..100).map(|idx| 512 * 1024 + idx), io_uring);
+}
@@ -0,0 +1,493 @@
+//! Match installed packages against local security advisory databases
+//!
+//! Supported are dumps of the Arch Linux security tracker (`all.json`), the
+//! Debian security tracker (`data/json`) and directories of OSV JSON files.
+//! No network access is needed, so this works on air-gapped hosts.
+
+use ahash::AHashMap;
+use compact_str::CompactString;
+use eyre::WrapErr;
+use paketkoll_types::intern::Interner;
+use paketkoll_types::intern::PackageRef;
+use paketkoll_types::version::VersionScheme;
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Kind of advisory database to load
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdvisorySource {
+    /// Arch Linux security tracker JSON (`all.json`)
+    ArchLinux,
+    /// Debian security tracker JSON, for the given release codename (e.g.
+    /// `bookworm`)
+    Debian { release: CompactString },
+    /// Directory of OSV JSON files, for the given ecosystem (e.g. `Debian:12`)
+    ///
+    /// Ecosystems with an additional suffix (such as `Ubuntu:22.04:LTS` for
+    /// `Ubuntu:22.04`) also match.
+    Osv { ecosystem: CompactString },
+}
+
+/// A security advisory for a single package
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Advisory {
+    /// Identifier of the advisory (AVG, CVE or OSV ID)
+    pub id: CompactString,
+    /// Other identifiers for the same issue (CVEs, ASAs, DSAs, ...)
+    pub aliases: Vec<CompactString>,
+    /// Name of affected package (the source package on Debian)
+    pub package: CompactString,
+    /// Affected version ranges, a version is affected if it is in any of them
+    #[serde(skip)]
+    pub ranges: Vec<Vec<RangeEvent>>,
+    /// Explicitly listed affected versions
+    #[serde(skip)]
+    pub versions: Vec<CompactString>,
+    /// First version with a fix (if any)
+    pub fixed: Option<CompactString>,
+    /// Severity (or urgency) as given by the database
+    pub severity: Option<CompactString>,
+    /// Short description
+    pub summary: Option<CompactString>,
+}
+
+/// Event in an affected version range (same semantics as OSV)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeEvent {
+    /// Versions from this one are affected (`0` means all versions)
+    Introduced(CompactString),
+    /// Versions from this one are not affected
+    Fixed(CompactString),
+    /// Versions after this one are not affected
+    LastAffected(CompactString),
+}
+
+/// An installed package affected by an advisory
+#[derive(Debug, Clone)]
+pub struct Finding<'advisory> {
+    /// Installed package
+    pub package: PackageRef,
+    /// Installed version
+    pub version: CompactString,
+    /// Advisory affecting the package
+    pub advisory: &'advisory Advisory,
+}
+
+impl Advisory {
+    /// Check if the given version is affected by this advisory
+    pub fn affects(&self, version: &str, scheme: VersionScheme) -> bool {
+        let compare = |a: &str, b: &str| scheme.compare(a, b);
+        self.versions
+            .iter()
+            .any(|v| compare(version, v) == Ordering::Equal)
+            || self.ranges.iter().any(|events| {
+                let mut affected = false;
+                for event in events {
+                    match event {
+                        RangeEvent::Introduced(v) => {
+                            if v == "0" || compare(version, v).is_ge() {
+                                affected = true;
+                            }
+                        }
+                        RangeEvent::Fixed(v) => {
+                            if compare(version, v).is_ge() {
+                                affected = false;
+                            }
+                        }
+                        RangeEvent::LastAffected(v) => {
+                            if compare(version, v).is_gt() {
+                                affected = false;
+                            }
+                        }
+                    }
+                }
+                affected
+            })
+    }
+}
+
+/// Load advisories from a local database
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn load_advisories(source: &AdvisorySource, path: &Path) -> eyre::Result<Vec<Advisory>> {
+    match source {
+        AdvisorySource::ArchLinux => {
+            let data = std::fs::read(path).wrap_err_with(|| format!("Failed to read {path:?}"))?;
+            parse_arch(&data).wrap_err_with(|| format!("Failed to parse {path:?}"))
+        }
+        AdvisorySource::Debian { release } => {
+            let data = std::fs::read(path).wrap_err_with(|| format!("Failed to read {path:?}"))?;
+            parse_debian(&data, release).wrap_err_with(|| format!("Failed to parse {path:?}"))
+        }
+        AdvisorySource::Osv { ecosystem } => {
+            let mut paths = vec![];
+            for entry in std::fs::read_dir(path)
+                .wrap_err_with(|| format!("Failed to read directory {path:?}"))?
+            {
+                let entry_path = entry?.path();
+                if entry_path.extension().is_some_and(|ext| ext == "json") {
+                    paths.push(entry_path);
+                }
+            }
+            let advisories: eyre::Result<Vec<Vec<Advisory>>> = paths
+                .into_par_iter()
+                .map(|path| {
+                    let data = std::fs::read(&path)
+                        .wrap_err_with(|| format!("Failed to read {path:?}"))?;
+                    parse_osv(&data, ecosystem)
+                        .wrap_err_with(|| format!("Failed to parse {path:?}"))
+                })
+                .collect();
+            Ok(advisories?.into_iter().flatten().collect())
+        }
+    }
+}
+
+/// Find all installed packages affected by any of the advisories
+///
+/// Versions are compared the way the package manager of the backend does.
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn audit<'advisory>(
+    backend: crate::backend::ConcreteBackend,
+    backend_config: &crate::backend::BackendConfiguration,
+    advisories: &'advisory [Advisory],
+) -> eyre::Result<(Interner, Vec<Finding<'advisory>>)> {
+    // Debian advisories are for source packages
+    #[cfg(feature = "debian")]
+    let sources = if backend == crate::backend::ConcreteBackend::Apt {
+        crate::backend::deb::source_packages()?
+    } else {
+        AHashMap::new()
+    };
+    #[cfg(not(feature = "debian"))]
+    let sources: AHashMap<CompactString, (CompactString, Option<CompactString>)> = AHashMap::new();
+
+    let interner = Interner::new();
+    let backend_impl = backend
+        .create_packages(backend_config, &interner)
+        .wrap_err_with(|| format!("Failed to create backend for {backend}"))?;
+    let packages = backend_impl
+        .packages(&interner)
+        .wrap_err_with(|| format!("Failed to collect information from backend {backend}"))?;
+    let scheme = backend_impl.version_scheme();
+
+    let mut by_package: AHashMap<&str, Vec<&Advisory>> = AHashMap::new();
+    for advisory in advisories {
+        by_package
+            .entry(advisory.package.as_str())
+            .or_default()
+            .push(advisory);
+    }
+
+    let findings = packages
+        .par_iter()
+        .flat_map_iter(|package| {
+            let name = package.name.as_str(&interner);
+            let (match_name, match_version) = match sources.get(name) {
+                Some((source, source_version)) => (
+                    source.as_str(),
+                    source_version.as_deref().unwrap_or(&package.version),
+                ),
+                None => (name, package.version.as_str()),
+            };
+            by_package
+                .get(match_name)
+                .into_iter()
+                .flatten()
+                .filter(move |advisory| advisory.affects(match_version, scheme))
+                .map(|advisory| Finding {
+                    package: package.name,
+                    version: package.version.clone(),
+                    advisory,
+                })
+        })
+        .collect();
+    Ok((interner, findings))
+}
+
+/// Parse the Arch Linux security tracker format
+fn parse_arch(data: &[u8]) -> eyre::Result<Vec<Advisory>> {
+    #[derive(serde::Deserialize)]
+    struct Group {
+        name: CompactString,
+        packages: Vec<CompactString>,
+        status: CompactString,
+        severity: Option<CompactString>,
+        #[serde(rename = "type")]
+        kind: Option<CompactString>,
+        fixed: Option<CompactString>,
+        #[serde(default)]
+        issues: Vec<CompactString>,
+        #[serde(default)]
+        advisories: Vec<CompactString>,
+    }
+
+    let groups: Vec<Group> = serde_json::from_slice(data)?;
+    let mut advisories = vec![];
+    for group in groups {
+        if group.status == "Not affected" {
+            continue;
+        }
+        let mut events = vec![RangeEvent::Introduced("0".into())];
+        events.extend(group.fixed.clone().map(RangeEvent::Fixed));
+        for package in group.packages {
+            advisories.push(Advisory {
+                id: group.name.clone(),
+                aliases: group
+                    .issues
+                    .iter()
+                    .chain(&group.advisories)
+                    .cloned()
+                    .collect(),
+                package,
+                ranges: vec![events.clone()],
+                versions: vec![],
+                fixed: group.fixed.clone(),
+                severity: group.severity.clone(),
+                summary: group.kind.clone(),
+            });
+        }
+    }
+    Ok(advisories)
+}
+
+/// Parse the Debian security tracker format
+fn parse_debian(data: &[u8], release: &str) -> eyre::Result<Vec<Advisory>> {
+    #[derive(serde::Deserialize)]
+    struct Issue {
+        description: Option<CompactString>,
+        #[serde(default)]
+        releases: HashMap<CompactString, Release>,
+    }
+    #[derive(serde::Deserialize)]
+    struct Release {
+        status: CompactString,
+        fixed_version: Option<CompactString>,
+        urgency: Option<CompactString>,
+    }
+
+    let packages: HashMap<CompactString, HashMap<CompactString, Issue>> =
+        serde_json::from_slice(data)?;
+    let mut advisories = vec![];
+    for (package, issues) in packages {
+        for (id, issue) in issues {
+            let Some(info) = issue.releases.get(release) else {
+                continue;
+            };
+            let fixed = match (info.status.as_str(), &info.fixed_version) {
+                // Fixed in version 0 means it was never affected
+                ("resolved", Some(version)) if version == "0" => continue,
+                ("resolved", Some(version)) => Some(version.clone()),
+                ("resolved", None) => continue,
+                _ => None,
+            };
+            let mut events = vec![RangeEvent::Introduced("0".into())];
+            events.extend(fixed.clone().map(RangeEvent::Fixed));
+            advisories.push(Advisory {
+                id,
+                aliases: vec![],
+                package: package.clone(),
+                ranges: vec![events],
+                versions: vec![],
+                fixed,
+                severity: info.urgency.clone(),
+                summary: issue.description,
+            });
+        }
+    }
+    Ok(advisories)
+}
+
+/// Parse a single OSV record, keeping the packages in the given ecosystem
+fn parse_osv(data: &[u8], ecosystem: &str) -> eyre::Result<Vec<Advisory>> {
+    #[derive(serde::Deserialize)]
+    struct Record {
+        id: CompactString,
+        #[serde(default)]
+        aliases: Vec<CompactString>,
+        summary: Option<CompactString>,
+        #[serde(default)]
+        affected: Vec<Affected>,
+        #[serde(default)]
+        database_specific: Option<serde_json::Value>,
+    }
+    #[derive(serde::Deserialize)]
+    struct Affected {
+        package: Option<AffectedPackage>,
+        #[serde(default)]
+        ranges: Vec<Range>,
+        #[serde(default)]
+        versions: Vec<CompactString>,
+        #[serde(default)]
+        ecosystem_specific: Option<serde_json::Value>,
+    }
+    #[derive(serde::Deserialize)]
+    struct AffectedPackage {
+        ecosystem: CompactString,
+        name: CompactString,
+    }
+    #[derive(serde::Deserialize)]
+    struct Range {
+        #[serde(rename = "type")]
+        kind: CompactString,
+        #[serde(default)]
+        events: Vec<HashMap<CompactString, CompactString>>,
+    }
+
+    let record: Record = serde_json::from_slice(data)?;
+    let mut advisories = vec![];
+    for affected in record.affected {
+        let Some(package) = affected.package else {
+            continue;
+        };
+        let matches_ecosystem = package
+            .ecosystem
+            .strip_prefix(ecosystem)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(':'));
+        if !matches_ecosystem {
+            continue;
+        }
+        let ranges: Vec<Vec<RangeEvent>> = affected
+            .ranges
+            .into_iter()
+            .filter(|range| range.kind == "ECOSYSTEM")
+            .map(|range| {
+                range
+                    .events
+                    .into_iter()
+                    .flat_map(IntoIterator::into_iter)
+                    .filter_map(|(kind, version)| match kind.as_str() {
+                        "introduced" => Some(RangeEvent::Introduced(version)),
+                        "fixed" => Some(RangeEvent::Fixed(version)),
+                        "last_affected" => Some(RangeEvent::LastAffected(version)),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect();
+        let fixed = ranges.iter().flatten().find_map(|event| match event {
+            RangeEvent::Fixed(version) => Some(version.clone()),
+            _ => None,
+        });
+        let severity = [&affected.ecosystem_specific, &record.database_specific]
+            .into_iter()
+            .flatten()
+            .find_map(|value| value.get("urgency").or_else(|| value.get("severity")))
+            .and_then(|value| value.as_str())
+            .map(Into::into);
+        advisories.push(Advisory {
+            id: record.id.clone(),
+            aliases: record.aliases.clone(),
+            package: package.name,
+            ranges,
+            versions: affected.versions,
+            fixed,
+            severity,
+            summary: record.summary.clone(),
+        });
+    }
+    Ok(advisories)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_arch() {
+        let data = br#"[
+            {"name": "AVG-1", "packages": ["openssl", "lib32-openssl"], "status": "Fixed",
+             "severity": "High", "type": "denial of service", "affected": "3.0.0-1",
+             "fixed": "3.0.1-1", "ticket": null, "issues": ["CVE-2022-0001"],
+             "advisories": ["ASA-202201-1"]},
+            {"name": "AVG-2", "packages": ["curl"], "status": "Vulnerable",
+             "severity": "Low", "type": "information disclosure", "affected": "8.0-1",
+             "fixed": null, "issues": ["CVE-2023-0002"], "advisories": []},
+            {"name": "AVG-3", "packages": ["zlib"], "status": "Not affected",
+             "severity": "Unknown", "type": "unknown", "affected": "1.0-1",
+             "fixed": null, "issues": [], "advisories": []}
+        ]"#;
+        let advisories = parse_arch(data).unwrap();
+        assert_eq!(advisories.len(), 3);
+        assert_eq!(advisories[0].package, "openssl");
+        assert_eq!(advisories[1].package, "lib32-openssl");
+        assert_eq!(advisories[0].aliases, ["CVE-2022-0001", "ASA-202201-1"]);
+        assert!(advisories[0].affects("3.0.0-1", VersionScheme::Pacman));
+        assert!(!advisories[0].affects("3.0.1-1", VersionScheme::Pacman));
+        assert!(!advisories[0].affects("3.0.10-1", VersionScheme::Pacman));
+        assert_eq!(advisories[2].package, "curl");
+        assert!(advisories[2].affects("8.5-1", VersionScheme::Pacman));
+    }
+
+    #[test]
+    fn test_parse_debian() {
+        let data = br#"{
+            "openssl": {
+                "CVE-2024-0001": {"description": "Bad thing", "scope": "local", "releases": {
+                    "bookworm": {"status": "resolved", "fixed_version": "3.0.13-1~deb12u1",
+                                 "urgency": "medium", "repositories": {}},
+                    "sid": {"status": "resolved", "fixed_version": "3.1.5-1", "urgency": "medium"}
+                }},
+                "CVE-2024-0002": {"releases": {
+                    "bookworm": {"status": "resolved", "fixed_version": "0", "urgency": "low"}
+                }},
+                "CVE-2024-0003": {"releases": {
+                    "bookworm": {"status": "open", "urgency": "unimportant"}
+                }},
+                "CVE-2024-0004": {"releases": {
+                    "bullseye": {"status": "open", "urgency": "high"}
+                }}
+            }
+        }"#;
+        let mut advisories = parse_debian(data, "bookworm").unwrap();
+        advisories.sort_by(|a, b| a.id.cmp(&b.id));
+        assert_eq!(advisories.len(), 2);
+        assert_eq!(advisories[0].id, "CVE-2024-0001");
+        assert_eq!(advisories[0].fixed.as_deref(), Some("3.0.13-1~deb12u1"));
+        assert_eq!(advisories[0].severity.as_deref(), Some("medium"));
+        assert!(advisories[0].affects("3.0.11-1~deb12u2", VersionScheme::Dpkg));
+        assert!(!advisories[0].affects("3.0.13-1~deb12u1", VersionScheme::Dpkg));
+        assert!(!advisories[0].affects("3.0.15-1~deb12u1", VersionScheme::Dpkg));
+        assert_eq!(advisories[1].id, "CVE-2024-0003");
+        assert!(advisories[1].affects("3.0.15-1~deb12u1", VersionScheme::Dpkg));
+    }
+
+    #[test]
+    fn test_parse_osv() {
+        let data = br#"{
+            "id": "DSA-5000-1",
+            "aliases": ["CVE-2024-0005"],
+            "summary": "openssl - security update",
+            "affected": [
+                {"package": {"ecosystem": "Debian:12", "name": "openssl"},
+                 "ranges": [{"type": "ECOSYSTEM", "events": [
+                     {"introduced": "0"}, {"fixed": "3.0.13-1~deb12u1"}]}]},
+                {"package": {"ecosystem": "Debian:11", "name": "openssl"},
+                 "ranges": [{"type": "ECOSYSTEM", "events": [
+                     {"introduced": "0"}, {"fixed": "1.1.1w-0+deb11u2"}]}]},
+                {"package": {"ecosystem": "Debian:12", "name": "libfoo"},
+                 "ranges": [{"type": "ECOSYSTEM", "events": [
+                     {"introduced": "1.0-1"}, {"last_affected": "1.2-1"}]}],
+                 "versions": ["0.9-1"]}
+            ]
+        }"#;
+        let advisories = parse_osv(data, "Debian:12").unwrap();
+        assert_eq!(advisories.len(), 2);
+        assert_eq!(advisories[0].package, "openssl");
+        assert_eq!(advisories[0].aliases, ["CVE-2024-0005"]);
+        assert_eq!(advisories[0].fixed.as_deref(), Some("3.0.13-1~deb12u1"));
+        assert!(advisories[0].affects("3.0.11-1~deb12u2", VersionScheme::Dpkg));
+        assert!(!advisories[0].affects("3.0.13-1~deb12u1", VersionScheme::Dpkg));
+
+        let libfoo = &advisories[1];
+        assert!(libfoo.affects("0.9-1", VersionScheme::Dpkg));
+        assert!(!libfoo.affects("0.9-2", VersionScheme::Dpkg));
+        assert!(libfoo.affects("1.0-1", VersionScheme::Dpkg));
+        assert!(libfoo.affects("1.2-1", VersionScheme::Dpkg));
+        assert!(!libfoo.affects("1.2-2", VersionScheme::Dpkg));
+
+        assert_eq!(parse_osv(data, "Debian").unwrap().len(), 3);
+        assert!(parse_osv(data, "Deb").unwrap().is_empty());
+    }
+}
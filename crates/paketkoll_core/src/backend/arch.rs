@@ -31,6 +31,7 @@ use paketkoll_types::files::FileEntry;
 use paketkoll_types::intern::Interner;
 use paketkoll_types::intern::PackageRef;
 use paketkoll_types::package::PackageInterned;
+use paketkoll_types::version::VersionScheme;
 use rayon::prelude::*;
 use regex::RegexSet;
 use std::borrow::Cow;
@@ -95,6 +96,19 @@ impl ArchLinuxBuilder {
     }
 }
 
+/// Path to the pacman log file
+pub(crate) fn log_file() -> eyre::Result<PathBuf> {
+    let pacman_config = ArchLinuxBuilder::load_config().wrap_err("Failed to load pacman.conf")?;
+    Ok(PathBuf::from(pacman_config.log_file.as_str()))
+}
+
+/// Path to the database of installed packages
+#[cfg(feature = "watch")]
+pub(crate) fn local_db_dir() -> eyre::Result<PathBuf> {
+    let pacman_config = ArchLinuxBuilder::load_config().wrap_err("Failed to load pacman.conf")?;
+    Ok(PathBuf::from(pacman_config.db_path.as_str()).join("local"))
+}
+
 impl Name for ArchLinux {
     fn name(&self) -> &'static str {
         NAME
@@ -396,6 +410,10 @@ impl Packages for ArchLinux {
         results
     }
 
+    fn version_scheme(&self) -> VersionScheme {
+        VersionScheme::Pacman
+    }
+
     fn transact(
         &self,
         install: &[&str],
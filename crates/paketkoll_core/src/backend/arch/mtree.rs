@@ -1,5 +1,6 @@
 //! Logic to take mtree data to `FileEntry`
 
+use crate::mtree::extract_path;
 use dashmap::DashSet;
 use eyre::OptionExt;
 use eyre::WrapErr;
@@ -17,7 +18,6 @@ use paketkoll_types::files::Symlink;
 use paketkoll_types::files::Uid;
 use paketkoll_types::intern::PackageRef;
 use std::collections::BTreeSet;
-use std::ffi::OsStr;
 use std::fs::File;
 use std::io::BufReader;
 use std::io::Read;
@@ -154,20 +154,3 @@ fn convert_mtree(
         }),
     })
 }
-
-/// Extract the path from an mtree entry, they start with a . which we want to
-/// remove
-fn extract_path(item: &mtree2::Entry) -> PathBuf {
-    let path = item.path();
-    let as_bytes = path.as_os_str().as_encoded_bytes();
-    if as_bytes[0] == b'.' {
-        // SAFETY:
-        // * The encoding is "an unspecified, platform-specific, self-synchronizing
-        //   superset of UTF-8"
-        // * We are removing a leading ASCII character here (.).
-        // * Thus, the buffer still contains the same superset of UTF-8
-        PathBuf::from(unsafe { OsStr::from_encoded_bytes_unchecked(&as_bytes[1..]) })
-    } else {
-        path.into()
-    }
-}
@@ -11,6 +11,7 @@ pub(crate) struct PacmanConfig {
     pub(crate) root: CompactString,
     pub(crate) db_path: CompactString,
     pub(crate) cache_dir: CompactString,
+    pub(crate) log_file: CompactString,
 }
 
 impl PacmanConfig {
@@ -27,6 +28,10 @@ impl PacmanConfig {
                 .get("CacheDir")
                 .unwrap_or("/var/cache/pacman/pkg/")
                 .into(),
+            log_file: options
+                .get("LogFile")
+                .unwrap_or("/var/log/pacman.log")
+                .into(),
         })
     }
 }
@@ -50,5 +55,6 @@ mod tests {
         assert_eq!(config.root, "/other");
         assert_eq!(config.db_path, "/dbpath");
         assert_eq!(config.cache_dir, "/var/cache/pacman/pkg/");
+        assert_eq!(config.log_file, "/var/log/pacman.log");
     }
 }
@@ -32,6 +32,7 @@ use paketkoll_types::intern::ArchitectureRef;
 use paketkoll_types::intern::Interner;
 use paketkoll_types::intern::PackageRef;
 use paketkoll_types::package::PackageInterned;
+use paketkoll_types::version::VersionScheme;
 use rayon::prelude::*;
 use regex::RegexSet;
 use std::borrow::Cow;
@@ -56,7 +57,7 @@ mod parsers;
 // /var/lib/dpkg/status for that.
 
 const DB_PATH: &str = "/var/lib/dpkg/info";
-const STATUS_PATH: &str = "/var/lib/dpkg/status";
+pub(crate) const STATUS_PATH: &str = "/var/lib/dpkg/status";
 const EXTENDED_STATUS_PATH: &str = "/var/lib/apt/extended_states";
 const CACHE_PATH: &str = "/var/cache/apt/archives";
 const NAME: &str = "Debian";
@@ -181,7 +182,8 @@ impl Files for Debian {
             })
             .collect();
         let paths = paths.as_slice();
-        let re = RegexSet::new(paths)?;
+        // Paths may contain regex meta characters (such as libstdc++.so)
+        let re = RegexSet::new(paths.iter().map(|path| regex::escape(path)))?;
 
         std::fs::read_dir(db_root)
             .wrap_err("Failed to read dpkg database directory")?
@@ -583,6 +585,53 @@ fn process_file(interner: &Interner, entry: &DirEntry) -> eyre::Result<Option<Ve
     Ok(result)
 }
 
+/// A package file that has been moved elsewhere by a diversion
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DivertedFile {
+    pub package: PackageRef,
+    pub path: PathBuf,
+    pub by_package: PackageRef,
+    pub new_path: PathBuf,
+}
+
+/// Get the files of every package without merging entries for the same path.
+///
+/// Diversions are applied, and the files that were diverted are returned
+/// separately.
+pub(crate) fn unmerged_files(
+    interner: &Interner,
+) -> eyre::Result<(Vec<FileEntry>, Vec<DivertedFile>)> {
+    let diversions = divert::get_diversions(interner).wrap_err("Failed to get dpkg diversions")?;
+    let mut diverted = vec![];
+    let mut files: Vec<FileEntry> = get_package_files(interner)?.flatten().collect();
+    for file in &mut files {
+        if let Some(diversion) = diversions.get(&file.path)
+            && Some(diversion.by_package) != file.package
+        {
+            // Every path is in the .list file (with unknown properties), only
+            // record those to not report paths also in .md5sums twice.
+            if let (Properties::Unknown, Some(package)) = (&file.properties, file.package) {
+                diverted.push(DivertedFile {
+                    package,
+                    path: file.path.clone(),
+                    by_package: diversion.by_package,
+                    new_path: diversion.new_path.clone(),
+                });
+            }
+            file.path.clone_from(&diversion.new_path);
+        }
+    }
+    Ok((files, diverted))
+}
+
+/// Get the source package of every installed binary package (by name)
+#[cfg(feature = "audit")]
+pub(crate) fn source_packages()
+-> eyre::Result<ahash::AHashMap<CompactString, parsers::SourcePackage>> {
+    let mut status = BufReader::new(File::open(STATUS_PATH)?);
+    parsers::parse_sources(&mut status).context(format!("Failed to parse {STATUS_PATH}"))
+}
+
 impl Packages for Debian {
     fn packages(&self, interner: &Interner) -> eyre::Result<Vec<PackageInterned>> {
         // Parse status
@@ -616,6 +665,10 @@ impl Packages for Debian {
         Ok(packages)
     }
 
+    fn version_scheme(&self) -> VersionScheme {
+        VersionScheme::Dpkg
+    }
+
     fn transact(
         &self,
         install: &[&str],
@@ -1,8 +1,11 @@
 //! Parsers for Debian package files.
 
+use ahash::AHashMap;
 use bstr::ByteSlice;
 use bstr::ByteVec;
 use bstr::io::BufReadExt;
+#[cfg(feature = "audit")]
+use compact_str::CompactString;
 use compact_str::format_compact;
 use eyre::WrapErr;
 use eyre::bail;
@@ -343,11 +346,11 @@ enum StatusParsingState {
 pub(super) fn parse_extended_status(
     interner: &Interner,
     input: &mut impl BufRead,
-) -> eyre::Result<ahash::AHashMap<(PackageRef, ArchitectureRef), Option<InstallReason>>> {
+) -> eyre::Result<AHashMap<(PackageRef, ArchitectureRef), Option<InstallReason>>> {
     let mut state = ExtendedStatusParsingState::Start;
 
     let all_arch = ArchitectureRef::get_or_intern(interner, "all");
-    let mut result = ahash::AHashMap::new();
+    let mut result = AHashMap::new();
 
     let mut buffer = String::new();
     while input.read_line(&mut buffer)? > 0 {
@@ -383,6 +386,48 @@ pub(super) fn parse_extended_status(
     Ok(result)
 }
 
+/// Source package name and version of a binary package
+///
+/// The version is only set if it differs from the version of the binary
+/// package (such as for binNMUs).
+#[cfg(feature = "audit")]
+pub(crate) type SourcePackage = (CompactString, Option<CompactString>);
+
+/// Parse `/var/lib/dpkg/status` for the source package of each binary package
+///
+/// Packages without a `Source` field are built from a source package with the
+/// same name.
+#[cfg(feature = "audit")]
+pub(super) fn parse_sources(
+    input: &mut impl BufRead,
+) -> eyre::Result<AHashMap<CompactString, SourcePackage>> {
+    let mut result = AHashMap::new();
+    let mut package: Option<CompactString> = None;
+
+    let mut buffer = String::new();
+    while input.read_line(&mut buffer)? > 0 {
+        let line = buffer.trim_end();
+        if let Some(stripped) = line.strip_prefix("Package: ") {
+            package = Some(stripped.into());
+            result.insert(stripped.into(), (stripped.into(), None));
+        } else if let Some(stripped) = line.strip_prefix("Source: ") {
+            let Some(ref package) = package else {
+                bail!("Source not in a package");
+            };
+            let source = match stripped.split_once(' ') {
+                Some((name, version)) => {
+                    (name.into(), Some(version.trim_matches(['(', ')']).into()))
+                }
+                None => (stripped.into(), None),
+            };
+            result.insert(package.clone(), source);
+        }
+        buffer.clear();
+    }
+
+    Ok(result)
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum ExtendedStatusParsingState {
     Start,
@@ -399,6 +444,8 @@ enum ExtendedStatusParsingState {
 mod tests {
     use super::parse_md5sums;
     use super::parse_paths;
+    #[cfg(feature = "audit")]
+    use super::parse_sources;
     use super::parse_status;
     use paketkoll_types::files::Checksum;
     use paketkoll_types::files::FileEntry;
@@ -688,6 +735,31 @@ mod tests {
         );
     }
 
+    #[test]
+    #[cfg(feature = "audit")]
+    fn test_parse_sources() {
+        let input = indoc::indoc! {"
+            Package: libc6
+            Status: install ok installed
+            Source: glibc
+            Version: 2.36-9+deb12u4
+
+            Package: adduser
+            Status: install ok installed
+            Version: 3.134
+
+            Package: libfoo1
+            Source: foo (1.2-3)
+            Version: 1.2-3+b1
+            "};
+        let mut input = input.as_bytes();
+        let sources = parse_sources(&mut input).unwrap();
+        assert_eq!(sources.len(), 3);
+        assert_eq!(sources["libc6"], ("glibc".into(), None));
+        assert_eq!(sources["adduser"], ("adduser".into(), None));
+        assert_eq!(sources["libfoo1"], ("foo".into(), Some("1.2-3".into())));
+    }
+
     #[test]
     fn test_parse_extended_status() {
         let input = indoc::indoc! {"
@@ -30,7 +30,10 @@ use std::io::ErrorKind;
 use std::io::Read;
 use std::os::unix::fs::FileTypeExt;
 use std::os::unix::fs::MetadataExt;
+use std::path::Path;
 use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
 
 /// Determine if a given file should be processed
 const fn should_process(file: &FileEntry, config: &CommonFileCheckConfiguration) -> bool {
@@ -42,18 +45,107 @@ const fn should_process(file: &FileEntry, config: &CommonFileCheckConfiguration)
     }
 }
 
+/// The metadata of a file that is needed for checking it
+///
+/// This comes from [`std::fs::symlink_metadata`], or from `statx` when
+/// checking files in batches with `io_uring`.
+#[derive(Debug, Clone)]
+pub(crate) struct FileStat {
+    pub(crate) file_type: EntryType,
+    /// Mode, including the file type bits
+    pub(crate) mode: u32,
+    pub(crate) uid: u32,
+    pub(crate) gid: u32,
+    pub(crate) size: u64,
+    pub(crate) mtime: SystemTime,
+    /// Major and minor number (for device nodes)
+    pub(crate) rdev: (u64, u64),
+}
+
+impl FileStat {
+    fn is_file(&self) -> bool {
+        self.file_type == EntryType::RegularFile
+    }
+
+    fn is_dir(&self) -> bool {
+        self.file_type == EntryType::Directory
+    }
+
+    fn is_symlink(&self) -> bool {
+        self.file_type == EntryType::Symlink
+    }
+}
+
+impl From<&std::fs::Metadata> for FileStat {
+    fn from(metadata: &std::fs::Metadata) -> Self {
+        let rdev = metadata.rdev();
+        Self {
+            file_type: entry_type_from_mode(metadata.mode()),
+            mode: metadata.mode(),
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            size: metadata.len(),
+            mtime: system_time(metadata.mtime(), metadata.mtime_nsec() as u32),
+            rdev: (u64::from(libc::major(rdev)), u64::from(libc::minor(rdev))),
+        }
+    }
+}
+
+/// Get the type of a file from the type bits of the mode
+pub(crate) const fn entry_type_from_mode(mode: u32) -> EntryType {
+    match mode & libc::S_IFMT {
+        libc::S_IFREG => EntryType::RegularFile,
+        libc::S_IFDIR => EntryType::Directory,
+        libc::S_IFLNK => EntryType::Symlink,
+        libc::S_IFBLK => EntryType::BlockDevice,
+        libc::S_IFCHR => EntryType::CharDevice,
+        libc::S_IFIFO => EntryType::Fifo,
+        libc::S_IFSOCK => EntryType::Socket,
+        _ => EntryType::Special,
+    }
+}
+
+/// Convert a timestamp relative to the Unix epoch
+pub(crate) fn system_time(seconds: i64, nanoseconds: u32) -> SystemTime {
+    let since_epoch = Duration::new(seconds.unsigned_abs(), 0);
+    let whole_seconds = if seconds < 0 {
+        SystemTime::UNIX_EPOCH - since_epoch
+    } else {
+        SystemTime::UNIX_EPOCH + since_epoch
+    };
+    whole_seconds + Duration::from_nanos(u64::from(nanoseconds))
+}
+
 /// Check a single file entry from a package database against the file system
 pub(crate) fn check_file(
     file: &FileEntry,
     config: &CommonFileCheckConfiguration,
+) -> Result<Option<Issue>> {
+    if config.is_skipped_network_path(&file.path) {
+        return Ok(None);
+    }
+    let stat = std::fs::symlink_metadata(&file.path).map(|metadata| FileStat::from(&metadata));
+    check_file_stat(file, config, stat, None)
+}
+
+/// Check a file entry given the metadata of the file
+///
+/// If the checksum of the contents has already been computed it can be
+/// passed in, otherwise the file is read if needed (see
+/// [`checksum_to_verify`]).
+pub(crate) fn check_file_stat(
+    file: &FileEntry,
+    config: &CommonFileCheckConfiguration,
+    stat: std::io::Result<FileStat>,
+    computed_checksum: Option<std::io::Result<Checksum>>,
 ) -> Result<Option<Issue>> {
     let mut issues = IssueVec::new();
-    match std::fs::symlink_metadata(&file.path) {
-        Ok(metadata) => match &file.properties {
+    match stat {
+        Ok(stat) => match &file.properties {
             Properties::RegularFileBasic(RegularFileBasic { size, checksum }) => {
-                if !metadata.is_file() {
+                if !stat.is_file() {
                     issues.push(IssueKind::TypeIncorrect {
-                        actual: metadata.file_type().into(),
+                        actual: stat.file_type.clone(),
                         expected: EntryType::RegularFile,
                     });
                 }
@@ -61,11 +153,12 @@ pub(crate) fn check_file(
                     check_contents(
                         &mut issues,
                         config,
-                        &file.path,
-                        &metadata,
+                        file,
+                        &stat,
                         None,
                         *size,
                         checksum,
+                        computed_checksum,
                     )?;
                 }
             }
@@ -77,22 +170,23 @@ pub(crate) fn check_file(
                 checksum,
                 contents: _,
             }) => {
-                if !metadata.is_file() {
+                if !stat.is_file() {
                     issues.push(IssueKind::TypeIncorrect {
-                        actual: metadata.file_type().into(),
+                        actual: stat.file_type.clone(),
                         expected: EntryType::RegularFile,
                     });
                 }
                 if should_process(file, config) {
-                    check_permissions(&mut issues, &metadata, *owner, *group, *mode);
+                    check_permissions(&mut issues, &stat, *owner, *group, *mode);
                     check_contents(
                         &mut issues,
                         config,
-                        &file.path,
-                        &metadata,
+                        file,
+                        &stat,
                         None,
                         *size,
                         checksum,
+                        computed_checksum,
                     )?;
                 }
             }
@@ -104,22 +198,23 @@ pub(crate) fn check_file(
                 size,
                 checksum,
             }) => {
-                if !metadata.is_file() {
+                if !stat.is_file() {
                     issues.push(IssueKind::TypeIncorrect {
-                        actual: metadata.file_type().into(),
+                        actual: stat.file_type.clone(),
                         expected: EntryType::RegularFile,
                     });
                 }
                 if should_process(file, config) {
-                    check_permissions(&mut issues, &metadata, *owner, *group, *mode);
+                    check_permissions(&mut issues, &stat, *owner, *group, *mode);
                     check_contents(
                         &mut issues,
                         config,
-                        &file.path,
-                        &metadata,
+                        file,
+                        &stat,
                         Some(mtime),
                         Some(*size),
                         checksum,
+                        computed_checksum,
                     )?;
                 }
             }
@@ -128,10 +223,10 @@ pub(crate) fn check_file(
                 group,
                 target,
             }) => {
-                check_ownership(&mut issues, &metadata, *owner, *group);
-                if !metadata.is_symlink() {
+                check_ownership(&mut issues, &stat, *owner, *group);
+                if !stat.is_symlink() {
                     issues.push(IssueKind::TypeIncorrect {
-                        actual: metadata.file_type().into(),
+                        actual: stat.file_type.clone(),
                         expected: EntryType::Symlink,
                     });
                 } else {
@@ -151,23 +246,23 @@ pub(crate) fn check_file(
                 }
             }
             Properties::Directory(Directory { mode, owner, group }) => {
-                if !metadata.is_dir() {
+                if !stat.is_dir() {
                     issues.push(IssueKind::TypeIncorrect {
-                        actual: metadata.file_type().into(),
+                        actual: stat.file_type.clone(),
                         expected: EntryType::Directory,
                     });
                 }
-                check_permissions(&mut issues, &metadata, *owner, *group, *mode);
+                check_permissions(&mut issues, &stat, *owner, *group, *mode);
                 // We don't do anything with mtime here currently
             }
             Properties::Fifo(Fifo { mode, owner, group }) => {
-                if !metadata.file_type().is_fifo() {
+                if stat.file_type != EntryType::Fifo {
                     issues.push(IssueKind::TypeIncorrect {
-                        actual: metadata.file_type().into(),
+                        actual: stat.file_type.clone(),
                         expected: EntryType::Fifo,
                     });
                 }
-                check_permissions(&mut issues, &metadata, *owner, *group, *mode);
+                check_permissions(&mut issues, &stat, *owner, *group, *mode);
             }
             Properties::DeviceNode(DeviceNode {
                 mode,
@@ -177,27 +272,18 @@ pub(crate) fn check_file(
                 major,
                 minor,
             }) => {
-                let is_expected_type: bool = match device_type {
-                    DeviceType::Block => metadata.file_type().is_block_device(),
-                    DeviceType::Char => metadata.file_type().is_char_device(),
+                let expected_type = match device_type {
+                    DeviceType::Block => EntryType::BlockDevice,
+                    DeviceType::Char => EntryType::CharDevice,
                 };
-                if !is_expected_type {
+                if stat.file_type != expected_type {
                     issues.push(IssueKind::TypeIncorrect {
-                        actual: metadata.file_type().into(),
-                        expected: match device_type {
-                            DeviceType::Block => EntryType::BlockDevice,
-                            DeviceType::Char => EntryType::CharDevice,
-                        },
+                        actual: stat.file_type.clone(),
+                        expected: expected_type,
                     });
                 } else {
                     // Only check major/minor if we have a device node
-                    let rdev = metadata.rdev();
-                    // SAFETY: As far as I can find out, these do not actually
-                    // have any safety invariants, as they just perform some simple bitwise
-                    // arithmetics.
-                    let major_actual = u64::from(libc::major(rdev));
-                    // SAFETY: Same as for major
-                    let minor_actual = u64::from(libc::minor(rdev));
+                    let (major_actual, minor_actual) = stat.rdev;
                     if (major_actual, minor_actual) != (*major, *minor) {
                         issues.push(IssueKind::WrongDeviceNodeId {
                             actual: (*device_type, major_actual, minor_actual),
@@ -205,13 +291,13 @@ pub(crate) fn check_file(
                         });
                     }
                 }
-                check_permissions(&mut issues, &metadata, *owner, *group, *mode);
+                check_permissions(&mut issues, &stat, *owner, *group, *mode);
             }
             Properties::Special => {
                 // Should be something other than dir, symlink or file:
-                if metadata.is_dir() || metadata.is_file() || metadata.is_symlink() {
+                if stat.is_dir() || stat.is_file() || stat.is_symlink() {
                     issues.push(IssueKind::TypeIncorrect {
-                        actual: metadata.file_type().into(),
+                        actual: stat.file_type.clone(),
                         expected: EntryType::Special,
                     });
                 }
@@ -222,15 +308,15 @@ pub(crate) fn check_file(
             }
             Properties::Unknown => {
                 // Should be something other than a file (but Debian doesn't tell us what)
-                if metadata.is_file() {
+                if stat.is_file() {
                     issues.push(IssueKind::TypeIncorrect {
-                        actual: metadata.file_type().into(),
+                        actual: stat.file_type.clone(),
                         expected: EntryType::Unknown,
                     });
                 }
             }
             Properties::Permissions(Permissions { mode, owner, group }) => {
-                check_permissions(&mut issues, &metadata, *owner, *group, *mode);
+                check_permissions(&mut issues, &stat, *owner, *group, *mode);
             }
         },
         Err(err) => match err.kind() {
@@ -249,127 +335,212 @@ pub(crate) fn check_file(
     if issues.is_empty() {
         Ok(None)
     } else {
-        Ok(Some(Issue::new(
-            file.path.clone(),
-            issues,
-            Some(file.source),
-        )))
+        Ok(Some(
+            Issue::new(file.path.clone(), issues, Some(file.source))
+                .with_config_file(file.flags.contains(FileFlags::CONFIG)),
+        ))
+    }
+}
+
+/// Find the checksum that needs to be verified by reading the file, if any
+///
+/// This is the case for regular files whose size matches and where the
+/// configuration doesn't let us skip the contents.
+#[cfg(feature = "io_uring")]
+pub(crate) fn checksum_to_verify<'a>(
+    file: &'a FileEntry,
+    config: &CommonFileCheckConfiguration,
+    stat: &FileStat,
+) -> Option<&'a Checksum> {
+    if !stat.is_file() || !should_process(file, config) {
+        return None;
+    }
+    let (expected_mtime, expected_size, checksum) = match &file.properties {
+        Properties::RegularFileBasic(RegularFileBasic { size, checksum })
+        | Properties::RegularFileSystemd(RegularFileSystemd { size, checksum, .. }) => {
+            (None, *size, checksum)
+        }
+        Properties::RegularFile(RegularFile {
+            mtime,
+            size,
+            checksum,
+            ..
+        }) => (Some(mtime), Some(*size), checksum),
+        _ => return None,
+    };
+    if expected_size.is_some_and(|size| size != stat.size)
+        || can_skip_checksum(config, file, stat, expected_mtime)
+    {
+        return None;
     }
+    ChecksumHasher::new(checksum).map(|_| checksum)
+}
+
+/// Check if the configuration allows not reading the file contents
+fn can_skip_checksum(
+    config: &CommonFileCheckConfiguration,
+    file: &FileEntry,
+    stat: &FileStat,
+    expected_mtime: Option<&SystemTime>,
+) -> bool {
+    if file.flags.contains(FileFlags::METADATA_ONLY) {
+        return true;
+    }
+    // Possibly fast path using mtime
+    if config.trust_mtime && expected_mtime.is_some_and(|mtime| *mtime == stat.mtime) {
+        return true;
+    }
+    if let Some(max_size) = config.max_file_size
+        && stat.size > max_size
+    {
+        tracing::debug!("Not computing checksum of large file {:?}", file.path);
+        return true;
+    }
+    false
 }
 
 /// Check the contents of a regular file against the expected values
+#[allow(clippy::too_many_arguments)]
 fn check_contents(
     issues: &mut IssueVec,
     config: &CommonFileCheckConfiguration,
-    path: &PathBuf,
-    actual_metadata: &std::fs::Metadata,
-    expected_mtime: Option<&std::time::SystemTime>,
+    file: &FileEntry,
+    actual_stat: &FileStat,
+    expected_mtime: Option<&SystemTime>,
     expected_size: Option<u64>,
     expected_checksum: &Checksum,
+    computed_checksum: Option<std::io::Result<Checksum>>,
 ) -> Result<()> {
     // Fast path with size
     if let Some(size) = expected_size
-        && size != actual_metadata.len()
+        && size != actual_stat.size
     {
         issues.push(IssueKind::SizeIncorrect {
-            actual: actual_metadata.len(),
+            actual: actual_stat.size,
             expected: size,
         });
         return Ok(());
     }
 
-    // Possibly fast path using mtime
-    if config.trust_mtime
-        && let Some(mtime) = expected_mtime
-        && *mtime == actual_metadata.modified()?
-    {
+    if can_skip_checksum(config, file, actual_stat, expected_mtime) {
         return Ok(());
     }
     // Otherwise, check checksum
-    let mut reader = match File::open(path) {
-        Ok(file) => file,
-        Err(err) => match err.kind() {
-            ErrorKind::PermissionDenied => {
-                issues.push(IssueKind::PermissionDenied);
-                return Ok(());
-            }
-            _ => Err(err).wrap_err_with(|| format!("IO error while reading {path:?}"))?,
-        },
+    let Some(hasher) = ChecksumHasher::new(expected_checksum) else {
+        tracing::error!("Checksum {expected_checksum} is of an unsupported type");
+        issues.push(IssueKind::FsCheckError(Box::new(eyre::eyre!(
+            "Unsupported checksum type"
+        ))));
+        return Ok(());
     };
-
-    let mut buffer = [0; 16 * 1024];
-
-    match *expected_checksum {
-        #[cfg(feature = "__md5")]
-        Checksum::Md5(ref expected) => {
-            use md5::Digest;
-            use paketkoll_types::files::Checksum;
-            let mut hasher = md5::Md5::new();
-            loop {
-                match reader.read(&mut buffer) {
-                    Ok(0) => break,
-                    Ok(n) => {
-                        hasher.update(&buffer[..n]);
-                    }
-                    Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
-                    Err(e) => Err(e)?,
-                }
-            }
-            let mut actual = Default::default();
-            hasher.finalize_into(&mut actual);
-
-            if actual[..] != expected[..] {
+    let actual = computed_checksum.unwrap_or_else(|| hash_file(config, &file.path, hasher));
+    match actual {
+        Ok(actual) => {
+            if actual != *expected_checksum {
                 issues.push(IssueKind::ChecksumIncorrect {
-                    actual: Checksum::Md5(actual[..].try_into().expect("Invalid length")),
+                    actual,
                     expected: expected_checksum.clone(),
                 });
             }
         }
-        #[cfg(feature = "__sha256")]
-        Checksum::Sha256(ref expected) => {
-            let mut hasher = ring::digest::Context::new(&ring::digest::SHA256);
-            loop {
-                match reader.read(&mut buffer) {
-                    Ok(0) => break,
-                    Ok(n) => {
-                        hasher.update(&buffer[..n]);
-                    }
-                    Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
-                    Err(e) => Err(e)?,
+        Err(err) if err.kind() == ErrorKind::PermissionDenied => {
+            issues.push(IssueKind::PermissionDenied);
+        }
+        Err(err) => Err(err).wrap_err_with(|| format!("IO error while reading {:?}", file.path))?,
+    }
+
+    Ok(())
+}
+
+/// Compute the checksum of a file by reading it
+fn hash_file(
+    config: &CommonFileCheckConfiguration,
+    path: &Path,
+    mut hasher: ChecksumHasher,
+) -> std::io::Result<Checksum> {
+    let mut reader = File::open(path)?;
+    let mut buffer = [0; 16 * 1024];
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => {
+                if let Some(ref limiter) = config.read_limit {
+                    limiter.consume(n);
                 }
+                hasher.update(&buffer[..n]);
             }
-            let actual = hasher.finish();
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(hasher.finish())
+}
 
-            if actual.as_ref() != expected {
-                issues.push(IssueKind::ChecksumIncorrect {
-                    actual: Checksum::Sha256(actual.as_ref().try_into().expect("Invalid length")),
-                    expected: expected_checksum.clone(),
-                });
+/// Incremental computation of a checksum of the same type as an expected one
+pub(crate) enum ChecksumHasher {
+    #[cfg(feature = "__md5")]
+    Md5(md5::Md5),
+    #[cfg(feature = "__sha256")]
+    Sha256(ring::digest::Context),
+}
+
+impl ChecksumHasher {
+    /// Create a hasher for the same algorithm as the given checksum (if
+    /// supported)
+    pub(crate) fn new(checksum: &Checksum) -> Option<Self> {
+        match checksum {
+            #[cfg(feature = "__md5")]
+            Checksum::Md5(_) => {
+                use md5::Digest;
+                Some(Self::Md5(md5::Md5::new()))
             }
+            #[cfg(feature = "__sha256")]
+            Checksum::Sha256(_) => Some(Self::Sha256(ring::digest::Context::new(
+                &ring::digest::SHA256,
+            ))),
+            _ => None,
         }
-        _ => {
-            tracing::error!("Checksum {expected_checksum} is of an unsupported type");
-            issues.push(IssueKind::FsCheckError(Box::new(eyre::eyre!(
-                "Unsupported checksum type"
-            ))));
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            #[cfg(feature = "__md5")]
+            Self::Md5(hasher) => {
+                use md5::Digest;
+                hasher.update(data);
+            }
+            #[cfg(feature = "__sha256")]
+            Self::Sha256(hasher) => hasher.update(data),
         }
     }
 
-    Ok(())
+    pub(crate) fn finish(self) -> Checksum {
+        match self {
+            #[cfg(feature = "__md5")]
+            Self::Md5(hasher) => {
+                use md5::Digest;
+                Checksum::Md5(hasher.finalize().into())
+            }
+            #[cfg(feature = "__sha256")]
+            Self::Sha256(hasher) => {
+                Checksum::Sha256(hasher.finish().as_ref().try_into().expect("Invalid length"))
+            }
+        }
+    }
 }
 
 /// Check if permissions match
 fn check_permissions(
     issues: &mut IssueVec,
-    actual_metadata: &std::fs::Metadata,
+    actual_stat: &FileStat,
     expected_owner: Uid,
     expected_group: Gid,
     expected_mode: Mode,
 ) {
-    check_ownership(issues, actual_metadata, expected_owner, expected_group);
+    check_ownership(issues, actual_stat, expected_owner, expected_group);
     // There are some extra bits further up in the mode mask that we need to mask
     // out here. They indicate things like file/directory/fifo/device-node
-    let actual_mode = actual_metadata.mode() & MODE_MASK;
+    let actual_mode = actual_stat.mode & MODE_MASK;
     if actual_mode != expected_mode.as_raw() {
         issues.push(IssueKind::WrongMode {
             actual: Mode::new(actual_mode),
@@ -381,20 +552,78 @@ fn check_permissions(
 /// Check if owner/group matches
 fn check_ownership(
     issues: &mut IssueVec,
-    actual_metadata: &std::fs::Metadata,
+    actual_stat: &FileStat,
     expected_owner: Uid,
     expected_group: Gid,
 ) {
-    if actual_metadata.uid() != expected_owner.as_raw() {
+    if actual_stat.uid != expected_owner.as_raw() {
         issues.push(IssueKind::WrongOwner {
-            actual: Uid::new(actual_metadata.uid()),
+            actual: Uid::new(actual_stat.uid),
             expected: expected_owner,
         });
     }
-    if actual_metadata.gid() != expected_group.as_raw() {
+    if actual_stat.gid != expected_group.as_raw() {
         issues.push(IssueKind::WrongGroup {
-            actual: Gid::new(actual_metadata.gid()),
+            actual: Gid::new(actual_stat.gid),
             expected: expected_group,
         });
     }
 }
+
+/// Create a file entry describing the current state of a path on the file
+/// system
+pub(crate) fn entry_from_file_system(path: PathBuf, source: &'static str) -> Result<FileEntry> {
+    let metadata = std::fs::symlink_metadata(&path)
+        .wrap_err_with(|| format!("Failed to get metadata for {path:?}"))?;
+    let mode = Mode::new(metadata.mode() & MODE_MASK);
+    let owner = Uid::new(metadata.uid());
+    let group = Gid::new(metadata.gid());
+    let file_type = metadata.file_type();
+    let properties = if file_type.is_file() {
+        let mut reader = File::open(&path).wrap_err_with(|| format!("Failed to open {path:?}"))?;
+        Properties::RegularFile(RegularFile {
+            mode,
+            owner,
+            group,
+            size: metadata.len(),
+            mtime: metadata.modified()?,
+            checksum: paketkoll_utils::checksum::sha256_readable(&mut reader)
+                .wrap_err_with(|| format!("Failed to checksum {path:?}"))?,
+        })
+    } else if file_type.is_symlink() {
+        Properties::Symlink(Symlink {
+            owner,
+            group,
+            target: std::fs::read_link(&path)
+                .wrap_err_with(|| format!("Failed to read link target for {path:?}"))?,
+        })
+    } else if file_type.is_dir() {
+        Properties::Directory(Directory { mode, owner, group })
+    } else if file_type.is_fifo() {
+        Properties::Fifo(Fifo { mode, owner, group })
+    } else if file_type.is_block_device() || file_type.is_char_device() {
+        let rdev = metadata.rdev();
+        Properties::DeviceNode(DeviceNode {
+            mode,
+            owner,
+            group,
+            device_type: if file_type.is_block_device() {
+                DeviceType::Block
+            } else {
+                DeviceType::Char
+            },
+            major: u64::from(libc::major(rdev)),
+            minor: u64::from(libc::minor(rdev)),
+        })
+    } else {
+        Properties::Special
+    };
+    Ok(FileEntry {
+        package: None,
+        path,
+        properties,
+        flags: FileFlags::empty(),
+        source,
+        seen: Default::default(),
+    })
+}
@@ -0,0 +1,122 @@
+//! Baselines of files not owned by any package
+//!
+//! A baseline records the state of all unexpected files at one point in time.
+//! It can later be verified against the file system to find files that have
+//! been added, removed or modified since then.
+
+use crate::file_ops::CheckStatistics;
+use crate::file_ops::canonicalize_file_entries;
+use crate::file_ops::create_path_map;
+use crate::file_ops::mismatching_and_unexpected_files;
+use eyre::WrapErr;
+use paketkoll_types::files::FileEntry;
+use paketkoll_types::files::FileFlags;
+use paketkoll_types::intern::Interner;
+use paketkoll_types::issue::IssueKind;
+use paketkoll_types::issue::PackageIssue;
+use rayon::prelude::*;
+
+/// Source name used for file entries loaded from a baseline
+pub const SOURCE: &str = "baseline";
+
+/// Create a baseline of all files not owned by any package
+///
+/// Files that can not be read are skipped with a warning.
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn create(
+    backend: crate::backend::ConcreteBackend,
+    backend_config: &crate::backend::BackendConfiguration,
+    unexpected_cfg: &crate::config::CheckAllFilesConfiguration,
+) -> eyre::Result<Vec<FileEntry>> {
+    let interner = Interner::new();
+    let expected_files = package_files(backend, backend_config, unexpected_cfg, &interner)?;
+    let path_map = create_path_map(&expected_files);
+    // Package files are not checked here, so don't waste time on checksums
+    let filecheck_config = crate::config::CommonFileCheckConfiguration::builder()
+        .trust_mtime(true)
+        .build()?;
+    let issues = mismatching_and_unexpected_files(
+        &expected_files,
+        &path_map,
+        &filecheck_config,
+        unexpected_cfg,
+    )?;
+
+    tracing::debug!("Collecting metadata of unexpected files");
+    let mut entries: Vec<FileEntry> = issues
+        .into_par_iter()
+        .filter(|(_, issue)| issue.kinds().any(|k| matches!(k, IssueKind::Unexpected)))
+        .filter_map(|(_, issue)| {
+            let path = issue.path().to_path_buf();
+            match crate::backend::filesystem::entry_from_file_system(path, SOURCE) {
+                Ok(entry) => Some(entry),
+                Err(err) => {
+                    tracing::warn!("Skipping {:?}: {err:?}", issue.path());
+                    None
+                }
+            }
+        })
+        .collect();
+    entries.par_sort_unstable_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+/// Verify the file system against a previously created baseline
+///
+/// Reported are files that have been added (unexpected), removed (missing) or
+/// changed compared to the baseline. Issues with package owned files are not
+/// included.
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn verify(
+    backend: crate::backend::ConcreteBackend,
+    backend_config: &crate::backend::BackendConfiguration,
+    filecheck_config: &crate::config::CommonFileCheckConfiguration,
+    unexpected_cfg: &crate::config::CheckAllFilesConfiguration,
+    baseline: Vec<FileEntry>,
+) -> eyre::Result<(Interner, Vec<PackageIssue>, CheckStatistics)> {
+    let interner = Interner::new();
+    let mut expected_files = package_files(backend, backend_config, unexpected_cfg, &interner)?;
+    // Package files are only needed to tell which files are unexpected, issues
+    // with them are not reported. So don't read their contents.
+    for entry in &mut expected_files {
+        entry.flags |= FileFlags::METADATA_ONLY;
+    }
+    let statistics = CheckStatistics::from_entries(&baseline);
+    expected_files.extend(baseline);
+    let path_map = create_path_map(&expected_files);
+
+    let mut issues = mismatching_and_unexpected_files(
+        &expected_files,
+        &path_map,
+        filecheck_config,
+        unexpected_cfg,
+    )?;
+    // Unexpected files have no source, everything from the baseline has ours.
+    issues.retain(|(_, issue)| issue.source().is_none_or(|source| source == SOURCE));
+
+    drop(path_map);
+    rayon::spawn(move || {
+        drop(expected_files);
+    });
+    Ok((interner, issues, statistics))
+}
+
+/// Get the (possibly canonicalized) files owned by packages
+fn package_files(
+    backend: crate::backend::ConcreteBackend,
+    backend_config: &crate::backend::BackendConfiguration,
+    unexpected_cfg: &crate::config::CheckAllFilesConfiguration,
+    interner: &Interner,
+) -> eyre::Result<Vec<FileEntry>> {
+    let backend_impl = backend
+        .create_files(backend_config, interner)
+        .wrap_err_with(|| format!("Failed to create backend for {backend}"))?;
+    let mut files = backend_impl
+        .files(interner)
+        .wrap_err_with(|| format!("Failed to collect information from backend {backend}"))?;
+    if unexpected_cfg.canonicalize_paths {
+        tracing::debug!("Canonicalizing paths");
+        canonicalize_file_entries(&mut files);
+    }
+    Ok(files)
+}
@@ -0,0 +1,216 @@
+//! Output compatible with the verification commands of the native package
+//! managers (`pacman -Qkk` and `dpkg --verify`)
+//!
+//! This allows existing tooling that parses the output of those commands to
+//! use paketkoll instead. Issues without an equivalent in the native tool are
+//! still written, in the same line format, with a description of the issue.
+
+use paketkoll_types::files::Checksum;
+use paketkoll_types::issue::Issue;
+use paketkoll_types::issue::IssueKind;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+
+/// Write an issue the way `pacman -Qkk` reports it
+///
+/// This writes one line per kind of issue, such as
+/// `warning: foo: /etc/foo.conf (Permissions mismatch)`. Pacman writes these
+/// to stderr, and the per package summary (see [`write_pacman_qkk_summary`])
+/// to stdout.
+pub fn write_pacman_qkk(
+    writer: &mut impl Write,
+    package: Option<&str>,
+    issue: &Issue,
+) -> std::io::Result<()> {
+    for kind in issue.kinds() {
+        writer.write_all(b"warning: ")?;
+        if let Some(package) = package {
+            write!(writer, "{package}: ")?;
+        }
+        writer.write_all(issue.path().as_os_str().as_bytes())?;
+        match pacman_message(kind) {
+            Some(message) => writeln!(writer, " ({message})")?,
+            None => writeln!(writer, " ({kind})")?,
+        }
+    }
+    Ok(())
+}
+
+/// Write the summary line `pacman -Qkk` writes after checking a package
+///
+/// Such as `foo: 12 total files, 1 altered file`. Altered files are the files
+/// with any issue (not the number of issues).
+pub fn write_pacman_qkk_summary(
+    writer: &mut impl Write,
+    package: &str,
+    total_files: usize,
+    altered_files: usize,
+) -> std::io::Result<()> {
+    let plural = |count: usize| if count == 1 { "" } else { "s" };
+    writeln!(
+        writer,
+        "{package}: {total_files} total file{}, {altered_files} altered file{}",
+        plural(total_files),
+        plural(altered_files)
+    )
+}
+
+/// Message used by pacman for a kind of issue
+fn pacman_message(kind: &IssueKind) -> Option<&'static str> {
+    match kind {
+        IssueKind::Missing => Some("No such file or directory"),
+        IssueKind::PermissionDenied => Some("Permission denied"),
+        IssueKind::TypeIncorrect { .. } => Some("File type mismatch"),
+        IssueKind::SizeIncorrect { .. } => Some("Size mismatch"),
+        IssueKind::ChecksumIncorrect { expected, .. } => match expected {
+            Checksum::Md5(_) => Some("MD5 checksum mismatch"),
+            Checksum::Sha256(_) => Some("SHA256 checksum mismatch"),
+            _ => None,
+        },
+        IssueKind::SymlinkTarget { .. } => Some("Symlink path mismatch"),
+        IssueKind::WrongOwner { .. } => Some("UID mismatch"),
+        IssueKind::WrongGroup { .. } => Some("GID mismatch"),
+        IssueKind::WrongMode { .. } => Some("Permissions mismatch"),
+        _ => None,
+    }
+}
+
+/// Write an issue the way `dpkg --verify` reports it (the rpm format)
+///
+/// This writes one line per path, such as `??5?????? c /etc/foo`. The nine
+/// characters are the rpm verify flags (`SM5DLUGTP`), with `?` for checks
+/// that passed or were not performed, `c` marks config files. Missing files
+/// are written as `missing`, followed by the error in parenthesis like dpkg
+/// does. Files that couldn't be read just keep `?` in the digest column.
+/// Other issues with no corresponding flag are described in parenthesis after
+/// the path.
+pub fn write_dpkg_verify(writer: &mut impl Write, issue: &Issue) -> std::io::Result<()> {
+    let mut result = *b"?????????";
+    let mut errors = vec![];
+    for kind in issue.kinds() {
+        match kind {
+            IssueKind::Missing => {
+                result = *b"missing  ";
+                errors.push("No such file or directory".to_string());
+            }
+            IssueKind::PermissionDenied => (),
+            IssueKind::SizeIncorrect { .. } => result[0] = b'S',
+            IssueKind::TypeIncorrect { .. } | IssueKind::WrongMode { .. } => result[1] = b'M',
+            IssueKind::ChecksumIncorrect { .. } => result[2] = b'5',
+            IssueKind::WrongDeviceNodeId { .. } => result[3] = b'D',
+            IssueKind::SymlinkTarget { .. } => result[4] = b'L',
+            IssueKind::WrongOwner { .. } => result[5] = b'U',
+            IssueKind::WrongGroup { .. } => result[6] = b'G',
+            _ => errors.push(kind.to_string()),
+        }
+    }
+    writer.write_all(&result)?;
+    writer.write_all(if issue.is_config_file() {
+        b" c "
+    } else {
+        b"   "
+    })?;
+    writer.write_all(issue.path().as_os_str().as_bytes())?;
+    if errors.is_empty() {
+        writeln!(writer)
+    } else {
+        writeln!(writer, " ({})", errors.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use paketkoll_types::files::Mode;
+    use paketkoll_types::files::Uid;
+    use std::path::PathBuf;
+
+    fn issue(path: &str, kinds: Vec<IssueKind>) -> Issue {
+        Issue::new(PathBuf::from(path), kinds.into(), None)
+    }
+
+    #[test]
+    fn test_pacman_qkk() {
+        let mut out = vec![];
+        let changed = issue(
+            "/etc/foo.conf",
+            vec![
+                IssueKind::WrongMode {
+                    actual: Mode::new(0o600),
+                    expected: Mode::new(0o644),
+                },
+                IssueKind::ChecksumIncorrect {
+                    actual: Checksum::Sha256([0; 32]),
+                    expected: Checksum::Sha256([1; 32]),
+                },
+            ],
+        );
+        write_pacman_qkk(&mut out, Some("foo"), &changed).unwrap();
+        write_pacman_qkk(
+            &mut out,
+            None,
+            &issue("/etc/bar", vec![IssueKind::Unexpected]),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            indoc! {"
+                warning: foo: /etc/foo.conf (Permissions mismatch)
+                warning: foo: /etc/foo.conf (SHA256 checksum mismatch)
+                warning: /etc/bar (unexpected file)
+            "}
+        );
+    }
+
+    #[test]
+    fn test_pacman_qkk_summary() {
+        let mut out = vec![];
+        write_pacman_qkk_summary(&mut out, "foo", 12, 1).unwrap();
+        write_pacman_qkk_summary(&mut out, "bar", 1, 0).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            indoc! {"
+                foo: 12 total files, 1 altered file
+                bar: 1 total file, 0 altered files
+            "}
+        );
+    }
+
+    #[test]
+    fn test_dpkg_verify() {
+        let mut out = vec![];
+        let changed = issue(
+            "/etc/foo.conf",
+            vec![IssueKind::ChecksumIncorrect {
+                actual: Checksum::Md5([0; 16]),
+                expected: Checksum::Md5([1; 16]),
+            }],
+        )
+        .with_config_file(true);
+        write_dpkg_verify(&mut out, &changed).unwrap();
+        write_dpkg_verify(&mut out, &issue("/usr/bin/foo", vec![IssueKind::Missing])).unwrap();
+        write_dpkg_verify(
+            &mut out,
+            &issue(
+                "/usr/bin/bar",
+                vec![
+                    IssueKind::WrongOwner {
+                        actual: Uid::new(1000),
+                        expected: Uid::new(0),
+                    },
+                    IssueKind::PermissionDenied,
+                ],
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            indoc! {"
+                ??5?????? c /etc/foo.conf
+                missing     /usr/bin/foo (No such file or directory)
+                ?????U???   /usr/bin/bar
+            "}
+        );
+    }
+}
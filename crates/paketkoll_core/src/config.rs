@@ -1,6 +1,10 @@
 //! Configuration for [`crate::file_ops`] and [`crate::package_ops`]
 
 use compact_str::CompactString;
+#[cfg(feature = "watch")]
+use std::path::PathBuf;
+#[cfg(feature = "watch")]
+use std::time::Duration;
 
 /// Configuration for [`crate::file_ops::check_all_files`]
 #[derive(Debug, derive_builder::Builder)]
@@ -13,6 +17,13 @@ pub struct CheckAllFilesConfiguration {
     /// for example)
     #[builder(default = "false")]
     pub canonicalize_paths: bool,
+    /// Known generated files. Unexpected files matching these are not
+    /// reported, unless `report_generated` is set.
+    #[builder(default = "None")]
+    pub generated_files: Option<crate::generated::GeneratedFiles>,
+    /// Report known generated files as such (instead of leaving them out)
+    #[builder(default = "false")]
+    pub report_generated: bool,
 }
 
 impl CheckAllFilesConfiguration {
@@ -34,6 +45,41 @@ pub struct CommonFileCheckConfiguration {
     /// Should configuration files be included
     #[builder(default = "ConfigFiles::Include")]
     pub config_files: ConfigFiles,
+    /// Additional file backends (such as systemd-tmpfiles) whose files are
+    /// checked along with those of the main backend. For paths listed by
+    /// several backends, the main backend takes priority, followed by these in
+    /// order.
+    #[builder(default = "vec![]")]
+    pub extra_file_backends: Vec<crate::backend::ConcreteBackend>,
+    /// Number of threads to use for checking (default: number of CPUs)
+    #[builder(default = "None")]
+    pub threads: Option<usize>,
+    /// Nice level of the threads checking files
+    #[builder(default = "None")]
+    pub nice: Option<i32>,
+    /// I/O priority of the threads checking files
+    #[builder(default = "None")]
+    pub io_priority: Option<crate::resource_limits::IoPriority>,
+    /// Limit on the combined read throughput when computing checksums
+    #[builder(default = "None")]
+    pub read_limit: Option<crate::resource_limits::ReadLimiter>,
+    /// Files larger than this (in bytes) are not checksummed
+    #[builder(default = "None")]
+    pub max_file_size: Option<u64>,
+    /// Skip files on network file systems (such as NFS or CIFS)
+    #[builder(default = "false")]
+    pub skip_network_filesystems: bool,
+    /// If set, only the contents of files belonging to these packages are
+    /// verified. The files of other packages only get their metadata checked.
+    #[builder(default = "None")]
+    pub verify_contents_of: Option<ahash::AHashSet<CompactString>>,
+    /// Check files in batches using `io_uring` (if available)
+    #[cfg(feature = "io_uring")]
+    #[builder(default = "true")]
+    pub io_uring: bool,
+    /// Network mounts, loaded on first use
+    #[builder(setter(skip))]
+    network_mounts: std::sync::OnceLock<crate::resource_limits::NetworkMounts>,
 }
 
 impl CommonFileCheckConfiguration {
@@ -42,6 +88,61 @@ impl CommonFileCheckConfiguration {
     pub fn builder() -> CommonFileCheckConfigurationBuilder {
         Default::default()
     }
+
+    /// Check if a path should be skipped because it is on a network file system
+    pub(crate) fn is_skipped_network_path(&self, path: &std::path::Path) -> bool {
+        self.skip_network_filesystems
+            && self
+                .network_mounts
+                .get_or_init(|| {
+                    crate::resource_limits::NetworkMounts::load().unwrap_or_else(|err| {
+                        tracing::warn!("Failed to find network file systems: {err}");
+                        Default::default()
+                    })
+                })
+                .contains(path)
+    }
+}
+
+/// Configuration for [`crate::watch::watch_files`]
+#[cfg(feature = "watch")]
+#[derive(Debug, derive_builder::Builder)]
+#[non_exhaustive]
+pub struct WatchConfiguration {
+    /// Directory trees to watch for changes
+    #[builder(default = "vec![PathBuf::from(\"/\")]")]
+    pub paths: Vec<PathBuf>,
+    /// How to get notified of changes
+    #[builder(default = "WatchMethod::Auto")]
+    pub method: WatchMethod,
+    /// How long a file has to be left alone before it is verified. This avoids
+    /// reporting files that are in the middle of being written.
+    #[builder(default = "Duration::from_secs(2)")]
+    pub settle_time: Duration,
+}
+
+#[cfg(feature = "watch")]
+impl WatchConfiguration {
+    /// Get a builder for this struct
+    #[must_use]
+    pub fn builder() -> WatchConfigurationBuilder {
+        Default::default()
+    }
+}
+
+/// How to get notified of file system changes
+#[cfg(feature = "watch")]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum WatchMethod {
+    /// inotify, falling back to fanotify if inotify is not available
+    Auto,
+    /// fanotify on the mounts of the watched trees (requires root). This sees
+    /// all writes, but not permission changes, deletions or files replaced by
+    /// renaming another file over them.
+    Fanotify,
+    /// inotify on each directory with package files. This sees all changes,
+    /// but needs one watch per directory.
+    Inotify,
 }
 
 /// Describe how to check config files
@@ -0,0 +1,289 @@
+//! Find paths claimed by more than one package (or backend)
+
+use crate::file_ops::build_ignore_overrides;
+use crate::file_ops::canonicalize_file_entries;
+use ahash::AHashMap;
+use eyre::WrapErr;
+use ignore::Match;
+use paketkoll_types::files::FileEntry;
+use paketkoll_types::files::Properties;
+use paketkoll_types::intern::Interner;
+use paketkoll_types::intern::PackageRef;
+use rayon::prelude::*;
+use smallvec::SmallVec;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// A path that is claimed by more than one party
+#[derive(Debug)]
+pub struct Conflict {
+    pub path: PathBuf,
+    /// Packages owning the path
+    pub packages: SmallVec<[PackageRef; 2]>,
+    pub kind: ConflictKind,
+}
+
+/// What else claims a path owned by a package
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConflictKind {
+    /// Several packages own the path. Lists the properties that the packages
+    /// disagree on (if any).
+    MultiplePackages { differences: Vec<&'static str> },
+    /// The file is diverted (with `dpkg-divert`) by another package
+    Diverted { by: PackageRef, to: PathBuf },
+    /// Another backend (such as systemd-tmpfiles) also manages the path
+    Shadowed { by: &'static str },
+}
+
+/// Find paths owned by several packages, diverted package files, and package
+/// files that are also managed by any of the `shadowing` backends.
+///
+/// Directories are not considered, they are commonly shared.
+pub fn file_conflicts(
+    backend: crate::backend::ConcreteBackend,
+    backend_config: &crate::backend::BackendConfiguration,
+    unexpected_cfg: &crate::config::CheckAllFilesConfiguration,
+    shadowing: &[crate::backend::ConcreteBackend],
+) -> eyre::Result<(Interner, Vec<Conflict>)> {
+    let interner = Interner::new();
+    let mut conflicts = vec![];
+    let mut files = match backend {
+        #[cfg(feature = "debian")]
+        crate::backend::ConcreteBackend::Apt => {
+            let (files, diverted) = crate::backend::deb::unmerged_files(&interner)?;
+            conflicts.extend(diverted.into_iter().map(|diverted| Conflict {
+                path: diverted.path,
+                packages: smallvec::smallvec![diverted.package],
+                kind: ConflictKind::Diverted {
+                    by: diverted.by_package,
+                    to: diverted.new_path,
+                },
+            }));
+            files
+        }
+        _ => backend_files(backend, backend_config, &interner)?,
+    };
+    if unexpected_cfg.canonicalize_paths {
+        tracing::debug!("Canonicalizing paths");
+        canonicalize_file_entries(&mut files);
+    }
+    let overrides = build_ignore_overrides(&unexpected_cfg.ignored_paths)?;
+    files.retain(|entry| !matches!(overrides.matched(&entry.path, false), Match::Ignore(_)));
+
+    tracing::debug!("Finding paths with multiple owners");
+    files.par_sort_unstable_by(|a, b| a.path.cmp(&b.path));
+    let mut owners: AHashMap<&Path, SmallVec<[&FileEntry; 2]>> = AHashMap::new();
+    for entries in files.chunk_by(|a, b| a.path == b.path) {
+        owners.insert(&entries[0].path, collapse_package_entries(entries.iter()));
+    }
+    conflicts.extend(
+        owners
+            .par_iter()
+            .filter(|(_, entries)| entries.len() > 1)
+            .filter(|(_, entries)| distinct_packages(entries, &interner))
+            .filter(|(_, entries)| !is_directory(entries))
+            .map(|(path, entries)| Conflict {
+                path: path.to_path_buf(),
+                packages: entries.iter().filter_map(|e| e.package).collect(),
+                kind: ConflictKind::MultiplePackages {
+                    differences: differences(entries),
+                },
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    for &other in shadowing {
+        tracing::debug!("Finding paths also managed by {other}");
+        let other_files = backend_files(other, backend_config, &interner)?;
+        conflicts.extend(other_files.into_iter().filter_map(|other_entry| {
+            if other_entry.properties.is_dir() == Some(true) {
+                return None;
+            }
+            let entries = owners.get(other_entry.path.as_path())?;
+            if is_directory(entries) {
+                return None;
+            }
+            Some(Conflict {
+                path: other_entry.path,
+                packages: entries.iter().filter_map(|e| e.package).collect(),
+                kind: ConflictKind::Shadowed {
+                    by: other_entry.source,
+                },
+            })
+        }));
+    }
+
+    Ok((interner, conflicts))
+}
+
+fn backend_files(
+    backend: crate::backend::ConcreteBackend,
+    backend_config: &crate::backend::BackendConfiguration,
+    interner: &Interner,
+) -> eyre::Result<Vec<FileEntry>> {
+    backend
+        .create_files(backend_config, interner)
+        .wrap_err_with(|| format!("Failed to create backend for {backend}"))?
+        .files(interner)
+        .wrap_err_with(|| format!("Failed to collect information from backend {backend}"))
+}
+
+/// Reduce the entries for one path to one per package, preferring entries
+/// with known properties (a package may list a path more than once, Debian
+/// does so for regular files for example).
+fn collapse_package_entries<'a>(
+    entries: impl Iterator<Item = &'a FileEntry>,
+) -> SmallVec<[&'a FileEntry; 2]> {
+    let mut result: SmallVec<[&FileEntry; 2]> = SmallVec::new();
+    for entry in entries {
+        match result.iter_mut().find(|e| e.package == entry.package) {
+            Some(existing) => {
+                if matches!(existing.properties, Properties::Unknown) {
+                    *existing = entry;
+                }
+            }
+            None => result.push(entry),
+        }
+    }
+    result
+}
+
+/// Check if the owners are actually different packages (and not the same
+/// package for different architectures, as for Debian multi-arch)
+fn distinct_packages(entries: &[&FileEntry], interner: &Interner) -> bool {
+    let base_name = |entry: &FileEntry| {
+        let name = entry.package.map_or("", |p| p.as_str(interner));
+        name.split_once(':').map_or(name, |(name, _)| name)
+    };
+    let first = base_name(entries[0]);
+    entries.iter().any(|entry| base_name(entry) != first)
+}
+
+/// Check if any owner says this is a directory. If no owner knows, check the
+/// file system (following symlinks, as with /usr merge `/bin` is a symlink to
+/// a directory that packages still list as a directory).
+fn is_directory(entries: &[&FileEntry]) -> bool {
+    match entries.iter().find_map(|entry| entry.properties.is_dir()) {
+        Some(is_dir) => is_dir,
+        None => entries
+            .first()
+            .and_then(|entry| std::fs::metadata(&entry.path).ok())
+            .is_some_and(|metadata| metadata.is_dir()),
+    }
+}
+
+/// Find which properties differ between entries for the same path (ignoring
+/// properties that are unknown for any of them)
+fn differences(entries: &[&FileEntry]) -> Vec<&'static str> {
+    fn differs<T: PartialEq>(
+        entries: &[&FileEntry],
+        get: impl Fn(&Properties) -> Option<T>,
+    ) -> bool {
+        let values: Option<Vec<T>> = entries.iter().map(|e| get(&e.properties)).collect();
+        values.is_some_and(|values| values.iter().any(|v| *v != values[0]))
+    }
+
+    let mut result = vec![];
+    if entries
+        .iter()
+        .all(|e| !matches!(e.properties, Properties::Unknown))
+        && entries
+            .iter()
+            .any(|e| e.properties.type_name() != entries[0].properties.type_name())
+    {
+        result.push("type");
+    }
+    if differs(entries, Properties::mode) {
+        result.push("mode");
+    }
+    if differs(entries, Properties::owner) {
+        result.push("owner");
+    }
+    if differs(entries, Properties::group) {
+        result.push("group");
+    }
+    if differs(entries, Properties::size) {
+        result.push("size");
+    }
+    if differs(entries, |p| p.checksum().cloned()) {
+        result.push("checksum");
+    }
+    if differs(entries, |p| match p {
+        Properties::Symlink(link) => Some(link.target.clone()),
+        _ => None,
+    }) {
+        result.push("target");
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paketkoll_types::files::Checksum;
+    use paketkoll_types::files::FileFlags;
+    use paketkoll_types::files::RegularFileBasic;
+
+    fn entry(interner: &Interner, package: &str, properties: Properties) -> FileEntry {
+        FileEntry {
+            package: Some(PackageRef::get_or_intern(interner, package)),
+            path: "/usr/bin/foo".into(),
+            properties,
+            flags: FileFlags::empty(),
+            source: "test",
+            seen: Default::default(),
+        }
+    }
+
+    fn basic(checksum: u8) -> Properties {
+        Properties::RegularFileBasic(RegularFileBasic {
+            size: None,
+            checksum: Checksum::Md5([checksum; 16]),
+        })
+    }
+
+    #[test]
+    fn test_collapse_and_differences() {
+        let interner = Interner::new();
+        let files = [
+            entry(&interner, "a", Properties::Unknown),
+            entry(&interner, "a", basic(1)),
+            entry(&interner, "b", basic(2)),
+            entry(&interner, "b", Properties::Unknown),
+        ];
+        let collapsed = collapse_package_entries(files.iter());
+        assert_eq!(collapsed.len(), 2);
+        assert_eq!(differences(&collapsed), vec!["checksum"]);
+
+        let files = [
+            entry(&interner, "a", basic(1)),
+            entry(&interner, "b", basic(1)),
+            entry(&interner, "c", Properties::Unknown),
+        ];
+        let collapsed = collapse_package_entries(files.iter());
+        assert_eq!(collapsed.len(), 3);
+        assert!(differences(&collapsed).is_empty());
+    }
+
+    #[test]
+    fn test_distinct_packages() {
+        let interner = Interner::new();
+        let files = [
+            entry(&interner, "libc6:amd64", basic(1)),
+            entry(&interner, "libc6:i386", basic(1)),
+        ];
+        assert!(!distinct_packages(
+            &files.iter().collect::<Vec<_>>(),
+            &interner
+        ));
+        let files = [
+            entry(&interner, "libc6:amd64", basic(1)),
+            entry(&interner, "libc-bin", basic(1)),
+        ];
+        assert!(distinct_packages(
+            &files.iter().collect::<Vec<_>>(),
+            &interner
+        ));
+    }
+}
@@ -0,0 +1,226 @@
+//! Disk usage of files not owned by any package
+
+use crate::file_ops::build_ignore_overrides;
+use crate::file_ops::canonicalize_file_entries;
+use crate::file_ops::create_path_map;
+use crate::file_ops::file_system_walker;
+use ahash::AHashMap;
+use dashmap::DashSet;
+use eyre::WrapErr;
+use ignore::WalkState;
+use paketkoll_types::files::PathMap;
+use paketkoll_types::intern::Interner;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Disk usage of a directory (or file) and the largest entries below it
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct DiskUsage {
+    pub path: PathBuf,
+    /// Allocated size in bytes (like `du`, hard links are only counted once)
+    pub size: u64,
+    /// Number of unexpected file system entries at or below this path
+    pub entries: u64,
+    /// Largest children, sorted by size
+    pub children: Vec<Self>,
+}
+
+/// Find all files not owned by any package along with their allocated size
+pub fn unexpected_files(
+    backend: crate::backend::ConcreteBackend,
+    backend_config: &crate::backend::BackendConfiguration,
+    unexpected_cfg: &crate::config::CheckAllFilesConfiguration,
+) -> eyre::Result<Vec<(PathBuf, u64)>> {
+    let interner = Interner::new();
+    let backend_impl = backend
+        .create_files(backend_config, &interner)
+        .wrap_err_with(|| format!("Failed to create backend for {backend}"))?;
+    let mut expected_files = backend_impl
+        .files(&interner)
+        .wrap_err_with(|| format!("Failed to collect information from backend {backend}"))?;
+    if unexpected_cfg.canonicalize_paths {
+        tracing::debug!("Canonicalizing paths");
+        canonicalize_file_entries(&mut expected_files);
+    }
+    let path_map = create_path_map(&expected_files);
+    let overrides = build_ignore_overrides(&unexpected_cfg.ignored_paths)?;
+
+    tracing::debug!("Walking file system");
+    let (collector, collected) = flume::unbounded();
+    do_walk(&path_map, file_system_walker(overrides), &collector);
+    drop(collector);
+
+    Ok(collected.drain().collect())
+}
+
+#[tracing::instrument(level = "debug", name = "File system walk", skip_all)]
+fn do_walk(
+    path_map: &PathMap<'_>,
+    walker: ignore::WalkParallel,
+    collector: &flume::Sender<(PathBuf, u64)>,
+) {
+    // (device, inode) of files with multiple hard links that we have seen
+    let seen_inodes = DashSet::new();
+    walker.run(|| {
+        Box::new(|entry| {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    tracing::warn!("Error while walking file system: {err}");
+                    return WalkState::Continue;
+                }
+            };
+            if path_map.contains_key(entry.path()) {
+                return WalkState::Continue;
+            }
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    tracing::warn!("Failed to get metadata for {:?}: {err}", entry.path());
+                    return WalkState::Continue;
+                }
+            };
+            let size = if !metadata.is_dir()
+                && metadata.nlink() > 1
+                && !seen_inodes.insert((metadata.dev(), metadata.ino()))
+            {
+                0
+            } else {
+                // st_blocks is always in units of 512 bytes
+                metadata.blocks() * 512
+            };
+            collector
+                .send((entry.path().to_path_buf(), size))
+                .expect("Unbounded queue");
+            WalkState::Continue
+        })
+    });
+}
+
+impl DiskUsage {
+    /// Aggregate file sizes into a tree of directories.
+    ///
+    /// Paths must be absolute. Only entries at most `max_depth` levels below
+    /// `/` are included in the tree (but deeper files still count towards the
+    /// size of their ancestors). If `top` is given, only that many of the
+    /// largest children of each directory are kept.
+    #[must_use]
+    pub fn aggregate(
+        files: impl IntoIterator<Item = (PathBuf, u64)>,
+        max_depth: usize,
+        top: Option<usize>,
+    ) -> Self {
+        let mut totals: AHashMap<PathBuf, (u64, u64)> = AHashMap::new();
+        totals.insert(PathBuf::from("/"), (0, 0));
+        for (path, size) in files {
+            // Absolute paths always have the root component, which is depth 0
+            let depth = path.components().count() - 1;
+            for (ancestor, ancestor_depth) in path.ancestors().zip((0..=depth).rev()) {
+                if ancestor_depth > max_depth {
+                    continue;
+                }
+                let total = match totals.get_mut(ancestor) {
+                    Some(total) => total,
+                    None => totals.entry(ancestor.to_path_buf()).or_default(),
+                };
+                total.0 += size;
+                total.1 += 1;
+            }
+        }
+
+        let mut children: AHashMap<&Path, Vec<&Path>> = AHashMap::new();
+        for path in totals.keys() {
+            if let Some(parent) = path.parent() {
+                children.entry(parent).or_default().push(path);
+            }
+        }
+        Self::build(Path::new("/"), &totals, &children, top)
+    }
+
+    fn build(
+        path: &Path,
+        totals: &AHashMap<PathBuf, (u64, u64)>,
+        children: &AHashMap<&Path, Vec<&Path>>,
+        top: Option<usize>,
+    ) -> Self {
+        let (size, entries) = totals.get(path).copied().unwrap_or_default();
+        let mut nodes: Vec<_> = children
+            .get(path)
+            .into_iter()
+            .flatten()
+            .map(|child| Self::build(child, totals, children, top))
+            .collect();
+        nodes.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        if let Some(top) = top {
+            nodes.truncate(top);
+        }
+        Self {
+            path: path.to_path_buf(),
+            size,
+            entries,
+            children: nodes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn node(path: &str, size: u64, entries: u64, children: Vec<DiskUsage>) -> DiskUsage {
+        DiskUsage {
+            path: path.into(),
+            size,
+            entries,
+            children,
+        }
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let files = vec![
+            ("/var/cache/foo".into(), 4096),
+            ("/var/cache/foo/a".into(), 1000),
+            ("/var/cache/foo/b".into(), 3000),
+            ("/var/log/big.log".into(), 10000),
+            ("/opt/thing".into(), 500),
+        ];
+        let tree = DiskUsage::aggregate(files.clone(), 2, None);
+        assert_eq!(
+            tree,
+            node(
+                "/",
+                18596,
+                5,
+                vec![
+                    node(
+                        "/var",
+                        18096,
+                        4,
+                        vec![
+                            node("/var/log", 10000, 1, vec![]),
+                            node("/var/cache", 8096, 3, vec![]),
+                        ]
+                    ),
+                    node("/opt", 500, 1, vec![node("/opt/thing", 500, 1, vec![])]),
+                ]
+            )
+        );
+
+        let tree = DiskUsage::aggregate(files, 1, Some(1));
+        assert_eq!(
+            tree,
+            node("/", 18596, 5, vec![node("/var", 18096, 4, vec![])])
+        );
+    }
+
+    #[test]
+    fn test_aggregate_empty() {
+        assert_eq!(
+            DiskUsage::aggregate(vec![], 3, Some(10)),
+            node("/", 0, 0, vec![])
+        );
+    }
+}
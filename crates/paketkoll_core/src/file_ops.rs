@@ -1,14 +1,19 @@
 //! Contain file checking functionality
 
+use crate::resource_limits::run_limited;
+use ahash::AHashMap;
+use ahash::AHashSet;
 use compact_str::CompactString;
 use eyre::WrapErr;
 use ignore::Match;
 use ignore::WalkBuilder;
 use ignore::WalkState;
 use ignore::overrides::OverrideBuilder;
+use paketkoll_types::backend::ArchiveQueryError;
 use paketkoll_types::backend::OriginalFileQuery;
 use paketkoll_types::backend::OriginalFilesResult;
 use paketkoll_types::files::FileEntry;
+use paketkoll_types::files::FileFlags;
 use paketkoll_types::files::PathMap;
 use paketkoll_types::intern::Interner;
 use paketkoll_types::intern::PackageRef;
@@ -17,7 +22,11 @@ use paketkoll_types::issue::IssueKind;
 use paketkoll_types::issue::PackageIssue;
 use rayon::prelude::*;
 use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
 
 /// Perform a query of original files
 #[doc(hidden)]
@@ -43,45 +52,260 @@ pub fn original_files(
     Ok(results)
 }
 
+/// Get the files belonging to packages, as recorded by the package manager.
+///
+/// The packages to include are controlled by the package filter in the
+/// backend configuration. If `from_archives` is given, the data is instead
+/// extracted from the package archives of exactly those packages (which may
+/// have more information for some backends, but is much slower).
+pub fn package_files(
+    backend: crate::backend::ConcreteBackend,
+    backend_config: &crate::backend::BackendConfiguration,
+    from_archives: Option<&[&str]>,
+) -> eyre::Result<(Interner, Vec<FileEntry>)> {
+    let interner = Interner::new();
+    let files = match from_archives {
+        None => {
+            let backend_impl = backend
+                .create_files(backend_config, &interner)
+                .wrap_err_with(|| format!("Failed to create backend for {backend}"))?;
+            backend_impl
+                .files(&interner)
+                .wrap_err_with(|| format!("Failed to collect information from backend {backend}"))?
+        }
+        Some(packages) => {
+            let backend_impl = backend
+                .create_full(backend_config, &interner)
+                .wrap_err_with(|| format!("Failed to create backend for {backend}"))?;
+            let package_map = backend_impl
+                .package_map_complete(&interner)
+                .wrap_err_with(|| {
+                    format!("Failed to collect information from backend {backend}")
+                })?;
+            let pkg_refs: Vec<_> = packages
+                .iter()
+                .map(|pkg| PackageRef::get_or_intern(&interner, pkg))
+                .collect();
+            let results = backend_impl
+                .files_from_archives(&pkg_refs, &package_map, &interner)
+                .wrap_err_with(|| {
+                    format!("Failed to collect file information from archives with {backend}")
+                })?;
+            let mut files = Vec::new();
+            for result in results {
+                match result {
+                    Ok((_, entries)) => files.extend(entries),
+                    Err(ArchiveQueryError::PackageMissing { query, .. }) => {
+                        eyre::bail!(
+                            "Failed to find or download package archive for {}",
+                            query.as_str(&interner)
+                        );
+                    }
+                    Err(err) => {
+                        return Err(err).wrap_err("Failed to load files from package archive");
+                    }
+                }
+            }
+            files
+        }
+    };
+    Ok((interner, files))
+}
+
+/// Statistics about what a file system check covered
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CheckStatistics {
+    /// Number of file entries (from the package manager or a baseline)
+    /// checked
+    pub files_checked: usize,
+    /// Packages with files that were checked (without duplicates), with the
+    /// number of file entries checked for each
+    pub packages_checked: Vec<(PackageRef, usize)>,
+}
+
+impl CheckStatistics {
+    /// Collect statistics for the file entries that are about to be checked
+    #[must_use]
+    pub fn from_entries(entries: &[FileEntry]) -> Self {
+        let mut files_per_package: AHashMap<PackageRef, usize> = AHashMap::new();
+        for package in entries.iter().filter_map(|entry| entry.package) {
+            *files_per_package.entry(package).or_default() += 1;
+        }
+        let mut packages_checked: Vec<_> = files_per_package.into_iter().collect();
+        packages_checked.sort_unstable();
+        Self {
+            files_checked: entries.len(),
+            packages_checked,
+        }
+    }
+}
+
+/// Token to stop a running check early
+///
+/// Clones share the same state, so one clone can be used to cancel a check
+/// running on another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request that the check stops as soon as possible
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
 /// Check file system for differences using the given configuration
 pub fn check_installed_files(
     backend: crate::backend::ConcreteBackend,
     backend_config: &crate::backend::BackendConfiguration,
     filecheck_config: &crate::config::CommonFileCheckConfiguration,
-) -> eyre::Result<(Interner, Vec<PackageIssue>)> {
+) -> eyre::Result<(Interner, Vec<PackageIssue>, CheckStatistics)> {
     let interner = Interner::new();
-    let backend_impl = backend
-        .create_files(backend_config, &interner)
-        .wrap_err_with(|| format!("Failed to create backend for {backend}"))?;
     // Get distro specific file list
-    let results = backend_impl
-        .files(&interner)
-        .wrap_err_with(|| format!("Failed to collect information from backend {backend}"))?;
+    let results = layered_files(backend, backend_config, filecheck_config, &interner, false)?;
+    let statistics = CheckStatistics::from_entries(&results);
 
     tracing::debug!("Checking file system");
-    // For all file entries, check on file system
-    // Par-bridge is used here to avoid batching. We do too much work for
-    // batching to be useful, and this way we avoid pathological cases with
-    // slow batches of large files at the end.
-    let mismatches: Vec<_> = results
-        .into_iter()
-        .par_bridge()
-        .filter_map(|file_entry| {
-            match crate::backend::filesystem::check_file(&file_entry, filecheck_config) {
-                Ok(Some(inner)) => Some((file_entry.package, inner)),
-                Ok(None) => None,
-                Err(err) => {
-                    let issues = smallvec::smallvec![IssueKind::FsCheckError(Box::new(err))];
-                    Some((
-                        file_entry.package,
-                        Issue::new(file_entry.path, issues, Some(file_entry.source)),
-                    ))
+    let mismatches = check_file_entries(&results, filecheck_config)?;
+
+    Ok((interner, mismatches, statistics))
+}
+
+/// Streaming version of [`check_installed_files`]
+///
+/// Issues are sent to `collector` as they are found, in no particular order.
+/// The check stops early if `cancel` is cancelled or if the receiving end of
+/// the channel is dropped. The channel is closed when the check is done.
+///
+/// The interner is used for package names and can be used to resolve them
+/// while the check is still running.
+pub fn check_installed_files_streaming(
+    backend: crate::backend::ConcreteBackend,
+    backend_config: &crate::backend::BackendConfiguration,
+    filecheck_config: &crate::config::CommonFileCheckConfiguration,
+    interner: &Interner,
+    collector: flume::Sender<PackageIssue>,
+    cancel: &CancellationToken,
+) -> eyre::Result<CheckStatistics> {
+    let results = layered_files(backend, backend_config, filecheck_config, interner, false)?;
+    let statistics = CheckStatistics::from_entries(&results);
+
+    tracing::debug!("Checking file system");
+    run_limited(filecheck_config, || {
+        // An error means that the check was cancelled, which is not an error
+        // for the caller.
+        let _ = results
+            .par_chunks(check_batch_size(filecheck_config))
+            .try_for_each(|batch| {
+                if cancel.is_cancelled() {
+                    return Err(());
                 }
-            }
-        })
-        .collect();
+                check_file_batch(batch, filecheck_config)
+                    .into_iter()
+                    .try_for_each(|issue| collector.send(issue).map_err(|_| cancel.cancel()))
+            });
+    })?;
+    // Close the channel
+    drop(collector);
+
+    Ok(statistics)
+}
+
+/// Check the given file entries against the file system
+pub fn check_file_entries(
+    file_entries: &[FileEntry],
+    filecheck_config: &crate::config::CommonFileCheckConfiguration,
+) -> eyre::Result<Vec<PackageIssue>> {
+    // For all file entries, check on file system
+    // Par-bridge is used here to avoid rayon's batching. We do too much work
+    // for batching to be useful, and this way we avoid pathological cases with
+    // slow batches of large files at the end. (The batches for io_uring are
+    // small in comparison.)
+    run_limited(filecheck_config, || {
+        file_entries
+            .chunks(check_batch_size(filecheck_config))
+            .par_bridge()
+            .flat_map_iter(|batch| check_file_batch(batch, filecheck_config))
+            .collect()
+    })
+}
+
+/// Number of file entries to check together (see [`check_file_batch`])
+#[cfg(feature = "io_uring")]
+fn check_batch_size(filecheck_config: &crate::config::CommonFileCheckConfiguration) -> usize {
+    if crate::uring::enabled(filecheck_config) {
+        crate::uring::BATCH_SIZE
+    } else {
+        1
+    }
+}
+
+/// Number of file entries to check together (see [`check_file_batch`])
+#[cfg(not(feature = "io_uring"))]
+const fn check_batch_size(_: &crate::config::CommonFileCheckConfiguration) -> usize {
+    1
+}
+
+/// Check a batch of file entries, using `io_uring` if enabled and available
+fn check_file_batch(
+    batch: &[FileEntry],
+    filecheck_config: &crate::config::CommonFileCheckConfiguration,
+) -> Vec<PackageIssue> {
+    #[cfg(feature = "io_uring")]
+    if batch.len() > 1
+        && let Some(results) = crate::uring::check_files(batch, filecheck_config)
+    {
+        return batch
+            .iter()
+            .zip(results)
+            .filter_map(|(file_entry, result)| to_package_issue(file_entry, result))
+            .collect();
+    }
+    batch
+        .iter()
+        .filter_map(|file_entry| check_file_entry(file_entry, filecheck_config))
+        .collect()
+}
+
+/// Check a single file entry, turning errors into issues
+pub(crate) fn check_file_entry(
+    file_entry: &FileEntry,
+    filecheck_config: &crate::config::CommonFileCheckConfiguration,
+) -> Option<PackageIssue> {
+    to_package_issue(
+        file_entry,
+        crate::backend::filesystem::check_file(file_entry, filecheck_config),
+    )
+}
 
-    Ok((interner, mismatches))
+/// Convert the result of checking a file entry, turning errors into issues
+fn to_package_issue(
+    file_entry: &FileEntry,
+    result: eyre::Result<Option<Issue>>,
+) -> Option<PackageIssue> {
+    match result {
+        Ok(Some(inner)) => Some((file_entry.package, inner)),
+        Ok(None) => None,
+        Err(err) => {
+            let issues = smallvec::smallvec![IssueKind::FsCheckError(Box::new(err))];
+            Some((
+                file_entry.package,
+                Issue::new(file_entry.path.clone(), issues, Some(file_entry.source))
+                    .with_config_file(file_entry.flags.contains(FileFlags::CONFIG)),
+            ))
+        }
+    }
 }
 
 /// Check file system for differences (including unexpected files) using the
@@ -91,32 +315,57 @@ pub fn check_all_files(
     backend_config: &crate::backend::BackendConfiguration,
     filecheck_config: &crate::config::CommonFileCheckConfiguration,
     unexpected_cfg: &crate::config::CheckAllFilesConfiguration,
-) -> eyre::Result<(Interner, Vec<PackageIssue>)> {
+) -> eyre::Result<(Interner, Vec<PackageIssue>, CheckStatistics)> {
     let interner = Interner::new();
-    // Collect distro files
-    let backend_impl = backend
-        .create_files(backend_config, &interner)
-        .wrap_err_with(|| format!("Failed to create backend for {backend}"))?;
-    // Get distro specific file list
-    let mut expected_files = backend_impl
-        .files(&interner)
-        .wrap_err_with(|| format!("Failed to collect information from backend {backend}",))?;
-
-    // Possibly canonicalize paths
-    if unexpected_cfg.canonicalize_paths {
-        tracing::debug!("Canonicalizing paths");
-        canonicalize_file_entries(&mut expected_files);
-    }
+    let (collector, collected_issues) = flume::unbounded();
+    let statistics = check_all_files_streaming(
+        backend,
+        backend_config,
+        filecheck_config,
+        unexpected_cfg,
+        &interner,
+        collector,
+        &CancellationToken::new(),
+    )?;
+    let mismatches = collected_issues.drain().collect();
+    Ok((interner, mismatches, statistics))
+}
+
+/// Streaming version of [`check_all_files`]
+///
+/// See [`check_installed_files_streaming`] for how issues are reported and
+/// how to cancel the check. Missing files are only reported at the end, after
+/// the file system walk (and not at all if the check is cancelled).
+pub fn check_all_files_streaming(
+    backend: crate::backend::ConcreteBackend,
+    backend_config: &crate::backend::BackendConfiguration,
+    filecheck_config: &crate::config::CommonFileCheckConfiguration,
+    unexpected_cfg: &crate::config::CheckAllFilesConfiguration,
+    interner: &Interner,
+    collector: flume::Sender<PackageIssue>,
+    cancel: &CancellationToken,
+) -> eyre::Result<CheckStatistics> {
+    // Collect distro files (possibly with canonicalized paths)
+    let expected_files = layered_files(
+        backend,
+        backend_config,
+        filecheck_config,
+        interner,
+        unexpected_cfg.canonicalize_paths,
+    )?;
+    let statistics = CheckStatistics::from_entries(&expected_files);
 
     tracing::debug!("Preparing data structures");
     // We want a hashmap from path to data here.
     let path_map = create_path_map(&expected_files);
 
-    let mismatches = mismatching_and_unexpected_files(
+    find_mismatching_and_unexpected_files(
         &expected_files,
         &path_map,
         filecheck_config,
         unexpected_cfg,
+        collector,
+        cancel,
     )?;
 
     // Drop on a background thread, this help a bit.
@@ -124,7 +373,98 @@ pub fn check_all_files(
     rayon::spawn(move || {
         drop(expected_files);
     });
-    Ok((interner, mismatches))
+    Ok(statistics)
+}
+
+/// Collect the files of the backend and of any additional file backends in
+/// the configuration (see [`merge_file_layers`])
+pub(crate) fn layered_files(
+    backend: crate::backend::ConcreteBackend,
+    backend_config: &crate::backend::BackendConfiguration,
+    filecheck_config: &crate::config::CommonFileCheckConfiguration,
+    interner: &Interner,
+    canonicalize: bool,
+) -> eyre::Result<Vec<FileEntry>> {
+    let mut files = vec![];
+    let extra = filecheck_config
+        .extra_file_backends
+        .iter()
+        .filter(|&&extra| extra != backend);
+    for &backend in std::iter::once(&backend).chain(extra) {
+        let backend_impl = backend
+            .create_files(backend_config, interner)
+            .wrap_err_with(|| format!("Failed to create backend for {backend}"))?;
+        let mut layer = backend_impl
+            .files(interner)
+            .wrap_err_with(|| format!("Failed to collect information from backend {backend}"))?;
+        // Canonicalize before merging, so that the same path is recognised
+        // even if backends disagree about /usr merge.
+        if canonicalize {
+            tracing::debug!("Canonicalizing paths from {backend}");
+            canonicalize_file_entries(&mut layer);
+        }
+        merge_file_layers(&mut files, layer);
+    }
+    if let Some(ref packages) = filecheck_config.verify_contents_of {
+        mark_metadata_only(&mut files, packages, interner);
+    }
+    Ok(files)
+}
+
+/// Only check metadata for files not belonging to the given packages
+///
+/// Package names are compared without any architecture qualifier (Debian
+/// names packages that are Multi-Arch: same as `libfoo:amd64`), as the
+/// package manager logs don't agree with the package database on when to
+/// include it.
+pub(crate) fn mark_metadata_only(
+    files: &mut [FileEntry],
+    packages: &AHashSet<CompactString>,
+    interner: &Interner,
+) {
+    fn base_name(name: &str) -> &str {
+        name.split_once(':').map_or(name, |(name, _)| name)
+    }
+    let packages: AHashSet<&str> = packages.iter().map(|name| base_name(name)).collect();
+    let mut count = 0;
+    for entry in files.iter_mut() {
+        let verify = entry
+            .package
+            .is_some_and(|pkg| packages.contains(base_name(pkg.as_str(interner))));
+        if !verify {
+            entry.flags |= FileFlags::METADATA_ONLY;
+            count += 1;
+        }
+    }
+    tracing::debug!(
+        "Verifying contents of {} files, metadata only for {count}",
+        files.len() - count
+    );
+}
+
+/// Add the files of a lower priority backend to the files collected so far.
+///
+/// Entries for paths that are already present are dropped, so each path is
+/// attributed to (and checked against) the highest priority backend that
+/// lists it.
+pub fn merge_file_layers(files: &mut Vec<FileEntry>, layer: Vec<FileEntry>) {
+    if files.is_empty() {
+        *files = layer;
+        return;
+    }
+    let seen: AHashSet<&Path> = files.iter().map(|entry| entry.path.as_path()).collect();
+    let before = layer.len();
+    let layer: Vec<_> = layer
+        .into_iter()
+        .filter(|entry| !seen.contains(entry.path.as_path()))
+        .collect();
+    drop(seen);
+    tracing::debug!(
+        "Merging {} entries ({} already provided by higher priority backends)",
+        layer.len(),
+        before - layer.len()
+    );
+    files.extend(layer);
 }
 
 /// Find mismatching and unexpected files
@@ -140,102 +480,131 @@ pub fn mismatching_and_unexpected_files<'a>(
     filecheck_config: &crate::config::CommonFileCheckConfiguration,
     unexpected_cfg: &crate::config::CheckAllFilesConfiguration,
 ) -> eyre::Result<Vec<(Option<PackageRef>, Issue)>> {
+    let (collector, collected_issues) = flume::unbounded();
+    find_mismatching_and_unexpected_files(
+        expected_files,
+        path_map,
+        filecheck_config,
+        unexpected_cfg,
+        collector,
+        &CancellationToken::new(),
+    )?;
+
+    tracing::debug!("Collecting results");
+    // Collect all items from queue into vec
+    Ok(collected_issues.drain().collect())
+}
+
+/// Find mismatching and unexpected files, sending issues to the collector as
+/// they are found
+#[allow(clippy::needless_pass_by_value)]
+fn find_mismatching_and_unexpected_files<'a>(
+    expected_files: &'a Vec<FileEntry>,
+    path_map: &PathMap<'a>,
+    filecheck_config: &crate::config::CommonFileCheckConfiguration,
+    unexpected_cfg: &crate::config::CheckAllFilesConfiguration,
+    collector: flume::Sender<PackageIssue>,
+    cancel: &CancellationToken,
+) -> eyre::Result<()> {
     tracing::debug!("Building ignores");
     // Build glob set of ignores
     let overrides = build_ignore_overrides(&unexpected_cfg.ignored_paths)?;
 
-    tracing::debug!("Walking file system");
-    let walker = WalkBuilder::new("/")
-        .hidden(false)
-        .parents(false)
-        .ignore(false)
-        .overrides(overrides.clone())
-        .git_global(false)
-        .git_ignore(false)
-        .git_exclude(false)
-        .follow_links(false)
-        .same_file_system(false)
-        .threads(num_cpus::get())
-        .build_parallel();
-
-    let (collector, collected_issues) = flume::unbounded();
-
-    do_walk(path_map, filecheck_config, walker, collector.clone());
+    run_limited(filecheck_config, || {
+        tracing::debug!("Walking file system");
+        let walker = file_system_walker(overrides.clone());
 
-    tracing::debug!("Identifying and processing missing files");
-    // Identify missing files (we should have seen them walking through the file
-    // system)
-    find_missing_files(expected_files, overrides, collector);
+        do_walk(
+            path_map,
+            filecheck_config,
+            unexpected_cfg,
+            walker,
+            &collector,
+            cancel,
+        );
 
-    tracing::debug!("Collecting results");
-    // Collect all items from queue into vec
-    let mut mismatches = Vec::new();
-    for item in collected_issues.drain() {
-        mismatches.push(item);
-    }
-    Ok(mismatches)
+        // If the walk was cancelled, not all files have been seen.
+        if !cancel.is_cancelled() {
+            tracing::debug!("Identifying and processing missing files");
+            // Identify missing files (we should have seen them walking through
+            // the file system)
+            find_missing_files(
+                expected_files,
+                overrides,
+                filecheck_config,
+                &collector,
+                cancel,
+            );
+        }
+    })
 }
 
 #[tracing::instrument(level = "debug", name = "File system walk", skip_all)]
-#[allow(clippy::needless_pass_by_value)]
 fn do_walk<'a>(
     path_map: &PathMap<'a>,
     filecheck_config: &crate::config::CommonFileCheckConfiguration,
+    unexpected_cfg: &crate::config::CheckAllFilesConfiguration,
     walker: ignore::WalkParallel,
-    collector: flume::Sender<(Option<PackageRef>, Issue)>,
+    collector: &flume::Sender<PackageIssue>,
+    cancel: &CancellationToken,
 ) {
     walker.run(|| {
         Box::new(|entry| {
-            match entry {
+            if cancel.is_cancelled() {
+                return WalkState::Quit;
+            }
+            let mut state = WalkState::Continue;
+            let issue = match entry {
                 Ok(entry) => {
                     let path = entry.path();
+                    if filecheck_config.is_skipped_network_path(path) {
+                        return WalkState::Skip;
+                    }
                     if let Some(file_entry) = path_map.get(path) {
-                        file_entry
-                            .seen
-                            .store(true, std::sync::atomic::Ordering::Relaxed);
-                        match crate::backend::filesystem::check_file(file_entry, filecheck_config) {
-                            Ok(Some(inner)) => {
-                                collector
-                                    .send((file_entry.package, inner))
-                                    .expect("Unbounded queue");
-                            }
-                            Ok(None) => (),
-                            Err(err) => {
-                                let issues =
-                                    smallvec::smallvec![IssueKind::FsCheckError(Box::new(err))];
-                                collector
-                                    .send((
-                                        file_entry.package,
-                                        Issue::new(
-                                            file_entry.path.clone(),
-                                            issues,
-                                            Some(file_entry.source),
-                                        ),
-                                    ))
-                                    .expect("Unbounded queue");
-                            }
+                        file_entry.seen.store(true, Ordering::Relaxed);
+                        check_file_entry(file_entry, filecheck_config)
+                    } else if let Some(by) = unexpected_cfg
+                        .generated_files
+                        .as_ref()
+                        .and_then(|generated| generated.generator(path))
+                    {
+                        // Known generated file (or directory, in which case
+                        // everything in it is generated as well)
+                        if entry.file_type().is_some_and(|t| t.is_dir()) {
+                            state = WalkState::Skip;
                         }
-                    } else {
-                        // Unexpected file found
-                        collector
-                            .send((
+                        unexpected_cfg.report_generated.then(|| {
+                            (
                                 None,
                                 Issue::new(
                                     path.to_path_buf(),
-                                    smallvec::smallvec![IssueKind::Unexpected],
+                                    smallvec::smallvec![IssueKind::Generated { by: by.into() }],
                                     None,
                                 ),
-                            ))
-                            .expect("Unbounded queue");
+                            )
+                        })
+                    } else {
+                        // Unexpected file found
+                        Some((
+                            None,
+                            Issue::new(
+                                path.to_path_buf(),
+                                smallvec::smallvec![IssueKind::Unexpected],
+                                None,
+                            ),
+                        ))
                     }
                 }
-                Err(ignore_err) => {
-                    collector
-                        .send(interpret_ignore_error(ignore_err, None))
-                        .expect("Unbounded queue");
-                }
+                Err(ignore_err) => Some(interpret_ignore_error(ignore_err, None)),
+            };
+            // Sending fails if the receiver is gone, no point in continuing
+            if let Some(issue) = issue
+                && collector.send(issue).is_err()
+            {
+                cancel.cancel();
+                return WalkState::Quit;
             }
-            WalkState::Continue
+            state
         })
     });
 }
@@ -245,23 +614,31 @@ fn do_walk<'a>(
 fn find_missing_files(
     expected_files: &Vec<FileEntry>,
     overrides: ignore::overrides::Override,
-    collector: flume::Sender<(Option<PackageRef>, Issue)>,
+    filecheck_config: &crate::config::CommonFileCheckConfiguration,
+    collector: &flume::Sender<PackageIssue>,
+    cancel: &CancellationToken,
 ) {
-    expected_files.par_iter().for_each(|file_entry| {
-        if file_entry.seen.load(std::sync::atomic::Ordering::Relaxed) {
-            return;
+    // An error means that the check was cancelled
+    let _ = expected_files.par_iter().try_for_each(|file_entry| {
+        if cancel.is_cancelled() {
+            return Err(());
+        }
+        if file_entry.seen.load(Ordering::Relaxed)
+            || filecheck_config.is_skipped_network_path(&file_entry.path)
+        {
+            return Ok(());
         }
         if let Match::Ignore(_) = overrides.matched(
             &file_entry.path,
             file_entry.properties.is_dir().unwrap_or(false),
         ) {
-            return;
+            return Ok(());
         }
         // We also need to check the parent directories against ignores
         for parent in file_entry.path.ancestors() {
             match overrides.matched(parent, true) {
                 Match::None => (),
-                Match::Ignore(_) => return,
+                Match::Ignore(_) => return Ok(()),
                 Match::Whitelist(_) => break,
             }
         }
@@ -272,16 +649,35 @@ fn find_missing_files(
                     file_entry.path.clone(),
                     smallvec::smallvec![IssueKind::Missing],
                     Some(file_entry.source),
-                ),
+                )
+                .with_config_file(file_entry.flags.contains(FileFlags::CONFIG)),
             ))
-            .expect("Unbounded queue");
+            .map_err(|_| cancel.cancel())
     });
 }
 
+/// Create a parallel walker over the whole file system, not following symlinks
+///
+/// This uses as many threads as the current rayon thread pool.
+pub(crate) fn file_system_walker(overrides: ignore::overrides::Override) -> ignore::WalkParallel {
+    WalkBuilder::new("/")
+        .hidden(false)
+        .parents(false)
+        .ignore(false)
+        .overrides(overrides)
+        .git_global(false)
+        .git_ignore(false)
+        .git_exclude(false)
+        .follow_links(false)
+        .same_file_system(false)
+        .threads(rayon::current_num_threads())
+        .build_parallel()
+}
+
 #[doc(hidden)]
 /// Build the ignore overrides for the given configuration
 pub fn build_ignore_overrides(
-    ignored_paths: &Vec<CompactString>,
+    ignored_paths: &[CompactString],
 ) -> eyre::Result<ignore::overrides::Override> {
     let mut builder = OverrideBuilder::new("/");
     for pattern in BUILTIN_IGNORES {
@@ -404,3 +800,83 @@ const BUILTIN_IGNORES: &[&str] = &[
     "!/tmp/",
     "!/var/tmp/",
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paketkoll_types::files::FileFlags;
+    use paketkoll_types::files::Properties;
+
+    fn entry(path: &str, source: &'static str) -> FileEntry {
+        FileEntry {
+            package: None,
+            path: path.into(),
+            properties: Properties::Unknown,
+            flags: FileFlags::empty(),
+            source,
+            seen: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_merge_file_layers() {
+        let mut files = vec![];
+        merge_file_layers(&mut files, vec![entry("/a", "first"), entry("/a", "first")]);
+        merge_file_layers(
+            &mut files,
+            vec![entry("/a", "second"), entry("/b", "second")],
+        );
+        merge_file_layers(&mut files, vec![entry("/b", "third"), entry("/c", "third")]);
+        let merged: Vec<_> = files
+            .iter()
+            .map(|e| (e.path.to_str().unwrap(), e.source))
+            .collect();
+        // Duplicates within a layer are kept, only later layers are filtered
+        assert_eq!(
+            merged,
+            vec![
+                ("/a", "first"),
+                ("/a", "first"),
+                ("/b", "second"),
+                ("/c", "third")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mark_metadata_only() {
+        let interner = Interner::new();
+        let owned = |package: &str, path: &str| FileEntry {
+            package: Some(PackageRef::get_or_intern(&interner, package)),
+            ..entry(path, "test")
+        };
+        // Multi-Arch: same packages are arch qualified in the package database,
+        // but not always in the package manager logs
+        let mut files = vec![
+            owned("foo:amd64", "/usr/lib/libfoo.so.1"),
+            owned("bar", "/usr/bin/bar"),
+            owned("baz", "/usr/bin/baz"),
+            entry("/etc/unowned", "test"),
+        ];
+        let packages = AHashSet::from_iter(["foo".into(), "bar:amd64".into()]);
+        mark_metadata_only(&mut files, &packages, &interner);
+        let metadata_only: Vec<_> = files
+            .iter()
+            .map(|e| {
+                (
+                    e.path.to_str().unwrap(),
+                    e.flags.contains(FileFlags::METADATA_ONLY),
+                )
+            })
+            .collect();
+        assert_eq!(
+            metadata_only,
+            vec![
+                ("/usr/lib/libfoo.so.1", false),
+                ("/usr/bin/bar", false),
+                ("/usr/bin/baz", true),
+                ("/etc/unowned", true)
+            ]
+        );
+    }
+}
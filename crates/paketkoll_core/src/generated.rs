@@ -0,0 +1,179 @@
+//! Knowledge base of files generated by package scripts, hooks and services
+//!
+//! These files are not owned by any package, but are still an expected part
+//! of the system (caches, host keys, initramfs images, ...).
+
+use compact_str::CompactString;
+use eyre::WrapErr;
+use globset::GlobBuilder;
+use globset::GlobSet;
+use globset::GlobSetBuilder;
+use std::path::Path;
+
+/// Marker for rules that say that matching paths are *not* generated
+const NOT_GENERATED: &str = "-";
+
+/// A rule attributing paths matching a glob to what generated them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeneratedRule {
+    /// Glob matching the generated paths. Use `**` to match any number of
+    /// path components.
+    pub pattern: CompactString,
+    /// Package, hook or command generating the files. `None` means that
+    /// matching paths are not generated (to override earlier rules).
+    pub generator: Option<CompactString>,
+}
+
+impl GeneratedRule {
+    /// Parse rules, one per line in the form `<glob> <generator>`.
+    ///
+    /// Empty lines and lines starting with `#` are ignored. A generator of
+    /// `-` marks matching paths as not generated.
+    pub fn parse(rules: &str) -> eyre::Result<Vec<Self>> {
+        let mut result = vec![];
+        for (idx, line) in rules.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((pattern, generator)) = line.split_once(char::is_whitespace) else {
+                eyre::bail!("Line {}: expected a glob and a generator", idx + 1);
+            };
+            let generator = generator.trim();
+            result.push(Self {
+                pattern: pattern.into(),
+                generator: (generator != NOT_GENERATED).then(|| generator.into()),
+            });
+        }
+        Ok(result)
+    }
+
+    /// Get the built in rules for a backend
+    #[must_use]
+    pub fn builtin(backend: crate::backend::ConcreteBackend) -> Vec<Self> {
+        let distro = match backend {
+            #[cfg(feature = "arch_linux")]
+            crate::backend::ConcreteBackend::Pacman => {
+                include_str!("../data/generated/arch_linux.rules")
+            }
+            #[cfg(feature = "debian")]
+            crate::backend::ConcreteBackend::Apt => include_str!("../data/generated/debian.rules"),
+            _ => "",
+        };
+        let mut rules = Self::parse(include_str!("../data/generated/common.rules"))
+            .expect("Builtin rules failed to parse");
+        rules.extend(Self::parse(distro).expect("Builtin rules failed to parse"));
+        rules
+    }
+}
+
+/// Matcher for generated files
+#[derive(Debug, Clone)]
+pub struct GeneratedFiles {
+    globs: GlobSet,
+    generators: Vec<Option<CompactString>>,
+}
+
+impl GeneratedFiles {
+    /// Build a matcher from a set of rules. Later rules take precedence over
+    /// earlier ones.
+    pub fn new(rules: impl IntoIterator<Item = GeneratedRule>) -> eyre::Result<Self> {
+        let mut builder = GlobSetBuilder::new();
+        let mut generators = vec![];
+        for rule in rules {
+            let glob = GlobBuilder::new(&rule.pattern)
+                .literal_separator(true)
+                .build()
+                .wrap_err_with(|| format!("Invalid glob {:?}", rule.pattern))?;
+            builder.add(glob);
+            generators.push(rule.generator);
+        }
+        Ok(Self {
+            globs: builder.build()?,
+            generators,
+        })
+    }
+
+    /// Find what generated a path, if it is a known generated file
+    #[must_use]
+    pub fn generator(&self, path: &Path) -> Option<&str> {
+        let idx = self.globs.matches(path).into_iter().max()?;
+        self.generators[idx].as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_parse() {
+        let rules = GeneratedRule::parse(indoc! {"
+            # A comment
+            /etc/ld.so.cache   ldconfig
+
+            /etc/ssh/ssh_host_*  ssh-keygen (host keys)
+            /etc/machine-id -
+        "})
+        .unwrap();
+        assert_eq!(
+            rules,
+            vec![
+                GeneratedRule {
+                    pattern: "/etc/ld.so.cache".into(),
+                    generator: Some("ldconfig".into()),
+                },
+                GeneratedRule {
+                    pattern: "/etc/ssh/ssh_host_*".into(),
+                    generator: Some("ssh-keygen (host keys)".into()),
+                },
+                GeneratedRule {
+                    pattern: "/etc/machine-id".into(),
+                    generator: None,
+                },
+            ]
+        );
+        assert!(GeneratedRule::parse("/etc/ld.so.cache").is_err());
+    }
+
+    #[test]
+    fn test_generator() {
+        let mut rules = GeneratedRule::builtin(crate::backend::ConcreteBackend::Flatpak);
+        rules.extend(GeneratedRule::parse("/etc/machine-id -\n/etc/foo/* foo").unwrap());
+        let generated = GeneratedFiles::new(rules).unwrap();
+        assert_eq!(
+            generated.generator(Path::new("/etc/ld.so.cache")),
+            Some("ldconfig")
+        );
+        assert_eq!(
+            generated.generator(Path::new("/usr/lib/python3.12/json/__pycache__")),
+            Some("python (byte compilation)")
+        );
+        assert_eq!(
+            generated.generator(Path::new("/usr/lib/modules/6.9.1-arch1-1/modules.dep")),
+            Some("depmod")
+        );
+        // Overridden by a later rule
+        assert_eq!(generated.generator(Path::new("/etc/machine-id")), None);
+        // * doesn't match across directories
+        assert_eq!(generated.generator(Path::new("/etc/foo/bar")), Some("foo"));
+        assert_eq!(generated.generator(Path::new("/etc/foo/bar/baz")), None);
+        assert_eq!(generated.generator(Path::new("/etc/fstab")), None);
+    }
+
+    #[test]
+    fn test_builtin_rules_parse() {
+        for backend in [
+            #[cfg(feature = "arch_linux")]
+            crate::backend::ConcreteBackend::Pacman,
+            #[cfg(feature = "debian")]
+            crate::backend::ConcreteBackend::Apt,
+            crate::backend::ConcreteBackend::Flatpak,
+        ] {
+            let rules = GeneratedRule::builtin(backend);
+            assert!(!rules.is_empty());
+            GeneratedFiles::new(rules).unwrap();
+        }
+    }
+}
@@ -13,8 +13,27 @@ pub use paketkoll_types;
 #[cfg(not(any(feature = "arch_linux", feature = "debian")))]
 compile_error!("At least one backend must be enabled");
 
+#[cfg(feature = "audit")]
+pub mod audit;
 pub mod backend;
+pub mod baseline;
+pub mod compat;
 pub mod config;
+pub mod conflicts;
+pub mod disk_usage;
 pub mod file_ops;
+pub mod generated;
+pub mod mtree;
+pub mod package_log;
 pub mod package_ops;
+pub mod permissions;
+pub mod reports;
+pub mod resource_limits;
+pub mod shared_libs;
+pub mod time;
+#[cfg(feature = "io_uring")]
+mod uring;
 pub mod utils;
+pub mod waivers;
+#[cfg(feature = "watch")]
+pub mod watch;
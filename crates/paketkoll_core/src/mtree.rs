@@ -0,0 +1,299 @@
+//! Conversion between [`FileEntry`] and mtree specifications
+
+use eyre::OptionExt;
+use eyre::WrapErr;
+use mtree2::MTree;
+use mtree2::MTreeWriter;
+use paketkoll_types::files::Checksum;
+use paketkoll_types::files::DeviceNode;
+use paketkoll_types::files::DeviceType;
+use paketkoll_types::files::Directory;
+use paketkoll_types::files::Fifo;
+use paketkoll_types::files::FileEntry;
+use paketkoll_types::files::FileFlags;
+use paketkoll_types::files::Gid;
+use paketkoll_types::files::Mode;
+use paketkoll_types::files::Properties;
+use paketkoll_types::files::RegularFile;
+use paketkoll_types::files::Symlink;
+use paketkoll_types::files::Uid;
+use paketkoll_utils::MODE_MASK;
+use std::ffi::OsStr;
+use std::io::Read;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Write file entries as an mtree specification
+///
+/// All paths are written relative to the root directory. Package information
+/// and file flags are not included, as mtree has no way to represent those.
+pub fn write_entries<'entry>(
+    writer: &mut impl Write,
+    entries: impl Iterator<Item = &'entry FileEntry>,
+) -> eyre::Result<()> {
+    let entries: Vec<mtree2::Entry> = entries.map(convert_entry).collect();
+    MTreeWriter::new().write(writer, &entries)?;
+    Ok(())
+}
+
+/// Convert a [`FileEntry`] to an mtree entry
+fn convert_entry(entry: &FileEntry) -> mtree2::Entry {
+    let properties = &entry.properties;
+    let mut item = mtree2::Entry::new(&entry.path);
+    item.set_file_type(mtree_type(properties))
+        .set_mode(properties.mode().map(|m| (m.as_raw() & MODE_MASK).into()))
+        .set_uid(properties.owner().map(Uid::as_raw))
+        .set_gid(properties.group().map(Gid::as_raw))
+        .set_size(properties.size());
+    match properties.checksum() {
+        Some(Checksum::Md5(value)) => {
+            item.set_md5(Some(u128::from_be_bytes(*value)));
+        }
+        Some(Checksum::Sha256(value)) => {
+            item.set_sha256(Some(*value));
+        }
+        Some(_) | None => (),
+    }
+    match properties {
+        Properties::RegularFile(RegularFile { mtime, .. }) => {
+            item.set_time(Some(*mtime));
+        }
+        Properties::Symlink(link) => {
+            item.set_link(Some(link.target.clone()));
+        }
+        Properties::DeviceNode(node) => {
+            item.set_device(Some(mtree2::Device {
+                format: mtree2::Format::Linux,
+                major: node.major.to_string().into_bytes(),
+                minor: node.minor.to_string().into_bytes(),
+                subunit: None,
+            }));
+        }
+        _ => (),
+    }
+    item
+}
+
+/// Get the mtree type for properties (if there is one)
+const fn mtree_type(properties: &Properties) -> Option<mtree2::FileType> {
+    match properties {
+        Properties::RegularFileBasic(_)
+        | Properties::RegularFileSystemd(_)
+        | Properties::RegularFile(_) => Some(mtree2::FileType::File),
+        Properties::Symlink(_) => Some(mtree2::FileType::SymbolicLink),
+        Properties::Directory(_) => Some(mtree2::FileType::Directory),
+        Properties::Fifo(_) => Some(mtree2::FileType::Fifo),
+        Properties::DeviceNode(DeviceNode {
+            device_type: DeviceType::Block,
+            ..
+        }) => Some(mtree2::FileType::BlockDevice),
+        Properties::DeviceNode(DeviceNode {
+            device_type: DeviceType::Char,
+            ..
+        }) => Some(mtree2::FileType::CharacterDevice),
+        // On Linux sockets are the only file type without properties of its
+        // own, and they are read back as such
+        Properties::Special => Some(mtree2::FileType::Socket),
+        Properties::Removed | Properties::Unknown | Properties::Permissions(_) => None,
+    }
+}
+
+/// Read file entries from an mtree specification
+///
+/// This is the inverse of [`write_entries`]. All entries will get the given
+/// source and no package.
+pub fn read_entries(reader: impl Read, source: &'static str) -> eyre::Result<Vec<FileEntry>> {
+    MTree::from_reader_with_cwd(reader, PathBuf::from("/"))
+        .map(|item| {
+            let item = item.wrap_err("Failed to parse mtree")?;
+            Ok(FileEntry {
+                package: None,
+                path: extract_path(&item),
+                properties: convert_properties(&item)
+                    .wrap_err_with(|| format!("Invalid mtree entry for {:?}", item.path()))?,
+                flags: FileFlags::empty(),
+                source,
+                seen: Default::default(),
+            })
+        })
+        .collect()
+}
+
+/// Convert the parameters of an mtree entry to [`Properties`]
+fn convert_properties(item: &mtree2::Entry) -> eyre::Result<Properties> {
+    let owner = || -> eyre::Result<Uid> { Ok(Uid::new(item.uid().ok_or_eyre("No uid")?)) };
+    let group = || -> eyre::Result<Gid> { Ok(Gid::new(item.gid().ok_or_eyre("No gid")?)) };
+    let mode =
+        || -> eyre::Result<Mode> { Ok(Mode::new(item.mode().ok_or_eyre("No mode")?.into())) };
+    Ok(match item.file_type() {
+        Some(mtree2::FileType::File) => Properties::RegularFile(RegularFile {
+            mode: mode()?,
+            owner: owner()?,
+            group: group()?,
+            size: item.size().ok_or_eyre("No size")?,
+            mtime: item.time().ok_or_eyre("No mtime")?,
+            checksum: match (item.sha256(), item.md5()) {
+                (Some(sha256), _) => Checksum::Sha256(*sha256),
+                (None, Some(md5)) => Checksum::Md5(md5.to_be_bytes()),
+                (None, None) => eyre::bail!("No checksum"),
+            },
+        }),
+        Some(mtree2::FileType::Directory) => Properties::Directory(Directory {
+            mode: mode()?,
+            owner: owner()?,
+            group: group()?,
+        }),
+        Some(mtree2::FileType::SymbolicLink) => Properties::Symlink(Symlink {
+            owner: owner()?,
+            group: group()?,
+            target: item.link().ok_or_eyre("No link target")?.into(),
+        }),
+        Some(mtree2::FileType::Fifo) => Properties::Fifo(Fifo {
+            mode: mode()?,
+            owner: owner()?,
+            group: group()?,
+        }),
+        Some(file_type @ (mtree2::FileType::BlockDevice | mtree2::FileType::CharacterDevice)) => {
+            let device = item.device().ok_or_eyre("No device")?;
+            let parse =
+                |value: &[u8]| -> eyre::Result<u64> { Ok(std::str::from_utf8(value)?.parse()?) };
+            Properties::DeviceNode(DeviceNode {
+                mode: mode()?,
+                owner: owner()?,
+                group: group()?,
+                device_type: if file_type == mtree2::FileType::BlockDevice {
+                    DeviceType::Block
+                } else {
+                    DeviceType::Char
+                },
+                major: parse(&device.major)?,
+                minor: parse(&device.minor)?,
+            })
+        }
+        Some(mtree2::FileType::Socket) => Properties::Special,
+        None => Properties::Unknown,
+    })
+}
+
+/// Extract the path from an mtree entry, they start with a . which we want to
+/// remove
+pub(crate) fn extract_path(item: &mtree2::Entry) -> PathBuf {
+    let path = item.path();
+    let as_bytes = path.as_os_str().as_encoded_bytes();
+    if as_bytes[0] == b'.' {
+        // SAFETY:
+        // * The encoding is "an unspecified, platform-specific, self-synchronizing
+        //   superset of UTF-8"
+        // * We are removing a leading ASCII character here (.).
+        // * Thus, the buffer still contains the same superset of UTF-8
+        PathBuf::from(unsafe { OsStr::from_encoded_bytes_unchecked(&as_bytes[1..]) })
+    } else {
+        path.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_entries;
+    use super::write_entries;
+    use paketkoll_types::files::Checksum;
+    use paketkoll_types::files::DeviceNode;
+    use paketkoll_types::files::DeviceType;
+    use paketkoll_types::files::Directory;
+    use paketkoll_types::files::FileEntry;
+    use paketkoll_types::files::FileFlags;
+    use paketkoll_types::files::Gid;
+    use paketkoll_types::files::Mode;
+    use paketkoll_types::files::Properties;
+    use paketkoll_types::files::RegularFile;
+    use paketkoll_types::files::Symlink;
+    use paketkoll_types::files::Uid;
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+    use std::time::UNIX_EPOCH;
+
+    fn entry(path: &str, properties: Properties) -> FileEntry {
+        FileEntry {
+            package: None,
+            path: path.into(),
+            properties,
+            flags: FileFlags::empty(),
+            source: "test",
+            seen: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let entries = [
+            entry(
+                "/etc",
+                Properties::Directory(Directory {
+                    mode: Mode::new(0o755),
+                    owner: Uid::new(0),
+                    group: Gid::new(0),
+                }),
+            ),
+            entry(
+                "/etc/with space#and\\odd",
+                Properties::RegularFile(RegularFile {
+                    mode: Mode::new(0o640),
+                    owner: Uid::new(0),
+                    group: Gid::new(42),
+                    size: 1234,
+                    mtime: UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789),
+                    checksum: Checksum::Sha256([0xab; 32]),
+                }),
+            ),
+            entry(
+                "/etc/old.conf",
+                Properties::RegularFile(RegularFile {
+                    mode: Mode::new(0o644),
+                    owner: Uid::new(1000),
+                    group: Gid::new(1000),
+                    size: 0,
+                    mtime: UNIX_EPOCH + Duration::new(1_600_000_000, 0),
+                    checksum: Checksum::Md5([
+                        0xd4, 0x1d, 0x8c, 0xd9, 0x8f, 0x00, 0xb2, 0x04, 0xe9, 0x80, 0x09, 0x98,
+                        0xec, 0xf8, 0x42, 0x7e,
+                    ]),
+                }),
+            ),
+            entry(
+                "/etc/localtime",
+                Properties::Symlink(Symlink {
+                    owner: Uid::new(0),
+                    group: Gid::new(0),
+                    target: "/usr/share/zoneinfo/UTC".into(),
+                }),
+            ),
+            entry(
+                "/dev/null",
+                Properties::DeviceNode(DeviceNode {
+                    mode: Mode::new(0o666),
+                    owner: Uid::new(0),
+                    group: Gid::new(0),
+                    device_type: DeviceType::Char,
+                    major: 1,
+                    minor: 3,
+                }),
+            ),
+            entry("/run/foo.sock", Properties::Special),
+        ];
+
+        let mut buffer = Vec::new();
+        write_entries(&mut buffer, entries.iter()).unwrap();
+        let parsed = read_entries(buffer.as_slice(), "test").unwrap();
+
+        assert_eq!(
+            parsed
+                .iter()
+                .map(|e| (&e.path, &e.properties))
+                .collect::<Vec<_>>(),
+            entries
+                .iter()
+                .map(|e| (&e.path, &e.properties))
+                .collect::<Vec<_>>()
+        );
+    }
+}
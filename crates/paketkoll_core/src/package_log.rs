@@ -0,0 +1,713 @@
+//! Package transaction history from package manager logs
+//!
+//! This is also used for incremental checks, where only the contents of files
+//! of changed packages are verified.
+
+use crate::backend::ConcreteBackend;
+use crate::time::CivilTime;
+use ahash::AHashSet;
+use compact_str::CompactString;
+use eyre::WrapErr;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// dpkg logs, newest first (rotated logs that are compressed are not read)
+#[cfg(feature = "debian")]
+const DPKG_LOGS: &[&str] = &["/var/log/dpkg.log", "/var/log/dpkg.log.1"];
+
+/// apt history logs, newest first
+#[cfg(feature = "debian")]
+const APT_HISTORY_LOGS: &[&str] = &["/var/log/apt/history.log", "/var/log/apt/history.log.1"];
+
+/// Transactions found in the package manager logs
+#[derive(Debug, Default)]
+#[non_exhaustive]
+pub struct History {
+    /// Transactions, ordered by start time
+    pub transactions: Vec<Transaction>,
+    /// Earliest time the logs have entries for
+    pub earliest: Option<i64>,
+}
+
+impl History {
+    /// Note that the logs cover the given time
+    fn saw(&mut self, time: i64) {
+        self.earliest = Some(self.earliest.map_or(time, |earliest| earliest.min(time)));
+    }
+
+    /// Add a finished transaction, unless it is empty
+    fn finish(&mut self, transaction: Option<Transaction>) {
+        if let Some(transaction) = transaction
+            && !transaction.changes.is_empty()
+        {
+            self.transactions.push(transaction);
+        }
+    }
+}
+
+/// A set of package changes done together
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[non_exhaustive]
+pub struct Transaction {
+    /// Start time in seconds since the Unix epoch
+    pub start: i64,
+    /// End time in seconds since the Unix epoch (if known)
+    pub end: Option<i64>,
+    /// Which log this transaction comes from
+    pub source: &'static str,
+    /// Command that performed the transaction (if known)
+    pub command: Option<CompactString>,
+    /// User that requested the transaction (if known)
+    pub requested_by: Option<CompactString>,
+    pub changes: Vec<PackageChange>,
+}
+
+impl Transaction {
+    const fn new(start: i64, source: &'static str, command: Option<CompactString>) -> Self {
+        Self {
+            start,
+            end: None,
+            source,
+            command,
+            requested_by: None,
+            changes: vec![],
+        }
+    }
+}
+
+/// A change to a single package
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[non_exhaustive]
+pub struct PackageChange {
+    pub action: Action,
+    /// Package name (without architecture)
+    pub package: CompactString,
+    pub old_version: Option<CompactString>,
+    pub new_version: Option<CompactString>,
+}
+
+impl PackageChange {
+    fn new(
+        action: Action,
+        package: &str,
+        old_version: Option<&str>,
+        new_version: Option<&str>,
+    ) -> Self {
+        let package = package.split_once(':').map_or(package, |(name, _)| name);
+        Self {
+            action,
+            package: package.into(),
+            old_version: old_version.map(Into::into),
+            new_version: new_version.map(Into::into),
+        }
+    }
+}
+
+/// What happened to a package
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, strum::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Action {
+    Install,
+    Upgrade,
+    Downgrade,
+    Reinstall,
+    Remove,
+    /// Removed including configuration files (Debian)
+    Purge,
+}
+
+/// Parse a point in time given on the command line
+///
+/// Accepts `YYYY-MM-DD`, optionally followed by a space or `T` and
+/// `HH:MM[:SS]` in local time, or `@<seconds since the Unix epoch>`.
+pub fn parse_time(s: &str) -> eyre::Result<i64> {
+    if let Some(secs) = s.strip_prefix('@') {
+        return secs
+            .parse()
+            .wrap_err_with(|| format!("Invalid Unix timestamp {secs:?}"));
+    }
+    CivilTime::parse(s)
+        .and_then(CivilTime::to_local_timestamp)
+        .ok_or_else(|| eyre::eyre!("Invalid time {s:?} (expected YYYY-MM-DD [HH:MM[:SS]])"))
+}
+
+/// Format seconds since the Unix epoch as local time (`YYYY-MM-DD HH:MM:SS`)
+#[must_use]
+pub fn format_local_time(time: i64) -> String {
+    match CivilTime::from_local_timestamp(time) {
+        Some(civil) => civil.to_string(),
+        None => format!("@{time}"),
+    }
+}
+
+/// Read the transaction history of a backend from its logs
+pub fn history(backend: ConcreteBackend) -> eyre::Result<History> {
+    let mut history = History::default();
+    match backend {
+        #[cfg(feature = "arch_linux")]
+        ConcreteBackend::Pacman => {
+            let path = crate::backend::arch::log_file()?;
+            if let Some(log) = read_log(&path)? {
+                parse_pacman_log(&log, &mut history);
+            }
+        }
+        #[cfg(feature = "debian")]
+        ConcreteBackend::Apt => {
+            for path in APT_HISTORY_LOGS {
+                if let Some(log) = read_log(Path::new(path))? {
+                    parse_apt_history_log(&log, &mut history);
+                }
+            }
+            let mut dpkg = History::default();
+            for path in DPKG_LOGS {
+                if let Some(log) = read_log(Path::new(path))? {
+                    parse_dpkg_log(&log, &mut dpkg);
+                }
+            }
+            merge_dpkg_history(&mut history, dpkg);
+        }
+        _ => eyre::bail!("Reading the package history is not supported for {backend}"),
+    }
+    history
+        .transactions
+        .sort_by_key(|transaction| transaction.start);
+    Ok(history)
+}
+
+/// Find the packages that were installed, upgraded or removed since the given
+/// time (in seconds since the Unix epoch).
+///
+/// Returns `None` if the logs don't go back far enough to tell.
+pub fn changed_packages(
+    backend: ConcreteBackend,
+    since: i64,
+) -> eyre::Result<Option<AHashSet<CompactString>>> {
+    Ok(changed_since(&history(backend)?, since))
+}
+
+fn changed_since(history: &History, since: i64) -> Option<AHashSet<CompactString>> {
+    if history.earliest.is_none_or(|earliest| earliest > since) {
+        tracing::debug!(
+            "Logs start at {:?}, which is after {since}",
+            history.earliest
+        );
+        return None;
+    }
+    Some(
+        history
+            .transactions
+            .iter()
+            .filter(|transaction| transaction.end.unwrap_or(transaction.start) >= since)
+            .flat_map(|transaction| &transaction.changes)
+            .map(|change| change.package.clone())
+            .collect(),
+    )
+}
+
+/// Read a log file, returning `None` if it doesn't exist
+fn read_log(path: &Path) -> eyre::Result<Option<String>> {
+    match std::fs::read(path) {
+        // Logs may contain output from scriptlets in any encoding
+        Ok(data) => Ok(Some(String::from_utf8_lossy(&data).into_owned())),
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            tracing::debug!("Log file {path:?} does not exist");
+            Ok(None)
+        }
+        Err(err) => Err(err).wrap_err_with(|| format!("Failed to read {path:?}")),
+    }
+}
+
+/// Parse `/var/log/pacman.log`
+///
+/// Lines look like `[2024-05-01T12:34:56+0200] [ALPM] upgraded foo (1.0 ->
+/// 1.1)`. Older versions of pacman used `[2019-01-01 12:34]` in local time,
+/// no `[ALPM]` tag and didn't log the start and end of transactions.
+#[cfg(feature = "arch_linux")]
+fn parse_pacman_log(log: &str, history: &mut History) {
+    let mut command = None;
+    let mut current: Option<Transaction> = None;
+    for line in log.lines() {
+        let Some((timestamp, rest)) = line
+            .strip_prefix('[')
+            .and_then(|line| line.split_once("] "))
+        else {
+            continue;
+        };
+        let Some(time) = parse_pacman_timestamp(timestamp) else {
+            continue;
+        };
+        history.saw(time);
+        let (tag, message) = match rest.strip_prefix('[') {
+            Some(tagged) => match tagged.split_once("] ") {
+                Some((tag, message)) => (Some(tag), message),
+                None => continue,
+            },
+            None => (None, rest),
+        };
+        if matches!(tag, None | Some("PACMAN"))
+            && let Some(running) = message.strip_prefix("Running '")
+        {
+            command = Some(running.trim_end_matches('\'').into());
+            if tag.is_none() {
+                // Old logs have no end of transaction, so use the next command
+                history.finish(current.take());
+            }
+            continue;
+        }
+        if !matches!(tag, None | Some("ALPM")) {
+            continue;
+        }
+        match message {
+            "transaction started" => {
+                history.finish(current.take());
+                current = Some(Transaction::new(time, "pacman", command.take()));
+            }
+            "transaction completed" | "transaction failed" | "transaction interrupted" => {
+                if let Some(ref mut transaction) = current {
+                    transaction.end = Some(time);
+                }
+                history.finish(current.take());
+            }
+            _ => {
+                if let Some(change) = parse_pacman_change(message) {
+                    let transaction = current
+                        .get_or_insert_with(|| Transaction::new(time, "pacman", command.take()));
+                    transaction.end = Some(time);
+                    transaction.changes.push(change);
+                }
+            }
+        }
+    }
+    history.finish(current);
+}
+
+/// Parse a pacman log message such as `upgraded foo (1.0-1 -> 1.1-1)`
+#[cfg(feature = "arch_linux")]
+fn parse_pacman_change(message: &str) -> Option<PackageChange> {
+    let (action, rest) = message.split_once(' ')?;
+    let (package, versions) = rest.split_once(" (")?;
+    let versions = versions.strip_suffix(')')?;
+    let change = match action {
+        "installed" => PackageChange::new(Action::Install, package, None, Some(versions)),
+        "reinstalled" => PackageChange::new(Action::Reinstall, package, None, Some(versions)),
+        "removed" => PackageChange::new(Action::Remove, package, Some(versions), None),
+        "upgraded" | "downgraded" => {
+            let (old, new) = versions.split_once(" -> ")?;
+            let action = if action == "upgraded" {
+                Action::Upgrade
+            } else {
+                Action::Downgrade
+            };
+            PackageChange::new(action, package, Some(old), Some(new))
+        }
+        _ => return None,
+    };
+    Some(change)
+}
+
+/// Parse the timestamp of a pacman log line
+#[cfg(feature = "arch_linux")]
+fn parse_pacman_timestamp(timestamp: &str) -> Option<i64> {
+    // The date itself contains dashes, so only look for an offset after it
+    match timestamp.rfind(['+', '-']) {
+        Some(idx) if idx > 10 => {
+            let (time, offset) = timestamp.split_at(idx);
+            let hours: i64 = offset.get(1..3)?.parse().ok()?;
+            let minutes: i64 = offset.get(3..5)?.parse().ok()?;
+            let offset_secs = (hours * 60 + minutes) * 60;
+            let offset_secs = if offset.starts_with('-') {
+                -offset_secs
+            } else {
+                offset_secs
+            };
+            Some(CivilTime::parse(time)?.to_utc_timestamp() - offset_secs)
+        }
+        _ => CivilTime::parse(timestamp)?.to_local_timestamp(),
+    }
+}
+
+/// Parse `/var/log/dpkg.log`
+///
+/// Lines look like `2024-05-01 12:34:56 upgrade foo:amd64 1.0 1.1`. Each run
+/// of dpkg starts with a `startup` line.
+#[cfg(feature = "debian")]
+fn parse_dpkg_log(log: &str, history: &mut History) {
+    let mut current: Option<Transaction> = None;
+    for line in log.lines() {
+        let mut fields = line.split(' ');
+        let (Some(date), Some(time), Some(action)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let Some(time) =
+            CivilTime::parse_parts(date, Some(time)).and_then(CivilTime::to_local_timestamp)
+        else {
+            continue;
+        };
+        history.saw(time);
+        if action == "startup" {
+            history.finish(current.take());
+            current = Some(Transaction::new(time, "dpkg", None));
+            continue;
+        }
+        let transaction = current.get_or_insert_with(|| Transaction::new(time, "dpkg", None));
+        transaction.end = Some(time);
+        let (Some(package), Some(old), Some(new)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let version = |version| (version != "<none>").then_some(version);
+        let (old, new) = (version(old), version(new));
+        let action = match action {
+            "install" => Action::Install,
+            "upgrade" if old == new => Action::Reinstall,
+            "upgrade" => Action::Upgrade,
+            "remove" => Action::Remove,
+            "purge" => Action::Purge,
+            _ => continue,
+        };
+        transaction
+            .changes
+            .push(PackageChange::new(action, package, old, new));
+    }
+    history.finish(current);
+}
+
+/// Parse `/var/log/apt/history.log`
+///
+/// Each transaction starts with `Start-Date: 2024-05-01  12:34:56`, followed
+/// by lines such as `Upgrade: foo:amd64 (1.0, 1.1), bar:amd64 (2.0, 2.1)`.
+#[cfg(feature = "debian")]
+fn parse_apt_history_log(log: &str, history: &mut History) {
+    let mut current: Option<Transaction> = None;
+    for line in log.lines() {
+        let Some((field, value)) = line.split_once(": ") else {
+            continue;
+        };
+        if field == "Start-Date" {
+            history.finish(current.take());
+            current = parse_apt_time(value).map(|time| {
+                history.saw(time);
+                Transaction::new(time, "apt", None)
+            });
+            continue;
+        }
+        let Some(ref mut transaction) = current else {
+            continue;
+        };
+        let action = match field {
+            "Commandline" => {
+                transaction.command = Some(value.into());
+                continue;
+            }
+            "Requested-By" => {
+                transaction.requested_by = Some(value.into());
+                continue;
+            }
+            "End-Date" => {
+                transaction.end = parse_apt_time(value);
+                history.finish(current.take());
+                continue;
+            }
+            "Install" => Action::Install,
+            "Upgrade" => Action::Upgrade,
+            "Downgrade" => Action::Downgrade,
+            "Reinstall" => Action::Reinstall,
+            "Remove" => Action::Remove,
+            "Purge" => Action::Purge,
+            _ => continue,
+        };
+        for entry in value.split("), ") {
+            let Some((package, versions)) = entry.split_once(" (") else {
+                continue;
+            };
+            let mut versions = versions
+                .trim_end_matches(')')
+                .split(", ")
+                .filter(|&version| version != "automatic");
+            let (old, new) = match action {
+                Action::Upgrade | Action::Downgrade => (versions.next(), versions.next()),
+                Action::Remove | Action::Purge => (versions.next(), None),
+                Action::Install | Action::Reinstall => (None, versions.next()),
+            };
+            transaction
+                .changes
+                .push(PackageChange::new(action, package, old, new));
+        }
+    }
+    history.finish(current);
+}
+
+#[cfg(feature = "debian")]
+fn parse_apt_time(value: &str) -> Option<i64> {
+    CivilTime::parse(value).and_then(CivilTime::to_local_timestamp)
+}
+
+/// Add the dpkg runs not already covered by apt transactions
+///
+/// apt runs dpkg for the actual changes, so those changes are logged twice.
+#[cfg(feature = "debian")]
+fn merge_dpkg_history(history: &mut History, dpkg: History) {
+    let apt_runs: Vec<_> = history
+        .transactions
+        .iter()
+        .map(|transaction| transaction.start..=transaction.end.unwrap_or(transaction.start))
+        .collect();
+    history.transactions.extend(
+        dpkg.transactions
+            .into_iter()
+            .filter(|transaction| !apt_runs.iter().any(|run| run.contains(&transaction.start))),
+    );
+    if let Some(earliest) = dpkg.earliest {
+        history.saw(earliest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    fn local(s: &str) -> i64 {
+        CivilTime::parse(s)
+            .and_then(CivilTime::to_local_timestamp)
+            .unwrap()
+    }
+
+    #[cfg(feature = "arch_linux")]
+    fn utc(s: &str) -> i64 {
+        CivilTime::parse(s).unwrap().to_utc_timestamp()
+    }
+
+    fn changed_since_sorted(history: &History, since: i64) -> Option<Vec<&str>> {
+        let changed = changed_since(history, since)?;
+        let mut packages: Vec<_> = history
+            .transactions
+            .iter()
+            .flat_map(|transaction| &transaction.changes)
+            .map(|change| change.package.as_str())
+            .filter(|package| changed.contains(*package))
+            .collect();
+        packages.sort_unstable();
+        packages.dedup();
+        Some(packages)
+    }
+
+    fn change(
+        action: Action,
+        package: &str,
+        old_version: Option<&str>,
+        new_version: Option<&str>,
+    ) -> PackageChange {
+        PackageChange::new(action, package, old_version, new_version)
+    }
+
+    #[test]
+    fn test_format_local_time() {
+        assert_eq!(
+            format_local_time(local("2024-03-01 01:02:03")),
+            "2024-03-01 01:02:03"
+        );
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("@1700000000").unwrap(), 1_700_000_000);
+        assert_eq!(
+            parse_time("2024-03-01 12:00").unwrap(),
+            local("2024-03-01 12:00")
+        );
+        assert_eq!(
+            parse_time("2024-03-01").unwrap() + 12 * 3600,
+            parse_time("2024-03-01T12:00:00").unwrap()
+        );
+        assert!(parse_time("last week").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "arch_linux")]
+    fn test_pacman_log() {
+        let log = indoc! {"
+            [2019-01-01 12:34] Running 'pacman -S old'
+            [2019-01-01 12:34] installed old (1.0-1)
+            [2024-05-01T12:00:00+0000] [PACMAN] Running 'pacman -Syu'
+            [2024-05-01T12:00:00+0000] [ALPM] transaction started
+            [2024-05-01T12:00:01+0000] [ALPM] upgraded before (1.0-1 -> 1.1-1)
+            [2024-05-01T14:00:05+0200] [ALPM] upgraded same-second (1.0-1 -> 1.1-1)
+            [2024-05-01T12:00:06+0000] [ALPM] downgraded down (1.1-1 -> 1.0-1)
+            [2024-05-01T12:00:07+0000] [ALPM-SCRIPTLET] installed nothing (really)
+            [2024-05-01T12:00:08+0000] [ALPM] removed gone (1.0-1)
+            [2024-05-01T12:00:09+0000] [ALPM] transaction completed
+            [2024-05-01T13:00:00+0000] [PACMAN] Running 'pacman -S new'
+            [2024-05-01T13:00:00+0000] [ALPM] transaction started
+            [2024-05-01T13:00:01+0000] [ALPM] installed new (2.0-1)
+            [2024-05-01T13:00:02+0000] [ALPM] transaction completed
+            garbage
+        "};
+        let mut history = History::default();
+        parse_pacman_log(log, &mut history);
+        assert_eq!(history.earliest, Some(local("2019-01-01 12:34")));
+        assert_eq!(
+            history.transactions,
+            vec![
+                Transaction {
+                    start: local("2019-01-01 12:34"),
+                    end: Some(local("2019-01-01 12:34")),
+                    source: "pacman",
+                    command: Some("pacman -S old".into()),
+                    requested_by: None,
+                    changes: vec![change(Action::Install, "old", None, Some("1.0-1"))],
+                },
+                Transaction {
+                    start: utc("2024-05-01 12:00:00"),
+                    end: Some(utc("2024-05-01 12:00:09")),
+                    source: "pacman",
+                    command: Some("pacman -Syu".into()),
+                    requested_by: None,
+                    changes: vec![
+                        change(Action::Upgrade, "before", Some("1.0-1"), Some("1.1-1")),
+                        change(Action::Upgrade, "same-second", Some("1.0-1"), Some("1.1-1")),
+                        change(Action::Downgrade, "down", Some("1.1-1"), Some("1.0-1")),
+                        change(Action::Remove, "gone", Some("1.0-1"), None),
+                    ],
+                },
+                Transaction {
+                    start: utc("2024-05-01 13:00:00"),
+                    end: Some(utc("2024-05-01 13:00:02")),
+                    source: "pacman",
+                    command: Some("pacman -S new".into()),
+                    requested_by: None,
+                    changes: vec![change(Action::Install, "new", None, Some("2.0-1"))],
+                },
+            ]
+        );
+
+        // Transactions ending after the requested time count as changed
+        assert_eq!(
+            changed_since_sorted(&history, utc("2024-05-01 12:00:05")),
+            Some(vec!["before", "down", "gone", "new", "same-second"])
+        );
+        assert_eq!(
+            changed_since_sorted(&history, utc("2024-05-01 12:30:00")),
+            Some(vec!["new"])
+        );
+
+        // Logs starting after the requested time don't tell us anything
+        let (_, recent) = log.split_once('\n').unwrap().1.split_once('\n').unwrap();
+        let mut history = History::default();
+        parse_pacman_log(recent, &mut history);
+        assert_eq!(
+            changed_since_sorted(&history, utc("2024-05-01 11:00:00")),
+            None
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "debian")]
+    fn test_dpkg_log() {
+        let log = indoc! {"
+            2024-05-01 11:00:00 startup archives unpack
+            2024-05-01 11:00:01 upgrade early:amd64 1.0 1.1
+            2024-05-01 12:00:00 startup archives unpack
+            2024-05-01 12:00:01 upgrade libfoo:amd64 1.0 1.1
+            2024-05-01 12:00:01 status half-configured libfoo:amd64 1.1
+            2024-05-01 12:00:02 install new:all <none> 2.0
+            2024-05-01 12:00:03 remove old:amd64 1.0 <none>
+            2024-05-01 12:00:04 upgrade same:amd64 1.0 1.0
+            2024-05-01 12:00:05 startup packages configure
+            2024-05-01 12:00:05 configure libfoo:amd64 1.1 <none>
+            2024-05-01 12:00:06 trigproc man-db:amd64 2.12 <none>
+        "};
+        let mut history = History::default();
+        parse_dpkg_log(log, &mut history);
+        assert_eq!(history.transactions.len(), 2);
+        assert_eq!(
+            history.transactions[1],
+            Transaction {
+                start: local("2024-05-01 12:00:00"),
+                end: Some(local("2024-05-01 12:00:04")),
+                source: "dpkg",
+                command: None,
+                requested_by: None,
+                changes: vec![
+                    change(Action::Upgrade, "libfoo", Some("1.0"), Some("1.1")),
+                    change(Action::Install, "new", None, Some("2.0")),
+                    change(Action::Remove, "old", Some("1.0"), None),
+                    change(Action::Reinstall, "same", Some("1.0"), Some("1.0")),
+                ],
+            }
+        );
+        assert_eq!(
+            changed_since_sorted(&history, local("2024-05-01 12:00:00")),
+            Some(vec!["libfoo", "new", "old", "same"])
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "debian")]
+    fn test_apt_history_log() {
+        let log = indoc! {"
+
+            Start-Date: 2024-05-01  11:00:00
+            Commandline: apt install early
+            Install: early:amd64 (1.0)
+            End-Date: 2024-05-01  11:00:01
+
+            Start-Date: 2024-05-01  12:00:00
+            Commandline: apt full-upgrade
+            Requested-By: user (1000)
+            Install: new:amd64 (2.0, automatic)
+            Upgrade: libfoo:amd64 (1.0, 1.1), bar:all (2.0, 2.1)
+            Remove: old:amd64 (1.0)
+            End-Date: 2024-05-01  12:00:10
+        "};
+        let mut history = History::default();
+        parse_apt_history_log(log, &mut history);
+        assert_eq!(history.transactions.len(), 2);
+        assert_eq!(
+            history.transactions[1],
+            Transaction {
+                start: local("2024-05-01 12:00:00"),
+                end: Some(local("2024-05-01 12:00:10")),
+                source: "apt",
+                command: Some("apt full-upgrade".into()),
+                requested_by: Some("user (1000)".into()),
+                changes: vec![
+                    change(Action::Install, "new", None, Some("2.0")),
+                    change(Action::Upgrade, "libfoo", Some("1.0"), Some("1.1")),
+                    change(Action::Upgrade, "bar", Some("2.0"), Some("2.1")),
+                    change(Action::Remove, "old", Some("1.0"), None),
+                ],
+            }
+        );
+        assert_eq!(
+            changed_since_sorted(&history, local("2024-05-01 11:30:00")),
+            Some(vec!["bar", "libfoo", "new", "old"])
+        );
+
+        // dpkg runs during apt transactions are left out
+        let mut dpkg = History::default();
+        parse_dpkg_log(
+            indoc! {"
+                2024-05-01 10:00:00 startup archives unpack
+                2024-05-01 10:00:01 install manual:amd64 <none> 1.0
+                2024-05-01 12:00:01 startup archives unpack
+                2024-05-01 12:00:02 upgrade libfoo:amd64 1.0 1.1
+            "},
+            &mut dpkg,
+        );
+        merge_dpkg_history(&mut history, dpkg);
+        assert_eq!(history.earliest, Some(local("2024-05-01 10:00:00")));
+        let sources: Vec<_> = history
+            .transactions
+            .iter()
+            .map(|transaction| (transaction.source, transaction.changes[0].package.as_str()))
+            .collect();
+        assert_eq!(
+            sources,
+            vec![("apt", "early"), ("apt", "new"), ("dpkg", "manual")]
+        );
+    }
+}
@@ -0,0 +1,419 @@
+//! Find files with security relevant permissions (setuid/setgid, world
+//! writable, file capabilities)
+
+use crate::file_ops::build_ignore_overrides;
+use crate::file_ops::canonicalize_file_entries;
+use crate::file_ops::create_path_map;
+use crate::file_ops::file_system_walker;
+use compact_str::CompactString;
+use eyre::WrapErr;
+use ignore::WalkState;
+use paketkoll_types::files::FileEntry;
+use paketkoll_types::files::Mode;
+use paketkoll_types::files::PathMap;
+use paketkoll_types::intern::Interner;
+use paketkoll_types::intern::PackageRef;
+use paketkoll_utils::MODE_MASK;
+use smallvec::SmallVec;
+use std::collections::BTreeMap;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Name of the extended attribute holding file capabilities
+const CAPABILITY_XATTR: &str = "security.capability";
+
+/// A security relevant property of a file
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum PermissionKind {
+    /// Set-user-ID bit on a non-directory
+    Setuid,
+    /// Set-group-ID bit on a non-directory
+    Setgid,
+    /// Writable by everyone (and not a directory with the sticky bit set)
+    WorldWritable,
+    /// File capabilities (in the same text format as `getcap`)
+    Capabilities(CompactString),
+}
+
+impl std::fmt::Display for PermissionKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Setuid => write!(f, "setuid"),
+            Self::Setgid => write!(f, "setgid"),
+            Self::WorldWritable => write!(f, "world writable"),
+            Self::Capabilities(caps) => write!(f, "capabilities {caps}"),
+        }
+    }
+}
+
+/// How a finding relates to what the package manager expects
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case", tag = "status")]
+#[non_exhaustive]
+pub enum Ownership {
+    /// Owned by a package, and the mode matches what the package expects
+    Expected,
+    /// Owned by a package, but the mode differs from what the package expects
+    Unexpected { expected: Mode },
+    /// Owned by a package, but the package manager doesn't record the mode
+    Unknown,
+    /// Not owned by any package
+    Unowned,
+}
+
+/// A file with security relevant permissions
+#[derive(Debug)]
+pub struct PermissionIssue {
+    pub path: PathBuf,
+    /// Package owning the file (if any)
+    pub package: Option<PackageRef>,
+    /// Actual mode on the file system
+    pub mode: Mode,
+    pub kinds: SmallVec<[PermissionKind; 2]>,
+    pub ownership: Ownership,
+}
+
+/// Walk the file system and find setuid/setgid, world writable and
+/// capability carrying files.
+///
+/// Package managers don't record extended attributes, so only the mode is
+/// compared against the package database.
+pub fn audit_permissions(
+    backend: crate::backend::ConcreteBackend,
+    backend_config: &crate::backend::BackendConfiguration,
+    unexpected_cfg: &crate::config::CheckAllFilesConfiguration,
+) -> eyre::Result<(Interner, Vec<PermissionIssue>)> {
+    let interner = Interner::new();
+    let backend_impl = backend
+        .create_files(backend_config, &interner)
+        .wrap_err_with(|| format!("Failed to create backend for {backend}"))?;
+    let mut expected_files = backend_impl
+        .files(&interner)
+        .wrap_err_with(|| format!("Failed to collect information from backend {backend}"))?;
+    if unexpected_cfg.canonicalize_paths {
+        tracing::debug!("Canonicalizing paths");
+        canonicalize_file_entries(&mut expected_files);
+    }
+    let path_map = create_path_map(&expected_files);
+    let overrides = build_ignore_overrides(&unexpected_cfg.ignored_paths)?;
+
+    tracing::debug!("Walking file system");
+    let (collector, collected) = flume::unbounded();
+    do_walk(&path_map, file_system_walker(overrides), &collector);
+    drop(collector);
+
+    Ok((interner, collected.drain().collect()))
+}
+
+#[tracing::instrument(level = "debug", name = "File system walk", skip_all)]
+fn do_walk(
+    path_map: &PathMap<'_>,
+    walker: ignore::WalkParallel,
+    collector: &flume::Sender<PermissionIssue>,
+) {
+    walker.run(|| {
+        Box::new(|entry| {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    tracing::warn!("Error while walking file system: {err}");
+                    return WalkState::Continue;
+                }
+            };
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    tracing::warn!("Failed to get metadata for {:?}: {err}", entry.path());
+                    return WalkState::Continue;
+                }
+            };
+            let mut kinds = mode_kinds(metadata.mode());
+            if metadata.is_file()
+                && let Some(caps) = capabilities(entry.path())
+            {
+                kinds.push(PermissionKind::Capabilities(caps));
+            }
+            if kinds.is_empty() {
+                return WalkState::Continue;
+            }
+            let mode = Mode::new(metadata.mode() & MODE_MASK);
+            let file_entry = path_map.get(entry.path()).copied();
+            collector
+                .send(PermissionIssue {
+                    path: entry.path().to_path_buf(),
+                    package: file_entry.and_then(|e| e.package),
+                    mode,
+                    kinds,
+                    ownership: ownership(file_entry, mode),
+                })
+                .expect("Unbounded queue");
+            WalkState::Continue
+        })
+    });
+}
+
+/// Find the security relevant bits in a raw `st_mode`
+fn mode_kinds(st_mode: u32) -> SmallVec<[PermissionKind; 2]> {
+    let mut kinds = SmallVec::new();
+    let file_type = st_mode & libc::S_IFMT;
+    // Symlinks are always 0777, and setuid/setgid on directories only affect
+    // ownership of new files.
+    if file_type == libc::S_IFLNK {
+        return kinds;
+    }
+    if file_type != libc::S_IFDIR {
+        if st_mode & libc::S_ISUID != 0 {
+            kinds.push(PermissionKind::Setuid);
+        }
+        if st_mode & libc::S_ISGID != 0 {
+            kinds.push(PermissionKind::Setgid);
+        }
+    }
+    if st_mode & libc::S_IWOTH != 0 && !(file_type == libc::S_IFDIR && st_mode & libc::S_ISVTX != 0)
+    {
+        kinds.push(PermissionKind::WorldWritable);
+    }
+    kinds
+}
+
+/// Classify a finding against the package database
+fn ownership(file_entry: Option<&FileEntry>, mode: Mode) -> Ownership {
+    let Some(file_entry) = file_entry else {
+        return Ownership::Unowned;
+    };
+    match file_entry.properties.mode() {
+        Some(expected) if expected.as_raw() & MODE_MASK == mode.as_raw() => Ownership::Expected,
+        Some(expected) => Ownership::Unexpected { expected },
+        None => Ownership::Unknown,
+    }
+}
+
+/// Read the file capabilities of a file (without following symlinks)
+fn capabilities(path: &Path) -> Option<CompactString> {
+    match xattr::get(path, CAPABILITY_XATTR) {
+        Ok(Some(raw)) => Some(decode_capabilities(&raw).unwrap_or_else(|| {
+            tracing::warn!("Failed to decode capabilities of {path:?}");
+            CompactString::from("<invalid>")
+        })),
+        Ok(None) => None,
+        Err(err) => {
+            tracing::trace!("Failed to read xattrs of {path:?}: {err}");
+            None
+        }
+    }
+}
+
+/// Capability names, indexed by capability number
+const CAPABILITY_NAMES: [&str; 41] = [
+    "cap_chown",
+    "cap_dac_override",
+    "cap_dac_read_search",
+    "cap_fowner",
+    "cap_fsetid",
+    "cap_kill",
+    "cap_setgid",
+    "cap_setuid",
+    "cap_setpcap",
+    "cap_linux_immutable",
+    "cap_net_bind_service",
+    "cap_net_broadcast",
+    "cap_net_admin",
+    "cap_net_raw",
+    "cap_ipc_lock",
+    "cap_ipc_owner",
+    "cap_sys_module",
+    "cap_sys_rawio",
+    "cap_sys_chroot",
+    "cap_sys_ptrace",
+    "cap_sys_pacct",
+    "cap_sys_admin",
+    "cap_sys_boot",
+    "cap_sys_nice",
+    "cap_sys_resource",
+    "cap_sys_time",
+    "cap_sys_tty_config",
+    "cap_mknod",
+    "cap_lease",
+    "cap_audit_write",
+    "cap_audit_control",
+    "cap_setfcap",
+    "cap_mac_override",
+    "cap_mac_admin",
+    "cap_syslog",
+    "cap_wake_alarm",
+    "cap_block_suspend",
+    "cap_audit_read",
+    "cap_perfmon",
+    "cap_bpf",
+    "cap_checkpoint_restore",
+];
+
+const VFS_CAP_REVISION_MASK: u32 = 0xff00_0000;
+const VFS_CAP_REVISION_1: u32 = 0x0100_0000;
+const VFS_CAP_REVISION_2: u32 = 0x0200_0000;
+const VFS_CAP_REVISION_3: u32 = 0x0300_0000;
+const VFS_CAP_FLAGS_EFFECTIVE: u32 = 0x1;
+
+/// Decode a `vfs_cap_data` structure into the textual format used by `getcap`
+/// (e.g. `cap_net_admin,cap_net_raw=ep`)
+fn decode_capabilities(raw: &[u8]) -> Option<CompactString> {
+    let word = |idx: usize| -> Option<u32> {
+        let bytes = raw.get(idx * 4..idx * 4 + 4)?;
+        Some(u32::from_le_bytes(bytes.try_into().ok()?))
+    };
+    let magic = word(0)?;
+    let (words, expected_len) = match magic & VFS_CAP_REVISION_MASK {
+        VFS_CAP_REVISION_1 => (1, 12),
+        VFS_CAP_REVISION_2 => (2, 20),
+        // Revision 3 has an extra root user ID at the end (for namespaces)
+        VFS_CAP_REVISION_3 => (2, 24),
+        _ => return None,
+    };
+    if raw.len() != expected_len {
+        return None;
+    }
+    let effective = magic & VFS_CAP_FLAGS_EFFECTIVE != 0;
+    let mut permitted = 0_u64;
+    let mut inheritable = 0_u64;
+    for i in 0..words {
+        permitted |= u64::from(word(1 + 2 * i)?) << (32 * i);
+        inheritable |= u64::from(word(2 + 2 * i)?) << (32 * i);
+    }
+
+    // Group capabilities by their set of flags
+    let mut groups: BTreeMap<CompactString, Vec<CompactString>> = BTreeMap::new();
+    for cap in 0..64 {
+        let in_permitted = permitted & (1 << cap) != 0;
+        let in_inheritable = inheritable & (1 << cap) != 0;
+        if !in_permitted && !in_inheritable {
+            continue;
+        }
+        let mut flags = CompactString::default();
+        if effective && in_permitted {
+            flags.push('e');
+        }
+        if in_inheritable {
+            flags.push('i');
+        }
+        if in_permitted {
+            flags.push('p');
+        }
+        let name = match CAPABILITY_NAMES.get(cap) {
+            Some(name) => CompactString::from(*name),
+            None => compact_str::format_compact!("cap_{cap}"),
+        };
+        groups.entry(flags).or_default().push(name);
+    }
+    let text = groups
+        .into_iter()
+        .map(|(flags, names)| format!("{}={flags}", names.join(",")))
+        .collect::<Vec<_>>()
+        .join(" ");
+    Some(text.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paketkoll_types::files::Checksum;
+    use paketkoll_types::files::FileFlags;
+    use paketkoll_types::files::Gid;
+    use paketkoll_types::files::Properties;
+    use paketkoll_types::files::RegularFile;
+    use paketkoll_types::files::Uid;
+
+    #[test]
+    fn test_mode_kinds() {
+        assert!(mode_kinds(libc::S_IFREG | 0o755).is_empty());
+        assert_eq!(
+            mode_kinds(libc::S_IFREG | 0o4755).as_slice(),
+            &[PermissionKind::Setuid]
+        );
+        assert_eq!(
+            mode_kinds(libc::S_IFREG | 0o6757).as_slice(),
+            &[
+                PermissionKind::Setuid,
+                PermissionKind::Setgid,
+                PermissionKind::WorldWritable
+            ]
+        );
+        // Setgid directories and sticky world writable directories are fine
+        assert!(mode_kinds(libc::S_IFDIR | 0o2775).is_empty());
+        assert!(mode_kinds(libc::S_IFDIR | 0o1777).is_empty());
+        assert_eq!(
+            mode_kinds(libc::S_IFDIR | 0o777).as_slice(),
+            &[PermissionKind::WorldWritable]
+        );
+        assert!(mode_kinds(libc::S_IFLNK | 0o777).is_empty());
+    }
+
+    #[test]
+    fn test_ownership() {
+        let entry = FileEntry {
+            package: None,
+            path: "/usr/bin/sudo".into(),
+            properties: Properties::RegularFile(RegularFile {
+                mode: Mode::new(0o4755),
+                owner: Uid::new(0),
+                group: Gid::new(0),
+                size: 0,
+                mtime: std::time::SystemTime::UNIX_EPOCH,
+                checksum: Checksum::Sha256([0; 32]),
+            }),
+            flags: FileFlags::empty(),
+            source: "test",
+            seen: Default::default(),
+        };
+        assert_eq!(ownership(None, Mode::new(0o4755)), Ownership::Unowned);
+        assert_eq!(
+            ownership(Some(&entry), Mode::new(0o4755)),
+            Ownership::Expected
+        );
+        assert_eq!(
+            ownership(Some(&entry), Mode::new(0o4777)),
+            Ownership::Unexpected {
+                expected: Mode::new(0o4755)
+            }
+        );
+    }
+
+    #[test]
+    fn test_decode_capabilities() {
+        // cap_net_raw=ep (revision 2, as written by setcap)
+        let mut raw = vec![];
+        for word in [
+            VFS_CAP_REVISION_2 | VFS_CAP_FLAGS_EFFECTIVE,
+            1 << 13,
+            0,
+            0,
+            0,
+        ] {
+            raw.extend_from_slice(&u32::to_le_bytes(word));
+        }
+        assert_eq!(decode_capabilities(&raw).as_deref(), Some("cap_net_raw=ep"));
+
+        // Mixed flags and a capability in the upper word (revision 3)
+        let mut raw = vec![];
+        for word in [
+            VFS_CAP_REVISION_3,
+            (1 << 10) | (1 << 12),
+            1 << 12,
+            1 << (38 - 32),
+            0,
+            0,
+        ] {
+            raw.extend_from_slice(&u32::to_le_bytes(word));
+        }
+        assert_eq!(
+            decode_capabilities(&raw).as_deref(),
+            Some("cap_net_admin=ip cap_net_bind_service,cap_perfmon=p")
+        );
+
+        // Wrong length
+        assert_eq!(decode_capabilities(&raw[..20]), None);
+        assert_eq!(decode_capabilities(&[]), None);
+    }
+}
@@ -0,0 +1,313 @@
+//! Reports of check results for monitoring systems and CI
+//!
+//! Supported are the text file format of the Prometheus node exporter and
+//! `JUnit` XML.
+
+use crate::file_ops::CheckStatistics;
+use paketkoll_types::intern::Interner;
+use paketkoll_types::issue::Issue;
+use paketkoll_types::issue::IssueKind;
+use paketkoll_types::issue::PackageIssue;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::time::Duration;
+
+/// Name of the `JUnit` test case for issues not belonging to any package
+const UNOWNED_TEST_CASE: &str = "(unowned files)";
+
+/// Write metrics in the Prometheus text format (for the node exporter
+/// textfile collector)
+///
+/// Issues are counted by kind, package and backend. Issues not belonging to a
+/// package have an empty package label, and issues not from a backend (such
+/// as unexpected files) have an empty backend label.
+pub fn write_prometheus(
+    writer: &mut impl Write,
+    issues: &[PackageIssue],
+    interner: &Interner,
+    statistics: &CheckStatistics,
+    duration: Duration,
+) -> std::io::Result<()> {
+    let mut counts: BTreeMap<(&str, &str, &str), u64> = BTreeMap::new();
+    for (package, issue) in issues {
+        let package = package.and_then(|p| p.try_as_str(interner)).unwrap_or("");
+        let backend = issue.source().unwrap_or("");
+        for kind in issue.kinds() {
+            *counts.entry((kind.name(), package, backend)).or_default() += 1;
+        }
+    }
+
+    writeln!(writer, "# HELP paketkoll_issues Number of issues found")?;
+    writeln!(writer, "# TYPE paketkoll_issues gauge")?;
+    for ((kind, package, backend), count) in counts {
+        writeln!(
+            writer,
+            "paketkoll_issues{{kind=\"{}\",package=\"{}\",backend=\"{}\"}} {count}",
+            prometheus_escape(kind),
+            prometheus_escape(package),
+            prometheus_escape(backend)
+        )?;
+    }
+    writeln!(
+        writer,
+        "# HELP paketkoll_files_checked Number of files checked"
+    )?;
+    writeln!(writer, "# TYPE paketkoll_files_checked gauge")?;
+    writeln!(
+        writer,
+        "paketkoll_files_checked {}",
+        statistics.files_checked
+    )?;
+    writeln!(
+        writer,
+        "# HELP paketkoll_packages_checked Number of packages with files checked"
+    )?;
+    writeln!(writer, "# TYPE paketkoll_packages_checked gauge")?;
+    writeln!(
+        writer,
+        "paketkoll_packages_checked {}",
+        statistics.packages_checked.len()
+    )?;
+    writeln!(
+        writer,
+        "# HELP paketkoll_scan_duration_seconds Time taken by the check"
+    )?;
+    writeln!(writer, "# TYPE paketkoll_scan_duration_seconds gauge")?;
+    writeln!(
+        writer,
+        "paketkoll_scan_duration_seconds {:.3}",
+        duration.as_secs_f64()
+    )?;
+    Ok(())
+}
+
+/// Escape a label value for the Prometheus text format
+fn prometheus_escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+/// Write a `JUnit` XML report
+///
+/// There is one test case for each package that had files checked, with a
+/// failure for each issue. Issues not belonging to any package are grouped
+/// by backend, or in a separate test case for unowned files.
+pub fn write_junit(
+    writer: &mut impl Write,
+    suite_name: &str,
+    issues: &[PackageIssue],
+    interner: &Interner,
+    statistics: &CheckStatistics,
+    duration: Duration,
+) -> std::io::Result<()> {
+    let mut test_cases: BTreeMap<&str, Vec<&Issue>> = statistics
+        .packages_checked
+        .iter()
+        .filter_map(|(p, _)| p.try_as_str(interner))
+        .map(|name| (name, vec![]))
+        .collect();
+    for (package, issue) in issues {
+        let name = match package {
+            Some(package) => package.try_as_str(interner).unwrap_or(UNOWNED_TEST_CASE),
+            None => issue.source().unwrap_or(UNOWNED_TEST_CASE),
+        };
+        test_cases.entry(name).or_default().push(issue);
+    }
+    let failures = test_cases
+        .values()
+        .filter(|issues| !issues.is_empty())
+        .count();
+    let time = duration.as_secs_f64();
+    let suite_name = xml_escape(suite_name);
+
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<testsuites name="{suite_name}" tests="{}" failures="{failures}" errors="0" time="{time:.3}">"#,
+        test_cases.len()
+    )?;
+    writeln!(
+        writer,
+        r#"  <testsuite name="{suite_name}" tests="{}" failures="{failures}" errors="0" skipped="0" time="{time:.3}">"#,
+        test_cases.len()
+    )?;
+    for (name, issues) in &test_cases {
+        let name = xml_escape(name);
+        if issues.is_empty() {
+            writeln!(
+                writer,
+                r#"    <testcase name="{name}" classname="{suite_name}"/>"#
+            )?;
+            continue;
+        }
+        writeln!(
+            writer,
+            r#"    <testcase name="{name}" classname="{suite_name}">"#
+        )?;
+        for issue in issues {
+            for kind in issue.kinds() {
+                write_junit_failure(writer, issue, kind)?;
+            }
+        }
+        writeln!(writer, "    </testcase>")?;
+    }
+    writeln!(writer, "  </testsuite>")?;
+    writeln!(writer, "</testsuites>")?;
+    Ok(())
+}
+
+/// Write a failure element for one kind of issue
+fn write_junit_failure(
+    writer: &mut impl Write,
+    issue: &Issue,
+    kind: &IssueKind,
+) -> std::io::Result<()> {
+    let message = xml_escape(&format!("{} {kind}", issue.path().display()));
+    writeln!(
+        writer,
+        r#"      <failure type="{}" message="{message}">{message}</failure>"#,
+        kind.name()
+    )
+}
+
+/// Escape text for use in XML attributes and text.
+///
+/// Control characters not allowed in XML are replaced.
+fn xml_escape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&apos;"),
+            '\t' | '\n' | '\r' => result.push(c),
+            c if c.is_control() => result.push(char::REPLACEMENT_CHARACTER),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use paketkoll_types::files::Mode;
+    use paketkoll_types::intern::PackageRef;
+    use std::path::PathBuf;
+
+    fn test_data() -> (Interner, Vec<PackageIssue>, CheckStatistics) {
+        let interner = Interner::new();
+        let foo = PackageRef::get_or_intern(&interner, "foo");
+        let bar = PackageRef::get_or_intern(&interner, "bar");
+        let issues = vec![
+            (
+                Some(foo),
+                Issue::new(
+                    PathBuf::from("/usr/bin/foo"),
+                    smallvec::smallvec![
+                        IssueKind::Missing,
+                        IssueKind::WrongMode {
+                            actual: Mode::new(0o755),
+                            expected: Mode::new(0o4755),
+                        }
+                    ],
+                    Some("arch"),
+                ),
+            ),
+            (
+                Some(foo),
+                Issue::new(
+                    PathBuf::from("/usr/bin/foo<2>"),
+                    smallvec::smallvec![IssueKind::Missing],
+                    Some("arch"),
+                ),
+            ),
+            (
+                None,
+                Issue::new(
+                    PathBuf::from("/etc/stray"),
+                    smallvec::smallvec![IssueKind::Unexpected],
+                    None,
+                ),
+            ),
+        ];
+        let statistics = CheckStatistics {
+            files_checked: 10,
+            packages_checked: vec![(foo, 6), (bar, 4)],
+        };
+        (interner, issues, statistics)
+    }
+
+    #[test]
+    fn test_prometheus() {
+        let (interner, issues, statistics) = test_data();
+        let mut out = vec![];
+        write_prometheus(
+            &mut out,
+            &issues,
+            &interner,
+            &statistics,
+            Duration::from_millis(1500),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            indoc! {r#"
+                # HELP paketkoll_issues Number of issues found
+                # TYPE paketkoll_issues gauge
+                paketkoll_issues{kind="missing",package="foo",backend="arch"} 2
+                paketkoll_issues{kind="unexpected",package="",backend=""} 1
+                paketkoll_issues{kind="wrong_mode",package="foo",backend="arch"} 1
+                # HELP paketkoll_files_checked Number of files checked
+                # TYPE paketkoll_files_checked gauge
+                paketkoll_files_checked 10
+                # HELP paketkoll_packages_checked Number of packages with files checked
+                # TYPE paketkoll_packages_checked gauge
+                paketkoll_packages_checked 2
+                # HELP paketkoll_scan_duration_seconds Time taken by the check
+                # TYPE paketkoll_scan_duration_seconds gauge
+                paketkoll_scan_duration_seconds 1.500
+            "#}
+        );
+        assert_eq!(prometheus_escape("a\"b\\c\n"), r#"a\"b\\c\n"#);
+    }
+
+    #[test]
+    fn test_junit() {
+        let (interner, issues, statistics) = test_data();
+        let mut out = vec![];
+        write_junit(
+            &mut out,
+            "paketkoll check",
+            &issues,
+            &interner,
+            &statistics,
+            Duration::from_millis(1500),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            indoc! {r#"
+                <?xml version="1.0" encoding="UTF-8"?>
+                <testsuites name="paketkoll check" tests="3" failures="2" errors="0" time="1.500">
+                  <testsuite name="paketkoll check" tests="3" failures="2" errors="0" skipped="0" time="1.500">
+                    <testcase name="(unowned files)" classname="paketkoll check">
+                      <failure type="unexpected" message="/etc/stray unexpected file">/etc/stray unexpected file</failure>
+                    </testcase>
+                    <testcase name="bar" classname="paketkoll check"/>
+                    <testcase name="foo" classname="paketkoll check">
+                      <failure type="missing" message="/usr/bin/foo missing or inaccessible file/directory/...">/usr/bin/foo missing or inaccessible file/directory/...</failure>
+                      <failure type="wrong_mode" message="/usr/bin/foo permission mismatch (expected 4755, actual 755)">/usr/bin/foo permission mismatch (expected 4755, actual 755)</failure>
+                      <failure type="missing" message="/usr/bin/foo&lt;2&gt; missing or inaccessible file/directory/...">/usr/bin/foo&lt;2&gt; missing or inaccessible file/directory/...</failure>
+                    </testcase>
+                  </testsuite>
+                </testsuites>
+            "#}
+        );
+    }
+}
@@ -0,0 +1,293 @@
+//! Limits on the resources used by checks
+//!
+//! This allows running checks on busy production systems without saturating
+//! the CPU or disks.
+
+use crate::config::CommonFileCheckConfiguration;
+use eyre::WrapErr;
+use parking_lot::Mutex;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Unit used for sizes and rates on the command line
+pub const MEGABYTE: u64 = 1_000_000;
+
+/// File systems considered to be network file systems
+const NETWORK_FILESYSTEMS: &[&str] = &[
+    "9p",
+    "afs",
+    "beegfs",
+    "ceph",
+    "cifs",
+    "coda",
+    "davfs",
+    "fuse.davfs2",
+    "fuse.glusterfs",
+    "fuse.rclone",
+    "fuse.s3fs",
+    "fuse.sshfs",
+    "glusterfs",
+    "gpfs",
+    "lustre",
+    "ncpfs",
+    "nfs",
+    "nfs4",
+    "smb3",
+    "smbfs",
+];
+
+/// I/O scheduling class and priority (see `ionice(1)`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoPriority {
+    /// Only get disk time when no other program needs it
+    Idle,
+    /// Best effort with a priority level from 0 (highest) to 7 (lowest)
+    BestEffort(u8),
+}
+
+impl IoPriority {
+    /// Value for the `ioprio_set` system call
+    const fn as_raw(self) -> libc::c_int {
+        const CLASS_SHIFT: libc::c_int = 13;
+        match self {
+            Self::Idle => 3 << CLASS_SHIFT,
+            Self::BestEffort(level) => (2 << CLASS_SHIFT) | level as libc::c_int,
+        }
+    }
+}
+
+impl FromStr for IoPriority {
+    type Err = eyre::Error;
+
+    /// Parse `idle`, `best-effort` or `best-effort:<level>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "idle" => Ok(Self::Idle),
+            None if s == "best-effort" => Ok(Self::BestEffort(4)),
+            Some(("best-effort", level)) => {
+                let level: u8 = level
+                    .parse()
+                    .wrap_err_with(|| format!("Invalid I/O priority level {level:?}"))?;
+                if level > 7 {
+                    eyre::bail!("I/O priority level must be between 0 and 7");
+                }
+                Ok(Self::BestEffort(level))
+            }
+            _ => eyre::bail!(
+                "Unknown I/O priority {s:?} (expected idle, best-effort or best-effort:<level>)"
+            ),
+        }
+    }
+}
+
+/// Limits the combined read throughput of all threads
+///
+/// Clones share the same limit.
+#[derive(Debug, Clone)]
+pub struct ReadLimiter {
+    bytes_per_second: u64,
+    /// Point in time when the already read data is "paid for"
+    next_free: Arc<Mutex<Instant>>,
+}
+
+impl ReadLimiter {
+    #[must_use]
+    pub fn new(bytes_per_second: u64) -> Self {
+        Self {
+            bytes_per_second: bytes_per_second.max(1),
+            next_free: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Account for bytes read, sleeping if we are over the limit
+    pub(crate) fn consume(&self, bytes: usize) {
+        let cost = Duration::from_secs_f64(bytes as f64 / self.bytes_per_second as f64);
+        let now = Instant::now();
+        let start = {
+            let mut next_free = self.next_free.lock();
+            // Time not used for reading can't be saved up for later bursts
+            let start = (*next_free).max(now);
+            *next_free = start + cost;
+            start
+        };
+        if start > now {
+            std::thread::sleep(start - now);
+        }
+    }
+}
+
+/// Mount points of network file systems
+#[derive(Debug, Default)]
+pub(crate) struct NetworkMounts {
+    mount_points: Vec<PathBuf>,
+}
+
+impl NetworkMounts {
+    /// Find the network file systems currently mounted
+    pub(crate) fn load() -> eyre::Result<Self> {
+        let mountinfo = std::fs::read_to_string("/proc/self/mountinfo")
+            .wrap_err("Failed to read /proc/self/mountinfo")?;
+        Ok(Self::parse(&mountinfo))
+    }
+
+    /// Parse the format of `/proc/self/mountinfo`
+    fn parse(mountinfo: &str) -> Self {
+        let mount_points = mountinfo
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split(' ');
+                let mount_point = fields.nth(4)?;
+                // Optional fields are terminated by a single "-"
+                let fs_type = fields.skip_while(|&field| field != "-").nth(1)?;
+                NETWORK_FILESYSTEMS
+                    .contains(&fs_type)
+                    .then(|| PathBuf::from(unescape_mount_point(mount_point)))
+            })
+            .collect();
+        Self { mount_points }
+    }
+
+    /// Check if a path is on (or is the mount point of) a network file system
+    pub(crate) fn contains(&self, path: &Path) -> bool {
+        self.mount_points
+            .iter()
+            .any(|mount_point| path.starts_with(mount_point))
+    }
+}
+
+/// Mount points use octal escapes for whitespace and backslash
+fn unescape_mount_point(mount_point: &str) -> String {
+    let mut result = String::with_capacity(mount_point.len());
+    let mut rest = mount_point;
+    while let Some(idx) = rest.find('\\') {
+        result.push_str(&rest[..idx]);
+        let escape = rest.get(idx + 1..idx + 4);
+        match escape.and_then(|octal| u8::from_str_radix(octal, 8).ok()) {
+            Some(byte) => {
+                result.push(char::from(byte));
+                rest = &rest[idx + 4..];
+            }
+            None => {
+                result.push('\\');
+                rest = &rest[idx + 1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Run a check with the thread count and priorities from the configuration.
+///
+/// If none of those are set, the check runs on the current (or global)
+/// thread pool.
+pub(crate) fn run_limited<T: Send>(
+    config: &CommonFileCheckConfiguration,
+    f: impl FnOnce() -> T + Send,
+) -> eyre::Result<T> {
+    if config.threads.is_none() && config.nice.is_none() && config.io_priority.is_none() {
+        return Ok(f());
+    }
+    let nice = config.nice;
+    let io_priority = config.io_priority;
+    let mut builder = rayon::ThreadPoolBuilder::new()
+        .thread_name(|idx| format!("paketkoll-check-{idx}"))
+        .start_handler(move |_| set_thread_priority(nice, io_priority));
+    if let Some(threads) = config.threads {
+        builder = builder.num_threads(threads);
+    }
+    let pool = builder
+        .build()
+        .wrap_err("Failed to create thread pool for check")?;
+    // Threads created by the workers (such as for walking the file system)
+    // inherit the priorities.
+    Ok(pool.install(f))
+}
+
+/// Set the CPU and I/O priority of the current thread
+fn set_thread_priority(nice: Option<i32>, io_priority: Option<IoPriority>) {
+    if let Some(nice) = nice {
+        // SAFETY: Only integer arguments. On Linux this applies to the calling
+        // thread only.
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } != 0 {
+            tracing::warn!(
+                "Failed to set nice level {nice}: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+    if let Some(io_priority) = io_priority {
+        const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+        // SAFETY: Only integer arguments. With IOPRIO_WHO_PROCESS and 0 this
+        // applies to the calling thread.
+        if unsafe {
+            libc::syscall(
+                libc::SYS_ioprio_set,
+                IOPRIO_WHO_PROCESS,
+                0,
+                io_priority.as_raw(),
+            )
+        } != 0
+        {
+            tracing::warn!(
+                "Failed to set I/O priority {io_priority:?}: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_io_priority() {
+        assert_eq!("idle".parse::<IoPriority>().unwrap(), IoPriority::Idle);
+        assert_eq!(
+            "best-effort".parse::<IoPriority>().unwrap(),
+            IoPriority::BestEffort(4)
+        );
+        assert_eq!(
+            "best-effort:7".parse::<IoPriority>().unwrap(),
+            IoPriority::BestEffort(7)
+        );
+        assert!("best-effort:8".parse::<IoPriority>().is_err());
+        assert!("realtime".parse::<IoPriority>().is_err());
+    }
+
+    #[test]
+    fn test_network_mounts() {
+        let mounts = NetworkMounts::parse(indoc! {r"
+            22 1 259:2 / / rw,relatime shared:1 - ext4 /dev/nvme0n1p2 rw
+            40 22 0:36 / /home/user/nas\040share rw,relatime shared:20 - nfs4 nas:/share rw
+            41 22 0:37 / /mnt/win rw,relatime - cifs //win/c rw
+            42 22 0:38 / /tmp rw,nosuid shared:5 - tmpfs tmpfs rw
+        "});
+        assert_eq!(
+            mounts.mount_points,
+            vec![
+                PathBuf::from("/home/user/nas share"),
+                PathBuf::from("/mnt/win")
+            ]
+        );
+        assert!(mounts.contains(Path::new("/mnt/win")));
+        assert!(mounts.contains(Path::new("/mnt/win/foo")));
+        assert!(!mounts.contains(Path::new("/mnt/windows")));
+        assert!(!mounts.contains(Path::new("/tmp/foo")));
+    }
+
+    #[test]
+    fn test_read_limiter() {
+        let limiter = ReadLimiter::new(1000);
+        let start = Instant::now();
+        limiter.consume(10);
+        limiter.consume(40);
+        assert!(start.elapsed() >= Duration::from_millis(10));
+    }
+}
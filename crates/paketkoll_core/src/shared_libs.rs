@@ -0,0 +1,594 @@
+//! Find executables and libraries with unresolvable shared library
+//! dependencies
+
+use ahash::AHashSet;
+use compact_str::CompactString;
+use dashmap::DashMap;
+use eyre::WrapErr;
+use object::elf;
+use object::read::elf::Dyn;
+use object::read::elf::FileHeader;
+use object::read::elf::ProgramHeader;
+use paketkoll_types::files::FileEntry;
+use paketkoll_types::intern::Interner;
+use paketkoll_types::intern::PackageRef;
+use rayon::prelude::*;
+use std::io::Read;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Main configuration file of the dynamic linker
+const LD_SO_CONF: &str = "/etc/ld.so.conf";
+/// Offset of the class (32/64-bit) in the ELF identification
+const EI_CLASS: usize = 4;
+/// Offset of the byte order in the ELF identification
+const EI_DATA: usize = 5;
+
+/// A problem with a shared library dependency of an executable or library
+#[derive(Debug)]
+pub struct LibraryIssue {
+    /// Executable or library that has the dependency
+    pub path: PathBuf,
+    /// Package owning `path`
+    pub package: Option<PackageRef>,
+    /// Library as listed in `DT_NEEDED`
+    pub library: CompactString,
+    pub kind: LibraryIssueKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LibraryIssueKind {
+    /// The library can not be found. If known, the package that (according to
+    /// the package database) should provide it is included.
+    Missing { provider: Option<PackageRef> },
+    /// The library resolves to a file not owned by any package
+    Unowned { resolved: PathBuf },
+}
+
+/// Check that `DT_NEEDED` of all package owned ELF files can be resolved.
+///
+/// Libraries are searched for like the dynamic linker does (`DT_RPATH`,
+/// `DT_RUNPATH`, `/etc/ld.so.conf` and the default directories), except that
+/// the directory of the object itself is also accepted as a last resort.
+/// That is where plugins typically find libraries already loaded by the main
+/// program.
+///
+/// If `attribute` is true, packages are looked up for the libraries: which
+/// package should provide missing libraries, and resolved libraries not owned
+/// by any package are reported.
+pub fn check_libraries(
+    backend: crate::backend::ConcreteBackend,
+    backend_config: &crate::backend::BackendConfiguration,
+    attribute: bool,
+) -> eyre::Result<(Interner, Vec<LibraryIssue>)> {
+    let interner = Interner::new();
+    let backend_impl = backend
+        .create_files(backend_config, &interner)
+        .wrap_err_with(|| format!("Failed to create backend for {backend}"))?;
+    let files = backend_impl
+        .files(&interner)
+        .wrap_err_with(|| format!("Failed to collect information from backend {backend}"))?;
+
+    let mut ld_so_conf = vec![];
+    read_ld_so_conf(Path::new(LD_SO_CONF), &mut ld_so_conf, 0);
+    let resolver = Resolver {
+        ld_so_conf,
+        targets: DashMap::with_hasher(ahash::RandomState::new()),
+        system: DashMap::with_hasher(ahash::RandomState::new()),
+    };
+
+    tracing::debug!("Resolving shared libraries");
+    let results: Vec<(&FileEntry, CompactString, Resolution)> = files
+        .par_iter()
+        .filter(|entry| entry.properties.is_regular_file() != Some(false))
+        .filter_map(|entry| match parse_elf(&entry.path) {
+            Ok(info) => info.map(|info| (entry, info)),
+            Err(err) => {
+                tracing::debug!("Failed to parse {:?} as ELF: {err}", entry.path);
+                None
+            }
+        })
+        .flat_map_iter(|(entry, info)| {
+            let resolver = &resolver;
+            info.needed.clone().into_iter().map(move |library| {
+                let resolution = resolver.resolve(&entry.path, &info, &library);
+                (entry, library, resolution)
+            })
+        })
+        .collect();
+
+    let owners = if attribute {
+        tracing::debug!("Finding owners of libraries");
+        let mut paths: AHashSet<&Path> = AHashSet::new();
+        for (_, _, resolution) in &results {
+            match resolution {
+                Resolution::Found(path) => paths.extend(path.iter().map(PathBuf::as_path)),
+                Resolution::Missing(candidates) => {
+                    paths.extend(candidates.iter().map(PathBuf::as_path));
+                }
+            }
+        }
+        backend_impl
+            .owning_packages(&paths, &interner)
+            .wrap_err_with(|| format!("Failed to find owning packages with {backend}"))?
+    } else {
+        Default::default()
+    };
+    let owner = |path: &PathBuf| owners.get(path).and_then(|owner| *owner);
+
+    let issues = results
+        .into_iter()
+        .filter_map(|(entry, library, resolution)| {
+            let kind = match resolution {
+                Resolution::Found(_) if !attribute => return None,
+                Resolution::Found(paths) => {
+                    if paths.iter().any(|path| owner(path).is_some()) {
+                        return None;
+                    }
+                    LibraryIssueKind::Unowned {
+                        resolved: paths.into_iter().next()?,
+                    }
+                }
+                Resolution::Missing(candidates) => LibraryIssueKind::Missing {
+                    provider: candidates.iter().find_map(owner),
+                },
+            };
+            Some(LibraryIssue {
+                path: entry.path.clone(),
+                package: entry.package,
+                library,
+                kind,
+            })
+        })
+        .collect();
+    Ok((interner, issues))
+}
+
+/// ELF class, byte order and machine. Libraries must match what loads them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Target {
+    is_64: bool,
+    little_endian: bool,
+    machine: u16,
+}
+
+/// Dynamic linking information of an ELF file
+#[derive(Debug)]
+struct ElfInfo {
+    target: Target,
+    needed: Vec<CompactString>,
+    rpath: Vec<CompactString>,
+    runpath: Vec<CompactString>,
+}
+
+/// Outcome of looking up a library
+#[derive(Debug)]
+enum Resolution {
+    /// Found, both as found in the search path and with the directory
+    /// canonicalized (packages may list either with /usr merge)
+    Found(Vec<PathBuf>),
+    /// Not found, with the paths that were tried
+    Missing(Vec<PathBuf>),
+}
+
+struct Resolver {
+    ld_so_conf: Vec<PathBuf>,
+    /// Cache of ELF targets of candidate files (`None` if not ELF)
+    targets: DashMap<PathBuf, Option<Target>, ahash::RandomState>,
+    /// Cache of lookups in the system wide search paths
+    system: DashMap<(CompactString, Target), Option<PathBuf>, ahash::RandomState>,
+}
+
+impl Resolver {
+    fn resolve(&self, object: &Path, info: &ElfInfo, library: &str) -> Resolution {
+        let origin = object.parent().unwrap_or_else(|| Path::new("/"));
+        if library.contains('/') {
+            // The dynamic linker expands $ORIGIN here too
+            let candidates = expand_tokens(library, origin, info.target);
+            return match candidates
+                .iter()
+                .find(|path| self.matches(path, info.target))
+            {
+                Some(path) => Resolution::Found(with_canonical_parent(path.clone())),
+                None => Resolution::Missing(candidates),
+            };
+        }
+        // DT_RPATH is ignored if DT_RUNPATH is present
+        let object_dirs = if info.runpath.is_empty() {
+            &info.rpath
+        } else {
+            &info.runpath
+        };
+        let object_dirs: Vec<PathBuf> = object_dirs
+            .iter()
+            .flat_map(|dir| expand_tokens(dir, origin, info.target))
+            .collect();
+        for dir in &object_dirs {
+            let candidate = dir.join(library);
+            if self.matches(&candidate, info.target) {
+                return Resolution::Found(with_canonical_parent(candidate));
+            }
+        }
+        let key = (CompactString::from(library), info.target);
+        let system = match self.system.get(&key) {
+            Some(found) => found.clone(),
+            None => {
+                let found = self
+                    .system_dirs(info.target)
+                    .map(|dir| dir.join(library))
+                    .find(|candidate| self.matches(candidate, info.target));
+                self.system.insert(key, found.clone());
+                found
+            }
+        };
+        if let Some(found) = system {
+            return Resolution::Found(with_canonical_parent(found));
+        }
+        let next_to_object = origin.join(library);
+        if self.matches(&next_to_object, info.target) {
+            return Resolution::Found(with_canonical_parent(next_to_object));
+        }
+        Resolution::Missing(
+            object_dirs
+                .iter()
+                .map(PathBuf::as_path)
+                .chain(self.system_dirs(info.target))
+                .map(|dir| dir.join(library))
+                .collect(),
+        )
+    }
+
+    /// Directories from ld.so.conf and the default directories
+    fn system_dirs(&self, target: Target) -> impl Iterator<Item = &Path> {
+        let defaults: &[&str] = if target.is_64 {
+            &["/lib64", "/usr/lib64", "/lib", "/usr/lib"]
+        } else {
+            &["/lib32", "/usr/lib32", "/lib", "/usr/lib"]
+        };
+        self.ld_so_conf
+            .iter()
+            .map(PathBuf::as_path)
+            .chain(defaults.iter().map(Path::new))
+    }
+
+    /// Check if the path exists and is an ELF file for the given target
+    fn matches(&self, path: &Path, target: Target) -> bool {
+        if let Some(cached) = self.targets.get(path) {
+            return *cached == Some(target);
+        }
+        let actual = read_target(path).ok().flatten();
+        self.targets.insert(path.to_path_buf(), actual);
+        actual == Some(target)
+    }
+}
+
+/// Get the path both as is and with the parent directory canonicalized
+fn with_canonical_parent(path: PathBuf) -> Vec<PathBuf> {
+    let canonical = path
+        .parent()
+        .and_then(|parent| parent.canonicalize().ok())
+        .zip(path.file_name())
+        .map(|(parent, name)| parent.join(name));
+    match canonical {
+        Some(canonical) if canonical != path => vec![path, canonical],
+        _ => vec![path],
+    }
+}
+
+/// Expand dynamic string tokens in search paths
+///
+/// What `$LIB` expands to depends on how glibc was built (`lib64` on Fedora,
+/// `lib/x86_64-linux-gnu` on Debian, `lib` on Arch Linux), so each possible
+/// expansion for the target is returned. Returns nothing for paths that can't
+/// be expanded (`$PLATFORM`).
+fn expand_tokens(dir: &str, origin: &Path, target: Target) -> Vec<PathBuf> {
+    if dir.contains("PLATFORM") {
+        return vec![];
+    }
+    let origin = origin.to_string_lossy();
+    let expanded = dir
+        .replace("${ORIGIN}", &origin)
+        .replace("$ORIGIN", &origin);
+    if !expanded.contains("$LIB") && !expanded.contains("${LIB}") {
+        return vec![PathBuf::from(expanded)];
+    }
+    let multiarch = multiarch_tuples(target)
+        .iter()
+        .map(|tuple| format!("lib/{tuple}"));
+    let native = if target.is_64 { "lib64" } else { "lib32" };
+    multiarch
+        .chain([native.to_string(), "lib".to_string()])
+        .map(|lib| PathBuf::from(expanded.replace("${LIB}", &lib).replace("$LIB", &lib)))
+        .collect()
+}
+
+/// Debian multiarch tuples that libraries for the target may be installed
+/// under
+fn multiarch_tuples(target: Target) -> &'static [&'static str] {
+    match (target.machine, target.is_64, target.little_endian) {
+        (elf::EM_X86_64, true, _) => &["x86_64-linux-gnu"],
+        (elf::EM_X86_64, false, _) => &["x86_64-linux-gnux32"],
+        (elf::EM_386, _, _) => &["i386-linux-gnu"],
+        (elf::EM_AARCH64, _, true) => &["aarch64-linux-gnu"],
+        (elf::EM_ARM, _, true) => &["arm-linux-gnueabihf", "arm-linux-gnueabi"],
+        (elf::EM_PPC64, _, true) => &["powerpc64le-linux-gnu"],
+        (elf::EM_PPC64, _, false) => &["powerpc64-linux-gnu"],
+        (elf::EM_RISCV, true, _) => &["riscv64-linux-gnu"],
+        (elf::EM_S390, true, _) => &["s390x-linux-gnu"],
+        (elf::EM_LOONGARCH, true, _) => &["loongarch64-linux-gnu"],
+        _ => &[],
+    }
+}
+
+/// Parse ld.so.conf, following includes
+fn read_ld_so_conf(path: &Path, dirs: &mut Vec<PathBuf>, depth: usize) {
+    // Protect against include loops
+    if depth > 8 {
+        tracing::warn!("Too deeply nested includes in {path:?}");
+        return;
+    }
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) => {
+            tracing::debug!("Failed to read {path:?}: {err}");
+            return;
+        }
+    };
+    for line in contents.lines() {
+        let line = line.split_once('#').map_or(line, |(line, _)| line).trim();
+        if line.is_empty() || line.starts_with("hwcap ") {
+            continue;
+        }
+        if let Some(pattern) = line.strip_prefix("include") {
+            let pattern = pattern.trim();
+            let pattern = match path.parent() {
+                Some(parent) if !pattern.starts_with('/') => {
+                    parent.join(pattern).to_string_lossy().into_owned()
+                }
+                _ => pattern.to_string(),
+            };
+            let Ok(matches) = glob::glob(&pattern) else {
+                tracing::warn!("Invalid include pattern in {path:?}: {pattern}");
+                continue;
+            };
+            let mut included: Vec<_> = matches.filter_map(Result::ok).collect();
+            included.sort();
+            for include in included {
+                read_ld_so_conf(&include, dirs, depth + 1);
+            }
+        } else {
+            // Directories may be separated by colons, commas or whitespace
+            dirs.extend(
+                line.split([':', ',', ' ', '\t'])
+                    .filter(|dir| !dir.is_empty())
+                    .map(PathBuf::from),
+            );
+        }
+    }
+}
+
+/// Read just the ELF target from the start of the file (following symlinks)
+fn read_target(path: &Path) -> std::io::Result<Option<Target>> {
+    let mut header = [0; 20];
+    let mut file = std::fs::File::open(path)?;
+    file.read_exact(&mut header)?;
+    Ok(target_from_ident(&header))
+}
+
+fn target_from_ident(header: &[u8; 20]) -> Option<Target> {
+    if header[..4] != elf::ELFMAG {
+        return None;
+    }
+    let is_64 = match header[EI_CLASS] {
+        elf::ELFCLASS32 => false,
+        elf::ELFCLASS64 => true,
+        _ => return None,
+    };
+    let (little_endian, machine) = match header[EI_DATA] {
+        elf::ELFDATA2LSB => (true, u16::from_le_bytes([header[18], header[19]])),
+        elf::ELFDATA2MSB => (false, u16::from_be_bytes([header[18], header[19]])),
+        _ => return None,
+    };
+    Some(Target {
+        is_64,
+        little_endian,
+        machine,
+    })
+}
+
+/// Parse the dynamic section of a (possible) ELF file.
+///
+/// Returns `None` for files that aren't dynamically linked ELF executables or
+/// libraries.
+fn parse_elf(path: &Path) -> eyre::Result<Option<ElfInfo>> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    let is_library = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().contains(".so"));
+    if !metadata.is_file() || (metadata.mode() & 0o111 == 0 && !is_library) {
+        return Ok(None);
+    }
+    let Ok(Some(target)) = read_target(path) else {
+        return Ok(None);
+    };
+    let file = std::fs::File::open(path)?;
+    let data = object::ReadCache::new(file);
+    if target.is_64 {
+        parse_dynamic::<elf::FileHeader64<object::Endianness>>(&data, target)
+    } else {
+        parse_dynamic::<elf::FileHeader32<object::Endianness>>(&data, target)
+    }
+}
+
+fn parse_dynamic<Elf: FileHeader<Endian = object::Endianness>>(
+    data: &object::ReadCache<std::fs::File>,
+    target: Target,
+) -> eyre::Result<Option<ElfInfo>> {
+    let header = Elf::parse(data)?;
+    let endian = header.endian()?;
+    if !matches!(header.e_type(endian), elf::ET_EXEC | elf::ET_DYN) {
+        return Ok(None);
+    }
+    // Use the program headers like the dynamic linker does, section headers
+    // are optional (and may be stripped or wrong)
+    let segments = header.program_headers(endian, data)?;
+    let Some(dynamic) = segments
+        .iter()
+        .find_map(|segment| segment.dynamic(endian, data).transpose())
+        .transpose()?
+    else {
+        return Ok(None);
+    };
+    let strings = dynamic_strings::<Elf>(segments, dynamic, endian, data)?;
+    let mut info = ElfInfo {
+        target,
+        needed: vec![],
+        rpath: vec![],
+        runpath: vec![],
+    };
+    for entry in dynamic {
+        let tag = entry.tag32(endian);
+        let search_path = match tag {
+            Some(elf::DT_NEEDED) => {
+                let value = dynamic_string::<Elf>(entry, endian, strings)?;
+                info.needed
+                    .push(String::from_utf8_lossy(value).as_ref().into());
+                continue;
+            }
+            Some(elf::DT_RPATH) => &mut info.rpath,
+            Some(elf::DT_RUNPATH) => &mut info.runpath,
+            Some(elf::DT_NULL) => break,
+            _ => continue,
+        };
+        let value = String::from_utf8_lossy(dynamic_string::<Elf>(entry, endian, strings)?);
+        search_path.extend(value.split(':').filter(|s| !s.is_empty()).map(Into::into));
+    }
+    Ok(Some(info))
+}
+
+/// Find the string table of the dynamic segment
+///
+/// `DT_STRTAB` is a virtual address, which is mapped to a file offset using
+/// the loadable segments.
+fn dynamic_strings<'data, Elf: FileHeader<Endian = object::Endianness>>(
+    segments: &[Elf::ProgramHeader],
+    dynamic: &[Elf::Dyn],
+    endian: object::Endianness,
+    data: &'data object::ReadCache<std::fs::File>,
+) -> eyre::Result<object::read::StringTable<'data, &'data object::ReadCache<std::fs::File>>> {
+    let mut address = None;
+    let mut size = None;
+    for entry in dynamic {
+        match entry.tag32(endian) {
+            Some(elf::DT_STRTAB) => address = Some(entry.d_val(endian).into()),
+            Some(elf::DT_STRSZ) => size = Some(entry.d_val(endian).into()),
+            Some(elf::DT_NULL) => break,
+            _ => (),
+        }
+    }
+    let (Some(address), Some(size)): (Option<u64>, Option<u64>) = (address, size) else {
+        eyre::bail!("Dynamic segment without string table");
+    };
+    let offset = segments
+        .iter()
+        .filter(|segment| segment.p_type(endian) == elf::PT_LOAD)
+        .find_map(|segment| {
+            let start = address.checked_sub(segment.p_vaddr(endian).into())?;
+            let file_size: u64 = segment.p_filesz(endian).into();
+            (start.checked_add(size)? <= file_size)
+                .then(|| start + Into::<u64>::into(segment.p_offset(endian)))
+        })
+        .ok_or_else(|| eyre::eyre!("Dynamic string table not in a loadable segment"))?;
+    Ok(object::read::StringTable::new(data, offset, offset + size))
+}
+
+/// Look up the string a dynamic entry refers to
+fn dynamic_string<'data, Elf: FileHeader<Endian = object::Endianness>>(
+    entry: &Elf::Dyn,
+    endian: object::Endianness,
+    strings: object::read::StringTable<'data, &'data object::ReadCache<std::fs::File>>,
+) -> eyre::Result<&'data [u8]> {
+    let offset: u64 = entry.d_val(endian).into();
+    strings
+        .get(u32::try_from(offset)?)
+        .map_err(|()| eyre::eyre!("Invalid string offset in dynamic section"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_from_ident() {
+        let mut header = [0; 20];
+        header[..4].copy_from_slice(&elf::ELFMAG);
+        header[EI_CLASS] = elf::ELFCLASS64;
+        header[EI_DATA] = elf::ELFDATA2LSB;
+        header[18..20].copy_from_slice(&elf::EM_X86_64.to_le_bytes());
+        assert_eq!(
+            target_from_ident(&header),
+            Some(Target {
+                is_64: true,
+                little_endian: true,
+                machine: elf::EM_X86_64,
+            })
+        );
+        header[0] = b'#';
+        assert_eq!(target_from_ident(&header), None);
+    }
+
+    #[test]
+    fn test_expand_tokens() {
+        let target = Target {
+            is_64: true,
+            little_endian: true,
+            machine: elf::EM_X86_64,
+        };
+        assert_eq!(
+            expand_tokens("$ORIGIN/../lib", Path::new("/opt/foo/bin"), target),
+            vec![PathBuf::from("/opt/foo/bin/../lib")]
+        );
+        assert_eq!(
+            expand_tokens("${ORIGIN}/$LIB", Path::new("/opt/foo"), target),
+            vec![
+                PathBuf::from("/opt/foo/lib/x86_64-linux-gnu"),
+                PathBuf::from("/opt/foo/lib64"),
+                PathBuf::from("/opt/foo/lib"),
+            ]
+        );
+        assert_eq!(
+            expand_tokens("/usr/lib/$PLATFORM", Path::new("/"), target),
+            Vec::<PathBuf>::new()
+        );
+    }
+
+    #[test]
+    #[cfg(target_env = "gnu")]
+    fn test_parse_self() {
+        // The test binary itself is a dynamically linked ELF file
+        let exe = std::env::current_exe().unwrap();
+        let info = parse_elf(&exe).unwrap().unwrap();
+        assert!(info.needed.iter().any(|lib| lib.starts_with("libc.so")));
+    }
+
+    #[test]
+    #[cfg(all(target_env = "gnu", target_pointer_width = "64"))]
+    fn test_parse_without_section_headers() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // The dynamic linker doesn't need section headers, so neither should we
+        let mut data = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+        // e_shoff, e_shentsize, e_shnum and e_shstrndx of the ELF64 header
+        data[0x28..0x30].fill(0);
+        data[0x3a..0x40].fill(0);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stripped");
+        std::fs::write(&path, data).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let info = parse_elf(&path).unwrap().unwrap();
+        assert!(info.needed.iter().any(|lib| lib.starts_with("libc.so")));
+    }
+}
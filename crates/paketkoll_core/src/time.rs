@@ -0,0 +1,243 @@
+//! Calendar dates and times
+//!
+//! Package manager logs, waivers and SBOMs all need to convert between
+//! seconds since the Unix epoch and civil dates. This is the one place that
+//! does that (in either UTC or local time).
+
+/// A date and time without time zone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CivilTime {
+    pub year: i32,
+    pub month: i32,
+    pub day: i32,
+    pub hour: i32,
+    pub minute: i32,
+    pub second: i32,
+}
+
+impl CivilTime {
+    /// Parse `YYYY-MM-DD`, optionally followed by whitespace or `T` and
+    /// `HH:MM[:SS]`
+    #[must_use]
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.split_once([' ', 'T']) {
+            Some((date, time)) => Self::parse_parts(date, Some(time.trim_start())),
+            None => Self::parse_parts(s, None),
+        }
+    }
+
+    pub(crate) fn parse_parts(date: &str, time: Option<&str>) -> Option<Self> {
+        let mut date = date.split('-');
+        let year = date.next()?.parse().ok()?;
+        let month = date.next()?.parse().ok()?;
+        let day = date.next()?.parse().ok()?;
+        if date.next().is_some() {
+            return None;
+        }
+        let (hour, minute, second) = match time {
+            Some(time) => {
+                let mut time = time.split(':');
+                let hour = time.next()?.parse().ok()?;
+                let minute = time.next()?.parse().ok()?;
+                let second = time.next().map_or(Some(0), |second| second.parse().ok())?;
+                if time.next().is_some() {
+                    return None;
+                }
+                (hour, minute, second)
+            }
+            None => (0, 0, 0),
+        };
+        let valid = is_valid_date(year, month, day)
+            && (0..24).contains(&hour)
+            && (0..60).contains(&minute)
+            && (0..=60).contains(&second);
+        valid.then_some(Self {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        })
+    }
+
+    /// Convert to seconds since the Unix epoch, interpreting this as UTC
+    #[must_use]
+    pub fn to_utc_timestamp(self) -> i64 {
+        let days = days_from_civil(i64::from(self.year), self.month, self.day);
+        days * 86400
+            + i64::from(self.hour) * 3600
+            + i64::from(self.minute) * 60
+            + i64::from(self.second)
+    }
+
+    /// Convert seconds since the Unix epoch to UTC
+    #[must_use]
+    pub fn from_utc_timestamp(time: i64) -> Option<Self> {
+        let (days, secs) = (time.div_euclid(86400), time.rem_euclid(86400));
+        let (year, month, day) = civil_from_days(days);
+        Some(Self {
+            year: year.try_into().ok()?,
+            month,
+            day,
+            // These are all less than 86400, so they fit
+            hour: (secs / 3600) as i32,
+            minute: (secs / 60 % 60) as i32,
+            second: (secs % 60) as i32,
+        })
+    }
+
+    /// Convert to seconds since the Unix epoch, interpreting this as local time
+    #[must_use]
+    pub fn to_local_timestamp(self) -> Option<i64> {
+        // SAFETY: libc::tm is a plain C struct, for which all zeros is valid
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        tm.tm_year = self.year - 1900;
+        tm.tm_mon = self.month - 1;
+        tm.tm_mday = self.day;
+        tm.tm_hour = self.hour;
+        tm.tm_min = self.minute;
+        tm.tm_sec = self.second;
+        // Let mktime figure out if daylight saving time is in effect
+        tm.tm_isdst = -1;
+        // SAFETY: tm is a valid and initialised struct that we have exclusive
+        // access to.
+        let time = unsafe { libc::mktime(&raw mut tm) };
+        (time != -1).then_some(time)
+    }
+
+    /// Convert seconds since the Unix epoch to local time
+    #[must_use]
+    pub fn from_local_timestamp(time: i64) -> Option<Self> {
+        // SAFETY: libc::tm is a plain C struct, for which all zeros is valid
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        // SAFETY: Both pointers are valid, and localtime_r (unlike localtime)
+        // doesn't use shared state for the result.
+        if unsafe { libc::localtime_r(&raw const time, &raw mut tm) }.is_null() {
+            return None;
+        }
+        Some(Self {
+            year: tm.tm_year + 1900,
+            month: tm.tm_mon + 1,
+            day: tm.tm_mday,
+            hour: tm.tm_hour,
+            minute: tm.tm_min,
+            second: tm.tm_sec,
+        })
+    }
+}
+
+impl std::fmt::Display for CivilTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Format seconds since the Unix epoch as an RFC 3339 UTC timestamp (such as
+/// `2024-05-20T12:00:00Z`)
+#[must_use]
+pub fn format_rfc3339_utc(time: i64) -> String {
+    match CivilTime::from_utc_timestamp(time) {
+        Some(civil) => format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            civil.year, civil.month, civil.day, civil.hour, civil.minute, civil.second
+        ),
+        None => format!("@{time}"),
+    }
+}
+
+/// Is the day valid for the month (taking leap years into account)
+#[must_use]
+pub const fn is_valid_date(year: i32, month: i32, day: i32) -> bool {
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        _ => return false,
+    };
+    1 <= day && day <= days_in_month
+}
+
+/// Convert a civil date to days since the Unix epoch, see
+/// <https://howardhinnant.github.io/date_algorithms.html#days_from_civil>
+const fn days_from_civil(year: i64, month: i32, day: i32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Convert days since the Unix epoch to a civil date, see
+/// <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+const fn civil_from_days(days: i64) -> (i64, i32, i32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as i32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as i32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn utc(s: &str) -> i64 {
+        CivilTime::parse(s).unwrap().to_utc_timestamp()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            CivilTime::parse("2024-03-01  01:02").unwrap(),
+            CivilTime::parse("2024-03-01 01:02:00").unwrap()
+        );
+        assert!(CivilTime::parse("2024-13-01").is_none());
+        assert!(CivilTime::parse("2024-04-31").is_none());
+        assert!(CivilTime::parse("2024-03-01 25:00").is_none());
+        assert!(CivilTime::parse("yesterday").is_none());
+    }
+
+    #[test]
+    fn test_utc_timestamp() {
+        assert_eq!(utc("1970-01-01"), 0);
+        assert_eq!(utc("2024-03-01T01:02:03"), 1_709_254_923);
+        assert_eq!(utc("1969-12-31 23:59:59"), -1);
+        for time in [0, -1, 951_782_400, 1_709_254_923, 4_107_542_399] {
+            let civil = CivilTime::from_utc_timestamp(time).unwrap();
+            assert_eq!(civil.to_utc_timestamp(), time);
+        }
+    }
+
+    #[test]
+    fn test_format_rfc3339_utc() {
+        assert_eq!(format_rfc3339_utc(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_rfc3339_utc(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_rfc3339_utc(1_716_206_400), "2024-05-20T12:00:00Z");
+    }
+
+    #[test]
+    fn test_is_valid_date() {
+        assert!(is_valid_date(2024, 2, 29));
+        assert!(is_valid_date(2000, 2, 29));
+        assert!(!is_valid_date(1900, 2, 29));
+        assert!(!is_valid_date(2025, 2, 29));
+        assert!(!is_valid_date(2025, 4, 31));
+        assert!(is_valid_date(2025, 12, 31));
+        assert!(!is_valid_date(2025, 13, 1));
+        assert!(!is_valid_date(2025, 1, 0));
+    }
+}
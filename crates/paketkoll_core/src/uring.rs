@@ -0,0 +1,519 @@
+//! Checking files in batches using `io_uring`
+//!
+//! Checking a small file takes several system calls (stat, open, a couple of
+//! reads and close), so with many small files most of the time is spent on
+//! system call overhead. Here each of those steps is submitted for a whole
+//! batch of files at once.
+//!
+//! If `io_uring` is not available (old kernel, or blocked by seccomp as in
+//! many containers) the caller falls back to checking files one at a time.
+
+use crate::backend::filesystem::ChecksumHasher;
+use crate::backend::filesystem::FileStat;
+use crate::backend::filesystem::check_file;
+use crate::backend::filesystem::check_file_stat;
+use crate::backend::filesystem::checksum_to_verify;
+use crate::backend::filesystem::entry_type_from_mode;
+use crate::backend::filesystem::system_time;
+use crate::config::CommonFileCheckConfiguration;
+use io_uring::IoUring;
+use io_uring::Probe;
+use io_uring::opcode;
+use io_uring::squeue;
+use io_uring::types::Fd;
+use paketkoll_types::files::Checksum;
+use paketkoll_types::files::FileEntry;
+use paketkoll_types::issue::Issue;
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::io::ErrorKind;
+use std::os::unix::ffi::OsStrExt;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+/// Number of files checked together
+pub(crate) const BATCH_SIZE: usize = 64;
+
+/// Largest read to submit for a file
+const MAX_READ_SIZE: usize = 64 * 1024;
+
+/// Set when `io_uring` turned out not to work, to not try again on every thread
+static UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// Ring for the current thread, None if it could not be created
+    static RING: RefCell<Option<IoUring>> = RefCell::new(create_ring());
+}
+
+/// Check if batched checking should be attempted
+pub(crate) fn enabled(config: &CommonFileCheckConfiguration) -> bool {
+    config.io_uring && !UNAVAILABLE.load(Ordering::Relaxed)
+}
+
+/// Create a ring supporting the operations we need
+fn create_ring() -> Option<IoUring> {
+    if UNAVAILABLE.load(Ordering::Relaxed) {
+        return None;
+    }
+    let ring = IoUring::new(BATCH_SIZE as u32).and_then(|ring| {
+        let mut probe = Probe::new();
+        ring.submitter().register_probe(&mut probe)?;
+        let supported = [
+            opcode::Statx::CODE,
+            opcode::OpenAt::CODE,
+            opcode::Read::CODE,
+            opcode::Close::CODE,
+        ]
+        .into_iter()
+        .all(|code| probe.is_supported(code));
+        if supported {
+            Ok(ring)
+        } else {
+            Err(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "Required operations not supported",
+            ))
+        }
+    });
+    match ring {
+        Ok(ring) => Some(ring),
+        Err(err) => {
+            if !UNAVAILABLE.swap(true, Ordering::Relaxed) {
+                tracing::info!("io_uring not available, checking files one at a time: {err}");
+            }
+            None
+        }
+    }
+}
+
+/// Check a batch of (at most [`BATCH_SIZE`]) files
+///
+/// Returns the results in the same order as the files, or `None` if
+/// `io_uring` could not be used (in which case the files should be checked
+/// the normal way).
+pub(crate) fn check_files(
+    files: &[FileEntry],
+    config: &CommonFileCheckConfiguration,
+) -> Option<Vec<eyre::Result<Option<Issue>>>> {
+    debug_assert!(files.len() <= BATCH_SIZE);
+    RING.with_borrow_mut(|ring| {
+        let result = check_files_with_ring(ring.as_mut()?, files, config);
+        if result.is_none() {
+            // Something is wrong with the ring, don't use it again
+            *ring = None;
+        }
+        result
+    })
+}
+
+/// State of reading one file
+struct OpenFile {
+    /// Index in the batch
+    index: usize,
+    fd: libc::c_int,
+    hasher: Option<ChecksumHasher>,
+    buffer: Vec<u8>,
+    offset: u64,
+    result: Option<std::io::Result<Checksum>>,
+}
+
+fn check_files_with_ring(
+    ring: &mut IoUring,
+    files: &[FileEntry],
+    config: &CommonFileCheckConfiguration,
+) -> Option<Vec<eyre::Result<Option<Issue>>>> {
+    // Paths that can't be passed to the kernel (or that should be skipped)
+    // are handled separately.
+    let paths: Vec<Option<CString>> = files
+        .iter()
+        .map(|file| {
+            if config.is_skipped_network_path(&file.path) {
+                None
+            } else {
+                CString::new(file.path.as_os_str().as_bytes()).ok()
+            }
+        })
+        .collect();
+
+    // Stage 1: statx
+    // SAFETY: statx is a plain C struct, all zeroes is a valid value.
+    let mut statx_buffers: Vec<libc::statx> = vec![unsafe { std::mem::zeroed() }; files.len()];
+    let entries: Vec<_> = paths
+        .iter()
+        .zip(statx_buffers.iter_mut())
+        .enumerate()
+        .filter_map(|(index, (path, buffer))| {
+            let path = path.as_ref()?;
+            Some(
+                opcode::Statx::new(
+                    Fd(libc::AT_FDCWD),
+                    path.as_ptr(),
+                    std::ptr::from_mut(buffer).cast(),
+                )
+                .flags(libc::AT_SYMLINK_NOFOLLOW)
+                .mask(libc::STATX_BASIC_STATS)
+                .build()
+                .user_data(index as u64),
+            )
+        })
+        .collect();
+    let mut stats: Vec<Option<std::io::Result<FileStat>>> =
+        std::iter::repeat_with(|| None).take(files.len()).collect();
+    // SAFETY: The paths and buffers outlive the operations (they are leaked
+    // if waiting for the operations fails).
+    let Ok(completions) = (unsafe { run(ring, &entries) }) else {
+        std::mem::forget(paths);
+        std::mem::forget(statx_buffers);
+        return None;
+    };
+    for (index, result) in completions {
+        stats[index] = Some(if result < 0 {
+            Err(std::io::Error::from_raw_os_error(-result))
+        } else {
+            Ok(file_stat(&statx_buffers[index]))
+        });
+    }
+
+    // Stage 2: open the files we need to read
+    let to_open: Vec<(usize, ChecksumHasher, u64)> = files
+        .iter()
+        .zip(&stats)
+        .enumerate()
+        .filter_map(|(index, (file, stat))| {
+            let Some(Ok(stat)) = stat else {
+                return None;
+            };
+            let checksum = checksum_to_verify(file, config, stat)?;
+            Some((index, ChecksumHasher::new(checksum)?, stat.size))
+        })
+        .collect();
+    let entries: Vec<_> = to_open
+        .iter()
+        .map(|&(index, _, _)| {
+            let path = paths[index].as_ref().expect("Only opening stated paths");
+            // Non-blocking in case the file was replaced with a FIFO since
+            // the statx.
+            opcode::OpenAt::new(Fd(libc::AT_FDCWD), path.as_ptr())
+                .flags(
+                    libc::O_RDONLY
+                        | libc::O_CLOEXEC
+                        | libc::O_NOFOLLOW
+                        | libc::O_NOCTTY
+                        | libc::O_NONBLOCK,
+                )
+                .build()
+                .user_data(index as u64)
+        })
+        .collect();
+    // SAFETY: The paths outlive the operations.
+    let completions = match unsafe { run(ring, &entries) } {
+        Ok(completions) => completions,
+        Err(completed) => {
+            // Don't leak the files that did get opened, they will all be
+            // checked again the normal way.
+            for (_, result) in completed {
+                if result >= 0 {
+                    // SAFETY: The file descriptor was opened by us and is not
+                    // used after this.
+                    unsafe { libc::close(result) };
+                }
+            }
+            std::mem::forget(paths);
+            return None;
+        }
+    };
+    let mut hashers: Vec<Option<(ChecksumHasher, u64)>> =
+        std::iter::repeat_with(|| None).take(files.len()).collect();
+    for (index, hasher, size) in to_open {
+        hashers[index] = Some((hasher, size));
+    }
+    let mut open_files = vec![];
+    let mut checksums: Vec<Option<std::io::Result<Checksum>>> =
+        std::iter::repeat_with(|| None).take(files.len()).collect();
+    for (index, result) in completions {
+        let (hasher, size) = hashers[index].take().expect("Opened without hasher");
+        if result < 0 {
+            checksums[index] = Some(Err(std::io::Error::from_raw_os_error(-result)));
+        } else {
+            // One more than the size, so that small files are read in one go
+            // (followed by a read to confirm the end of the file).
+            let buffer_size = usize::try_from(size)
+                .unwrap_or(MAX_READ_SIZE)
+                .saturating_add(1)
+                .min(MAX_READ_SIZE);
+            open_files.push(OpenFile {
+                index,
+                fd: result,
+                hasher: Some(hasher),
+                buffer: vec![0; buffer_size],
+                offset: 0,
+                result: None,
+            });
+        }
+    }
+
+    // Stage 3: read until the end of all files
+    let read_ok = read_files(ring, config, &mut open_files);
+
+    // Stage 4: close the files
+    let entries: Vec<_> = open_files
+        .iter()
+        .map(|file| opcode::Close::new(Fd(file.fd)).build())
+        .collect();
+    if !read_ok {
+        for file in &open_files {
+            // SAFETY: The file descriptor was opened by us and is not used
+            // after this.
+            unsafe { libc::close(file.fd) };
+        }
+        std::mem::forget(open_files);
+        return None;
+    }
+    // SAFETY: Close doesn't reference any memory.
+    unsafe { run(ring, &entries) }.ok()?;
+
+    for file in open_files {
+        checksums[file.index] = file.result;
+    }
+
+    Some(
+        files
+            .iter()
+            .zip(paths)
+            .zip(stats)
+            .zip(checksums)
+            .map(|(((file, path), stat), checksum)| match (path, stat) {
+                (Some(_), Some(stat)) => check_file_stat(file, config, stat, checksum),
+                // Skipped or not representable as a C string
+                _ => check_file(file, config),
+            })
+            .collect(),
+    )
+}
+
+/// Read all open files until the end, computing their checksums
+///
+/// Returns false if waiting for the reads failed.
+fn read_files(
+    ring: &mut IoUring,
+    config: &CommonFileCheckConfiguration,
+    open_files: &mut [OpenFile],
+) -> bool {
+    loop {
+        let entries: Vec<_> = open_files
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, file)| file.result.is_none())
+            .map(|(slot, file)| {
+                opcode::Read::new(
+                    Fd(file.fd),
+                    file.buffer.as_mut_ptr(),
+                    file.buffer.len() as u32,
+                )
+                .offset(file.offset)
+                .build()
+                .user_data(slot as u64)
+            })
+            .collect();
+        if entries.is_empty() {
+            return true;
+        }
+        // SAFETY: The buffers outlive the operations (the caller leaks them
+        // if this fails).
+        let Ok(completions) = (unsafe { run(ring, &entries) }) else {
+            return false;
+        };
+        for (slot, result) in completions {
+            let file = &mut open_files[slot];
+            match result {
+                0 => {
+                    let hasher = file.hasher.take().expect("Finished file twice");
+                    file.result = Some(Ok(hasher.finish()));
+                }
+                n if n > 0 => {
+                    let n = n as usize;
+                    if let Some(ref limiter) = config.read_limit {
+                        limiter.consume(n);
+                    }
+                    file.hasher
+                        .as_mut()
+                        .expect("Read after end of file")
+                        .update(&file.buffer[..n]);
+                    file.offset += n as u64;
+                }
+                // Retried in the next round. EAGAIN is not retried, it means
+                // the file was replaced with something like a FIFO (opened
+                // non-blocking) that may never get any data.
+                err if -err == libc::EINTR => (),
+                err => file.result = Some(Err(std::io::Error::from_raw_os_error(-err))),
+            }
+        }
+    }
+}
+
+/// Index (user data) and result of completed operations
+type Completions = Vec<(usize, i32)>;
+
+/// Submit operations and wait for all of them to complete
+///
+/// Returns the index (user data) and result of each operation. If submitting
+/// or waiting failed, the error has the operations that completed so far.
+///
+/// # Safety
+/// Memory referenced by the operations must stay valid until they complete.
+/// If this returns an error some of them may still be in progress, so the
+/// memory must not be freed.
+unsafe fn run(ring: &mut IoUring, entries: &[squeue::Entry]) -> Result<Completions, Completions> {
+    if entries.is_empty() {
+        return Ok(vec![]);
+    }
+    for entry in entries {
+        // SAFETY: Upheld by the caller.
+        if unsafe { ring.submission().push(entry) }.is_err() {
+            tracing::warn!("io_uring submission queue full");
+            return Err(vec![]);
+        }
+    }
+    let mut completions = Vec::with_capacity(entries.len());
+    while completions.len() < entries.len() {
+        match ring.submit_and_wait(entries.len() - completions.len()) {
+            Ok(_) => (),
+            Err(err) if err.kind() == ErrorKind::Interrupted => (),
+            Err(err) => {
+                tracing::warn!("Failed to wait for io_uring operations: {err}");
+                completions.extend(
+                    ring.completion()
+                        .map(|entry| (entry.user_data() as usize, entry.result())),
+                );
+                return Err(completions);
+            }
+        }
+        completions.extend(
+            ring.completion()
+                .map(|entry| (entry.user_data() as usize, entry.result())),
+        );
+    }
+    Ok(completions)
+}
+
+/// Convert the result of statx
+fn file_stat(statx: &libc::statx) -> FileStat {
+    let mode = u32::from(statx.stx_mode);
+    FileStat {
+        file_type: entry_type_from_mode(mode),
+        mode,
+        uid: statx.stx_uid,
+        gid: statx.stx_gid,
+        size: statx.stx_size,
+        mtime: system_time(statx.stx_mtime.tv_sec, statx.stx_mtime.tv_nsec),
+        rdev: (
+            u64::from(statx.stx_rdev_major),
+            u64::from(statx.stx_rdev_minor),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paketkoll_types::files::FileFlags;
+    use paketkoll_types::files::Properties;
+    use paketkoll_types::files::RegularFileBasic;
+    use paketkoll_utils::checksum::sha256_buffer;
+    use std::path::Path;
+
+    fn entry(path: &Path, contents: &[u8]) -> FileEntry {
+        FileEntry {
+            package: None,
+            path: path.to_path_buf(),
+            properties: Properties::RegularFileBasic(RegularFileBasic {
+                size: Some(contents.len() as u64),
+                checksum: sha256_buffer(contents),
+            }),
+            flags: FileFlags::empty(),
+            source: "test",
+            seen: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_same_as_unbatched() {
+        let dir = tempfile::tempdir().unwrap();
+        let large = vec![b'x'; 3 * MAX_READ_SIZE + 17];
+        let mut files = vec![];
+        for (name, expected, actual) in [
+            ("ok", &b"hello"[..], Some(&b"hello"[..])),
+            ("empty", b"", Some(b"")),
+            ("large", &large, Some(&large)),
+            ("changed", b"hello", Some(b"world")),
+            ("size", b"hello", Some(b"hello world")),
+            ("missing", b"hello", None),
+        ] {
+            let path = dir.path().join(name);
+            if let Some(actual) = actual {
+                std::fs::write(&path, actual).unwrap();
+            }
+            files.push(entry(&path, expected));
+        }
+        let symlink = dir.path().join("symlink");
+        std::os::unix::fs::symlink("ok", &symlink).unwrap();
+        files.push(entry(&symlink, b"hello"));
+
+        let config = CommonFileCheckConfiguration::builder().build().unwrap();
+        let batched = match check_files(&files, &config) {
+            Some(batched) => batched,
+            None => {
+                // Blocked in many containers. Then callers must fall back to
+                // checking files one at a time. Set PAKETKOLL_TEST_IO_URING=1
+                // to require the batched path instead.
+                assert_ne!(
+                    std::env::var("PAKETKOLL_TEST_IO_URING").as_deref(),
+                    Ok("1"),
+                    "io_uring is not available"
+                );
+                assert!(!enabled(&config));
+                files.iter().map(|file| check_file(file, &config)).collect()
+            }
+        };
+        let describe = |issue: Option<Issue>| {
+            issue.map(|issue| {
+                issue
+                    .kinds()
+                    .map(|kind| format!("{}: {kind}", kind.name()))
+                    .collect::<Vec<_>>()
+            })
+        };
+        let batched: Vec<_> = batched
+            .into_iter()
+            .map(|result| describe(result.unwrap()))
+            .collect();
+        let unbatched: Vec<_> = files
+            .iter()
+            .map(|file| describe(check_file(file, &config).unwrap()))
+            .collect();
+        assert_eq!(batched, unbatched);
+        let kinds: Vec<_> = batched
+            .iter()
+            .map(|kinds| {
+                kinds.as_ref().map(|kinds| {
+                    kinds
+                        .iter()
+                        .map(|kind| kind.split(':').next().unwrap())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                None,
+                None,
+                None,
+                Some(vec!["checksum_incorrect"]),
+                Some(vec!["size_incorrect"]),
+                Some(vec!["missing"]),
+                Some(vec!["type_incorrect", "size_incorrect"]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,381 @@
+//! Waivers for accepted deviations from the package manager
+//!
+//! Unlike ignores (which drop paths entirely), a waiver only accepts specific
+//! kinds of issues (and optionally only specific actual values) on matching
+//! paths, and only until it expires.
+
+use crate::time::CivilTime;
+use crate::time::is_valid_date;
+use compact_str::CompactString;
+use compact_str::ToCompactString;
+use eyre::WrapErr;
+use globset::GlobBuilder;
+use globset::GlobSet;
+use globset::GlobSetBuilder;
+use paketkoll_types::issue::Issue;
+use paketkoll_types::issue::IssueKind;
+use paketkoll_types::issue::PackageIssue;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// A calendar date (in local time, same as the times in package manager logs)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl Date {
+    /// The current date
+    #[must_use]
+    pub fn today() -> Self {
+        let secs = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Self::from_timestamp(i64::try_from(secs).unwrap_or(i64::MAX))
+            .expect("Current date out of range")
+    }
+
+    /// The local date at a time in seconds since the Unix epoch
+    fn from_timestamp(time: i64) -> Option<Self> {
+        let civil = CivilTime::from_local_timestamp(time)?;
+        Some(Self {
+            year: civil.year.try_into().ok()?,
+            month: civil.month.try_into().ok()?,
+            day: civil.day.try_into().ok()?,
+        })
+    }
+}
+
+impl FromStr for Date {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, '-');
+        let mut next = || {
+            parts
+                .next()
+                .ok_or_else(|| eyre::eyre!("Expected YYYY-MM-DD"))
+        };
+        let date = Self {
+            year: next()?.parse()?,
+            month: next()?.parse()?,
+            day: next()?.parse()?,
+        };
+        if !is_valid_date(date.year.into(), date.month.into(), date.day.into()) {
+            eyre::bail!("Invalid date {s}");
+        }
+        Ok(date)
+    }
+}
+
+impl Display for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// An accepted deviation for paths matching a glob
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Waiver {
+    /// Glob matching the paths. Use `**` to match any number of path
+    /// components.
+    pub pattern: CompactString,
+    /// Name of the kind of issue to accept (see [`IssueKind::name`]), or
+    /// `None` for any kind of issue
+    pub kind: Option<CompactString>,
+    /// Only accept the issue if the actual value (mode, owner, checksum,
+    /// symlink target, ...) is this
+    pub actual: Option<CompactString>,
+    /// Last day the waiver is valid, `None` if it never expires
+    pub expires: Option<Date>,
+    /// Why the deviation is accepted
+    pub reason: CompactString,
+}
+
+impl Waiver {
+    /// Parse waivers, one per line in the form
+    /// `<glob> <kind>[=<actual>] <expiry> <reason>`.
+    ///
+    /// The kind can be `*` to match any kind of issue, and the expiry is
+    /// either a date (`YYYY-MM-DD`) or `never`. Empty lines and lines starting
+    /// with `#` are ignored.
+    pub fn parse(waivers: &str) -> eyre::Result<Vec<Self>> {
+        let mut result = vec![];
+        for (idx, line) in waivers.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            result.push(Self::parse_line(line).wrap_err_with(|| format!("Line {}", idx + 1))?);
+        }
+        Ok(result)
+    }
+
+    fn parse_line(line: &str) -> eyre::Result<Self> {
+        let mut fields = line.split_whitespace();
+        let mut next = |what| fields.next().ok_or_else(|| eyre::eyre!("Missing {what}"));
+        let pattern = next("glob")?;
+        let kind = next("issue kind")?;
+        let (kind, actual) = match kind.split_once('=') {
+            Some((kind, actual)) => (kind, Some(actual.into())),
+            None => (kind, None),
+        };
+        if kind != "*" && !IssueKind::NAMES.contains(&kind) {
+            eyre::bail!("Unknown issue kind {kind}");
+        }
+        let expires = match next("expiry date")? {
+            "never" => None,
+            date => Some(date.parse()?),
+        };
+        let reason = fields.collect::<Vec<_>>().join(" ");
+        if reason.is_empty() {
+            eyre::bail!("Missing reason");
+        }
+        Ok(Self {
+            pattern: pattern.into(),
+            kind: (kind != "*").then(|| kind.into()),
+            actual,
+            expires,
+            reason: reason.into(),
+        })
+    }
+
+    fn is_valid(&self, today: Date) -> bool {
+        self.expires.is_none_or(|expires| today <= expires)
+    }
+
+    fn matches(&self, kind: &IssueKind) -> bool {
+        self.kind.as_ref().is_none_or(|name| name == kind.name())
+            && self
+                .actual
+                .as_ref()
+                .is_none_or(|actual| actual_value(kind).is_some_and(|value| *actual == value))
+    }
+}
+
+/// The actual value of an issue, formatted the same way as in check output
+fn actual_value(kind: &IssueKind) -> Option<CompactString> {
+    match kind {
+        IssueKind::TypeIncorrect { actual, .. } => Some(actual.to_compact_string()),
+        IssueKind::SizeIncorrect { actual, .. } => Some(actual.to_compact_string()),
+        IssueKind::ChecksumIncorrect { actual, .. } => Some(actual.to_compact_string()),
+        IssueKind::SymlinkTarget { actual, .. } => Some(actual.to_string_lossy().into()),
+        IssueKind::WrongOwner { actual, .. } => Some(actual.to_compact_string()),
+        IssueKind::WrongGroup { actual, .. } => Some(actual.to_compact_string()),
+        IssueKind::WrongMode { actual, .. } => Some(actual.to_compact_string()),
+        _ => None,
+    }
+}
+
+/// A set of waivers to apply to check results
+#[derive(Debug, Clone)]
+pub struct Waivers {
+    globs: GlobSet,
+    waivers: Vec<Waiver>,
+}
+
+impl Waivers {
+    /// Build a set of waivers
+    pub fn new(waivers: Vec<Waiver>) -> eyre::Result<Self> {
+        let mut builder = GlobSetBuilder::new();
+        for waiver in &waivers {
+            builder.add(
+                GlobBuilder::new(&waiver.pattern)
+                    .literal_separator(true)
+                    .build()
+                    .wrap_err_with(|| format!("Invalid glob {:?}", waiver.pattern))?,
+            );
+        }
+        Ok(Self {
+            globs: builder.build()?,
+            waivers,
+        })
+    }
+
+    /// Remove the issues accepted by waivers that are still valid on `today`.
+    ///
+    /// Returns an issue for each expired waiver (issues matching those are no
+    /// longer accepted).
+    pub fn apply(&self, issues: &mut Vec<PackageIssue>, today: Date) -> Vec<PackageIssue> {
+        issues.retain_mut(|(_, issue)| self.waive(issue, today));
+        self.expired(today)
+    }
+
+    /// Remove the kinds of a single issue that are accepted by waivers still
+    /// valid on `today`.
+    ///
+    /// Returns false if no kinds of issues remain.
+    pub fn waive(&self, issue: &mut Issue, today: Date) -> bool {
+        let matching: Vec<_> = self
+            .globs
+            .matches(issue.path())
+            .into_iter()
+            .map(|idx| &self.waivers[idx])
+            .filter(|waiver| waiver.is_valid(today))
+            .collect();
+        if !matching.is_empty() {
+            issue.retain_kinds(|kind| !matching.iter().any(|waiver| waiver.matches(kind)));
+        }
+        issue.kinds().next().is_some()
+    }
+
+    /// Get an issue for each waiver that has expired on `today`
+    #[must_use]
+    pub fn expired(&self, today: Date) -> Vec<PackageIssue> {
+        self.waivers
+            .iter()
+            .filter(|waiver| !waiver.is_valid(today))
+            .map(|waiver| {
+                (
+                    None,
+                    Issue::new(
+                        PathBuf::from(waiver.pattern.as_str()),
+                        smallvec::smallvec![IssueKind::WaiverExpired {
+                            expired: waiver
+                                .expires
+                                .map(|date| date.to_compact_string())
+                                .unwrap_or_default(),
+                            reason: waiver.reason.clone(),
+                        }],
+                        None,
+                    ),
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use paketkoll_types::files::Mode;
+
+    #[test]
+    fn test_date() {
+        let noon = crate::package_log::parse_time("2024-02-29 12:00").unwrap();
+        assert_eq!(
+            Date::from_timestamp(noon),
+            Some(Date {
+                year: 2024,
+                month: 2,
+                day: 29
+            })
+        );
+        assert_eq!(
+            "2024-02-29".parse::<Date>().unwrap().to_string(),
+            "2024-02-29"
+        );
+        assert!("2024-13-01".parse::<Date>().is_err());
+        assert!("2025-02-29".parse::<Date>().is_err());
+        assert!("2025-02-31".parse::<Date>().is_err());
+        assert!("2025-04-31".parse::<Date>().is_err());
+        assert!("2025-04-30".parse::<Date>().is_ok());
+        assert!("2024-01".parse::<Date>().is_err());
+        assert!("2024-01-01".parse::<Date>().unwrap() < "2024-01-02".parse().unwrap());
+    }
+
+    #[test]
+    fn test_parse() {
+        let waivers = Waiver::parse(indoc! {"
+            # A comment
+            /usr/bin/foo   wrong_mode=4755  2030-01-01  Needs setuid for legacy app
+
+            /etc/hosts     *                never       Managed by cloud-init
+        "})
+        .unwrap();
+        assert_eq!(
+            waivers,
+            vec![
+                Waiver {
+                    pattern: "/usr/bin/foo".into(),
+                    kind: Some("wrong_mode".into()),
+                    actual: Some("4755".into()),
+                    expires: Some(Date {
+                        year: 2030,
+                        month: 1,
+                        day: 1
+                    }),
+                    reason: "Needs setuid for legacy app".into(),
+                },
+                Waiver {
+                    pattern: "/etc/hosts".into(),
+                    kind: None,
+                    actual: None,
+                    expires: None,
+                    reason: "Managed by cloud-init".into(),
+                },
+            ]
+        );
+        assert!(Waiver::parse("/etc/hosts wrong_mood never Typo").is_err());
+        assert!(Waiver::parse("/etc/hosts wrong_mode never").is_err());
+        assert!(Waiver::parse("/etc/hosts wrong_mode someday Bad date").is_err());
+    }
+
+    fn mode_issue(path: &str, actual: u32) -> PackageIssue {
+        (
+            None,
+            Issue::new(
+                path.into(),
+                smallvec::smallvec![
+                    IssueKind::WrongMode {
+                        actual: Mode::new(actual),
+                        expected: Mode::new(0o755),
+                    },
+                    IssueKind::SizeIncorrect {
+                        actual: 1,
+                        expected: 2,
+                    }
+                ],
+                None,
+            ),
+        )
+    }
+
+    #[test]
+    fn test_apply() {
+        let waivers = Waivers::new(
+            Waiver::parse(indoc! {"
+                /usr/bin/foo  wrong_mode=4755  2030-01-01  Known
+                /usr/bin/bar  *                never       Everything
+                /usr/bin/baz  wrong_mode       2020-01-01  Expired
+            "})
+            .unwrap(),
+        )
+        .unwrap();
+        let today = "2025-06-01".parse().unwrap();
+        let mut issues = vec![
+            mode_issue("/usr/bin/foo", 0o4755),
+            mode_issue("/usr/bin/foo", 0o777),
+            mode_issue("/usr/bin/bar", 0o777),
+            mode_issue("/usr/bin/baz", 0o777),
+        ];
+        let expired = waivers.apply(&mut issues, today);
+        let remaining: Vec<_> = issues
+            .iter()
+            .map(|(_, issue)| {
+                (
+                    issue.path().to_str().unwrap(),
+                    issue.kinds().map(IssueKind::name).collect::<Vec<_>>(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            remaining,
+            vec![
+                ("/usr/bin/foo", vec!["size_incorrect"]),
+                ("/usr/bin/foo", vec!["wrong_mode", "size_incorrect"]),
+                ("/usr/bin/baz", vec!["wrong_mode", "size_incorrect"]),
+            ]
+        );
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].1.path().to_str(), Some("/usr/bin/baz"));
+        assert_eq!(
+            expired[0].1.kinds().next().unwrap().to_string(),
+            "waiver expired on 2020-01-01 (Expired)"
+        );
+    }
+}
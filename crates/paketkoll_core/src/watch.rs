@@ -0,0 +1,601 @@
+//! Watch the file system and verify package files as they change
+//!
+//! Changed files are verified once they have been left alone for a while (see
+//! [`WatchConfiguration::settle_time`]). While the package manager is changing
+//! its database no files are verified, and afterwards the expected files are
+//! reloaded. This avoids reporting files that are in the middle of being
+//! upgraded.
+
+use crate::backend::ConcreteBackend;
+use crate::config::CheckAllFilesConfiguration;
+use crate::config::CommonFileCheckConfiguration;
+use crate::config::WatchConfiguration;
+use crate::config::WatchMethod;
+use crate::file_ops::CancellationToken;
+use ahash::AHashMap;
+use ahash::AHashSet;
+use eyre::WrapErr;
+use ignore::Match;
+use ignore::overrides::Override;
+use nix::errno::Errno;
+use nix::poll::PollFd;
+use nix::poll::PollFlags;
+use nix::poll::PollTimeout;
+use nix::sys::fanotify;
+use nix::sys::fanotify::Fanotify;
+use nix::sys::inotify;
+use nix::sys::inotify::AddWatchFlags;
+use nix::sys::inotify::Inotify;
+use nix::sys::inotify::WatchDescriptor;
+use paketkoll_types::files::FileEntry;
+use paketkoll_types::intern::Interner;
+use paketkoll_types::issue::Issue;
+use paketkoll_types::issue::IssueKind;
+use paketkoll_types::issue::PackageIssue;
+use std::os::fd::AsFd;
+use std::os::fd::AsRawFd;
+use std::os::fd::BorrowedFd;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Longest time to wait for events before checking for cancellation
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Changes seen by inotify
+const INOTIFY_MASK: AddWatchFlags = AddWatchFlags::IN_CLOSE_WRITE
+    .union(AddWatchFlags::IN_ATTRIB)
+    .union(AddWatchFlags::IN_CREATE)
+    .union(AddWatchFlags::IN_DELETE)
+    .union(AddWatchFlags::IN_MOVE)
+    .union(AddWatchFlags::IN_ONLYDIR);
+
+/// Watch the file system, sending issues to `collector` as changed files are
+/// found to not match the package manager's expectations.
+///
+/// New files in directories with package files are reported as unexpected
+/// (taking ignores and generated files in `unexpected_cfg` into account).
+/// Each issue is only reported again when it changes.
+///
+/// This runs until `cancel` is cancelled or the receiving end of the channel
+/// is dropped.
+#[allow(clippy::too_many_arguments)]
+pub fn watch_files(
+    backend: ConcreteBackend,
+    backend_config: &crate::backend::BackendConfiguration,
+    filecheck_config: &CommonFileCheckConfiguration,
+    unexpected_cfg: &CheckAllFilesConfiguration,
+    watch_config: &WatchConfiguration,
+    interner: &Interner,
+    collector: &flume::Sender<PackageIssue>,
+    cancel: &CancellationToken,
+) -> eyre::Result<()> {
+    let overrides = crate::file_ops::build_ignore_overrides(&unexpected_cfg.ignored_paths)?;
+    let database = database_dir(backend)?;
+    let load = || {
+        tracing::debug!("Loading expected files");
+        Expected::load(
+            backend,
+            backend_config,
+            filecheck_config,
+            unexpected_cfg,
+            watch_config,
+            interner,
+        )
+    };
+    let mut expected = load()?;
+    let mut watcher = Watcher::new(watch_config, &expected, database.as_deref())?;
+
+    let settle_time = watch_config.settle_time;
+    // Changed paths and when they last changed
+    let mut pending: AHashMap<PathBuf, Instant> = AHashMap::new();
+    // When the package database last changed, if it has changed since loading
+    let mut database_changed = None;
+    // Kinds of issues last reported for each path
+    let mut reported: AHashMap<PathBuf, String> = AHashMap::new();
+    while !cancel.is_cancelled() {
+        let next_due = pending
+            .values()
+            .chain(database_changed.iter())
+            .min()
+            .map(|&changed| changed + settle_time);
+        let timeout = next_due.map_or(POLL_INTERVAL, |due| {
+            due.saturating_duration_since(Instant::now())
+                .min(POLL_INTERVAL)
+        });
+        for path in watcher.wait(timeout)? {
+            let now = Instant::now();
+            if database.as_ref().is_some_and(|db| path.starts_with(db)) {
+                database_changed = Some(now);
+            } else if watch_config.paths.iter().any(|tree| path.starts_with(tree)) {
+                pending.insert(path, now);
+            }
+        }
+
+        let now = Instant::now();
+        if let Some(changed) = database_changed {
+            // Wait for the package manager to be done before verifying anything
+            if now < changed + settle_time {
+                continue;
+            }
+            tracing::info!("Package database changed, reloading expected files");
+            expected = load()?;
+            watcher.add_dirs(&expected);
+            database_changed = None;
+        }
+        let due: Vec<PathBuf> = pending
+            .iter()
+            .filter(|&(_, &changed)| now >= changed + settle_time)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in due {
+            pending.remove(&path);
+            match verify(
+                &path,
+                &expected,
+                filecheck_config,
+                unexpected_cfg,
+                &overrides,
+            ) {
+                Some(issue) => {
+                    let kinds = issue
+                        .1
+                        .kinds()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    if reported.get(&path) == Some(&kinds) {
+                        continue;
+                    }
+                    reported.insert(path, kinds);
+                    if collector.send(issue).is_err() {
+                        return Ok(());
+                    }
+                }
+                None => {
+                    reported.remove(&path);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Directory (or file) that changes when packages are installed or removed
+fn database_dir(backend: ConcreteBackend) -> eyre::Result<Option<PathBuf>> {
+    match backend {
+        #[cfg(feature = "arch_linux")]
+        ConcreteBackend::Pacman => Ok(Some(crate::backend::arch::local_db_dir()?)),
+        #[cfg(feature = "debian")]
+        ConcreteBackend::Apt => Ok(Path::new(crate::backend::deb::STATUS_PATH)
+            .parent()
+            .map(Path::to_path_buf)),
+        _ => Ok(None),
+    }
+}
+
+/// What the package manager expects to be on the file system
+struct Expected {
+    files: AHashMap<PathBuf, FileEntry>,
+    /// Directories with package files (in the watched trees)
+    dirs: AHashSet<PathBuf>,
+}
+
+impl Expected {
+    fn load(
+        backend: ConcreteBackend,
+        backend_config: &crate::backend::BackendConfiguration,
+        filecheck_config: &CommonFileCheckConfiguration,
+        unexpected_cfg: &CheckAllFilesConfiguration,
+        watch_config: &WatchConfiguration,
+        interner: &Interner,
+    ) -> eyre::Result<Self> {
+        let files = crate::file_ops::layered_files(
+            backend,
+            backend_config,
+            filecheck_config,
+            interner,
+            unexpected_cfg.canonicalize_paths,
+        )?;
+        let mut dirs = AHashSet::new();
+        for entry in &files {
+            let dir = if entry.properties.is_dir() == Some(true) {
+                Some(entry.path.as_path())
+            } else {
+                entry.path.parent()
+            };
+            if let Some(dir) = dir
+                && watch_config.paths.iter().any(|tree| dir.starts_with(tree))
+            {
+                dirs.insert(dir.to_path_buf());
+            }
+        }
+        let files = files
+            .into_iter()
+            .map(|entry| (entry.path.clone(), entry))
+            .collect();
+        Ok(Self { files, dirs })
+    }
+}
+
+/// Verify a changed path
+fn verify(
+    path: &Path,
+    expected: &Expected,
+    filecheck_config: &CommonFileCheckConfiguration,
+    unexpected_cfg: &CheckAllFilesConfiguration,
+    overrides: &Override,
+) -> Option<PackageIssue> {
+    if filecheck_config.is_skipped_network_path(path) {
+        return None;
+    }
+    if let Some(entry) = expected.files.get(path) {
+        return crate::file_ops::check_file_entry(entry, filecheck_config);
+    }
+    // Only files in package directories are of interest. Files that are gone
+    // again (such as temporary files) are not.
+    if !path
+        .parent()
+        .is_some_and(|parent| expected.dirs.contains(parent))
+        || is_ignored(overrides, path)
+        || std::fs::symlink_metadata(path).is_err()
+    {
+        return None;
+    }
+    let kind = match unexpected_cfg
+        .generated_files
+        .as_ref()
+        .and_then(|generated| generated.generator(path))
+    {
+        Some(by) if unexpected_cfg.report_generated => IssueKind::Generated { by: by.into() },
+        Some(_) => return None,
+        None => IssueKind::Unexpected,
+    };
+    Some((
+        None,
+        Issue::new(path.to_path_buf(), smallvec::smallvec![kind], None),
+    ))
+}
+
+/// Check if a path or any of its parents are ignored
+fn is_ignored(overrides: &Override, path: &Path) -> bool {
+    for (idx, ancestor) in path.ancestors().enumerate() {
+        match overrides.matched(ancestor, idx > 0) {
+            Match::None => (),
+            Match::Ignore(_) => return true,
+            Match::Whitelist(_) => return false,
+        }
+    }
+    false
+}
+
+/// Source of file system change notifications
+enum Source {
+    Fanotify(Fanotify),
+    Inotify(Inotify),
+}
+
+struct Watcher {
+    source: Source,
+    /// Watched directories (inotify only)
+    watches: AHashMap<WatchDescriptor, PathBuf>,
+    watched: AHashSet<PathBuf>,
+    /// Set if the limit on the number of inotify watches has been reached
+    out_of_watches: bool,
+}
+
+impl Watcher {
+    fn new(
+        config: &WatchConfiguration,
+        expected: &Expected,
+        database: Option<&Path>,
+    ) -> eyre::Result<Self> {
+        let watcher = match config.method {
+            WatchMethod::Fanotify => {
+                Self::fanotify(config, database).wrap_err("Failed to set up fanotify")?
+            }
+            WatchMethod::Inotify => {
+                Self::inotify(expected, database).wrap_err("Failed to set up inotify")?
+            }
+            // fanotify (without FAN_REPORT_DFID_NAME, which nix doesn't
+            // support) misses too much to be the default
+            WatchMethod::Auto => match Self::inotify(expected, database) {
+                Ok(watcher) => watcher,
+                Err(err) => {
+                    tracing::info!("Using fanotify, as inotify is not available: {err}");
+                    Self::fanotify(config, database).wrap_err("Failed to set up fanotify")?
+                }
+            },
+        };
+        Ok(watcher)
+    }
+
+    /// Watch for writes on the mounts of the watched trees
+    ///
+    /// Only writes are seen, as other events are only reported with file
+    /// handles instead of open file descriptors.
+    fn fanotify(config: &WatchConfiguration, database: Option<&Path>) -> nix::Result<Self> {
+        let fanotify = Fanotify::init(
+            fanotify::InitFlags::FAN_CLASS_NOTIF
+                | fanotify::InitFlags::FAN_CLOEXEC
+                | fanotify::InitFlags::FAN_NONBLOCK,
+            fanotify::EventFFlags::O_RDONLY
+                | fanotify::EventFFlags::O_LARGEFILE
+                | fanotify::EventFFlags::O_CLOEXEC,
+        )?;
+        for path in config.paths.iter().map(PathBuf::as_path).chain(database) {
+            fanotify.mark(
+                fanotify::MarkFlags::FAN_MARK_ADD | fanotify::MarkFlags::FAN_MARK_MOUNT,
+                fanotify::MaskFlags::FAN_CLOSE_WRITE,
+                nix::fcntl::AT_FDCWD,
+                Some(path),
+            )?;
+        }
+        tracing::info!("Watching for changes using fanotify");
+        Ok(Self::with_source(Source::Fanotify(fanotify)))
+    }
+
+    /// Watch each directory with package files
+    fn inotify(expected: &Expected, database: Option<&Path>) -> nix::Result<Self> {
+        let inotify =
+            Inotify::init(inotify::InitFlags::IN_CLOEXEC | inotify::InitFlags::IN_NONBLOCK)?;
+        let mut watcher = Self::with_source(Source::Inotify(inotify));
+        if let Some(database) = database {
+            watcher.add_dir(database);
+        }
+        watcher.add_dirs(expected);
+        tracing::info!(
+            "Watching for changes using inotify ({} directories)",
+            watcher.watches.len()
+        );
+        Ok(watcher)
+    }
+
+    fn with_source(source: Source) -> Self {
+        Self {
+            source,
+            watches: AHashMap::new(),
+            watched: AHashSet::new(),
+            out_of_watches: false,
+        }
+    }
+
+    /// Start watching directories with package files that are not yet watched
+    fn add_dirs(&mut self, expected: &Expected) {
+        for dir in &expected.dirs {
+            self.add_dir(dir);
+        }
+    }
+
+    fn add_dir(&mut self, dir: &Path) {
+        let Source::Inotify(ref inotify) = self.source else {
+            return;
+        };
+        if self.out_of_watches || self.watched.contains(dir) {
+            return;
+        }
+        match inotify.add_watch(dir, INOTIFY_MASK) {
+            Ok(wd) => {
+                self.watches.insert(wd, dir.to_path_buf());
+                self.watched.insert(dir.to_path_buf());
+            }
+            Err(Errno::ENOSPC) => {
+                tracing::warn!(
+                    "Reached the limit on inotify watches after {} directories, increase \
+                     fs.inotify.max_user_watches to watch all package directories",
+                    self.watches.len()
+                );
+                self.out_of_watches = true;
+            }
+            // Directories that are missing will be reported when checking
+            Err(Errno::ENOENT | Errno::ENOTDIR) => (),
+            Err(err) => tracing::warn!("Failed to watch {dir:?}: {err}"),
+        }
+    }
+
+    /// Wait up to `timeout` for changes, returning the paths that changed
+    fn wait(&mut self, timeout: Duration) -> eyre::Result<Vec<PathBuf>> {
+        let fd = match self.source {
+            Source::Fanotify(ref fanotify) => fanotify.as_fd(),
+            Source::Inotify(ref inotify) => inotify.as_fd(),
+        };
+        let timeout = PollTimeout::try_from(timeout).unwrap_or(PollTimeout::MAX);
+        match nix::poll::poll(&mut [PollFd::new(fd, PollFlags::POLLIN)], timeout) {
+            Ok(0) | Err(Errno::EINTR) => return Ok(vec![]),
+            Ok(_) => (),
+            Err(err) => return Err(err).wrap_err("Failed to wait for file system events"),
+        }
+        let result = match self.source {
+            Source::Fanotify(ref fanotify) => fanotify.read_events().map(|events| {
+                let own_pid = std::process::id();
+                events
+                    .iter()
+                    .filter_map(|event| {
+                        if event.mask().contains(fanotify::MaskFlags::FAN_Q_OVERFLOW) {
+                            tracing::warn!("Too many changes at once, some were not verified");
+                        }
+                        // Ignore our own changes
+                        if u32::try_from(event.pid()).is_ok_and(|pid| pid == own_pid) {
+                            return None;
+                        }
+                        event.fd().and_then(fd_path)
+                    })
+                    .collect()
+            }),
+            Source::Inotify(ref inotify) => inotify.read_events().map(|events| {
+                events
+                    .into_iter()
+                    .filter_map(|event| {
+                        if event.mask.contains(AddWatchFlags::IN_Q_OVERFLOW) {
+                            tracing::warn!("Too many changes at once, some were not verified");
+                        }
+                        if event.mask.contains(AddWatchFlags::IN_IGNORED) {
+                            // The directory is gone
+                            if let Some(dir) = self.watches.remove(&event.wd) {
+                                self.watched.remove(&dir);
+                            }
+                            return None;
+                        }
+                        let dir = self.watches.get(&event.wd)?;
+                        Some(match event.name {
+                            Some(name) => dir.join(name),
+                            None => dir.clone(),
+                        })
+                    })
+                    .collect()
+            }),
+        };
+        match result {
+            Ok(paths) => Ok(paths),
+            Err(Errno::EAGAIN | Errno::EINTR) => Ok(vec![]),
+            Err(err) => Err(err).wrap_err("Failed to read file system events"),
+        }
+    }
+}
+
+/// Get the path of a file descriptor from a fanotify event
+fn fd_path(fd: BorrowedFd<'_>) -> Option<PathBuf> {
+    let path = std::fs::read_link(format!("/proc/self/fd/{}", fd.as_raw_fd())).ok()?;
+    // The file was deleted before we got to it
+    if path.as_os_str().as_bytes().ends_with(b" (deleted)") {
+        return None;
+    }
+    Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paketkoll_types::files::FileFlags;
+    use paketkoll_types::files::Properties;
+
+    fn entry(path: &str, properties: Properties) -> FileEntry {
+        FileEntry {
+            package: None,
+            path: path.into(),
+            properties,
+            flags: FileFlags::empty(),
+            source: "test",
+            seen: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_verify_unexpected() {
+        let dir = tempfile::tempdir().unwrap();
+        let owned = dir.path().join("owned");
+        std::fs::create_dir(&owned).unwrap();
+        std::fs::write(owned.join("new"), "").unwrap();
+        std::fs::write(dir.path().join("elsewhere"), "").unwrap();
+
+        let expected = Expected {
+            files: AHashMap::new(),
+            dirs: [owned.clone()].into_iter().collect(),
+        };
+        let filecheck_config = CommonFileCheckConfiguration::builder().build().unwrap();
+        let ignored = format!("{}/ignored*", owned.display());
+        std::fs::write(owned.join("ignored-file"), "").unwrap();
+        let unexpected_cfg = CheckAllFilesConfiguration::builder()
+            .ignored_paths(vec![ignored.clone().into()])
+            .build()
+            .unwrap();
+        // Without the builtin ignores, as the temporary directory is in /tmp
+        let mut overrides = ignore::overrides::OverrideBuilder::new("/");
+        overrides.add(&format!("!{ignored}")).unwrap();
+        let overrides = overrides.build().unwrap();
+
+        let verify = |path: &Path| {
+            verify(
+                path,
+                &expected,
+                &filecheck_config,
+                &unexpected_cfg,
+                &overrides,
+            )
+            .map(|(_, issue)| issue.kinds().map(ToString::to_string).collect::<Vec<_>>())
+        };
+        assert_eq!(
+            verify(&owned.join("new")),
+            Some(vec![IssueKind::Unexpected.to_string()])
+        );
+        // Not in a package directory
+        assert_eq!(verify(&dir.path().join("elsewhere")), None);
+        // Gone again
+        assert_eq!(verify(&owned.join("temporary")), None);
+        assert_eq!(verify(&owned.join("ignored-file")), None);
+    }
+
+    #[test]
+    fn test_verify_expected() {
+        let dir = tempfile::tempdir().unwrap();
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink("/target", &link).unwrap();
+        let path = link.to_str().unwrap();
+        let mut files = AHashMap::new();
+        files.insert(
+            link.clone(),
+            entry(
+                path,
+                Properties::Symlink(paketkoll_types::files::Symlink {
+                    owner: paketkoll_types::files::Uid::new(nix::unistd::getuid().as_raw()),
+                    group: paketkoll_types::files::Gid::new(nix::unistd::getgid().as_raw()),
+                    target: "/other".into(),
+                }),
+            ),
+        );
+        let missing = dir.path().join("missing");
+        files.insert(
+            missing.clone(),
+            entry(missing.to_str().unwrap(), Properties::Removed),
+        );
+        let expected = Expected {
+            files,
+            dirs: AHashSet::new(),
+        };
+        let filecheck_config = CommonFileCheckConfiguration::builder().build().unwrap();
+        let unexpected_cfg = CheckAllFilesConfiguration::builder().build().unwrap();
+        let overrides = crate::file_ops::build_ignore_overrides(&[]).unwrap();
+
+        let (_, issue) = verify(
+            &link,
+            &expected,
+            &filecheck_config,
+            &unexpected_cfg,
+            &overrides,
+        )
+        .unwrap();
+        assert!(matches!(
+            issue.kinds().collect::<Vec<_>>()[..],
+            [IssueKind::SymlinkTarget { .. }]
+        ));
+        assert!(
+            verify(
+                &missing,
+                &expected,
+                &filecheck_config,
+                &unexpected_cfg,
+                &overrides,
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn test_inotify_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let expected = Expected {
+            files: AHashMap::new(),
+            dirs: [dir.path().to_path_buf()].into_iter().collect(),
+        };
+        let mut watcher = Watcher::inotify(&expected, None).unwrap();
+        std::fs::write(dir.path().join("file"), "contents").unwrap();
+        let mut paths = AHashSet::new();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !paths.contains(&dir.path().join("file")) && Instant::now() < deadline {
+            paths.extend(watcher.wait(POLL_INTERVAL).unwrap());
+        }
+        assert!(paths.contains(&dir.path().join("file")));
+    }
+}
@@ -4,6 +4,7 @@ use crate::files::FileEntry;
 use crate::intern::Interner;
 use crate::intern::PackageRef;
 use crate::package::PackageInterned;
+use crate::version::VersionScheme;
 use ahash::AHashMap;
 use ahash::AHashSet;
 use compact_str::CompactString;
@@ -163,6 +164,14 @@ pub trait Packages: Name + std::fmt::Debug {
         Ok(packages_to_package_map(packages.iter()))
     }
 
+    /// The version scheme used by this package manager
+    ///
+    /// Use this to compare or constrain versions of packages from this
+    /// backend.
+    fn version_scheme(&self) -> VersionScheme {
+        VersionScheme::Generic
+    }
+
     /// Perform installation and uninstallation of a bunch of packages
     ///
     /// The package name format depends on the backend.
@@ -319,6 +319,8 @@ bitflags::bitflags! {
         const CONFIG = 0b0000_0000_0000_0001;
         /// It is OK if this file is missing (currently only relevant for systemd-tmpfiles)
         const OK_IF_MISSING = 0b0000_0000_0000_0010;
+        /// Only check the metadata of this file, not the contents (used for incremental checks)
+        const METADATA_ONLY = 0b0000_0000_0000_0100;
     }
 }
 
@@ -437,6 +439,67 @@ impl Properties {
             Self::Permissions(val) => Some(val.group),
         }
     }
+
+    /// Get size (if available)
+    #[must_use]
+    pub const fn size(&self) -> Option<u64> {
+        match self {
+            Self::RegularFileBasic(val) => val.size,
+            Self::RegularFileSystemd(val) => val.size,
+            Self::RegularFile(val) => Some(val.size),
+            Self::Symlink(_) => None,
+            Self::Directory(_) => None,
+            Self::Fifo(_) => None,
+            Self::DeviceNode(_) => None,
+            Self::Special => None,
+            Self::Removed => None,
+            Self::Unknown => None,
+            Self::Permissions(_) => None,
+        }
+    }
+
+    /// Get checksum (if available)
+    #[must_use]
+    pub const fn checksum(&self) -> Option<&Checksum> {
+        match self {
+            Self::RegularFileBasic(val) => Some(&val.checksum),
+            Self::RegularFileSystemd(val) => Some(&val.checksum),
+            Self::RegularFile(val) => Some(&val.checksum),
+            Self::Symlink(_) => None,
+            Self::Directory(_) => None,
+            Self::Fifo(_) => None,
+            Self::DeviceNode(_) => None,
+            Self::Special => None,
+            Self::Removed => None,
+            Self::Unknown => None,
+            Self::Permissions(_) => None,
+        }
+    }
+
+    /// Short human readable name of the type of entry
+    #[must_use]
+    pub const fn type_name(&self) -> &'static str {
+        match self {
+            Self::RegularFileBasic(_) | Self::RegularFileSystemd(_) | Self::RegularFile(_) => {
+                "file"
+            }
+            Self::Symlink(_) => "link",
+            Self::Directory(_) => "dir",
+            Self::Fifo(_) => "fifo",
+            Self::DeviceNode(DeviceNode {
+                device_type: DeviceType::Block,
+                ..
+            }) => "block",
+            Self::DeviceNode(DeviceNode {
+                device_type: DeviceType::Char,
+                ..
+            }) => "char",
+            Self::Special => "special",
+            Self::Removed => "removed",
+            Self::Unknown => "unknown",
+            Self::Permissions(_) => "permissions",
+        }
+    }
 }
 
 /// A set of permissions
@@ -6,6 +6,7 @@ use crate::files::Gid;
 use crate::files::Mode;
 use crate::files::Uid;
 use crate::intern::PackageRef;
+use compact_str::CompactString;
 use smallvec::SmallVec;
 use std::fmt::Display;
 use std::os::unix::fs::FileTypeExt;
@@ -82,6 +83,8 @@ pub struct Issue {
     path: PathBuf,
     kinds: IssueVec,
     source: Option<&'static str>,
+    #[serde(skip)]
+    config_file: bool,
 }
 
 impl Issue {
@@ -91,9 +94,17 @@ impl Issue {
             path,
             kinds,
             source,
+            config_file: false,
         }
     }
 
+    /// Mark if the path is a config file according to the package manager
+    #[must_use]
+    pub const fn with_config_file(mut self, config_file: bool) -> Self {
+        self.config_file = config_file;
+        self
+    }
+
     /// Path of file
     #[must_use]
     pub fn path(&self) -> &Path {
@@ -109,6 +120,26 @@ impl Issue {
     pub const fn source(&self) -> Option<&'static str> {
         self.source
     }
+
+    /// Who to attribute the issue to, given the package owning the path
+    ///
+    /// Files from backends without packages (such as systemd-tmpfiles) are
+    /// attributed to the backend (the source of the issue) instead.
+    #[must_use]
+    pub fn owner<'a>(&self, package: Option<&'a str>) -> Option<&'a str> {
+        package.or(self.source)
+    }
+
+    /// Is the path a config file according to the package manager
+    #[must_use]
+    pub const fn is_config_file(&self) -> bool {
+        self.config_file
+    }
+
+    /// Only keep the kinds of issues for which the predicate returns true
+    pub fn retain_kinds(&mut self, mut f: impl FnMut(&IssueKind) -> bool) {
+        self.kinds.retain(|kind| f(kind));
+    }
 }
 
 /// Type of issue found
@@ -127,6 +158,9 @@ pub enum IssueKind {
     Exists,
     /// Extra unexpected entity on file system
     Unexpected,
+    /// Entity not owned by any package, but known to be generated by
+    /// something (package script, hook, service, ...)
+    Generated { by: CompactString },
     /// Failed to check for (or check contents of) entity due to permissions
     PermissionDenied,
     /// Type of entity was not as expected (e.g. file vs symlink)
@@ -161,6 +195,58 @@ pub enum IssueKind {
     /// Some sort of unexpected error when processing the file system
     #[serde(serialize_with = "serialize_error")]
     FsCheckError(Box<eyre::Error>),
+    /// A waiver for accepted issues has expired (the path is the glob of the
+    /// waiver)
+    WaiverExpired {
+        expired: CompactString,
+        reason: CompactString,
+    },
+}
+
+impl IssueKind {
+    /// All names returned by [`IssueKind::name`]
+    pub const NAMES: &[&str] = &[
+        "missing",
+        "exists",
+        "unexpected",
+        "generated",
+        "permission_denied",
+        "type_incorrect",
+        "size_incorrect",
+        "checksum_incorrect",
+        "symlink_target",
+        "wrong_owner",
+        "wrong_group",
+        "wrong_mode",
+        "wrong_device_node_id",
+        "metadata_error",
+        "fs_check_error",
+        "waiver_expired",
+    ];
+
+    /// Short machine readable name of the kind of issue (same as used for
+    /// serialization)
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Missing => "missing",
+            Self::Exists => "exists",
+            Self::Unexpected => "unexpected",
+            Self::Generated { .. } => "generated",
+            Self::PermissionDenied => "permission_denied",
+            Self::TypeIncorrect { .. } => "type_incorrect",
+            Self::SizeIncorrect { .. } => "size_incorrect",
+            Self::ChecksumIncorrect { .. } => "checksum_incorrect",
+            Self::SymlinkTarget { .. } => "symlink_target",
+            Self::WrongOwner { .. } => "wrong_owner",
+            Self::WrongGroup { .. } => "wrong_group",
+            Self::WrongMode { .. } => "wrong_mode",
+            Self::WrongDeviceNodeId { .. } => "wrong_device_node_id",
+            Self::MetadataError(_) => "metadata_error",
+            Self::FsCheckError(_) => "fs_check_error",
+            Self::WaiverExpired { .. } => "waiver_expired",
+        }
+    }
 }
 
 impl Display for IssueKind {
@@ -169,6 +255,7 @@ impl Display for IssueKind {
             Self::Missing => write!(f, "missing or inaccessible file/directory/...")?,
             Self::Exists => write!(f, "unexpected file/directory/... (should be removed)")?,
             Self::Unexpected => write!(f, "unexpected file")?,
+            Self::Generated { by } => write!(f, "generated file (by {by})")?,
             Self::PermissionDenied => write!(f, "read error (Permission denied)")?,
             Self::TypeIncorrect { actual, expected } => {
                 write!(f, "type mismatch (expected {expected}, actual {actual})")?;
@@ -207,6 +294,9 @@ impl Display for IssueKind {
                 write!(f, "error when checking file")?;
                 format_error(f, err)?;
             }
+            Self::WaiverExpired { expired, reason } => {
+                write!(f, "waiver expired on {expired} ({reason})")?;
+            }
         }
         Ok(())
     }
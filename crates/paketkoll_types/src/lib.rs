@@ -5,3 +5,4 @@ pub mod files;
 pub mod intern;
 pub mod issue;
 pub mod package;
+pub mod version;
@@ -0,0 +1,560 @@
+//! Package versions, compared following the rules of each package manager
+
+use compact_str::CompactString;
+use std::cmp::Ordering;
+use std::fmt::Display;
+
+/// Version scheme (ordering rules) used by a package manager
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum VersionScheme {
+    /// pacman (`[epoch:]pkgver[-pkgrel]`, compared like `vercmp`)
+    Pacman,
+    /// dpkg (`[epoch:]upstream[-revision]`, compared like `dpkg
+    /// --compare-versions`)
+    Dpkg,
+    /// Versions without known structure, compared segment by segment
+    /// (numeric segments numerically, the rest alphabetically)
+    Generic,
+}
+
+impl VersionScheme {
+    /// Compare two versions according to this scheme
+    pub fn compare(self, a: &str, b: &str) -> Ordering {
+        match self {
+            Self::Pacman => pacman_vercmp(a, b),
+            Self::Dpkg => dpkg_vercmp(a, b),
+            Self::Generic => rpmvercmp(a, b),
+        }
+    }
+}
+
+/// A pacman package version (`[epoch:]pkgver[-pkgrel]`)
+///
+/// Note that like in pacman, the release is only compared if both versions
+/// have one, so `1.0` is equal to both `1.0-1` and `1.0-2`.
+#[derive(Debug, Clone)]
+pub struct PacmanVersion(CompactString);
+
+impl PacmanVersion {
+    pub fn new(version: impl Into<CompactString>) -> Self {
+        Self(version.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Epoch (`0` if not given)
+    pub fn epoch(&self) -> &str {
+        pacman_split(&self.0).0
+    }
+
+    /// Upstream version
+    pub fn pkgver(&self) -> &str {
+        pacman_split(&self.0).1
+    }
+
+    /// Package release (if given)
+    pub fn pkgrel(&self) -> Option<&str> {
+        pacman_split(&self.0).2
+    }
+}
+
+/// A dpkg package version (`[epoch:]upstream[-revision]`)
+#[derive(Debug, Clone)]
+pub struct DebianVersion(CompactString);
+
+impl DebianVersion {
+    pub fn new(version: impl Into<CompactString>) -> Self {
+        Self(version.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Epoch (0 if not given or invalid)
+    pub fn epoch(&self) -> u64 {
+        dpkg_split(&self.0).0
+    }
+
+    /// Upstream version
+    pub fn upstream(&self) -> &str {
+        dpkg_split(&self.0).1
+    }
+
+    /// Debian revision (empty for native packages)
+    pub fn revision(&self) -> &str {
+        dpkg_split(&self.0).2
+    }
+}
+
+/// Implement traits for version types, based on a comparison function
+macro_rules! version_impls {
+    ($type:ty, $compare:ident) => {
+        impl PartialEq for $type {
+            fn eq(&self, other: &Self) -> bool {
+                self.cmp(other) == Ordering::Equal
+            }
+        }
+
+        impl Eq for $type {}
+
+        impl PartialOrd for $type {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for $type {
+            fn cmp(&self, other: &Self) -> Ordering {
+                $compare(&self.0, &other.0)
+            }
+        }
+
+        impl Display for $type {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl From<&str> for $type {
+            fn from(value: &str) -> Self {
+                Self::new(value)
+            }
+        }
+
+        impl From<CompactString> for $type {
+            fn from(value: CompactString) -> Self {
+                Self::new(value)
+            }
+        }
+    };
+}
+
+version_impls!(PacmanVersion, pacman_vercmp);
+version_impls!(DebianVersion, dpkg_vercmp);
+
+/// Relation in a version constraint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Relation {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Display for Relation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Less => write!(f, "<"),
+            Self::LessOrEqual => write!(f, "<="),
+            Self::Equal => write!(f, "="),
+            Self::GreaterOrEqual => write!(f, ">="),
+            Self::Greater => write!(f, ">"),
+        }
+    }
+}
+
+/// A constraint on a version, such as `>= 1.2-3`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct VersionConstraint {
+    pub relation: Relation,
+    pub version: CompactString,
+}
+
+impl VersionConstraint {
+    /// Parse a constraint
+    ///
+    /// Both pacman (`<`, `>`) and dpkg (`<<`, `>>`) style strict relations are
+    /// accepted, as well as `<=`, `=`, `==` and `>=`. Whitespace between
+    /// relation and version is optional.
+    pub fn parse(input: &str) -> eyre::Result<Self> {
+        let input = input.trim();
+        let relations = [
+            ("<<", Relation::Less),
+            ("<=", Relation::LessOrEqual),
+            (">>", Relation::Greater),
+            (">=", Relation::GreaterOrEqual),
+            ("==", Relation::Equal),
+            ("<", Relation::Less),
+            (">", Relation::Greater),
+            ("=", Relation::Equal),
+        ];
+        let (relation, version) = relations
+            .into_iter()
+            .find_map(|(prefix, relation)| {
+                input
+                    .strip_prefix(prefix)
+                    .map(|version| (relation, version.trim_start()))
+            })
+            .ok_or_else(|| eyre::eyre!("Missing relation in version constraint: {input}"))?;
+        if version.is_empty() || version.contains(char::is_whitespace) {
+            eyre::bail!("Invalid version in version constraint: {input}");
+        }
+        Ok(Self {
+            relation,
+            version: version.into(),
+        })
+    }
+
+    /// Check if a version satisfies this constraint
+    pub fn matches(&self, scheme: VersionScheme, version: &str) -> bool {
+        let ordering = scheme.compare(version, &self.version);
+        match self.relation {
+            Relation::Less => ordering.is_lt(),
+            Relation::LessOrEqual => ordering.is_le(),
+            Relation::Equal => ordering.is_eq(),
+            Relation::GreaterOrEqual => ordering.is_ge(),
+            Relation::Greater => ordering.is_gt(),
+        }
+    }
+}
+
+impl Display for VersionConstraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.relation, self.version)
+    }
+}
+
+/// Compare two pacman versions (`[epoch:]pkgver[-pkgrel]`), like `vercmp`
+///
+/// The release is only compared if both versions have one.
+pub fn pacman_vercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let (epoch_a, ver_a, rel_a) = pacman_split(a);
+    let (epoch_b, ver_b, rel_b) = pacman_split(b);
+    rpmvercmp(epoch_a, epoch_b)
+        .then_with(|| rpmvercmp(ver_a, ver_b))
+        .then_with(|| match (rel_a, rel_b) {
+            (Some(rel_a), Some(rel_b)) => rpmvercmp(rel_a, rel_b),
+            _ => Ordering::Equal,
+        })
+}
+
+/// Split a pacman version into epoch, version and release
+fn pacman_split(evr: &str) -> (&str, &str, Option<&str>) {
+    let digits = evr.bytes().take_while(u8::is_ascii_digit).count();
+    let (epoch, rest) = match evr[digits..].strip_prefix(':') {
+        Some(rest) if digits == 0 => ("0", rest),
+        Some(rest) => (&evr[..digits], rest),
+        None => ("0", evr),
+    };
+    match rest.rsplit_once('-') {
+        Some((version, release)) => (epoch, version, Some(release)),
+        None => (epoch, rest, None),
+    }
+}
+
+/// Segment based version comparison, as used by pacman (and RPM)
+fn rpmvercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut one, mut two) = (0, 0);
+    while one < a.len() && two < b.len() {
+        let (sep_start_one, sep_start_two) = (one, two);
+        while one < a.len() && !a[one].is_ascii_alphanumeric() {
+            one += 1;
+        }
+        while two < b.len() && !b[two].is_ascii_alphanumeric() {
+            two += 1;
+        }
+        if one == a.len() || two == b.len() {
+            break;
+        }
+        // Different lengths of separators decide the comparison
+        if one - sep_start_one != two - sep_start_two {
+            return (one - sep_start_one).cmp(&(two - sep_start_two));
+        }
+
+        // Grab the next segment of the same type (numeric or alpha) from both
+        let is_num = a[one].is_ascii_digit();
+        let segment_pred: fn(&u8) -> bool = if is_num {
+            u8::is_ascii_digit
+        } else {
+            u8::is_ascii_alphabetic
+        };
+        let end_one = one + a[one..].iter().take_while(|c| segment_pred(c)).count();
+        let end_two = two + b[two..].iter().take_while(|c| segment_pred(c)).count();
+        let (mut seg_one, mut seg_two) = (&a[one..end_one], &b[two..end_two]);
+        if seg_two.is_empty() {
+            // Numeric segments are always newer than alpha segments
+            return if is_num {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+        }
+        if is_num {
+            seg_one = trim_leading_zeros(seg_one);
+            seg_two = trim_leading_zeros(seg_two);
+            match seg_one.len().cmp(&seg_two.len()) {
+                Ordering::Equal => (),
+                ordering => return ordering,
+            }
+        }
+        match seg_one.cmp(seg_two) {
+            Ordering::Equal => (),
+            ordering => return ordering,
+        }
+        one = end_one;
+        two = end_two;
+    }
+
+    let (rest_one, rest_two) = (&a[one..], &b[two..]);
+    if rest_one.is_empty() && rest_two.is_empty() {
+        return Ordering::Equal;
+    }
+    // A remaining alpha string never beats an empty string, so "1.0" is newer
+    // than "1.0alpha", but "1.0.1" is newer than "1.0".
+    let two_is_alpha = rest_two.first().is_some_and(u8::is_ascii_alphabetic);
+    let one_is_alpha = rest_one.first().is_some_and(u8::is_ascii_alphabetic);
+    if (rest_one.is_empty() && !two_is_alpha) || one_is_alpha {
+        Ordering::Less
+    } else {
+        Ordering::Greater
+    }
+}
+
+fn trim_leading_zeros(segment: &[u8]) -> &[u8] {
+    let zeros = segment.iter().take_while(|&&c| c == b'0').count();
+    &segment[zeros..]
+}
+
+/// Compare two dpkg versions (`[epoch:]upstream[-revision]`), like
+/// `dpkg --compare-versions`
+///
+/// Versions with an invalid epoch are compared as if the epoch was 0.
+pub fn dpkg_vercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let (epoch_a, upstream_a, revision_a) = dpkg_split(a);
+    let (epoch_b, upstream_b, revision_b) = dpkg_split(b);
+    epoch_a
+        .cmp(&epoch_b)
+        .then_with(|| verrevcmp(upstream_a, upstream_b))
+        .then_with(|| verrevcmp(revision_a, revision_b))
+}
+
+/// Split a dpkg version into epoch, upstream version and revision
+fn dpkg_split(version: &str) -> (u64, &str, &str) {
+    let (epoch, rest) = match version.split_once(':') {
+        Some((epoch, rest)) => (epoch.parse().unwrap_or(0), rest),
+        None => (0, version),
+    };
+    match rest.rsplit_once('-') {
+        Some((upstream, revision)) => (epoch, upstream, revision),
+        None => (epoch, rest, ""),
+    }
+}
+
+/// Sort weight of a character in a dpkg version (0 is end of string)
+fn dpkg_order(c: Option<u8>) -> i32 {
+    match c {
+        None => 0,
+        Some(c) if c.is_ascii_digit() => 0,
+        Some(c) if c.is_ascii_alphabetic() => i32::from(c),
+        Some(b'~') => -1,
+        Some(c) => i32::from(c) + 256,
+    }
+}
+
+/// Compare upstream versions or revisions, the way dpkg does
+fn verrevcmp(a: &str, b: &str) -> Ordering {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+    let is_digit = |s: &[u8], idx: usize| s.get(idx).is_some_and(u8::is_ascii_digit);
+    while i < a.len() || j < b.len() {
+        // Compare non-digit prefixes
+        while (i < a.len() && !is_digit(a, i)) || (j < b.len() && !is_digit(b, j)) {
+            let ac = dpkg_order(a.get(i).copied());
+            let bc = dpkg_order(b.get(j).copied());
+            if ac != bc {
+                return ac.cmp(&bc);
+            }
+            i += 1;
+            j += 1;
+        }
+        // Compare numeric parts
+        while a.get(i) == Some(&b'0') {
+            i += 1;
+        }
+        while b.get(j) == Some(&b'0') {
+            j += 1;
+        }
+        let mut first_diff = Ordering::Equal;
+        while is_digit(a, i) && is_digit(b, j) {
+            if first_diff == Ordering::Equal {
+                first_diff = a[i].cmp(&b[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+        if is_digit(a, i) {
+            return Ordering::Greater;
+        }
+        if is_digit(b, j) {
+            return Ordering::Less;
+        }
+        if first_diff != Ordering::Equal {
+            return first_diff;
+        }
+    }
+    Ordering::Equal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pacman_vercmp() {
+        // Test cases from pacman's vercmptest.sh
+        let cases = [
+            ("1.5.0", "1.5.0", Ordering::Equal),
+            ("1.5.1", "1.5.0", Ordering::Greater),
+            ("1.5.1", "1.5", Ordering::Greater),
+            ("1.5.0-1", "1.5.0-1", Ordering::Equal),
+            ("1.5.0-1", "1.5.0-2", Ordering::Less),
+            ("1.5.0-1", "1.5.1-1", Ordering::Less),
+            ("1.5.0-2", "1.5.1-1", Ordering::Less),
+            ("1.5-1", "1.5", Ordering::Equal),
+            ("1.1-1", "1.1", Ordering::Equal),
+            ("1.0-1", "1.1", Ordering::Less),
+            ("1.1-1", "1.0", Ordering::Greater),
+            ("1.5b-1", "1.5-1", Ordering::Less),
+            ("1.5b", "1.5", Ordering::Less),
+            ("1.5b-1", "1.5", Ordering::Less),
+            ("1.5b", "1.5.1", Ordering::Less),
+            ("1.0a", "1.0alpha", Ordering::Less),
+            ("1.0alpha", "1.0b", Ordering::Less),
+            ("1.0b", "1.0beta", Ordering::Less),
+            ("1.0beta", "1.0rc", Ordering::Less),
+            ("1.0rc", "1.0", Ordering::Less),
+            ("1.5.a", "1.5", Ordering::Greater),
+            ("1.5.b", "1.5.a", Ordering::Greater),
+            ("1.5.1", "1.5.b", Ordering::Greater),
+            ("1.5.b-1", "1.5.b", Ordering::Equal),
+            ("1.5-1", "1.5.b", Ordering::Less),
+            ("2.0", "2_0", Ordering::Equal),
+            ("2.0_a", "2_0.a", Ordering::Equal),
+            ("2.0a", "2.0.a", Ordering::Less),
+            ("2___a", "2_a", Ordering::Greater),
+            ("1.5.1-1", "1.5.1-1.1", Ordering::Less),
+            ("1.5.1-1", "1.5.1-2", Ordering::Less),
+            ("1.5.1-1.1", "1.5.1-2", Ordering::Less),
+            ("0:1.0", "1.0", Ordering::Equal),
+            ("1:1.0", "1.0", Ordering::Greater),
+            ("1:1.0", "1:1.1", Ordering::Less),
+            ("1:1.1", "2:1.0", Ordering::Less),
+            ("1:1.0-1", "1.0-1", Ordering::Greater),
+            ("1.1", "1:1.0-1", Ordering::Less),
+            ("1.1-1", "1:1.0-2", Ordering::Less),
+            ("1:1.0-1", "1:1.0-2", Ordering::Less),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(pacman_vercmp(a, b), expected, "{a} vs {b}");
+            assert_eq!(pacman_vercmp(b, a), expected.reverse(), "{b} vs {a}");
+        }
+    }
+
+    #[test]
+    fn test_dpkg_vercmp() {
+        let cases = [
+            ("1.0", "1.0", Ordering::Equal),
+            ("1.0", "1.0-0", Ordering::Equal),
+            ("1.0-1", "1.0-2", Ordering::Less),
+            ("1.0-1", "1.0-1.1", Ordering::Less),
+            ("1.0~rc1", "1.0", Ordering::Less),
+            ("1.0~rc1", "1.0~rc2", Ordering::Less),
+            ("1.0~~", "1.0~", Ordering::Less),
+            ("1.0", "1.0a", Ordering::Less),
+            ("1.0a", "1.0+", Ordering::Less),
+            ("1.0+deb12u1", "1.0", Ordering::Greater),
+            ("1.01", "1.1", Ordering::Equal),
+            ("1.10", "1.9", Ordering::Greater),
+            ("1:1.0", "2.0", Ordering::Greater),
+            ("0:1.0", "1.0", Ordering::Equal),
+            ("2.36-9+deb12u4", "2.36-9+deb12u14", Ordering::Less),
+            ("3.0.11-1~deb12u2", "3.0.11-1", Ordering::Less),
+            ("1.2.3-4-5", "1.2.3-4-4", Ordering::Greater),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(dpkg_vercmp(a, b), expected, "{a} vs {b}");
+            assert_eq!(dpkg_vercmp(b, a), expected.reverse(), "{b} vs {a}");
+        }
+    }
+
+    #[test]
+    fn test_version_types() {
+        let version = PacmanVersion::new("1:2.3.4-5");
+        assert_eq!(version.epoch(), "1");
+        assert_eq!(version.pkgver(), "2.3.4");
+        assert_eq!(version.pkgrel(), Some("5"));
+        let version = PacmanVersion::new("2.3.4");
+        assert_eq!(version.epoch(), "0");
+        assert_eq!(version.pkgrel(), None);
+
+        let version = DebianVersion::new("1:2.36-9+deb12u4");
+        assert_eq!(version.epoch(), 1);
+        assert_eq!(version.upstream(), "2.36");
+        assert_eq!(version.revision(), "9+deb12u4");
+        let version = DebianVersion::new("1.2-3-4");
+        assert_eq!(version.epoch(), 0);
+        assert_eq!(version.upstream(), "1.2-3");
+        assert_eq!(version.revision(), "4");
+
+        let mut versions: Vec<DebianVersion> = ["1.0", "1.0~rc1", "1:0.5", "1.0-1", "0.9"]
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        versions.sort();
+        let sorted: Vec<_> = versions.iter().map(DebianVersion::as_str).collect();
+        assert_eq!(sorted, ["0.9", "1.0~rc1", "1.0", "1.0-1", "1:0.5"]);
+        assert_eq!(DebianVersion::new("1.0"), DebianVersion::new("0:1.00"));
+
+        let mut versions: Vec<PacmanVersion> = ["1.0rc1", "1.0.1", "1.0", "1:0.1"]
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        versions.sort();
+        let sorted: Vec<_> = versions.iter().map(PacmanVersion::as_str).collect();
+        assert_eq!(sorted, ["1.0rc1", "1.0", "1.0.1", "1:0.1"]);
+    }
+
+    #[test]
+    fn test_version_constraint() {
+        let constraint = VersionConstraint::parse(">= 1.2-3").unwrap();
+        assert_eq!(constraint.relation, Relation::GreaterOrEqual);
+        assert_eq!(constraint.version, "1.2-3");
+        assert_eq!(constraint.to_string(), ">= 1.2-3");
+        assert!(constraint.matches(VersionScheme::Dpkg, "1.2-3"));
+        assert!(constraint.matches(VersionScheme::Dpkg, "1:1.0"));
+        assert!(!constraint.matches(VersionScheme::Dpkg, "1.2~rc1-1"));
+
+        let constraint = VersionConstraint::parse("<<2.0").unwrap();
+        assert_eq!(constraint.relation, Relation::Less);
+        assert!(constraint.matches(VersionScheme::Dpkg, "2.0~beta"));
+        assert!(!constraint.matches(VersionScheme::Dpkg, "2.0"));
+
+        let constraint = VersionConstraint::parse("<2.0").unwrap();
+        assert!(constraint.matches(VersionScheme::Pacman, "2.0beta"));
+        assert!(!constraint.matches(VersionScheme::Pacman, "2.0-1"));
+
+        let constraint = VersionConstraint::parse("= 1.0").unwrap();
+        assert!(constraint.matches(VersionScheme::Pacman, "1.0-7"));
+        assert!(constraint.matches(VersionScheme::Generic, "1.00"));
+
+        assert!(VersionConstraint::parse("1.0").is_err());
+        assert!(VersionConstraint::parse(">=").is_err());
+        assert!(VersionConstraint::parse(">= 1.0 2.0").is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use console::Style;
-use konfigkoll_core::confirm::Choices;
-use konfigkoll_core::confirm::MultiOptionConfirm;
+use paketkoll_utils::confirm::Choices;
+use paketkoll_utils::confirm::MultiOptionConfirm;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 enum PromptChoices {
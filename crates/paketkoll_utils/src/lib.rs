@@ -3,6 +3,8 @@
 //! Not for external usage. No stability guarantees whatsoever.
 
 pub mod checksum;
+#[cfg(feature = "confirm")]
+pub mod confirm;
 
 /// Mask out the bits of the mode that are actual permissions
 pub const MODE_MASK: u32 = 0o7777;